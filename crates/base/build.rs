@@ -11,13 +11,15 @@ mod supabase_startup_snapshot {
     use deno_http::DefaultHttpPropertyExtractor;
     use event_worker::js_interceptors::sb_events_js_interceptors;
     use event_worker::sb_user_event_worker;
+    use sb_cache::sb_cache;
     use sb_core::http_start::sb_core_http;
     use sb_core::net::sb_core_net;
-    use sb_core::permissions::sb_core_permissions;
+    use sb_core::permissions::{sb_core_permissions, FsWriteAccess};
     use sb_core::runtime::sb_core_runtime;
     use sb_core::sb_core_main_js;
     use sb_core::transpiler::maybe_transpile_source;
     use sb_env::sb_env;
+    use sb_kv::sb_kv;
     use sb_node::deno_node;
     use sb_workers::sb_user_workers;
     use std::path::Path;
@@ -154,7 +156,7 @@ mod supabase_startup_snapshot {
         let user_agent = String::from("supabase");
         let fs = Arc::new(deno_fs::RealFs);
         let mut extensions: Vec<Extension> = vec![
-            sb_core_permissions::init_ops_and_esm(false),
+            sb_core_permissions::init_ops_and_esm(false, FsWriteAccess::All),
             deno_webidl::deno_webidl::init_ops_and_esm(),
             deno_console::deno_console::init_ops_and_esm(),
             deno_url::deno_url::init_ops_and_esm(),
@@ -178,6 +180,8 @@ mod supabase_startup_snapshot {
             deno_http::deno_http::init_ops_and_esm::<DefaultHttpPropertyExtractor>(),
             deno_io::deno_io::init_ops_and_esm(Some(Default::default())),
             deno_fs::deno_fs::init_ops_and_esm::<Permissions>(fs.clone()),
+            sb_cache::init_ops_and_esm(None),
+            sb_kv::init_ops_and_esm(None),
             sb_env::init_ops_and_esm(),
             sb_os::sb_os::init_ops_and_esm(),
             sb_user_workers::init_ops_and_esm(),
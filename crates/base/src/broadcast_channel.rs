@@ -0,0 +1,279 @@
+use async_trait::async_trait;
+use deno_broadcast_channel::{BroadcastChannel, Message};
+use deno_core::error::{type_error, AnyError};
+use deno_core::parking_lot::Mutex;
+use deno_core::Resource;
+use futures_util::future::select_all;
+use once_cell::sync::Lazy;
+use sb_core::util::fs::resolve_from_cwd;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+/// The largest message `postMessage` sends to other workers.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Messages a slow worker hasn't received yet. Older ones are dropped.
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone)]
+struct ChannelMessage {
+    sender: Uuid,
+    name: String,
+    data: Arc<Vec<u8>>,
+}
+
+type Group = broadcast::Sender<ChannelMessage>;
+
+fn new_group() -> Group {
+    broadcast::channel(CAPACITY).0
+}
+
+/// The groups of the services some worker listens to, by normalized service
+/// path. A group is removed once the last of its receivers is dropped.
+static SERVICE_GROUPS: Lazy<Mutex<HashMap<String, Group>>> = Lazy::new(Default::default);
+static TO_MAIN: Lazy<Group> = Lazy::new(new_group);
+static FROM_MAIN: Lazy<Group> = Lazy::new(new_group);
+
+fn service_group_key(service_path: &str) -> String {
+    resolve_from_cwd(Path::new(service_path)).map_or_else(
+        |_| service_path.to_string(),
+        |it| it.to_string_lossy().to_string(),
+    )
+}
+
+fn prune_service_groups() {
+    SERVICE_GROUPS
+        .lock()
+        .retain(|_, group| group.receiver_count() > 0);
+}
+
+/// The `BroadcastChannel` of a worker. User workers exchange messages with
+/// the other workers of their service in the process, and with the main worker
+/// if they were created with `broadcastWithMain`.
+///
+/// Messages are copied out on the thread of the receiving worker, so their
+/// delivery counts against its CPU time rather than the sender's.
+#[derive(Clone)]
+pub struct WorkerBroadcastChannel {
+    /// The key of the service group, which is looked up on each use as it may
+    /// have been pruned in between.
+    service: Option<String>,
+    send_to: Vec<Group>,
+    receive_from: Vec<Group>,
+}
+
+impl WorkerBroadcastChannel {
+    pub fn for_service(service_path: &str, with_main: bool) -> Self {
+        let mut channel = Self {
+            service: Some(service_group_key(service_path)),
+            send_to: vec![],
+            receive_from: vec![],
+        };
+        if with_main {
+            channel.send_to.push(TO_MAIN.clone());
+            channel.receive_from.push(FROM_MAIN.clone());
+        }
+
+        channel
+    }
+
+    pub fn for_main() -> Self {
+        Self {
+            service: None,
+            send_to: vec![FROM_MAIN.clone()],
+            receive_from: vec![TO_MAIN.clone()],
+        }
+    }
+
+    /// A channel no other worker receives from.
+    pub fn isolated() -> Self {
+        let group = new_group();
+
+        Self {
+            service: None,
+            send_to: vec![group.clone()],
+            receive_from: vec![group],
+        }
+    }
+}
+
+pub struct WorkerBroadcastChannelResource {
+    id: Uuid,
+    receivers: tokio::sync::Mutex<(
+        Vec<broadcast::Receiver<ChannelMessage>>,
+        mpsc::UnboundedReceiver<()>,
+    )>,
+    cancel_tx: mpsc::UnboundedSender<()>,
+}
+
+impl Drop for WorkerBroadcastChannelResource {
+    fn drop(&mut self) {
+        self.receivers.get_mut().0.clear();
+        prune_service_groups();
+    }
+}
+
+impl Resource for WorkerBroadcastChannelResource {
+    fn name(&self) -> Cow<str> {
+        "broadcastChannel".into()
+    }
+}
+
+#[async_trait]
+impl BroadcastChannel for WorkerBroadcastChannel {
+    type Resource = WorkerBroadcastChannelResource;
+
+    fn subscribe(&self) -> Result<Self::Resource, AnyError> {
+        let (cancel_tx, cancel_rx) = mpsc::unbounded_channel();
+        let mut receivers: Vec<_> = self.receive_from.iter().map(|it| it.subscribe()).collect();
+        if let Some(service) = self.service.as_ref() {
+            receivers.push(
+                SERVICE_GROUPS
+                    .lock()
+                    .entry(service.clone())
+                    .or_insert_with(new_group)
+                    .subscribe(),
+            );
+        }
+
+        Ok(WorkerBroadcastChannelResource {
+            id: Uuid::new_v4(),
+            receivers: tokio::sync::Mutex::new((receivers, cancel_rx)),
+            cancel_tx,
+        })
+    }
+
+    fn unsubscribe(&self, resource: &Self::Resource) -> Result<(), AnyError> {
+        Ok(resource.cancel_tx.send(())?)
+    }
+
+    async fn send(
+        &self,
+        resource: &Self::Resource,
+        name: String,
+        data: Vec<u8>,
+    ) -> Result<(), AnyError> {
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(type_error(format!(
+                "broadcast messages can't be larger than {} bytes",
+                MAX_MESSAGE_SIZE
+            )));
+        }
+
+        let message = ChannelMessage {
+            sender: resource.id,
+            name,
+            data: Arc::new(data),
+        };
+        // nobody listens to a service without a group
+        let service_group = self
+            .service
+            .as_ref()
+            .and_then(|it| SERVICE_GROUPS.lock().get(it).cloned());
+        for group in self.send_to.iter().chain(service_group.iter()) {
+            // fails only when nobody listens
+            let _ = group.send(message.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn recv(&self, resource: &Self::Resource) -> Result<Option<Message>, AnyError> {
+        let mut guard = resource.receivers.lock().await;
+        let (receivers, cancel_rx) = &mut *guard;
+
+        loop {
+            let next = select_all(receivers.iter_mut().map(|rx| Box::pin(rx.recv())));
+            let result = tokio::select! {
+                _ = cancel_rx.recv() => return Ok(None),
+                (result, _, _) = next => result,
+            };
+
+            match result {
+                // the messages the worker was too slow for are skipped
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return Ok(None),
+                // other channels of the isolate already got it from JS
+                Ok(message) if message.sender == resource.id => continue,
+                Ok(message) => return Ok(Some((message.name, message.data.to_vec()))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{service_group_key, WorkerBroadcastChannel, SERVICE_GROUPS};
+    use deno_broadcast_channel::BroadcastChannel;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    async fn try_recv(
+        channel: &WorkerBroadcastChannel,
+        resource: &<WorkerBroadcastChannel as BroadcastChannel>::Resource,
+    ) -> Option<String> {
+        timeout(Duration::from_millis(50), channel.recv(resource))
+            .await
+            .ok()
+            .map(|it| it.unwrap().unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_groups() {
+        let a1 = WorkerBroadcastChannel::for_service("test-a", true);
+        let a2 = WorkerBroadcastChannel::for_service("test-a", false);
+        let b = WorkerBroadcastChannel::for_service("test-b", false);
+        let main = WorkerBroadcastChannel::for_main();
+        let (a1_rid, a2_rid, b_rid, main_rid) = (
+            a1.subscribe().unwrap(),
+            a2.subscribe().unwrap(),
+            b.subscribe().unwrap(),
+            main.subscribe().unwrap(),
+        );
+
+        a1.send(&a1_rid, "from-a1".to_string(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(try_recv(&a2, &a2_rid).await.as_deref(), Some("from-a1"));
+        assert_eq!(try_recv(&main, &main_rid).await.as_deref(), Some("from-a1"));
+        assert_eq!(try_recv(&a1, &a1_rid).await, None);
+        assert_eq!(try_recv(&b, &b_rid).await, None);
+
+        // only the workers created with `broadcastWithMain` hear the main one
+        main.send(&main_rid, "from-main".to_string(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(try_recv(&a1, &a1_rid).await.as_deref(), Some("from-main"));
+        assert_eq!(try_recv(&a2, &a2_rid).await, None);
+
+        assert!(a1
+            .send(
+                &a1_rid,
+                "large".to_string(),
+                vec![0; super::MAX_MESSAGE_SIZE + 1]
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_groups_are_normalized_and_pruned() {
+        let a = WorkerBroadcastChannel::for_service("./test-c", false);
+        let b = WorkerBroadcastChannel::for_service("test-c/../test-c", false);
+        let (a_rid, b_rid) = (a.subscribe().unwrap(), b.subscribe().unwrap());
+
+        a.send(&a_rid, "from-a".to_string(), vec![]).await.unwrap();
+        assert_eq!(try_recv(&b, &b_rid).await.as_deref(), Some("from-a"));
+
+        let key = service_group_key("test-c");
+        drop(a_rid);
+        assert!(SERVICE_GROUPS.lock().contains_key(&key));
+
+        drop(b_rid);
+        assert!(!SERVICE_GROUPS.lock().contains_key(&key));
+    }
+}
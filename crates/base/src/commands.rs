@@ -1,25 +1,9 @@
-use crate::{
-    rt_worker::worker_pool::WorkerPoolPolicy,
-    server::{Server, ServerCodes, WorkerEntrypoints},
-};
+use crate::server::{Server, ServerOptions};
 use anyhow::Error;
 use deno_core::JsRuntime;
 use log::error;
-use tokio::sync::mpsc::Sender;
-
-#[allow(clippy::too_many_arguments)]
-pub async fn start_server(
-    ip: &str,
-    port: u16,
-    main_service_path: String,
-    event_worker_path: Option<String>,
-    user_worker_policy: Option<WorkerPoolPolicy>,
-    import_map_path: Option<String>,
-    no_module_cache: bool,
-    no_signal_handler: bool,
-    callback_tx: Option<Sender<ServerCodes>>,
-    entrypoints: WorkerEntrypoints,
-) -> Result<(), Error> {
+
+pub async fn start_server(opts: ServerOptions) -> Result<(), Error> {
     set_v8_flags();
 
     // NOTE(denoland/deno/20495): Due to the new PKU (Memory Protection Keys)
@@ -27,19 +11,7 @@ pub async fn start_server(
     // the main thread that spawns V8 isolates.
     JsRuntime::init_platform(None);
 
-    let mut server = Server::new(
-        ip,
-        port,
-        main_service_path,
-        event_worker_path,
-        user_worker_policy,
-        import_map_path,
-        no_module_cache,
-        no_signal_handler,
-        callback_tx,
-        entrypoints,
-    )
-    .await?;
+    let mut server = Server::new(opts).await?;
     server.listen().await
 }
 
@@ -1,3 +1,5 @@
+use crate::broadcast_channel::WorkerBroadcastChannel;
+use crate::inspector_server::{Inspector, InspectorPause};
 use crate::utils::units::mib_to_bytes;
 
 use anyhow::{anyhow, bail, Error};
@@ -15,30 +17,45 @@ use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::fmt;
 use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::net::UnixStream;
 use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
 
 use crate::snapshot;
 use event_worker::events::{EventMetadata, WorkerEventWithMetadata};
 use event_worker::js_interceptors::sb_events_js_interceptors;
 use event_worker::sb_user_event_worker;
+use sb_cache::{get_or_create_backend, sb_cache};
+use sb_core::cache::deno_dir::DenoDir;
 use sb_core::cache::CacheSetting;
 use sb_core::cert::ValueRootCertStoreProvider;
 use sb_core::external_memory::custom_allocator;
 use sb_core::http_start::sb_core_http;
 use sb_core::net::sb_core_net;
-use sb_core::permissions::{sb_core_permissions, Permissions};
-use sb_core::runtime::sb_core_runtime;
+use sb_core::permissions::{sb_core_permissions, FsWriteAccess, Permissions};
+use sb_core::runtime::{sb_core_runtime, PendingBackgroundTasks};
 use sb_core::sb_core_main_js;
 use sb_env::sb_env as sb_env_op;
+use sb_fs::static_fs::StaticFs;
+use sb_fs::tmp_fs::{host_dir, TmpFs, TMP_DIR};
+use sb_graph::check::TYPESCRIPT_VERSION;
 use sb_graph::emitter::EmitterFactory;
 use sb_graph::import_map::load_import_map;
-use sb_graph::{generate_binary_eszip, EszipPayloadKind};
+use sb_graph::package_json::load_package_json;
+use sb_graph::{add_static_files, generate_binary_eszip, EszipPayloadKind};
+use sb_kv::{sb_kv, KvConfig};
+use sb_module_loader::metadata::{Metadata, SerializablePackageJsonDeps};
 use sb_module_loader::standalone::create_module_loader_for_standalone_from_eszip_kind;
 use sb_module_loader::RuntimeProviders;
 use sb_node::deno_node;
-use sb_workers::context::{UserWorkerMsgs, WorkerContextInitOpts, WorkerRuntimeOpts};
+use sb_npm::ResolvedNpmRc;
+use sb_workers::bindings::WorkerBindings;
+use sb_workers::context::{
+    SchedulerMsgs, UserWorkerLimits, UserWorkerLimitsConfig, UserWorkerMsgs, UserWorkerRuntimeOpts,
+    WorkerContextInitOpts, WorkerRuntimeOpts,
+};
 use sb_workers::sb_user_workers;
 
 pub struct DenoRuntimeError(Error);
@@ -64,22 +81,34 @@ pub struct DenoRuntime {
     pub env_vars: HashMap<String, String>, // TODO: does this need to be pub?
     main_module_id: ModuleId,
     pub conf: WorkerRuntimeOpts,
+    /// Local files the worker was built from. Empty if it was booted from an
+    /// eszip.
+    pub local_module_paths: Vec<PathBuf>,
+    pub maybe_inspector_pause: Option<InspectorPause>,
+    wait_for_inspector: bool,
 }
 
 impl DenoRuntime {
     #[allow(clippy::unnecessary_literal_unwrap)]
     #[allow(clippy::arc_with_non_send_sync)]
-    pub async fn new(opts: WorkerContextInitOpts) -> Result<Self, Error> {
+    pub async fn new(
+        opts: WorkerContextInitOpts,
+        maybe_inspector: Option<Inspector>,
+    ) -> Result<Self, Error> {
         let WorkerContextInitOpts {
             service_path,
             no_module_cache,
+            cached_only,
             import_map_path,
-            env_vars,
+            mut env_vars,
             events_rx,
-            conf,
+            mut conf,
             maybe_eszip,
             maybe_entrypoint,
+            maybe_function_name,
             maybe_module_code,
+            maybe_lockfile,
+            maybe_trusted_keys,
             ..
         } = opts;
 
@@ -96,22 +125,35 @@ impl DenoRuntime {
 
         let mut net_access_disabled = false;
         let mut allow_remote_modules = true;
+        let mut maybe_npmrc_path = None;
+        let mut node_modules_dir = false;
+        let mut maybe_package_json_path = None;
+        let mut static_patterns = vec![];
         if conf.is_user_worker() {
             let user_conf = conf.as_user_worker().unwrap();
             net_access_disabled = user_conf.net_access_disabled;
             allow_remote_modules = user_conf.allow_remote_modules;
+            maybe_npmrc_path = user_conf.npmrc_path.clone();
+            node_modules_dir = user_conf.node_modules_dir;
+            maybe_package_json_path = user_conf.package_json_path.clone();
+            static_patterns = user_conf.static_patterns.clone();
         }
 
         let mut maybe_arc_import_map = None;
+        let mut local_module_paths = vec![];
         let only_module_code =
             maybe_module_code.is_some() && maybe_eszip.is_none() && !is_some_entry_point;
 
+        // Only eszips given to the worker are checked, not the ones built here.
+        let maybe_trusted_keys = maybe_eszip.as_ref().and(maybe_trusted_keys);
         let eszip = if let Some(eszip_payload) = maybe_eszip {
             eszip_payload
         } else {
             let mut emitter_factory = EmitterFactory::new();
 
-            let cache_strategy = if no_module_cache {
+            let cache_strategy = if cached_only {
+                CacheSetting::Only
+            } else if no_module_cache {
                 CacheSetting::ReloadAll
             } else {
                 CacheSetting::Use
@@ -122,19 +164,45 @@ impl DenoRuntime {
 
             let maybe_import_map = load_import_map(import_map_path.clone())?;
             emitter_factory.set_import_map(maybe_import_map);
+            emitter_factory.set_lockfile(maybe_lockfile)?;
+            emitter_factory.set_npmrc(match maybe_npmrc_path {
+                Some(path) => ResolvedNpmRc::from_file(Path::new(&path))?,
+                None => ResolvedNpmRc::discover(Some(&base_dir_path))?,
+            });
+            if node_modules_dir {
+                emitter_factory.set_node_modules_dir(base_dir_path.join("node_modules"))?;
+            }
+
+            let main_module_url_file_path = main_module_url.clone().to_file_path().unwrap();
+            let package_json_dir = main_module_url_file_path
+                .parent()
+                .unwrap_or(base_dir_path.as_path());
+            if let Some(package_json) =
+                load_package_json(maybe_package_json_path, package_json_dir)?
+            {
+                emitter_factory.init_package_json_deps(&package_json);
+            }
+
+            // The module loader resolves bare specifiers through the metadata
+            // of the eszip, as it does for bundled ones.
+            let metadata = Metadata {
+                package_json_deps: emitter_factory
+                    .package_json_deps()
+                    .cloned()
+                    .map(SerializablePackageJsonDeps::from_deps),
+                ..Default::default()
+            };
             maybe_arc_import_map = emitter_factory.maybe_import_map.clone();
 
             let arc_emitter_factory = Arc::new(emitter_factory);
 
-            let main_module_url_file_path = main_module_url.clone().to_file_path().unwrap();
-
             let maybe_code = if only_module_code {
                 maybe_module_code
             } else {
                 None
             };
 
-            let eszip = generate_binary_eszip(
+            let mut eszip = generate_binary_eszip(
                 main_module_url_file_path,
                 arc_emitter_factory,
                 maybe_code,
@@ -142,6 +210,22 @@ impl DenoRuntime {
             )
             .await?;
 
+            if metadata.package_json_deps.is_some() {
+                metadata.add_to_eszip(&mut eszip)?;
+            }
+
+            add_static_files(&mut eszip, &base_dir_path, &static_patterns)?;
+
+            local_module_paths = eszip
+                .specifiers()
+                .iter()
+                .filter_map(|it| Url::parse(it).ok())
+                .filter(|it| it.scheme() == "file")
+                .filter_map(|it| it.to_file_path().ok())
+                .chain(import_map_path.as_ref().map(PathBuf::from))
+                .filter_map(|it| it.canonicalize().ok())
+                .collect();
+
             EszipPayloadKind::Eszip(eszip)
         };
 
@@ -193,26 +277,136 @@ impl DenoRuntime {
             });
         }
 
-        let fs = Arc::new(deno_fs::RealFs);
-
         let rt_provider = create_module_loader_for_standalone_from_eszip_kind(
             eszip,
             maybe_arc_import_map,
             import_map_path,
+            maybe_trusted_keys,
         )
         .await?;
 
         let RuntimeProviders {
             npm_resolver,
             fs: file_system,
+            static_files,
             module_loader,
             module_code,
+            source_map_getter,
+            metadata,
         } = rt_provider;
 
-        let mod_code = module_code;
+        // User workers only reach the static files of the function, and
+        // their own `/tmp` if they were given a quota for it.
+        let (fs, fs_write_access) = if let Some(user_conf) = conf.as_user_worker() {
+            let maybe_tmp = match user_conf.tmp_quota_mb {
+                Some(quota_mb) => {
+                    let key = user_conf.key.unwrap_or_else(Uuid::new_v4);
+                    let root = host_dir().join(key.to_string());
+
+                    Some(TmpFs::new(root, mib_to_bytes(quota_mb))?)
+                }
+                None => None,
+            };
+            let fs_write_access = if maybe_tmp.is_some() {
+                FsWriteAccess::Within(PathBuf::from(TMP_DIR))
+            } else {
+                FsWriteAccess::Denied
+            };
+
+            (
+                Arc::new(StaticFs::new(static_files, maybe_tmp)) as Arc<dyn deno_fs::FileSystem>,
+                fs_write_access,
+            )
+        } else {
+            (
+                Arc::new(deno_fs::RealFs) as Arc<dyn deno_fs::FileSystem>,
+                FsWriteAccess::All,
+            )
+        };
+
+        // `caches` is shared by the workers of the service. Main and event
+        // workers keep theirs in memory.
+        let (cache_backend, cache_quota_mb) = match conf.as_user_worker() {
+            Some(user_conf) => (user_conf.cache_backend, user_conf.cache_quota_mb),
+            None => {
+                let default_opts = UserWorkerRuntimeOpts::default();
+                (default_opts.cache_backend, default_opts.cache_quota_mb)
+            }
+        };
+        let cache_backend = get_or_create_backend(
+            cache_backend,
+            &base_dir_path.to_string_lossy(),
+            mib_to_bytes(cache_quota_mb),
+        )?;
+
+        // `Deno.openKv` opens the store of the namespace, which is the service
+        // path unless the worker was given another one.
+        let kv_config = {
+            let default_opts = UserWorkerRuntimeOpts::default();
+            let user_conf = conf.as_user_worker().unwrap_or(&default_opts);
+
+            KvConfig {
+                dir: match &user_conf.kv_dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None => DenoDir::new(None)?.kv_folder_path(),
+                },
+                namespace: user_conf
+                    .kv_namespace
+                    .clone()
+                    .unwrap_or_else(|| base_dir_path.to_string_lossy().to_string()),
+                quota: mib_to_bytes(user_conf.kv_quota_mb),
+            }
+        };
+
+        // `BroadcastChannel` messages reach the other isolates of the service.
+        let broadcast_channel = match &conf {
+            WorkerRuntimeOpts::UserWorker(user_conf) => WorkerBroadcastChannel::for_service(
+                &base_dir_path.to_string_lossy(),
+                user_conf.broadcast_with_main,
+            ),
+            WorkerRuntimeOpts::MainWorker(_) => WorkerBroadcastChannel::for_main(),
+            WorkerRuntimeOpts::EventsWorker(_) => WorkerBroadcastChannel::isolated(),
+        };
+
+        let mut mod_code = module_code;
+
+        if let Some(function_name) = maybe_function_name {
+            let Some(entrypoint) = metadata
+                .functions
+                .as_ref()
+                .and_then(|it| it.get(&function_name))
+            else {
+                bail!("function {} is not bundled in the eszip", function_name);
+            };
+
+            main_module_url = Url::parse(entrypoint)?;
+            mod_code = None;
+        } else if !is_some_entry_point {
+            // Defaults embedded by `bundle` only apply where the caller left
+            // the value unset.
+            if let Some(entrypoint) = metadata.entrypoint.as_deref() {
+                main_module_url = Url::parse(entrypoint)?;
+            }
+        }
+
+        if let Some(metadata_env_vars) = metadata.env_vars {
+            for (key, value) in metadata_env_vars {
+                env_vars.entry(key).or_insert(value);
+            }
+        }
+
+        if let (Some(limits), Some(user_conf)) = (metadata.limits, conf.as_user_worker_mut()) {
+            user_conf.apply_default_limits(&UserWorkerLimits {
+                memory_limit_mb: limits.memory_limit_mb,
+                low_memory_multiplier: None,
+                worker_timeout_ms: limits.worker_timeout_ms,
+                cpu_time_soft_limit_ms: limits.cpu_time_soft_limit_ms,
+                cpu_time_hard_limit_ms: limits.cpu_time_hard_limit_ms,
+            });
+        }
 
         let extensions = vec![
-            sb_core_permissions::init_ops(net_access_disabled),
+            sb_core_permissions::init_ops(net_access_disabled, fs_write_access),
             deno_webidl::deno_webidl::init_ops(),
             deno_console::deno_console::init_ops(),
             deno_url::deno_url::init_ops(),
@@ -232,14 +426,14 @@ impl DenoRuntime {
             ),
             // TODO: support providing a custom seed for crypto
             deno_crypto::deno_crypto::init_ops(None),
-            deno_broadcast_channel::deno_broadcast_channel::init_ops(
-                deno_broadcast_channel::InMemoryBroadcastChannel::default(),
-            ),
+            deno_broadcast_channel::deno_broadcast_channel::init_ops(broadcast_channel),
             deno_net::deno_net::init_ops::<Permissions>(Some(root_cert_store_provider), None),
             deno_tls::deno_tls::init_ops(),
             deno_http::deno_http::init_ops::<DefaultHttpPropertyExtractor>(),
             deno_io::deno_io::init_ops(stdio),
             deno_fs::deno_fs::init_ops::<Permissions>(fs.clone()),
+            sb_cache::init_ops(Some(cache_backend)),
+            sb_kv::init_ops(Some(kv_config)),
             sb_env_op::init_ops(),
             sb_os::sb_os::init_ops(),
             sb_user_workers::init_ops(),
@@ -271,6 +465,8 @@ impl DenoRuntime {
             compiled_wasm_module_store: Default::default(),
             startup_snapshot: Some(snapshot::snapshot()),
             module_loader: Some(module_loader),
+            source_map_getter: Some(source_map_getter),
+            inspector: maybe_inspector.is_some(),
             ..Default::default()
         };
 
@@ -279,12 +475,24 @@ impl DenoRuntime {
         let version: Option<&str> = option_env!("GIT_V_TAG");
 
         // Bootstrapping stage
+        let has_tmp_dir = conf
+            .as_user_worker()
+            .map_or(false, |it| it.tmp_quota_mb.is_some());
+        let binding_names = conf
+            .as_user_worker()
+            .map(|it| it.bindings.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
         let script = format!(
-            "globalThis.bootstrapSBEdge({}, {}, {}, '{}')",
-            deno_core::serde_json::json!({ "target": env!("TARGET") }),
+            "globalThis.bootstrapSBEdge({}, {}, {}, '{}', {}, {})",
+            deno_core::serde_json::json!({
+                "target": env!("TARGET"),
+                "tsVersion": TYPESCRIPT_VERSION,
+            }),
             conf.is_user_worker(),
             conf.is_events_worker(),
-            version.unwrap_or("0.1.0")
+            version.unwrap_or("0.1.0"),
+            has_tmp_dir,
+            deno_core::serde_json::json!(binding_names),
         );
 
         js_runtime
@@ -317,6 +525,8 @@ impl DenoRuntime {
                     conf.key.map_or("".to_string(), |k| k.to_string()),
                 );
 
+                op_state.put::<PendingBackgroundTasks>(PendingBackgroundTasks(watch::channel(0).0));
+
                 if let Some(events_msg_tx) = conf.events_msg_tx.clone() {
                     op_state.put::<mpsc::UnboundedSender<WorkerEventWithMetadata>>(events_msg_tx);
                     op_state.put::<EventMetadata>(EventMetadata {
@@ -324,11 +534,37 @@ impl DenoRuntime {
                         execution_id: conf.key,
                     });
                 }
+
+                if let Some(pool_msg_tx) = conf.pool_msg_tx.clone() {
+                    op_state.put::<WorkerBindings>(WorkerBindings {
+                        services: conf.bindings.clone(),
+                        pool_msg_tx,
+                    });
+                }
             }
 
             op_state.put::<sb_env::EnvVars>(env_vars);
         }
 
+        let mut maybe_inspector_pause = None;
+        let mut wait_for_inspector = false;
+        if let Some(inspector) = maybe_inspector {
+            let maybe_execution_id = conf.as_user_worker().and_then(|it| it.key);
+
+            wait_for_inspector = inspector.option.is_with_break();
+            maybe_inspector_pause = Some(inspector.server.register_inspector(
+                &mut js_runtime,
+                &main_module_url,
+                service_path.to_string_lossy().to_string(),
+                maybe_execution_id,
+                wait_for_inspector,
+            ));
+
+            // needed by `op_require_break_on_next_statement`
+            let inspector = js_runtime.inspector();
+            js_runtime.op_state().borrow_mut().put(inspector);
+        }
+
         let main_module_id = js_runtime
             .load_main_module(&main_module_url, mod_code)
             .await?;
@@ -338,6 +574,9 @@ impl DenoRuntime {
             main_module_id,
             env_vars,
             conf,
+            local_module_paths,
+            maybe_inspector_pause,
+            wait_for_inspector,
         })
     }
 
@@ -354,12 +593,29 @@ impl DenoRuntime {
                 );
 
             if self.conf.is_main_worker() {
-                op_state.put::<mpsc::UnboundedSender<UserWorkerMsgs>>(
-                    self.conf.as_main_worker().unwrap().worker_pool_tx.clone(),
-                );
+                let conf = self.conf.as_main_worker().unwrap();
+
+                op_state.put::<mpsc::UnboundedSender<UserWorkerMsgs>>(conf.worker_pool_tx.clone());
+                op_state.put::<Arc<UserWorkerLimitsConfig>>(conf.user_worker_limits.clone());
+
+                if let Some(scheduler_tx) = conf.scheduler_tx.clone() {
+                    op_state.put::<mpsc::UnboundedSender<SchedulerMsgs>>(scheduler_tx);
+                }
             }
         }
 
+        if self.wait_for_inspector {
+            // keep the supervisor from counting the time spent waiting
+            if let Some(pause) = self.maybe_inspector_pause.as_ref() {
+                pause.set(true);
+            }
+
+            self.js_runtime
+                .inspector()
+                .borrow_mut()
+                .wait_for_session_and_break_on_next_statement();
+        }
+
         let js_runtime = &mut self.js_runtime;
         let mod_result_rx = js_runtime.mod_evaluate(self.main_module_id);
 
@@ -396,12 +652,17 @@ impl DenoRuntime {
 mod test {
     use crate::deno_runtime::DenoRuntime;
     use deno_core::{FastString, ModuleCode};
+    use eszip::EszipV2;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
     use sb_core::conn_sync::ConnSync;
     use sb_graph::emitter::EmitterFactory;
+    use sb_graph::signature::{sign_eszip, EszipSignatureError, EszipSigningKey, EszipTrustedKeys};
     use sb_graph::{generate_binary_eszip, EszipPayloadKind};
+    use sb_module_loader::metadata::{Metadata, MetadataLimits};
     use sb_workers::context::{
-        MainWorkerRuntimeOpts, UserWorkerMsgs, UserWorkerRuntimeOpts, WorkerContextInitOpts,
-        WorkerRuntimeOpts,
+        MainWorkerRuntimeOpts, UserWorkerLimits, UserWorkerMsgs, UserWorkerRuntimeOpts,
+        WorkerContextInitOpts, WorkerRuntimeOpts,
     };
     use std::collections::HashMap;
     use std::fs;
@@ -415,20 +676,33 @@ mod test {
     #[tokio::test]
     async fn test_module_code_no_eszip() {
         let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
-        DenoRuntime::new(WorkerContextInitOpts {
-            service_path: PathBuf::from("./test_cases/"),
-            no_module_cache: false,
-            import_map_path: None,
-            env_vars: Default::default(),
-            events_rx: None,
-            timing: None,
-            maybe_eszip: None,
-            maybe_entrypoint: None,
-            maybe_module_code: Some(FastString::from(String::from(
-                "Deno.serve((req) => new Response('Hello World'));",
-            ))),
-            conf: { WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts { worker_pool_tx }) },
-        })
+        DenoRuntime::new(
+            WorkerContextInitOpts {
+                service_path: PathBuf::from("./test_cases/"),
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars: Default::default(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys: None,
+                maybe_module_code: Some(FastString::from(String::from(
+                    "Deno.serve((req) => new Response('Hello World'));",
+                ))),
+                conf: {
+                    WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                        worker_pool_tx,
+                        user_worker_limits: Default::default(),
+                        scheduler_tx: None,
+                    })
+                },
+            },
+            None,
+        )
         .await
         .expect("It should not panic");
     }
@@ -449,18 +723,31 @@ mod test {
 
         let eszip_code = bin_eszip.into_bytes();
 
-        let runtime = DenoRuntime::new(WorkerContextInitOpts {
-            service_path: PathBuf::from("./test_cases/"),
-            no_module_cache: false,
-            import_map_path: None,
-            env_vars: Default::default(),
-            events_rx: None,
-            timing: None,
-            maybe_eszip: Some(EszipPayloadKind::VecKind(eszip_code)),
-            maybe_entrypoint: None,
-            maybe_module_code: None,
-            conf: { WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts { worker_pool_tx }) },
-        })
+        let runtime = DenoRuntime::new(
+            WorkerContextInitOpts {
+                service_path: PathBuf::from("./test_cases/"),
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars: Default::default(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: Some(EszipPayloadKind::VecKind(eszip_code)),
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys: None,
+                maybe_module_code: None,
+                conf: {
+                    WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                        worker_pool_tx,
+                        user_worker_limits: Default::default(),
+                        scheduler_tx: None,
+                    })
+                },
+            },
+            None,
+        )
         .await;
 
         let mut rt = runtime.unwrap();
@@ -498,18 +785,31 @@ mod test {
 
         let eszip_code = binary_eszip.into_bytes();
 
-        let runtime = DenoRuntime::new(WorkerContextInitOpts {
-            service_path,
-            no_module_cache: false,
-            import_map_path: None,
-            env_vars: Default::default(),
-            events_rx: None,
-            timing: None,
-            maybe_eszip: Some(EszipPayloadKind::VecKind(eszip_code)),
-            maybe_entrypoint: None,
-            maybe_module_code: None,
-            conf: { WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts { worker_pool_tx }) },
-        })
+        let runtime = DenoRuntime::new(
+            WorkerContextInitOpts {
+                service_path,
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars: Default::default(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: Some(EszipPayloadKind::VecKind(eszip_code)),
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys: None,
+                maybe_module_code: None,
+                conf: {
+                    WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                        worker_pool_tx,
+                        user_worker_limits: Default::default(),
+                        scheduler_tx: None,
+                    })
+                },
+            },
+            None,
+        )
         .await;
 
         let mut rt = runtime.unwrap();
@@ -534,6 +834,251 @@ mod test {
         std::mem::drop(main_mod_ev);
     }
 
+    fn generate_key() -> (EszipSigningKey, EszipTrustedKeys) {
+        let dir = std::env::temp_dir().join(format!("sb-eszip-key-{}", uuid::Uuid::new_v4()));
+        let private_key_path = dir.join("private.key");
+        let public_key_path = dir.join("public.key");
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&private_key_path, base64::encode(pkcs8.as_ref())).unwrap();
+        fs::write(
+            &public_key_path,
+            base64::encode(key_pair.public_key().as_ref()),
+        )
+        .unwrap();
+
+        let signing_key = EszipSigningKey::from_file(&private_key_path).unwrap();
+        let trusted_keys = EszipTrustedKeys::from_files([&public_key_path]).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+        (signing_key, trusted_keys)
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn generate_silly_eszip() -> EszipV2 {
+        generate_binary_eszip(
+            PathBuf::from("./test_cases/eszip-silly-test/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn create_eszip_runtime(
+        eszip: EszipV2,
+        maybe_trusted_keys: Option<EszipTrustedKeys>,
+    ) -> Result<DenoRuntime, anyhow::Error> {
+        let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
+
+        DenoRuntime::new(
+            WorkerContextInitOpts {
+                service_path: PathBuf::from("./test_cases/eszip-silly-test"),
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars: Default::default(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: Some(EszipPayloadKind::VecKind(eszip.into_bytes())),
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys,
+                maybe_module_code: None,
+                conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                    worker_pool_tx,
+                    user_worker_limits: Default::default(),
+                    scheduler_tx: None,
+                }),
+            },
+            None,
+        )
+        .await
+    }
+
+    fn assert_signature_error(
+        result: Result<DenoRuntime, anyhow::Error>,
+        expected: fn(&EszipSignatureError) -> bool,
+    ) {
+        let Err(err) = result else {
+            panic!("worker booted from an eszip it should have rejected");
+        };
+        let err = err.downcast::<EszipSignatureError>().unwrap();
+
+        assert!(expected(&err), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    async fn test_eszip_signature_is_checked_on_boot() {
+        let (signing_key, trusted_keys) = generate_key();
+
+        let result =
+            create_eszip_runtime(generate_silly_eszip().await, Some(trusted_keys.clone())).await;
+        assert_signature_error(result, |it| matches!(it, EszipSignatureError::Unsigned));
+
+        let mut eszip = generate_silly_eszip().await;
+        sign_eszip(&mut eszip, &signing_key).await.unwrap();
+        eszip.add_opaque_data(String::from("tampered"), Arc::from(&b"tampered"[..]));
+        let result = create_eszip_runtime(eszip, Some(trusted_keys.clone())).await;
+        assert_signature_error(result, |it| {
+            matches!(it, EszipSignatureError::InvalidSignature)
+        });
+
+        let mut eszip = generate_silly_eszip().await;
+        sign_eszip(&mut eszip, &signing_key).await.unwrap();
+        assert!(create_eszip_runtime(eszip, Some(trusted_keys))
+            .await
+            .is_ok());
+    }
+
+    async fn create_runtime_with_metadata(
+        metadata: Metadata,
+        maybe_entrypoint: Option<String>,
+        env_vars: HashMap<String, String>,
+        conf: WorkerRuntimeOpts,
+    ) -> DenoRuntime {
+        let mut eszip = generate_silly_eszip().await;
+        metadata.add_to_eszip(&mut eszip).unwrap();
+
+        DenoRuntime::new(
+            WorkerContextInitOpts {
+                // holds no `index.ts`, so only an entrypoint finds the module
+                service_path: PathBuf::from("./test_cases/"),
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars,
+                events_rx: None,
+                timing: None,
+                maybe_eszip: Some(EszipPayloadKind::VecKind(eszip.into_bytes())),
+                maybe_entrypoint,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys: None,
+                maybe_module_code: None,
+                conf,
+            },
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    fn silly_entrypoint() -> String {
+        let path = PathBuf::from("./test_cases/eszip-silly-test/index.ts")
+            .canonicalize()
+            .unwrap();
+
+        deno_core::url::Url::from_file_path(path)
+            .unwrap()
+            .to_string()
+    }
+
+    fn main_worker_conf() -> WorkerRuntimeOpts {
+        let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
+
+        WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+            worker_pool_tx,
+            user_worker_limits: Default::default(),
+            scheduler_tx: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_eszip_metadata_entrypoint() {
+        let metadata = Metadata {
+            entrypoint: Some(silly_entrypoint()),
+            ..Default::default()
+        };
+        let mut rt =
+            create_runtime_with_metadata(metadata, None, HashMap::new(), main_worker_conf()).await;
+
+        let main_mod_ev = rt.js_runtime.mod_evaluate(rt.main_module_id);
+        let _ = rt.js_runtime.run_event_loop(false).await;
+
+        let is_ten_even = rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCode::from(String::from("globalThis.isTenEven")),
+            )
+            .unwrap();
+        let is_ten_even = rt.to_value::<deno_core::serde_json::Value>(&is_ten_even);
+        assert_eq!(is_ten_even.unwrap().to_string(), "true");
+        std::mem::drop(main_mod_ev);
+
+        // the entrypoint of the caller wins
+        let metadata = Metadata {
+            entrypoint: Some(String::from("file:///not-bundled/index.ts")),
+            ..Default::default()
+        };
+        create_runtime_with_metadata(
+            metadata,
+            Some(silly_entrypoint()),
+            HashMap::new(),
+            main_worker_conf(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_eszip_metadata_env_vars() {
+        let metadata = Metadata {
+            entrypoint: Some(silly_entrypoint()),
+            env_vars: Some(HashMap::from([
+                (String::from("FROM_ESZIP"), String::from("eszip")),
+                (String::from("OVERRIDDEN"), String::from("eszip")),
+            ])),
+            ..Default::default()
+        };
+        let rt = create_runtime_with_metadata(
+            metadata,
+            None,
+            HashMap::from([(String::from("OVERRIDDEN"), String::from("caller"))]),
+            main_worker_conf(),
+        )
+        .await;
+
+        assert_eq!(rt.env_vars.get("FROM_ESZIP").unwrap(), "eszip");
+        assert_eq!(rt.env_vars.get("OVERRIDDEN").unwrap(), "caller");
+    }
+
+    #[tokio::test]
+    async fn test_eszip_metadata_limits() {
+        let metadata = Metadata {
+            entrypoint: Some(silly_entrypoint()),
+            limits: Some(MetadataLimits {
+                memory_limit_mb: Some(100),
+                worker_timeout_ms: Some(5000),
+                cpu_time_soft_limit_ms: None,
+                cpu_time_hard_limit_ms: None,
+            }),
+            ..Default::default()
+        };
+        let default_opts = UserWorkerRuntimeOpts::default();
+        let conf = WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+            worker_timeout_ms: 1234,
+            requested_limits: UserWorkerLimits {
+                worker_timeout_ms: Some(1234),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let rt = create_runtime_with_metadata(metadata, None, HashMap::new(), conf).await;
+        let user_conf = rt.conf.as_user_worker().unwrap();
+
+        assert_eq!(user_conf.memory_limit_mb, 100);
+        assert_eq!(user_conf.worker_timeout_ms, 1234);
+        assert_eq!(
+            user_conf.cpu_time_soft_limit_ms,
+            default_opts.cpu_time_soft_limit_ms
+        );
+    }
+
     async fn create_runtime(
         path: Option<PathBuf>,
         env_vars: Option<HashMap<String, String>>,
@@ -541,28 +1086,53 @@ mod test {
     ) -> DenoRuntime {
         let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
 
-        DenoRuntime::new(WorkerContextInitOpts {
-            service_path: path.unwrap_or(PathBuf::from("./test_cases/main")),
-            no_module_cache: false,
-            import_map_path: None,
-            env_vars: env_vars.unwrap_or_default(),
-            events_rx: None,
-            timing: None,
-            maybe_eszip: None,
-            maybe_entrypoint: None,
-            maybe_module_code: None,
-            conf: {
-                if let Some(uc) = user_conf {
-                    uc
-                } else {
-                    WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts { worker_pool_tx })
-                }
+        DenoRuntime::new(
+            WorkerContextInitOpts {
+                service_path: path.unwrap_or(PathBuf::from("./test_cases/main")),
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars: env_vars.unwrap_or_default(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys: None,
+                maybe_module_code: None,
+                conf: {
+                    if let Some(uc) = user_conf {
+                        uc
+                    } else {
+                        WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                            worker_pool_tx,
+                            user_worker_limits: Default::default(),
+                            scheduler_tx: None,
+                        })
+                    }
+                },
             },
-        })
+            None,
+        )
         .await
         .unwrap()
     }
 
+    #[tokio::test]
+    async fn test_stack_trace_is_source_mapped() {
+        let mut rt =
+            create_runtime(Some(PathBuf::from("./test_cases/source-map")), None, None).await;
+        let (_unix_stream_tx, unix_stream_rx) =
+            mpsc::unbounded_channel::<(UnixStream, Option<watch::Receiver<ConnSync>>)>();
+
+        let err = rt.run(unix_stream_rx).await.unwrap_err().to_string();
+
+        // points at the `throw` in the original TypeScript, not the emitted code
+        assert!(err.contains("thrown from typescript"), "{}", err);
+        assert!(err.contains("index.ts:8:9"), "{}", err);
+    }
+
     // Main Runtime should have access to `EdgeRuntime`
     #[tokio::test]
     async fn test_main_runtime_creation() {
@@ -858,11 +1428,25 @@ mod test {
                 worker_timeout_ms,
                 cpu_time_soft_limit_ms: 100,
                 cpu_time_hard_limit_ms: 200,
+                background_timeout_ms: 10 * 1000,
+                tmp_quota_mb: None,
+                cache_backend: Default::default(),
+                cache_quota_mb: 50,
+                kv_dir: None,
+                kv_namespace: None,
+                kv_quota_mb: 10,
+                broadcast_with_main: false,
+                bindings: HashMap::new(),
                 low_memory_multiplier: 5,
+                requested_limits: Default::default(),
                 force_create: true,
                 net_access_disabled: false,
                 allow_remote_modules: true,
                 custom_module_root: None,
+                npmrc_path: None,
+                node_modules_dir: false,
+                package_json_path: None,
+                static_patterns: vec![],
                 key: None,
                 pool_msg_tx: None,
                 events_msg_tx: None,
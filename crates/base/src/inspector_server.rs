@@ -0,0 +1,521 @@
+// Based on https://github.com/denoland/deno/blob/v1.37.0/runtime/inspector_server.rs
+// Copyright 2018-2023 the Deno authors. All rights reserved. MIT license.
+
+use anyhow::Error;
+use deno_core::futures::channel::mpsc as futures_mpsc;
+use deno_core::futures::channel::oneshot as futures_oneshot;
+use deno_core::serde_json::{self, json, Value};
+use deno_core::url::Url;
+use deno_core::{InspectorMsg, InspectorMsgKind, InspectorSessionProxy, JsRuntime};
+use fastwebsockets::{Frame, OpCode, WebSocket};
+use futures_util::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Request, Response, StatusCode};
+use log::{error, info};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
+
+/// How the workers expose themselves to a debugger.
+#[derive(Debug, Clone, Copy)]
+pub enum InspectorOption {
+    /// `--inspect`
+    Inspect(SocketAddr),
+    /// `--inspect-brk`, which also waits for a debugger to attach and breaks
+    /// on the first statement of the worker.
+    WithBreak(SocketAddr),
+}
+
+impl InspectorOption {
+    pub fn socket_addr(&self) -> SocketAddr {
+        match self {
+            Self::Inspect(addr) | Self::WithBreak(addr) => *addr,
+        }
+    }
+
+    pub fn is_with_break(&self) -> bool {
+        matches!(self, Self::WithBreak(_))
+    }
+}
+
+/// An inspector server along with the option it was started with. Handed to
+/// every worker that should be debuggable.
+#[derive(Clone)]
+pub struct Inspector {
+    pub server: Arc<InspectorServer>,
+    pub option: InspectorOption,
+}
+
+impl Inspector {
+    pub fn from_option(option: InspectorOption) -> Result<Self, Error> {
+        Ok(Self {
+            server: Arc::new(InspectorServer::new(option.socket_addr(), "edge-runtime")?),
+            option,
+        })
+    }
+}
+
+/// Whether an isolate is paused in the debugger. The supervisor uses it to
+/// stop enforcing the time limits of the worker while it is paused.
+#[derive(Clone)]
+pub struct InspectorPause(Arc<watch::Sender<bool>>);
+
+impl InspectorPause {
+    fn new() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+
+    pub fn set(&self, is_paused: bool) {
+        self.0.send_replace(is_paused);
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0.subscribe()
+    }
+}
+
+/// Serves the DevTools protocol for every registered isolate, listing them at
+/// `/json/list` and accepting websocket sessions at `/ws/<id>`.
+pub struct InspectorServer {
+    pub host: SocketAddr,
+    register_inspector_tx: mpsc::UnboundedSender<(InspectorInfo, futures_oneshot::Receiver<()>)>,
+}
+
+impl InspectorServer {
+    pub fn new(host: SocketAddr, name: &'static str) -> Result<Self, Error> {
+        let listener = std::net::TcpListener::bind(host)?;
+        let host = listener.local_addr()?;
+
+        listener.set_nonblocking(true)?;
+
+        let (register_inspector_tx, register_inspector_rx) = mpsc::unbounded_channel();
+
+        thread::Builder::new()
+            .name("sb-inspector".to_string())
+            .spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+
+                rt.block_on(server(listener, register_inspector_rx, name))
+            })?;
+
+        Ok(Self {
+            host,
+            register_inspector_tx,
+        })
+    }
+
+    /// Makes the isolate of `js_runtime` available to debuggers. The
+    /// inspector must have been enabled when the runtime was created.
+    pub fn register_inspector(
+        &self,
+        js_runtime: &mut JsRuntime,
+        main_module_url: &Url,
+        service_path: String,
+        maybe_execution_id: Option<Uuid>,
+        wait_for_session: bool,
+    ) -> InspectorPause {
+        let inspector_rc = js_runtime.inspector();
+        let inspector = inspector_rc.borrow_mut();
+        let pause = InspectorPause::new();
+
+        let info = InspectorInfo {
+            host: self.host,
+            uuid: maybe_execution_id.unwrap_or_else(Uuid::new_v4),
+            new_session_tx: inspector.get_session_sender(),
+            url: main_module_url.to_string(),
+            service_path,
+            maybe_execution_id,
+            wait_for_session,
+            pause: pause.clone(),
+        };
+
+        if self
+            .register_inspector_tx
+            .send((info, inspector.add_deregister_handler()))
+            .is_err()
+        {
+            error!("inspector server is not running");
+        }
+
+        pause
+    }
+}
+
+struct InspectorInfo {
+    host: SocketAddr,
+    uuid: Uuid,
+    new_session_tx: futures_mpsc::UnboundedSender<InspectorSessionProxy>,
+    url: String,
+    service_path: String,
+    maybe_execution_id: Option<Uuid>,
+    wait_for_session: bool,
+    pause: InspectorPause,
+}
+
+impl InspectorInfo {
+    fn get_json_metadata(&self, host: &Option<String>) -> Value {
+        let host_listen = format!("{}", self.host);
+        let host = host.as_ref().unwrap_or(&host_listen);
+
+        json!({
+            "description": match self.maybe_execution_id {
+                Some(execution_id) => format!("user worker (execution id: {})", execution_id),
+                None => "main worker".to_string(),
+            },
+            "devtoolsFrontendUrl": self.get_frontend_url(host),
+            "faviconUrl": "https://supabase.com/favicon/favicon.ico",
+            "id": self.uuid.to_string(),
+            "title": self.service_path,
+            "type": "node",
+            "url": self.url,
+            "webSocketDebuggerUrl": self.get_websocket_debugger_url(host),
+            "servicePath": self.service_path,
+            "executionId": self.maybe_execution_id.map(|it| it.to_string()),
+        })
+    }
+
+    fn get_websocket_debugger_url(&self, host: &str) -> String {
+        format!("ws://{}/ws/{}", host, &self.uuid)
+    }
+
+    fn get_frontend_url(&self, host: &str) -> String {
+        format!(
+            "devtools://devtools/bundled/js_app.html?ws={}/ws/{}&experiments=true&v8only=true",
+            host, &self.uuid
+        )
+    }
+}
+
+type InspectorMap = Arc<Mutex<HashMap<Uuid, InspectorInfo>>>;
+
+async fn server(
+    listener: std::net::TcpListener,
+    mut register_inspector_rx: mpsc::UnboundedReceiver<(
+        InspectorInfo,
+        futures_oneshot::Receiver<()>,
+    )>,
+    name: &'static str,
+) {
+    let inspector_map = InspectorMap::default();
+
+    drop(tokio::spawn({
+        let inspector_map = inspector_map.clone();
+        async move {
+            while let Some((info, deregister_rx)) = register_inspector_rx.recv().await {
+                let uuid = info.uuid;
+
+                info!(
+                    "debugger listening on {} ({})",
+                    info.get_websocket_debugger_url(&info.host.to_string()),
+                    info.service_path
+                );
+
+                if info.wait_for_session {
+                    info!("waiting for debugger to connect ({})", info.service_path);
+                }
+
+                let _ = inspector_map.lock().unwrap().insert(uuid, info);
+
+                // the isolate is gone once the inspector is dropped
+                drop(tokio::spawn({
+                    let inspector_map = inspector_map.clone();
+                    async move {
+                        let _ = deregister_rx.await;
+                        let _ = inspector_map.lock().unwrap().remove(&uuid);
+                    }
+                }));
+            }
+        }
+    }));
+
+    let json_version_response = json!({
+        "Browser": name,
+        "Protocol-Version": "1.3",
+        "V8-Version": deno_core::v8_version(),
+    });
+
+    let make_svc = make_service_fn(move |_| {
+        let inspector_map = inspector_map.clone();
+        let json_version_response = json_version_response.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let res = handle_request(req, &inspector_map, &json_version_response);
+                async move { res }
+            }))
+        }
+    });
+
+    let server = match hyper::Server::from_tcp(listener) {
+        Ok(builder) => builder.serve(make_svc),
+        Err(err) => {
+            error!("cannot start inspector server: {}", err);
+            return;
+        }
+    };
+
+    if let Err(err) = server.await {
+        error!("inspector server error: {}", err);
+    }
+}
+
+fn handle_request(
+    req: Request<Body>,
+    inspector_map: &InspectorMap,
+    json_version_response: &Value,
+) -> Result<Response<Body>, http::Error> {
+    // If the host header can make a valid URL, use it
+    let host = req
+        .headers()
+        .get("host")
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| Url::parse(&format!("http://{}", host)).ok())
+        .and_then(|url| match (url.host(), url.port()) {
+            (Some(host), Some(port)) => Some(format!("{}:{}", host, port)),
+            (Some(host), None) => Some(format!("{}", host)),
+            _ => None,
+        });
+
+    match (req.method(), req.uri().path()) {
+        (&http::Method::GET, path) if path.starts_with("/ws/") => {
+            handle_ws_request(req, inspector_map)
+        }
+
+        (&http::Method::GET, "/json/version") => json_response(json_version_response),
+        (&http::Method::GET, "/json") | (&http::Method::GET, "/json/list") => {
+            let data = inspector_map
+                .lock()
+                .unwrap()
+                .values()
+                .map(|info| info.get_json_metadata(&host))
+                .collect::<Vec<_>>();
+
+            json_response(&json!(data))
+        }
+
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("Not Found".into()),
+    }
+}
+
+fn json_response(value: &Value) -> Result<Response<Body>, http::Error> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(value).unwrap().into())
+}
+
+fn handle_ws_request(
+    mut req: Request<Body>,
+    inspector_map: &InspectorMap,
+) -> Result<Response<Body>, http::Error> {
+    let Some(uuid) = req
+        .uri()
+        .path()
+        .strip_prefix("/ws/")
+        .and_then(|s| Uuid::parse_str(s).ok())
+    else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Malformed inspector UUID".into());
+    };
+
+    // run in a block to not hold the lock for too long
+    let Some((new_session_tx, pause)) = inspector_map
+        .lock()
+        .unwrap()
+        .get(&uuid)
+        .map(|info| (info.new_session_tx.clone(), info.pause.clone()))
+    else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("Invalid inspector UUID".into());
+    };
+
+    let Ok((resp, fut)) = fastwebsockets::upgrade::upgrade(&mut req) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Not a valid Websocket Request".into());
+    };
+
+    // spawn a task that will wait for websocket connection and then pump
+    // messages between the socket and inspector proxy
+    drop(tokio::spawn(async move {
+        let Ok(websocket) = fut.await else {
+            error!("inspector server failed to upgrade to websocket connection");
+            return;
+        };
+
+        // The 'outbound' channel carries messages sent to the websocket.
+        let (outbound_tx, outbound_rx) = futures_mpsc::unbounded();
+        // The 'inbound' channel carries messages received from the websocket.
+        let (inbound_tx, inbound_rx) = futures_mpsc::unbounded();
+
+        let inspector_session_proxy = InspectorSessionProxy {
+            tx: outbound_tx,
+            rx: inbound_rx,
+        };
+
+        info!("debugger session started ({})", uuid);
+        let _ = new_session_tx.unbounded_send(inspector_session_proxy);
+        pump_websocket_messages(websocket, inbound_tx, outbound_rx, &pause).await;
+
+        // V8 resumes the isolate when the last session goes away
+        pause.set(false);
+        info!("debugger session ended ({})", uuid);
+    }));
+
+    Ok(resp)
+}
+
+#[derive(Deserialize)]
+struct InspectorNotification<'a> {
+    method: &'a str,
+}
+
+async fn pump_websocket_messages(
+    mut websocket: WebSocket<Upgraded>,
+    inbound_tx: futures_mpsc::UnboundedSender<String>,
+    mut outbound_rx: futures_mpsc::UnboundedReceiver<InspectorMsg>,
+    pause: &InspectorPause,
+) {
+    loop {
+        tokio::select! {
+            Some(msg) = outbound_rx.next() => {
+                if let InspectorMsgKind::Notification = msg.kind {
+                    match serde_json::from_str::<InspectorNotification>(&msg.content) {
+                        Ok(InspectorNotification { method: "Debugger.paused" }) => pause.set(true),
+                        Ok(InspectorNotification { method: "Debugger.resumed" }) => pause.set(false),
+                        _ => {}
+                    }
+                }
+
+                let frame = Frame::text(msg.content.into_bytes().into());
+                let _ = websocket.write_frame(frame).await;
+            }
+
+            Ok(msg) = websocket.read_frame() => {
+                match msg.opcode {
+                    OpCode::Text => {
+                        if let Ok(s) = String::from_utf8(msg.payload.to_vec()) {
+                            let _ = inbound_tx.unbounded_send(s);
+                        }
+                    }
+
+                    OpCode::Close => break,
+
+                    // Ignore other messages.
+                    _ => {}
+                }
+            }
+
+            else => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deno_runtime::DenoRuntime;
+    use crate::inspector_server::{Inspector, InspectorOption};
+    use crate::rt_worker::worker_ctx::create_supervisor;
+    use crate::rt_worker::worker_pool::SupervisorPolicy;
+    use event_worker::events::{ShutdownEvent, ShutdownReason, WorkerEvents};
+    use sb_core::conn_sync::ConnSync;
+    use sb_workers::context::{UserWorkerRuntimeOpts, WorkerContextInitOpts, WorkerRuntimeOpts};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tokio::net::UnixStream;
+    use tokio::sync::{mpsc, oneshot, watch};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_limits_are_suspended_while_paused() {
+        let inspector =
+            Inspector::from_option(InspectorOption::Inspect("127.0.0.1:0".parse().unwrap()))
+                .unwrap();
+        let mut rt = DenoRuntime::new(
+            WorkerContextInitOpts {
+                service_path: PathBuf::from("./test_cases/json_import"),
+                no_module_cache: false,
+                cached_only: false,
+                import_map_path: None,
+                env_vars: HashMap::new(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                maybe_trusted_keys: None,
+                maybe_module_code: None,
+                conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                    worker_timeout_ms: 1000,
+                    // only the wall clock limit, which an idle worker reaches
+                    cpu_time_soft_limit_ms: 0,
+                    cpu_time_hard_limit_ms: 0,
+                    ..Default::default()
+                }),
+            },
+            Some(inspector),
+        )
+        .await
+        .unwrap();
+
+        // as if a debugger had paused the isolate
+        let pause = rt.maybe_inspector_pause.clone().unwrap();
+        pause.set(true);
+
+        let (termination_event_tx, mut termination_event_rx) = oneshot::channel::<WorkerEvents>();
+        let _cpu_timer = create_supervisor(
+            Uuid::new_v4(),
+            &mut rt,
+            SupervisorPolicy::PerWorker,
+            termination_event_tx,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let (_unix_stream_tx, unix_stream_rx) =
+            mpsc::unbounded_channel::<(UnixStream, Option<watch::Receiver<ConnSync>>)>();
+        let run = rt.run(unix_stream_rx);
+        tokio::pin!(run);
+
+        // well past the wall clock limit
+        tokio::select! {
+            _ = &mut run => panic!("worker terminated while paused"),
+            _ = &mut termination_event_rx => panic!("worker terminated while paused"),
+            () = tokio::time::sleep(Duration::from_secs(3)) => {}
+        }
+
+        pause.set(false);
+
+        let event = tokio::time::timeout(Duration::from_secs(5), async {
+            let _ = (&mut run).await;
+            termination_event_rx.await.unwrap()
+        })
+        .await
+        .expect("worker was not terminated once resumed");
+
+        assert!(matches!(
+            event,
+            WorkerEvents::Shutdown(ShutdownEvent {
+                reason: ShutdownReason::WallClockTime,
+                ..
+            })
+        ));
+    }
+}
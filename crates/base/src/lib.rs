@@ -1,9 +1,13 @@
 extern crate core;
 
+pub mod broadcast_channel;
 pub mod commands;
 pub mod deno_runtime;
+pub mod inspector_server;
 pub mod macros;
 pub mod rt_worker;
+pub mod scheduler;
 pub mod server;
 pub mod snapshot;
 pub mod utils;
+pub mod watcher;
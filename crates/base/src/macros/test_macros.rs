@@ -19,21 +19,13 @@ macro_rules! integration_test {
                     panic!("Request thread had a heart attack");
                 }
             }
-            _ = start_server(
-                "0.0.0.0",
-                $port,
-                String::from($main_file),
-                None,
-                None,
-                None,
-                false,
-                true,
-                Some(tx.clone()),
-                $crate::server::WorkerEntrypoints {
-                    main: None,
-                    events: None,
-                }
-            ) => {
+            _ = start_server($crate::server::ServerOptions {
+                port: $port,
+                main_service_path: String::from($main_file),
+                no_signal_handler: true,
+                callback_tx: Some(tx.clone()),
+                ..Default::default()
+            }) => {
                 panic!("This one should not end first");
             }
         }
@@ -8,7 +8,7 @@ use deno_core::v8::IsolateHandle;
 use futures_util::task::AtomicWaker;
 use log::error;
 use sb_workers::context::{Timing, UserWorkerMsgs, UserWorkerRuntimeOpts};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
 use uuid::Uuid;
 
 use super::worker_pool::SupervisorPolicy;
@@ -60,4 +60,48 @@ pub struct Arguments {
     pub isolate_memory_usage_tx: oneshot::Sender<IsolateMemoryStats>,
     pub thread_safe_handle: IsolateHandle,
     pub waker: Arc<AtomicWaker>,
+    pub maybe_inspector_pause_rx: Option<watch::Receiver<bool>>,
+    pub maybe_background_tasks_rx: Option<watch::Receiver<usize>>,
+}
+
+/// Resolves with the new state whenever a debugger pauses or resumes the
+/// isolate. Never resolves if the worker isn't being inspected.
+async fn wait_for_inspector_pause_change(
+    maybe_inspector_pause_rx: &mut Option<watch::Receiver<bool>>,
+) -> bool {
+    let Some(pause_rx) = maybe_inspector_pause_rx.as_mut() else {
+        return std::future::pending().await;
+    };
+
+    if pause_rx.changed().await.is_err() {
+        *maybe_inspector_pause_rx = None;
+        return std::future::pending().await;
+    }
+
+    *pause_rx.borrow()
+}
+
+/// Number of `EdgeRuntime.waitUntil` promises of the worker that haven't
+/// settled yet.
+fn pending_background_tasks(maybe_background_tasks_rx: &Option<watch::Receiver<usize>>) -> usize {
+    maybe_background_tasks_rx
+        .as_ref()
+        .map_or(0, |it| *it.borrow())
+}
+
+/// Resolves with the new number of pending background tasks whenever it
+/// changes. Never resolves once the isolate has gone away.
+async fn wait_for_background_tasks_change(
+    maybe_background_tasks_rx: &mut Option<watch::Receiver<usize>>,
+) -> usize {
+    let Some(tasks_rx) = maybe_background_tasks_rx.as_mut() else {
+        return std::future::pending().await;
+    };
+
+    if tasks_rx.changed().await.is_err() {
+        *maybe_background_tasks_rx = None;
+        return std::future::pending().await;
+    }
+
+    *tasks_rx.borrow()
 }
@@ -7,7 +7,10 @@ use tokio::time::Instant;
 
 use crate::rt_worker::supervisor::{handle_interrupt, IsolateInterruptData};
 
-use super::Arguments;
+use super::{
+    pending_background_tasks, wait_for_background_tasks_change, wait_for_inspector_pause_change,
+    Arguments,
+};
 
 pub async fn supervise(args: Arguments, oneshot: bool) -> ShutdownReason {
     let Arguments {
@@ -20,6 +23,8 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> ShutdownReason {
         isolate_memory_usage_tx,
         thread_safe_handle,
         cpu_timer,
+        mut maybe_inspector_pause_rx,
+        mut maybe_background_tasks_rx,
         ..
     } = args;
 
@@ -30,6 +35,10 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> ShutdownReason {
 
     let mut complete_reason = None::<ShutdownReason>;
     let mut req_start_ack = false;
+    let mut is_waiting_for_background = false;
+    let mut is_paused = maybe_inspector_pause_rx
+        .as_ref()
+        .map_or(false, |it| *it.borrow());
 
     // reduce 100ms from wall clock duration, so the interrupt can be handled before
     // isolate is dropped
@@ -38,11 +47,17 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> ShutdownReason {
 
     let wall_clock_duration_alert = tokio::time::sleep(wall_clock_duration);
 
+    // the request is kept open after the response was sent until the
+    // `EdgeRuntime.waitUntil` promises settle, but not longer than this
+    let background_duration = Duration::from_millis(runtime_opts.background_timeout_ms);
+    let background_deadline = tokio::time::sleep(background_duration);
+
     tokio::pin!(wall_clock_duration_alert);
+    tokio::pin!(background_deadline);
     loop {
         tokio::select! {
             Some(_) = cpu_alarms_rx.recv() => {
-                if req_start_ack {
+                if req_start_ack && !is_paused {
                     error!("CPU time limit reached. isolate: {:?}", key);
                     complete_reason = Some(ShutdownReason::CPUTime);
                 }
@@ -70,10 +85,40 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> ShutdownReason {
                 // request start signal has arrived during the same request
                 // cycle.
                 assert!(req_start_ack, "supervisor observed the request end signal but did not see request start signal");
+
+                if pending_background_tasks(&maybe_background_tasks_rx) == 0 {
+                    complete_reason = Some(ShutdownReason::EarlyDrop);
+                } else {
+                    is_waiting_for_background = true;
+                    background_deadline
+                        .as_mut()
+                        .reset(Instant::now() + background_duration);
+                }
+            }
+
+            pending = wait_for_background_tasks_change(&mut maybe_background_tasks_rx) => {
+                if is_waiting_for_background && pending == 0 {
+                    complete_reason = Some(ShutdownReason::EarlyDrop);
+                }
+            }
+
+            _ = &mut background_deadline, if is_waiting_for_background => {
+                error!("background tasks deadline reached. isolate: {:?}", key);
                 complete_reason = Some(ShutdownReason::EarlyDrop);
             }
 
-            _ = &mut wall_clock_duration_alert => {
+            // the limits are suspended while a debugger has the isolate paused
+            paused = wait_for_inspector_pause_change(&mut maybe_inspector_pause_rx) => {
+                is_paused = paused;
+
+                if !is_paused {
+                    wall_clock_duration_alert
+                        .as_mut()
+                        .reset(Instant::now() + wall_clock_duration);
+                }
+            }
+
+            _ = &mut wall_clock_duration_alert, if !is_paused => {
                 error!("wall clock duraiton reached. isolate: {:?}", key);
                 complete_reason = Some(ShutdownReason::WallClockTime);
             }
@@ -87,6 +132,7 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> ShutdownReason {
         match complete_reason.take() {
             Some(ShutdownReason::EarlyDrop) if !oneshot => {
                 req_start_ack = false;
+                is_waiting_for_background = false;
                 wall_clock_duration_alert
                     .as_mut()
                     .reset(Instant::now() + wall_clock_duration);
@@ -3,8 +3,12 @@ use std::{sync::atomic::Ordering, time::Duration};
 use event_worker::events::ShutdownReason;
 use log::error;
 use sb_workers::context::{Timing, TimingStatus, UserWorkerMsgs};
+use tokio::time::Instant;
 
-use super::{handle_interrupt, Arguments, IsolateInterruptData};
+use super::{
+    handle_interrupt, pending_background_tasks, wait_for_background_tasks_change,
+    wait_for_inspector_pause_change, Arguments, IsolateInterruptData,
+};
 
 pub async fn supervise(args: Arguments) -> ShutdownReason {
     let Arguments {
@@ -16,6 +20,8 @@ pub async fn supervise(args: Arguments) -> ShutdownReason {
         pool_msg_tx,
         isolate_memory_usage_tx,
         thread_safe_handle,
+        mut maybe_inspector_pause_rx,
+        mut maybe_background_tasks_rx,
         ..
     } = args;
 
@@ -29,6 +35,10 @@ pub async fn supervise(args: Arguments) -> ShutdownReason {
     let mut cpu_time_soft_limit_reached = false;
     let mut wall_clock_alerts = 0;
     let mut req_ack_count = 0usize;
+    let mut is_waiting_for_background = false;
+    let mut is_paused = maybe_inspector_pause_rx
+        .as_ref()
+        .map_or(false, |it| *it.borrow());
 
     // reduce 100ms from wall clock duration, so the interrupt can be handled before
     // isolate is dropped
@@ -43,6 +53,11 @@ pub async fn supervise(args: Arguments) -> ShutdownReason {
             .unwrap_or(Duration::from_millis(0)),
     );
 
+    // a retired worker outlives its last request until the
+    // `EdgeRuntime.waitUntil` promises settle, but not longer than this
+    let background_duration = Duration::from_millis(runtime_opts.background_timeout_ms);
+    let background_deadline = tokio::time::sleep(background_duration);
+
     let interrupt_fn = {
         let thread_safe_handle = thread_safe_handle.clone();
         move |should_terminate: bool| {
@@ -59,10 +74,15 @@ pub async fn supervise(args: Arguments) -> ShutdownReason {
     };
 
     tokio::pin!(wall_clock_duration_alert);
+    tokio::pin!(background_deadline);
 
     loop {
         tokio::select! {
             Some(_) = cpu_alarms_rx.recv() => {
+                if is_paused {
+                    continue;
+                }
+
                 if !cpu_time_soft_limit_reached {
                     // retire worker
                     is_retired.raise();
@@ -70,9 +90,14 @@ pub async fn supervise(args: Arguments) -> ShutdownReason {
                     cpu_time_soft_limit_reached = true;
 
                     if req_ack_count == demand.load(Ordering::Acquire) {
-                        interrupt_fn(true);
-                        error!("early termination due to the last request being completed. isolate: {:?}", key);
-                        return ShutdownReason::EarlyDrop;
+                        if pending_background_tasks(&maybe_background_tasks_rx) == 0 {
+                            interrupt_fn(true);
+                            error!("early termination due to the last request being completed. isolate: {:?}", key);
+                            return ShutdownReason::EarlyDrop;
+                        }
+
+                        is_waiting_for_background = true;
+                        background_deadline.as_mut().reset(Instant::now() + background_duration);
                     }
                 } else {
                     // shutdown worker
@@ -97,13 +122,46 @@ pub async fn supervise(args: Arguments) -> ShutdownReason {
                     continue;
                 }
 
+                if pending_background_tasks(&maybe_background_tasks_rx) != 0 {
+                    is_waiting_for_background = true;
+                    background_deadline.as_mut().reset(Instant::now() + background_duration);
+                    continue;
+                }
+
                 interrupt_fn(true);
                 error!("early termination due to the last request being completed. isolate: {:?}", key);
                 return ShutdownReason::EarlyDrop;
             }
 
+            pending = wait_for_background_tasks_change(&mut maybe_background_tasks_rx) => {
+                if !is_waiting_for_background || pending != 0 {
+                    continue;
+                }
+
+                interrupt_fn(true);
+                error!("early termination due to the background tasks being completed. isolate: {:?}", key);
+                return ShutdownReason::EarlyDrop;
+            }
+
+            _ = &mut background_deadline, if is_waiting_for_background => {
+                interrupt_fn(true);
+                error!("background tasks deadline reached. isolate: {:?}", key);
+                return ShutdownReason::EarlyDrop;
+            }
+
+            // the limits are suspended while a debugger has the isolate paused
+            paused = wait_for_inspector_pause_change(&mut maybe_inspector_pause_rx) => {
+                is_paused = paused;
+
+                if !is_paused && wall_clock_alerts > 0 {
+                    // start over from the first interval once resumed
+                    wall_clock_alerts = 1;
+                    wall_clock_duration_alert.reset();
+                }
+            }
+
             // wall clock warning
-            _ = wall_clock_duration_alert.tick() => {
+            _ = wall_clock_duration_alert.tick(), if !is_paused => {
                 if wall_clock_alerts == 0 {
                     // first tick completes immediately
                     wall_clock_alerts += 1;
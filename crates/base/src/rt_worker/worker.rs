@@ -1,6 +1,7 @@
 use crate::deno_runtime::DenoRuntime;
+use crate::inspector_server::Inspector;
 use crate::rt_worker::utils::{get_event_metadata, parse_worker_conf};
-use crate::rt_worker::worker_ctx::create_supervisor;
+use crate::rt_worker::worker_ctx::{create_supervisor, create_terminator};
 use crate::utils::send_event_if_event_worker_available;
 use anyhow::{anyhow, Error};
 use cpu_timer::get_thread_time;
@@ -12,6 +13,7 @@ use sb_core::conn_sync::ConnSync;
 use sb_workers::context::{UserWorkerMsgs, WorkerContextInitOpts};
 use std::any::Any;
 use std::future::Future;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::thread;
@@ -20,6 +22,7 @@ use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::{Receiver, Sender};
 use tokio::sync::{oneshot, watch, Notify};
 use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use super::worker_pool::SupervisorPolicy;
@@ -33,6 +36,8 @@ pub struct Worker {
     pub event_metadata: EventMetadata,
     pub worker_key: Option<Uuid>,
     pub supervisor_policy: Option<SupervisorPolicy>,
+    pub inspector: Option<Inspector>,
+    pub termination_token: Option<CancellationToken>,
     pub thread_name: String,
 }
 
@@ -59,6 +64,8 @@ impl Worker {
 
         Ok(Self {
             supervisor_policy: None,
+            inspector: None,
+            termination_token: None,
             worker_boot_start_time,
             events_msg_tx,
             pool_msg_tx,
@@ -73,11 +80,19 @@ impl Worker {
         self.supervisor_policy = supervisor_policy;
     }
 
+    pub fn set_inspector(&mut self, inspector: Option<Inspector>) {
+        self.inspector = inspector;
+    }
+
+    pub fn set_termination_token(&mut self, termination_token: Option<CancellationToken>) {
+        self.termination_token = termination_token;
+    }
+
     pub fn start(
         &self,
         mut opts: WorkerContextInitOpts,
         unix_channel_rx: UnboundedReceiver<(UnixStream, Option<watch::Receiver<ConnSync>>)>,
-        booter_signal: Sender<Result<(), Error>>,
+        booter_signal: Sender<Result<Vec<PathBuf>, Error>>,
     ) {
         let thread_name = self.thread_name.clone();
         let events_msg_tx = self.events_msg_tx.clone();
@@ -87,6 +102,8 @@ impl Worker {
         let worker_key = self.worker_key;
         let pool_msg_tx = self.pool_msg_tx.clone();
         let timing = opts.timing.take();
+        let inspector = self.inspector.clone();
+        let termination_token = self.termination_token.clone();
         let method_cloner = self.clone();
 
         let _handle: thread::JoinHandle<Result<(), Error>> = thread::Builder::new()
@@ -101,9 +118,9 @@ impl Worker {
                 let mut start_time = 0;
 
                 let result: Result<WorkerEvents, Error> = local.block_on(&runtime, async {
-                    match DenoRuntime::new(opts).await {
+                    match DenoRuntime::new(opts, inspector).await {
                         Ok(mut new_runtime) => {
-                            let _ = booter_signal.send(Ok(()));
+                            let _ = booter_signal.send(Ok(new_runtime.local_module_paths.clone()));
 
                             // CPU TIMER
                             let (termination_event_tx, termination_event_rx) =
@@ -122,6 +139,8 @@ impl Worker {
                                     cancel,
                                     timing,
                                 )?;
+                            } else if let Some(token) = termination_token {
+                                create_terminator(token, &mut new_runtime, termination_event_tx);
                             }
 
                             start_time = get_thread_time()?;
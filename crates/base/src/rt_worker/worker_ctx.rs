@@ -1,28 +1,36 @@
 use crate::deno_runtime::DenoRuntime;
+use crate::inspector_server::Inspector;
 use crate::utils::send_event_if_event_worker_available;
 use crate::utils::units::bytes_to_display;
+use crate::watcher::FileWatcher;
 
 use crate::rt_worker::worker::{Worker, WorkerHandler};
 use crate::rt_worker::worker_pool::WorkerPool;
 use anyhow::{anyhow, bail, Error};
 use cpu_timer::{CPUAlarmVal, CPUTimer};
 use event_worker::events::{
-    BootEvent, ShutdownEvent, WorkerEventWithMetadata, WorkerEvents, WorkerMemoryUsed,
+    BootEvent, ShutdownEvent, ShutdownReason, WorkerEventWithMetadata, WorkerEvents,
+    WorkerMemoryUsed,
 };
-use hyper::{Body, Request, Response};
+use hyper::{Body, Request, Response, StatusCode};
 use log::{debug, error};
 use once_cell::sync::Lazy;
 use sb_core::conn_sync::ConnSync;
+use sb_core::runtime::PendingBackgroundTasks;
+use sb_core::upgrade::{OnUpgradedStream, UpgradedStream};
+use sb_graph::emitter::LockfileOpts;
+use sb_graph::signature::EszipTrustedKeys;
 use sb_graph::EszipPayloadKind;
 use sb_workers::context::{
-    EventWorkerRuntimeOpts, MainWorkerRuntimeOpts, Timing, UserWorkerMsgs, WorkerContextInitOpts,
-    WorkerRequestMsg, WorkerRuntimeOpts,
+    EventWorkerRuntimeOpts, MainWorkerRuntimeOpts, SchedulerMsgs, Timing, UserWorkerLimitsConfig,
+    UserWorkerMsgs, WorkerContextInitOpts, WorkerRequestMsg, WorkerRuntimeOpts,
 };
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::net::UnixStream;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use super::supervisor;
@@ -52,6 +60,7 @@ async fn handle_request(
 
     // send the HTTP request to the worker over Unix stream
     let (mut request_sender, connection) = hyper::client::conn::handshake(sender_stream).await?;
+    let (upgrade_tx, upgrade_rx) = oneshot::channel::<oneshot::Sender<UpgradedStream>>();
 
     // spawn a task to poll the connection and drive the HTTP state
     tokio::task::spawn(async move {
@@ -61,8 +70,19 @@ async fn handle_request(
             }
 
             Ok(parts) => {
+                // the worker switched protocols, so the connection now belongs
+                // to whoever responds with the upgraded response
+                if let Ok(upgraded_tx) = upgrade_rx.await {
+                    let _ = upgraded_tx.send(UpgradedStream {
+                        io: parts.io,
+                        read_buf: parts.read_buf,
+                    });
+
+                    return;
+                }
+
                 if let Some(mut watcher) = conn_watch {
-                    if watcher.wait_for(|it| *it == ConnSync::Recv).await.is_err() {
+                    if watcher.wait_for(ConnSync::is_settled).await.is_err() {
                         error!("cannot track outbound connection correctly");
                     }
                 }
@@ -74,7 +94,16 @@ async fn handle_request(
 
     tokio::task::yield_now().await;
 
-    let result = request_sender.send_request(req).await;
+    let result = request_sender.send_request(req).await.map(|mut res| {
+        if res.status() == StatusCode::SWITCHING_PROTOCOLS {
+            let (upgraded_tx, upgraded_rx) = oneshot::channel();
+
+            res.extensions_mut().insert(OnUpgradedStream(upgraded_rx));
+            let _ = upgrade_tx.send(upgraded_tx);
+        }
+
+        res
+    });
     let _ = res_tx.send(result);
 
     Ok(())
@@ -90,6 +119,16 @@ pub fn create_supervisor(
     timing: Option<Timing>,
 ) -> Result<Option<CPUTimer>, Error> {
     let (memory_limit_tx, memory_limit_rx) = mpsc::unbounded_channel::<()>();
+    let maybe_inspector_pause_rx = worker_runtime
+        .maybe_inspector_pause
+        .as_ref()
+        .map(|it| it.subscribe());
+    let maybe_background_tasks_rx = worker_runtime
+        .js_runtime
+        .op_state()
+        .borrow()
+        .try_borrow::<PendingBackgroundTasks>()
+        .map(|it| it.0.subscribe());
     let (waker, thread_safe_handle) = {
         let js_runtime = &mut worker_runtime.js_runtime;
         (
@@ -162,6 +201,8 @@ pub fn create_supervisor(
             isolate_memory_usage_tx,
             thread_safe_handle,
             waker: waker.clone(),
+            maybe_inspector_pause_rx,
+            maybe_background_tasks_rx,
         };
 
         let reason = {
@@ -222,34 +263,105 @@ pub fn create_supervisor(
     Ok(cpu_timer)
 }
 
-pub struct CreateWorkerArgs(WorkerContextInitOpts, Option<SupervisorPolicy>);
+/// Terminates the isolate of a main or events worker once `token` is
+/// cancelled, e.g. when the server replaced the worker after its files changed.
+pub fn create_terminator(
+    token: CancellationToken,
+    worker_runtime: &mut DenoRuntime,
+    termination_event_tx: oneshot::Sender<WorkerEvents>,
+) {
+    let (waker, thread_safe_handle) = {
+        let js_runtime = &mut worker_runtime.js_runtime;
+        (
+            js_runtime.op_state().borrow().waker.clone(),
+            js_runtime.v8_isolate().thread_safe_handle(),
+        )
+    };
+
+    let _rt_guard = SUPERVISOR_RT.enter();
+
+    drop(tokio::spawn(async move {
+        token.cancelled().await;
+
+        let _ = termination_event_tx.send(WorkerEvents::Shutdown(ShutdownEvent {
+            reason: ShutdownReason::EarlyDrop,
+            memory_used: WorkerMemoryUsed {
+                total: 0,
+                heap: 0,
+                external: 0,
+            },
+            cpu_time_used: 0, // this will be set later
+        }));
+
+        // the event loop may be waiting on I/O, so it is woken up to notice
+        thread_safe_handle.terminate_execution();
+        waker.wake();
+    }));
+}
+
+pub struct CreateWorkerArgs(
+    WorkerContextInitOpts,
+    Option<SupervisorPolicy>,
+    Option<Inspector>,
+    Option<CancellationToken>,
+);
 
 impl From<WorkerContextInitOpts> for CreateWorkerArgs {
     fn from(val: WorkerContextInitOpts) -> Self {
-        CreateWorkerArgs(val, None)
+        CreateWorkerArgs(val, None, None, None)
     }
 }
 
 impl From<(WorkerContextInitOpts, SupervisorPolicy)> for CreateWorkerArgs {
     fn from(val: (WorkerContextInitOpts, SupervisorPolicy)) -> Self {
-        CreateWorkerArgs(val.0, Some(val.1))
+        CreateWorkerArgs(val.0, Some(val.1), None, None)
+    }
+}
+
+impl From<(WorkerContextInitOpts, Option<Inspector>)> for CreateWorkerArgs {
+    fn from(val: (WorkerContextInitOpts, Option<Inspector>)) -> Self {
+        CreateWorkerArgs(val.0, None, val.1, None)
     }
 }
 
+impl From<(WorkerContextInitOpts, SupervisorPolicy, Option<Inspector>)> for CreateWorkerArgs {
+    fn from(val: (WorkerContextInitOpts, SupervisorPolicy, Option<Inspector>)) -> Self {
+        CreateWorkerArgs(val.0, Some(val.1), val.2, None)
+    }
+}
+
+impl From<(WorkerContextInitOpts, Option<Inspector>, CancellationToken)> for CreateWorkerArgs {
+    fn from(val: (WorkerContextInitOpts, Option<Inspector>, CancellationToken)) -> Self {
+        CreateWorkerArgs(val.0, None, val.1, Some(val.2))
+    }
+}
+
+/// Boots a worker, returning the channel to send requests to it along with the
+/// local files it was built from.
 pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
     init_opts: Opt,
-) -> Result<mpsc::UnboundedSender<WorkerRequestMsg>, Error> {
-    let (worker_boot_result_tx, worker_boot_result_rx) = oneshot::channel::<Result<(), Error>>();
+) -> Result<(mpsc::UnboundedSender<WorkerRequestMsg>, Vec<PathBuf>), Error> {
+    let (worker_boot_result_tx, worker_boot_result_rx) =
+        oneshot::channel::<Result<Vec<PathBuf>, Error>>();
     let (unix_stream_tx, unix_stream_rx) =
         mpsc::unbounded_channel::<(UnixStream, Option<watch::Receiver<ConnSync>>)>();
 
-    let CreateWorkerArgs(init_opts, maybe_supervisor_policy) = init_opts.into();
+    let CreateWorkerArgs(
+        init_opts,
+        maybe_supervisor_policy,
+        maybe_inspector,
+        maybe_termination_token,
+    ) = init_opts.into();
     let mut worker_init = Worker::new(&init_opts)?;
 
     if init_opts.conf.is_user_worker() {
         worker_init.set_supervisor_policy(maybe_supervisor_policy);
+    } else {
+        worker_init.set_termination_token(maybe_termination_token);
     }
 
+    worker_init.set_inspector(maybe_inspector);
+
     let worker: Box<dyn WorkerHandler> = Box::new(worker_init);
 
     // Downcast to call the method in "Worker" since the implementation might be of worker
@@ -284,7 +396,7 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
                 worker_req_handle.abort();
                 bail!(err)
             }
-            Ok(_) => {
+            Ok(local_module_paths) => {
                 let elapsed = worker_struct_ref
                     .worker_boot_start_time
                     .elapsed()
@@ -296,7 +408,7 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
                     }),
                     worker_struct_ref.event_metadata.clone(),
                 );
-                Ok(worker_req_tx)
+                Ok((worker_req_tx, local_module_paths))
             }
         }
     } else {
@@ -334,9 +446,16 @@ pub async fn create_main_worker(
     main_worker_path: PathBuf,
     import_map_path: Option<String>,
     no_module_cache: bool,
+    cached_only: bool,
+    maybe_lockfile: Option<LockfileOpts>,
+    maybe_trusted_keys: Option<EszipTrustedKeys>,
     user_worker_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    user_worker_limits: Arc<UserWorkerLimitsConfig>,
+    maybe_scheduler_tx: Option<mpsc::UnboundedSender<SchedulerMsgs>>,
     maybe_entrypoint: Option<String>,
-) -> Result<mpsc::UnboundedSender<WorkerRequestMsg>, Error> {
+    maybe_inspector: Option<Inspector>,
+    termination_token: CancellationToken,
+) -> Result<(mpsc::UnboundedSender<WorkerRequestMsg>, Vec<PathBuf>), Error> {
     let mut service_path = main_worker_path.clone();
     let mut maybe_eszip = None;
     if let Some(ext) = main_worker_path.extension() {
@@ -346,32 +465,46 @@ pub async fn create_main_worker(
         }
     }
 
-    let main_worker_req_tx = create_worker(WorkerContextInitOpts {
-        service_path,
-        import_map_path,
-        no_module_cache,
-        events_rx: None,
-        timing: None,
-        maybe_eszip,
-        maybe_entrypoint,
-        maybe_module_code: None,
-        conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
-            worker_pool_tx: user_worker_msgs_tx,
-        }),
-        env_vars: std::env::vars().collect(),
-    })
+    let main_worker = create_worker((
+        WorkerContextInitOpts {
+            service_path,
+            import_map_path,
+            no_module_cache,
+            cached_only,
+            events_rx: None,
+            timing: None,
+            maybe_eszip,
+            maybe_entrypoint,
+            maybe_function_name: None,
+            maybe_lockfile,
+            maybe_trusted_keys,
+            maybe_module_code: None,
+            conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                worker_pool_tx: user_worker_msgs_tx,
+                user_worker_limits,
+                scheduler_tx: maybe_scheduler_tx,
+            }),
+            env_vars: std::env::vars().collect(),
+        },
+        maybe_inspector,
+        termination_token,
+    ))
     .await
     .map_err(|err| anyhow!("main worker boot error: {}", err))?;
 
-    Ok(main_worker_req_tx)
+    Ok(main_worker)
 }
 
 pub async fn create_events_worker(
     events_worker_path: PathBuf,
     import_map_path: Option<String>,
     no_module_cache: bool,
+    cached_only: bool,
+    maybe_lockfile: Option<LockfileOpts>,
+    maybe_trusted_keys: Option<EszipTrustedKeys>,
     maybe_entrypoint: Option<String>,
-) -> Result<mpsc::UnboundedSender<WorkerEventWithMetadata>, Error> {
+    termination_token: CancellationToken,
+) -> Result<(mpsc::UnboundedSender<WorkerEventWithMetadata>, Vec<PathBuf>), Error> {
     let (events_tx, events_rx) = mpsc::unbounded_channel::<WorkerEventWithMetadata>();
 
     let mut service_path = events_worker_path.clone();
@@ -385,27 +518,39 @@ pub async fn create_events_worker(
         }
     }
 
-    let _ = create_worker(WorkerContextInitOpts {
-        service_path,
-        no_module_cache,
-        import_map_path,
-        env_vars: std::env::vars().collect(),
-        events_rx: Some(events_rx),
-        timing: None,
-        maybe_eszip,
-        maybe_entrypoint,
-        maybe_module_code: None,
-        conf: WorkerRuntimeOpts::EventsWorker(EventWorkerRuntimeOpts {}),
-    })
+    let (_, local_module_paths) = create_worker((
+        WorkerContextInitOpts {
+            service_path,
+            no_module_cache,
+            cached_only,
+            import_map_path,
+            env_vars: std::env::vars().collect(),
+            events_rx: Some(events_rx),
+            timing: None,
+            maybe_eszip,
+            maybe_entrypoint,
+            maybe_function_name: None,
+            maybe_lockfile,
+            maybe_trusted_keys,
+            maybe_module_code: None,
+            conf: WorkerRuntimeOpts::EventsWorker(EventWorkerRuntimeOpts {}),
+        },
+        None,
+        termination_token,
+    ))
     .await
     .map_err(|err| anyhow!("events worker boot error: {}", err))?;
 
-    Ok(events_tx)
+    Ok((events_tx, local_module_paths))
 }
 
 pub async fn create_user_worker_pool(
     policy: WorkerPoolPolicy,
     worker_event_sender: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+    maybe_watcher: Option<Arc<FileWatcher>>,
+    maybe_inspector: Option<Inspector>,
+    maybe_trusted_keys: Option<EszipTrustedKeys>,
+    cached_only: bool,
 ) -> Result<mpsc::UnboundedSender<UserWorkerMsgs>, Error> {
     let (user_worker_msgs_tx, mut user_worker_msgs_rx) =
         mpsc::unbounded_channel::<UserWorkerMsgs>();
@@ -413,8 +558,15 @@ pub async fn create_user_worker_pool(
     let user_worker_msgs_tx_clone = user_worker_msgs_tx.clone();
 
     let _handle: tokio::task::JoinHandle<Result<(), Error>> = tokio::spawn(async move {
-        let mut worker_pool =
-            WorkerPool::new(policy, worker_event_sender, user_worker_msgs_tx_clone);
+        let mut worker_pool = WorkerPool::new(
+            policy,
+            worker_event_sender,
+            user_worker_msgs_tx_clone,
+            maybe_watcher,
+            maybe_inspector,
+            maybe_trusted_keys,
+            cached_only,
+        );
 
         // Note: Keep this loop non-blocking. Spawn a task to run blocking calls.
         // Handle errors within tasks and log them - do not bubble up errors.
@@ -436,6 +588,9 @@ pub async fn create_user_worker_pool(
                 Some(UserWorkerMsgs::Shutdown(key)) => {
                     worker_pool.shutdown(&key);
                 }
+                Some(UserWorkerMsgs::FilesChanged(paths)) => {
+                    worker_pool.reload(&paths);
+                }
             }
         }
 
@@ -1,17 +1,21 @@
+use crate::inspector_server::Inspector;
 use crate::rt_worker::worker_ctx::{create_worker, send_user_worker_request};
+use crate::watcher::FileWatcher;
 use anyhow::{anyhow, Context, Error};
 use event_worker::events::WorkerEventWithMetadata;
 use http::Request;
 use hyper::Body;
-use log::error;
+use log::{error, info};
 use sb_core::conn_sync::ConnSync;
 use sb_core::util::sync::AtomicFlag;
+use sb_graph::signature::EszipTrustedKeys;
 use sb_workers::context::{
-    CreateUserWorkerResult, SendRequestResult, Timing, TimingStatus, UserWorkerMsgs,
-    UserWorkerProfile, WorkerContextInitOpts, WorkerRuntimeOpts,
+    CreateUserWorkerResult, SendRequestResult, Timing, TimingStatus, UserWorkerLimitsConfig,
+    UserWorkerMsgs, UserWorkerProfile, WorkerContextInitOpts, WorkerRuntimeOpts,
 };
 use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
@@ -58,6 +62,7 @@ pub struct WorkerPoolPolicy {
     supervisor_policy: SupervisorPolicy,
     max_parallelism: usize,
     request_wait_timeout_ms: u64,
+    user_worker_limits: Arc<UserWorkerLimitsConfig>,
 }
 
 impl Default for WorkerPoolPolicy {
@@ -71,6 +76,7 @@ impl Default for WorkerPoolPolicy {
             supervisor_policy: SupervisorPolicy::default(),
             max_parallelism: available_parallelism,
             request_wait_timeout_ms: 10000,
+            user_worker_limits: Arc::default(),
         }
     }
 }
@@ -89,8 +95,17 @@ impl WorkerPoolPolicy {
             request_wait_timeout_ms: request_wait_timeout_ms
                 .into()
                 .unwrap_or(default.request_wait_timeout_ms),
+            user_worker_limits: default.user_worker_limits,
         }
     }
+
+    pub fn set_user_worker_limits(&mut self, limits: UserWorkerLimitsConfig) {
+        self.user_worker_limits = Arc::new(limits);
+    }
+
+    pub fn user_worker_limits(&self) -> Arc<UserWorkerLimitsConfig> {
+        self.user_worker_limits.clone()
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -203,6 +218,14 @@ pub struct WorkerPool {
 
     // TODO: refactor this out of worker pool
     pub worker_event_sender: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+
+    /// Set when running with `--watch`, so the files of new workers get watched.
+    pub maybe_watcher: Option<Arc<FileWatcher>>,
+    pub maybe_inspector: Option<Inspector>,
+    /// Eszips given to user workers must be signed by one of these keys.
+    pub maybe_trusted_keys: Option<EszipTrustedKeys>,
+    /// User workers must not download anything either.
+    pub cached_only: bool,
 }
 
 impl WorkerPool {
@@ -210,6 +233,10 @@ impl WorkerPool {
         policy: WorkerPoolPolicy,
         worker_event_sender: Option<UnboundedSender<WorkerEventWithMetadata>>,
         worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+        maybe_watcher: Option<Arc<FileWatcher>>,
+        maybe_inspector: Option<Inspector>,
+        maybe_trusted_keys: Option<EszipTrustedKeys>,
+        cached_only: bool,
     ) -> Self {
         Self {
             policy,
@@ -217,6 +244,10 @@ impl WorkerPool {
             user_workers: HashMap::new(),
             active_workers: HashMap::new(),
             worker_pool_msgs_tx,
+            maybe_watcher,
+            maybe_inspector,
+            maybe_trusted_keys,
+            cached_only,
         }
     }
 
@@ -225,12 +256,22 @@ impl WorkerPool {
         mut worker_options: WorkerContextInitOpts,
         tx: Sender<Result<CreateUserWorkerResult, Error>>,
     ) {
-        let service_path = worker_options
+        let mut service_path = worker_options
             .service_path
             .to_str()
             .unwrap_or("")
             .to_string();
 
+        // Functions bundled in the same eszip share a service path, but must
+        // not share workers.
+        if let Some(function_name) = worker_options.maybe_function_name.as_ref() {
+            service_path = format!("{}#{}", service_path, function_name);
+        }
+
+        // These are server settings, never taken from the main worker.
+        worker_options.maybe_trusted_keys = self.maybe_trusted_keys.clone();
+        worker_options.cached_only = self.cached_only;
+
         let is_oneshot_policy = self.policy.supervisor_policy.is_oneshot();
         let force_create = worker_options
             .conf
@@ -320,6 +361,7 @@ impl WorkerPool {
         let worker_pool_msgs_tx = self.worker_pool_msgs_tx.clone();
         let events_msg_tx = self.worker_event_sender.clone();
         let supervisor_policy = self.policy.supervisor_policy;
+        let maybe_inspector = self.maybe_inspector.clone();
 
         drop(tokio::spawn(async move {
             let (permit, tx) = match wait_fence_fut.await {
@@ -328,12 +370,16 @@ impl WorkerPool {
                     let WorkerContextInitOpts {
                         service_path,
                         no_module_cache,
+                        cached_only,
                         import_map_path,
                         env_vars,
                         conf,
                         maybe_eszip,
                         maybe_module_code,
                         maybe_entrypoint,
+                        maybe_function_name,
+                        maybe_lockfile,
+                        maybe_trusted_keys,
                         ..
                     } = worker_options;
 
@@ -342,6 +388,7 @@ impl WorkerPool {
                             WorkerContextInitOpts {
                                 service_path,
                                 no_module_cache,
+                                cached_only,
                                 import_map_path,
                                 env_vars,
                                 events_rx: None,
@@ -350,6 +397,9 @@ impl WorkerPool {
                                 maybe_eszip,
                                 maybe_module_code,
                                 maybe_entrypoint,
+                                maybe_function_name,
+                                maybe_lockfile,
+                                maybe_trusted_keys,
                             },
                             tx,
                         ))
@@ -394,12 +444,13 @@ impl WorkerPool {
 
             worker_options.conf = WorkerRuntimeOpts::UserWorker(user_worker_rt_opts);
 
-            match create_worker((worker_options, supervisor_policy)).await {
-                Ok(worker_request_msg_tx) => {
+            match create_worker((worker_options, supervisor_policy, maybe_inspector)).await {
+                Ok((worker_request_msg_tx, local_module_paths)) => {
                     let profile = UserWorkerProfile {
                         worker_request_msg_tx,
                         timing_tx_pair: (req_start_timing_tx, req_end_timing_tx),
                         service_path,
+                        local_module_paths,
                         permit: permit.map(Arc::new),
                         status: status.clone(),
                         cancel,
@@ -437,6 +488,10 @@ impl WorkerPool {
             .workers
             .insert(WorkerId(key, self.policy.supervisor_policy.is_per_worker()));
 
+        if let Some(watcher) = self.maybe_watcher.as_ref() {
+            watcher.watch(&profile.local_module_paths);
+        }
+
         self.user_workers.insert(key, profile);
     }
 
@@ -499,7 +554,13 @@ impl WorkerPool {
 
                 // Spawn the closure as an async task
                 tokio::task::spawn(async move {
-                    if res_tx.send(request_handler.await).is_err() {
+                    if let Err(result) = res_tx.send(request_handler.await) {
+                        // the main worker stopped waiting (e.g. the client
+                        // went away), so nobody will end the request
+                        if let Ok((_, req_end_tx)) = result {
+                            let _ = req_end_tx.send(());
+                        }
+
                         error!("main worker receiver dropped")
                     }
                 });
@@ -544,6 +605,39 @@ impl WorkerPool {
         let _ = notify_tx.send(None);
     }
 
+    /// Retires the workers built from any of the changed files, so the next
+    /// request for their service boots a fresh worker.
+    pub fn reload(&mut self, changed_paths: &[PathBuf]) {
+        let keys = self
+            .user_workers
+            .iter()
+            .filter(|(_, profile)| {
+                profile
+                    .local_module_paths
+                    .iter()
+                    .any(|it| changed_paths.contains(it))
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        let mut reloaded = HashSet::new();
+        for key in keys {
+            let Some(profile) = self.user_workers.get(&key) else {
+                continue;
+            };
+
+            if let Some(is_retired) = profile.status.is_retired.as_ref() {
+                is_retired.raise();
+            }
+
+            if reloaded.insert(profile.service_path.clone()) {
+                info!("reloading user worker: {}", profile.service_path);
+            }
+
+            self.retire(&key);
+        }
+    }
+
     fn retire(&mut self, key: &Uuid) {
         if let Some(profile) = self.user_workers.get_mut(key) {
             let registry = self
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, bail, Error};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How many years ahead the next run is looked for, so that expressions
+/// like `0 0 30 2 *` don't loop forever.
+const MAX_YEARS_AHEAD: i64 = 5;
+
+/// A standard 5-field cron expression (`minute hour day month weekday`),
+/// evaluated in UTC.
+///
+/// Fields take `*`, values, ranges (`1-5`), steps (`*/15`, `0-30/10`) and lists
+/// of those. Months and weekdays can also be names (`jan`, `mon`), and Sunday
+/// is either `0` or `7`. As in other crons, a day that matches either the day
+/// or the weekday field matches when both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self, Error> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+
+        let fields = expr.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!(
+                "invalid cron expression `{}`: expected 5 fields, found {}",
+                expr,
+                fields.len()
+            );
+        };
+
+        let field = |name: &str, text: &str, min: u64, max: u64, names: &[&str]| {
+            parse_field(text, min, max, names)
+                .map_err(|err| anyhow!("invalid cron expression `{}`: {}: {}", expr, name, err))
+        };
+
+        let mut weekdays = field("weekday", weekday, 0, 7, &WEEKDAYS)?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: field("minute", minute, 0, 59, &[])?,
+            hours: field("hour", hour, 0, 23, &[])?,
+            days: field("day", day, 1, 31, &[])?,
+            months: field("month", month, 1, 12, &MONTHS)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    /// The first time the expression matches strictly after `after`, both in
+    /// seconds since the Unix epoch.
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let after = i64::try_from(after).ok()?;
+        let mut time = (after / 60 + 1) * 60;
+        let (last_year, _, _) = civil_from_days(after / 86400);
+        let last_year = last_year + MAX_YEARS_AHEAD;
+
+        loop {
+            let days = time / 86400;
+            let (year, month, day) = civil_from_days(days);
+            if year > last_year {
+                return None;
+            }
+
+            if !matches(self.months, month) {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+
+                time = days_from_civil(year, month, 1) * 86400;
+                continue;
+            }
+
+            if !self.matches_day(day, weekday(days)) {
+                time = (days + 1) * 86400;
+                continue;
+            }
+
+            if !matches(self.hours, (time % 86400 / 3600) as u32) {
+                time = (time / 3600 + 1) * 3600;
+                continue;
+            }
+
+            if !matches(self.minutes, (time % 3600 / 60) as u32) {
+                time += 60;
+                continue;
+            }
+
+            return u64::try_from(time).ok();
+        }
+    }
+
+    fn matches_day(&self, day: u32, weekday: u32) -> bool {
+        let day_matches = matches(self.days, day);
+        let weekday_matches = matches(self.weekdays, weekday);
+
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        }
+    }
+}
+
+fn matches(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+fn parse_field(text: &str, min: u64, max: u64, names: &[&str]) -> Result<u64, Error> {
+    let mut set = 0;
+
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (part, None),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                parse_value(start, min, max, names)?,
+                parse_value(end, min, max, names)?,
+            )
+        } else {
+            let value = parse_value(range, min, max, names)?;
+            // `5/15` goes on until the end of the range
+            (value, if step.is_some() { max } else { value })
+        };
+
+        if start > end {
+            bail!("`{}` is an empty range", part);
+        }
+
+        let step = match step {
+            Some(step) => match step.parse::<u64>() {
+                Ok(step) if step > 0 => step,
+                _ => bail!("invalid step `{}`", step),
+            },
+            None => 1,
+        };
+
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
+
+fn parse_value(text: &str, min: u64, max: u64, names: &[&str]) -> Result<u64, Error> {
+    let value = match names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(text))
+    {
+        Some(index) => index as u64 + min,
+        None => text
+            .parse::<u64>()
+            .map_err(|_| anyhow!("invalid value `{}`", text))?,
+    };
+
+    if !(min..=max).contains(&value) {
+        bail!("`{}` is not between {} and {}", value, min, max);
+    }
+
+    Ok(value)
+}
+
+/// 0 is Sunday. The Unix epoch was a Thursday.
+fn weekday(days: i64) -> u32 {
+    (days + 4).rem_euclid(7) as u32
+}
+
+// The conversions between days since the Unix epoch and dates of the
+// proleptic Gregorian calendar from http://howardhinnant.github.io/date_algorithms.html
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod test {
+    use super::{civil_from_days, days_from_civil, Cron};
+
+    fn time(year: i64, month: u32, day: u32, hour: u64, minute: u64) -> u64 {
+        days_from_civil(year, month, day) as u64 * 86400 + hour * 3600 + minute * 60
+    }
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(days_from_civil(2024, 2, 29), 19782);
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let every_15 = Cron::parse("*/15 * * * *").unwrap();
+        assert_eq!(
+            every_15.next_after(time(2024, 1, 1, 10, 15)),
+            Some(time(2024, 1, 1, 10, 30))
+        );
+        assert_eq!(
+            every_15.next_after(time(2024, 1, 1, 23, 50)),
+            Some(time(2024, 1, 2, 0, 0))
+        );
+
+        let weekdays = Cron::parse("30 9 * * mon-fri").unwrap();
+        // 2024-01-06 is a Saturday
+        assert_eq!(
+            weekdays.next_after(time(2024, 1, 5, 10, 0)),
+            Some(time(2024, 1, 8, 9, 30))
+        );
+
+        // either the 1st of the month or a Sunday
+        let day_or_weekday = Cron::parse("0 0 1 * 7").unwrap();
+        assert_eq!(
+            day_or_weekday.next_after(time(2024, 1, 1, 0, 0)),
+            Some(time(2024, 1, 7, 0, 0))
+        );
+
+        let leap_day = Cron::parse("0 12 29 feb *").unwrap();
+        assert_eq!(
+            leap_day.next_after(time(2024, 3, 1, 0, 0)),
+            Some(time(2028, 2, 29, 12, 0))
+        );
+
+        assert_eq!(Cron::parse("0 0 30 2 *").unwrap().next_after(0), None);
+        assert_eq!(
+            Cron::parse("@daily").unwrap(),
+            Cron::parse("0 0 * * *").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cron_errors() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(Cron::parse(expr).is_err(), "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,632 @@
+pub mod cron;
+
+use crate::scheduler::cron::Cron;
+use crate::utils::send_event_if_event_worker_available;
+use anyhow::{anyhow, Error};
+use deno_core::serde_json;
+use event_worker::events::{
+    EventMetadata, ScheduledRunEvent, ScheduledRunOutcome, WorkerEventWithMetadata, WorkerEvents,
+};
+use hyper::{Body, Method, Request};
+use log::{error, warn};
+use sb_workers::context::{
+    CreateUserWorkerResult, OverlapPolicy, ScheduleOpts, SchedulerMsgs, SendRequestResult,
+    UserWorkerMsgs, UserWorkerTemplate,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+/// Names the schedule in the requests it sends.
+pub const SCHEDULE_HEADER: &str = "x-supabase-schedule";
+
+/// The schedules to start the server with.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulerOpts {
+    /// The file the last run of each schedule is kept in, so that a restarted
+    /// server doesn't run a schedule twice for the same time.
+    pub state_path: Option<PathBuf>,
+    pub schedules: Vec<ScheduleOpts>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LastRun {
+    /// In milliseconds since the Unix epoch.
+    scheduled_time: u64,
+    /// Unset while the run is going.
+    outcome: Option<ScheduledRunOutcome>,
+    status: Option<u16>,
+}
+
+struct Job {
+    opts: ScheduleOpts,
+    cron: Cron,
+    /// In seconds since the Unix epoch.
+    next: Option<u64>,
+    running: usize,
+    queued: Option<u64>,
+}
+
+struct Scheduler {
+    jobs: HashMap<String, Job>,
+    last_runs: HashMap<String, LastRun>,
+    state_path: Option<PathBuf>,
+    worker_pool_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    events_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+    done_tx: mpsc::UnboundedSender<(String, LastRun)>,
+}
+
+/// Starts sending requests to user workers on the schedules of `opts`, and the
+/// ones the main worker adds through the returned sender.
+pub fn create_scheduler(
+    opts: SchedulerOpts,
+    worker_pool_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    events_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+) -> Result<mpsc::UnboundedSender<SchedulerMsgs>, Error> {
+    let (msgs_tx, mut msgs_rx) = mpsc::unbounded_channel::<SchedulerMsgs>();
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+
+    let last_runs = match opts.state_path.as_ref() {
+        Some(path) => load_last_runs(path),
+        None => HashMap::new(),
+    };
+    let mut scheduler = Scheduler {
+        jobs: HashMap::new(),
+        last_runs,
+        state_path: opts.state_path,
+        worker_pool_tx,
+        events_tx,
+        done_tx,
+    };
+
+    for schedule in opts.schedules {
+        scheduler.schedule(schedule)?;
+    }
+
+    drop(tokio::spawn(async move {
+        loop {
+            let sleep = scheduler
+                .jobs
+                .values()
+                .filter_map(|it| it.next)
+                .min()
+                .map(|next| Duration::from_millis((next * 1000).saturating_sub(now_ms())));
+
+            tokio::select! {
+                msg = msgs_rx.recv() => match msg {
+                    Some(SchedulerMsgs::Schedule(opts, result_tx)) => {
+                        let _ = result_tx.send(scheduler.schedule(opts));
+                    }
+                    Some(SchedulerMsgs::Unschedule(name, result_tx)) => {
+                        let _ = result_tx.send(scheduler.jobs.remove(&name).is_some());
+                    }
+                    None => break,
+                },
+                Some((name, last_run)) = done_rx.recv() => scheduler.finish(name, last_run),
+                _ = tokio::time::sleep(sleep.unwrap_or_default()), if sleep.is_some() => {
+                    scheduler.fire_due_jobs();
+                }
+            }
+        }
+    }));
+
+    Ok(msgs_tx)
+}
+
+impl Scheduler {
+    fn schedule(&mut self, opts: ScheduleOpts) -> Result<(), Error> {
+        if opts.name.is_empty() {
+            return Err(anyhow!("schedule names must not be empty"));
+        }
+        if !opts.path.starts_with('/') {
+            return Err(anyhow!(
+                "schedule '{}': the path must start with `/`",
+                opts.name
+            ));
+        }
+
+        let cron = Cron::parse(&opts.cron)?;
+        // the last run may be from before a restart
+        let after = self
+            .last_runs
+            .get(&opts.name)
+            .map_or(0, |it| it.scheduled_time / 1000)
+            .max(now_ms() / 1000);
+        let next = cron.next_after(after);
+
+        match self.jobs.get_mut(&opts.name) {
+            Some(job) => {
+                job.opts = opts;
+                job.cron = cron;
+                job.next = next;
+            }
+            None => {
+                self.jobs.insert(
+                    opts.name.clone(),
+                    Job {
+                        opts,
+                        cron,
+                        next,
+                        running: 0,
+                        queued: None,
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fire_due_jobs(&mut self) {
+        let now = now_ms() / 1000;
+        let due = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.next.map_or(false, |next| next <= now))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in due {
+            let Some(job) = self.jobs.get_mut(&name) else {
+                continue;
+            };
+
+            let scheduled_time = job.next.unwrap_or(now);
+            // the times missed while the server was busy aren't caught up on
+            job.next = job.cron.next_after(now);
+
+            let skipped = match job.opts.overlap {
+                _ if job.running == 0 => false,
+                OverlapPolicy::Allow => false,
+                OverlapPolicy::Queue if job.queued.is_none() => {
+                    job.queued = Some(scheduled_time);
+                    continue;
+                }
+                OverlapPolicy::Queue | OverlapPolicy::Skip => true,
+            };
+
+            if skipped {
+                send_event_if_event_worker_available(
+                    self.events_tx.clone(),
+                    WorkerEvents::ScheduledRun(ScheduledRunEvent {
+                        name,
+                        scheduled_time: scheduled_time * 1000,
+                        outcome: ScheduledRunOutcome::Skipped,
+                        status: None,
+                        duration_ms: 0,
+                        error: None,
+                    }),
+                    event_metadata(&job.opts.worker, None),
+                );
+            } else {
+                self.start(&name, scheduled_time);
+            }
+        }
+    }
+
+    fn start(&mut self, name: &str, scheduled_time: u64) {
+        let Some(job) = self.jobs.get_mut(name) else {
+            return;
+        };
+
+        job.running += 1;
+
+        let scheduled_time = scheduled_time * 1000;
+        let opts = job.opts.clone();
+        let worker_pool_tx = self.worker_pool_tx.clone();
+        let events_tx = self.events_tx.clone();
+        let done_tx = self.done_tx.clone();
+
+        self.last_runs.insert(
+            opts.name.clone(),
+            LastRun {
+                scheduled_time,
+                outcome: None,
+                status: None,
+            },
+        );
+        self.save_last_runs();
+
+        drop(tokio::spawn(async move {
+            let started = Instant::now();
+            let result = run(&worker_pool_tx, &opts, scheduled_time).await;
+            let duration_ms = started.elapsed().as_millis() as u64;
+
+            let (key, status, error) = match result {
+                Ok((key, status)) if (200..300).contains(&status) => {
+                    (Some(key), Some(status), None)
+                }
+                Ok((key, status)) => (
+                    Some(key),
+                    Some(status),
+                    Some(format!("the worker responded with status {}", status)),
+                ),
+                Err(err) => (None, None, Some(err.to_string())),
+            };
+            let outcome = if error.is_none() {
+                ScheduledRunOutcome::Completed
+            } else {
+                ScheduledRunOutcome::Failed
+            };
+
+            if let Some(error) = error.as_ref() {
+                warn!("scheduled run of '{}' failed: {}", opts.name, error);
+            }
+
+            send_event_if_event_worker_available(
+                events_tx,
+                WorkerEvents::ScheduledRun(ScheduledRunEvent {
+                    name: opts.name.clone(),
+                    scheduled_time,
+                    outcome,
+                    status,
+                    duration_ms,
+                    error,
+                }),
+                event_metadata(&opts.worker, key),
+            );
+
+            let _ = done_tx.send((
+                opts.name,
+                LastRun {
+                    scheduled_time,
+                    outcome: Some(outcome),
+                    status,
+                },
+            ));
+        }));
+    }
+
+    fn finish(&mut self, name: String, last_run: LastRun) {
+        let is_latest = self
+            .last_runs
+            .get(&name)
+            .map_or(true, |it| it.scheduled_time <= last_run.scheduled_time);
+        if is_latest {
+            self.last_runs.insert(name.clone(), last_run);
+            self.save_last_runs();
+        }
+
+        let Some(job) = self.jobs.get_mut(&name) else {
+            return;
+        };
+
+        job.running = job.running.saturating_sub(1);
+        if job.running == 0 {
+            if let Some(scheduled_time) = job.queued.take() {
+                self.start(&name, scheduled_time);
+            }
+        }
+    }
+
+    fn save_last_runs(&self) {
+        let Some(path) = self.state_path.as_ref() else {
+            return;
+        };
+
+        // written next to the file first, so that a crash doesn't leave half of it
+        let tmp_path = path.with_extension("tmp");
+        let result = serde_json::to_vec_pretty(&self.last_runs)
+            .map_err(Error::from)
+            .and_then(|json| Ok(std::fs::write(&tmp_path, json)?))
+            .and_then(|_| Ok(std::fs::rename(&tmp_path, path)?));
+
+        if let Err(err) = result {
+            error!(
+                "failed to save the scheduler state ({}): {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+fn load_last_runs(path: &PathBuf) -> HashMap<String, LastRun> {
+    let Ok(json) = std::fs::read(path) else {
+        return HashMap::new();
+    };
+
+    serde_json::from_slice(&json).unwrap_or_else(|err| {
+        warn!(
+            "ignoring the invalid scheduler state ({}): {}",
+            path.display(),
+            err
+        );
+        HashMap::new()
+    })
+}
+
+/// Sends the request of a run to the worker of the schedule, through the pool
+/// like the main worker would, and waits for the whole response.
+async fn run(
+    worker_pool_tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+    opts: &ScheduleOpts,
+    scheduled_time: u64,
+) -> Result<(Uuid, u16), Error> {
+    let (result_tx, result_rx) = oneshot::channel::<Result<CreateUserWorkerResult, Error>>();
+    worker_pool_tx.send(UserWorkerMsgs::Create(
+        opts.worker.to_init_opts(),
+        result_tx,
+    ))?;
+    let key = result_rx.await??.key;
+
+    let body = serde_json::json!({
+        "name": opts.name,
+        "cron": opts.cron,
+        "scheduledTime": scheduled_time,
+    });
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://localhost{}", opts.path))
+        .header("content-type", "application/json")
+        .header(SCHEDULE_HEADER, opts.name.as_str())
+        .body(Body::from(body.to_string()))?;
+
+    let (result_tx, result_rx) = oneshot::channel::<Result<SendRequestResult, Error>>();
+    worker_pool_tx.send(UserWorkerMsgs::SendRequest(key, request, result_tx, None))?;
+    let (response, req_end_tx) = result_rx.await??;
+
+    let status = response.status().as_u16();
+    let body = hyper::body::to_bytes(response.into_body()).await;
+    let _ = req_end_tx.send(());
+    body?;
+
+    Ok((key, status))
+}
+
+fn event_metadata(worker: &UserWorkerTemplate, key: Option<Uuid>) -> EventMetadata {
+    EventMetadata {
+        service_path: Some(worker.service_path.to_string_lossy().to_string()),
+        execution_id: key,
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |it| it.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_last_runs, now_ms, LastRun, Scheduler, SCHEDULE_HEADER};
+    use anyhow::Error;
+    use deno_core::serde_json::{self, json};
+    use event_worker::events::ScheduledRunOutcome;
+    use hyper::{Body, Method, Request, Response};
+    use sb_workers::context::{
+        CreateUserWorkerResult, OverlapPolicy, ScheduleOpts, SendRequestResult, UserWorkerMsgs,
+        UserWorkerTemplate,
+    };
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tokio::sync::{mpsc, oneshot};
+    use uuid::Uuid;
+
+    fn schedule_opts(name: &str, overlap: OverlapPolicy) -> ScheduleOpts {
+        ScheduleOpts {
+            name: String::from(name),
+            cron: String::from("* * * * *"),
+            overlap,
+            path: String::from("/scheduled"),
+            worker: UserWorkerTemplate {
+                service_path: PathBuf::from("./test_cases/main"),
+                no_module_cache: false,
+                import_map_path: None,
+                env_vars: HashMap::new(),
+                maybe_entrypoint: None,
+                maybe_function_name: None,
+                maybe_lockfile: None,
+                conf: Default::default(),
+            },
+        }
+    }
+
+    fn create_test_scheduler(
+        state_path: Option<PathBuf>,
+    ) -> (
+        Scheduler,
+        mpsc::UnboundedReceiver<UserWorkerMsgs>,
+        mpsc::UnboundedReceiver<(String, LastRun)>,
+    ) {
+        let (worker_pool_tx, worker_pool_rx) = mpsc::unbounded_channel();
+        let (done_tx, done_rx) = mpsc::unbounded_channel();
+        let last_runs = state_path.as_ref().map(load_last_runs).unwrap_or_default();
+
+        let scheduler = Scheduler {
+            jobs: HashMap::new(),
+            last_runs,
+            state_path,
+            worker_pool_tx,
+            events_tx: None,
+            done_tx,
+        };
+
+        (scheduler, worker_pool_rx, done_rx)
+    }
+
+    /// Makes the next run of the job due now, instead of at the next minute.
+    fn make_due(scheduler: &mut Scheduler, name: &str) -> u64 {
+        let now = now_ms() / 1000;
+        scheduler.jobs.get_mut(name).unwrap().next = Some(now);
+        now
+    }
+
+    /// Answers the worker creation of a run, and returns the request it sends.
+    async fn accept_run(
+        worker_pool_rx: &mut mpsc::UnboundedReceiver<UserWorkerMsgs>,
+    ) -> (
+        Request<Body>,
+        oneshot::Sender<Result<SendRequestResult, Error>>,
+    ) {
+        let key = Uuid::new_v4();
+        let Some(UserWorkerMsgs::Create(opts, result_tx)) = worker_pool_rx.recv().await else {
+            panic!("the run didn't create a worker");
+        };
+        assert_eq!(opts.service_path, PathBuf::from("./test_cases/main"));
+        result_tx.send(Ok(CreateUserWorkerResult { key })).unwrap();
+
+        let Some(UserWorkerMsgs::SendRequest(req_key, req, res_tx, _)) =
+            worker_pool_rx.recv().await
+        else {
+            panic!("the run didn't send a request");
+        };
+        assert_eq!(req_key, key);
+
+        (req, res_tx)
+    }
+
+    async fn assert_no_run(worker_pool_rx: &mut mpsc::UnboundedReceiver<UserWorkerMsgs>) {
+        let result = tokio::time::timeout(Duration::from_millis(200), worker_pool_rx.recv()).await;
+        assert!(result.is_err(), "a run was started");
+    }
+
+    #[tokio::test]
+    async fn test_due_job_sends_request() {
+        let (mut scheduler, mut worker_pool_rx, mut done_rx) = create_test_scheduler(None);
+        scheduler
+            .schedule(schedule_opts("cleanup", OverlapPolicy::Skip))
+            .unwrap();
+
+        let scheduled_time = make_due(&mut scheduler, "cleanup");
+        scheduler.fire_due_jobs();
+
+        let (req, res_tx) = accept_run(&mut worker_pool_rx).await;
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.uri().path(), "/scheduled");
+        assert_eq!(req.headers()[SCHEDULE_HEADER], "cleanup");
+
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({
+                "name": "cleanup",
+                "cron": "* * * * *",
+                "scheduledTime": scheduled_time * 1000,
+            })
+        );
+
+        let (req_end_tx, _req_end_rx) = mpsc::unbounded_channel();
+        res_tx
+            .send(Ok((Response::new(Body::from("done")), req_end_tx)))
+            .unwrap();
+
+        let (name, last_run) = done_rx.recv().await.unwrap();
+        assert_eq!(name, "cleanup");
+        assert_eq!(last_run.scheduled_time, scheduled_time * 1000);
+        assert_eq!(last_run.outcome, Some(ScheduledRunOutcome::Completed));
+        assert_eq!(last_run.status, Some(200));
+
+        // the next run is at the start of a later minute
+        let next = scheduler.jobs["cleanup"].next.unwrap();
+        assert!(next > scheduled_time);
+        assert_eq!(next % 60, 0);
+    }
+
+    #[tokio::test]
+    async fn test_overlap_skip() {
+        let (mut scheduler, mut worker_pool_rx, _done_rx) = create_test_scheduler(None);
+        scheduler
+            .schedule(schedule_opts("skip", OverlapPolicy::Skip))
+            .unwrap();
+
+        make_due(&mut scheduler, "skip");
+        scheduler.fire_due_jobs();
+        let (_req, _res_tx) = accept_run(&mut worker_pool_rx).await;
+
+        make_due(&mut scheduler, "skip");
+        scheduler.fire_due_jobs();
+        assert_no_run(&mut worker_pool_rx).await;
+
+        let job = &scheduler.jobs["skip"];
+        assert_eq!(job.running, 1);
+        assert_eq!(job.queued, None);
+    }
+
+    #[tokio::test]
+    async fn test_overlap_queue() {
+        let (mut scheduler, mut worker_pool_rx, _done_rx) = create_test_scheduler(None);
+        scheduler
+            .schedule(schedule_opts("queue", OverlapPolicy::Queue))
+            .unwrap();
+
+        let first = make_due(&mut scheduler, "queue");
+        scheduler.fire_due_jobs();
+        let (_req, _res_tx) = accept_run(&mut worker_pool_rx).await;
+
+        // waits for the first run
+        let queued = make_due(&mut scheduler, "queue");
+        scheduler.fire_due_jobs();
+        assert_no_run(&mut worker_pool_rx).await;
+        assert_eq!(scheduler.jobs["queue"].queued, Some(queued));
+
+        // at most one run waits, the others are skipped
+        make_due(&mut scheduler, "queue");
+        scheduler.fire_due_jobs();
+        assert_no_run(&mut worker_pool_rx).await;
+        assert_eq!(scheduler.jobs["queue"].queued, Some(queued));
+
+        scheduler.finish(
+            String::from("queue"),
+            LastRun {
+                scheduled_time: first * 1000,
+                outcome: Some(ScheduledRunOutcome::Completed),
+                status: Some(200),
+            },
+        );
+        let (req, _res_tx) = accept_run(&mut worker_pool_rx).await;
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+        assert_eq!(body["scheduledTime"], queued * 1000);
+        assert_eq!(scheduler.jobs["queue"].running, 1);
+        assert_eq!(scheduler.jobs["queue"].queued, None);
+    }
+
+    #[tokio::test]
+    async fn test_last_run_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("sb-scheduler-{}", Uuid::new_v4()));
+        let state_path = dir.join("state.json");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // a run a few minutes from now, as a clock that's behind would see it
+        let scheduled_time = (now_ms() / 1000 / 60 + 5) * 60;
+
+        let (mut scheduler, mut worker_pool_rx, mut done_rx) =
+            create_test_scheduler(Some(state_path.clone()));
+        scheduler
+            .schedule(schedule_opts("report", OverlapPolicy::Skip))
+            .unwrap();
+        assert!(scheduler.jobs["report"].next.unwrap() <= scheduled_time);
+
+        scheduler.start("report", scheduled_time);
+        let (_req, res_tx) = accept_run(&mut worker_pool_rx).await;
+        let (req_end_tx, _req_end_rx) = mpsc::unbounded_channel();
+        res_tx
+            .send(Ok((Response::new(Body::empty()), req_end_tx)))
+            .unwrap();
+
+        let (name, last_run) = done_rx.recv().await.unwrap();
+        scheduler.finish(name, last_run);
+        drop(scheduler);
+
+        let (mut scheduler, _worker_pool_rx, _done_rx) =
+            create_test_scheduler(Some(state_path.clone()));
+        let last_run = &scheduler.last_runs["report"];
+        assert_eq!(last_run.scheduled_time, scheduled_time * 1000);
+        assert_eq!(last_run.outcome, Some(ScheduledRunOutcome::Completed));
+
+        // the time that already ran isn't run again
+        scheduler
+            .schedule(schedule_opts("report", OverlapPolicy::Skip))
+            .unwrap();
+        assert_eq!(scheduler.jobs["report"].next, Some(scheduled_time + 60));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
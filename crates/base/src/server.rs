@@ -1,36 +1,52 @@
+use crate::inspector_server::{Inspector, InspectorOption};
 use crate::rt_worker::worker_ctx::{
     create_events_worker, create_main_worker, create_user_worker_pool,
 };
 use crate::rt_worker::worker_pool::WorkerPoolPolicy;
+use crate::scheduler::{create_scheduler, SchedulerOpts};
+use crate::watcher::FileWatcher;
 use anyhow::Error;
 use event_worker::events::WorkerEventWithMetadata;
 use futures_util::Stream;
-use hyper::{server::conn::Http, service::Service, Body, Request, Response};
+use hyper::{server::conn::Http, service::Service, Body, Method, Request, Response, StatusCode};
 use log::{debug, error, info};
 use sb_core::conn_sync::ConnSync;
-use sb_workers::context::WorkerRequestMsg;
+use sb_core::upgrade::{splice, OnUpgradedStream};
+use sb_fs::tmp_fs::remove_stale_host_dirs;
+use sb_graph::emitter::LockfileOpts;
+use sb_graph::signature::EszipTrustedKeys;
+use sb_workers::context::{
+    SchedulerMsgs, UserWorkerLimitsConfig, UserWorkerMsgs, WorkerRequestMsg,
+};
 use std::future::Future;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::str;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::task::Poll;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{mpsc, oneshot, watch};
-use tokio_util::sync::CancellationToken;
+use tokio_util::sync::{CancellationToken, DropGuard};
 
 pub enum ServerCodes {
     Listening,
     Failure,
+    /// The main worker was replaced by one booted from its changed files.
+    Reloaded,
 }
 
 struct NotifyOnEos<S> {
     inner: S,
     cancel: Option<CancellationToken>,
+    /// Set once the body was sent in full, telling a finished response apart
+    /// from a client that went away.
+    is_eos: Arc<AtomicBool>,
 }
 
 impl<S> Drop for NotifyOnEos<S> {
@@ -48,21 +64,31 @@ impl<S: Stream + Unpin> Stream for NotifyOnEos<S> {
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.as_mut().inner).poll_next(cx)
+        let poll = Pin::new(&mut self.as_mut().inner).poll_next(cx);
+
+        if let Poll::Ready(None) = poll {
+            self.is_eos.store(true, Ordering::Release);
+        }
+
+        poll
     }
 }
 
 struct WorkerService {
-    worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    /// The main worker may be replaced while the connection is kept alive, so
+    /// the current one is looked up for each request.
+    worker_req_rx: watch::Receiver<mpsc::UnboundedSender<WorkerRequestMsg>>,
     cancel: CancellationToken,
 }
 
 impl WorkerService {
-    fn new(worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>) -> (Self, CancellationToken) {
+    fn new(
+        worker_req_rx: watch::Receiver<mpsc::UnboundedSender<WorkerRequestMsg>>,
+    ) -> (Self, CancellationToken) {
         let cancel = CancellationToken::new();
         (
             Self {
-                worker_req_tx,
+                worker_req_rx,
                 cancel: cancel.clone(),
             },
             cancel,
@@ -79,13 +105,16 @@ impl Service<Request<Body>> for WorkerService {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         // create a response in a future.
         let cancel = self.cancel.child_token();
-        let worker_req_tx = self.worker_req_tx.clone();
+        let worker_req_tx = self.worker_req_rx.borrow().clone();
+        let on_client_upgrade = hyper::upgrade::on(&mut req);
+        let is_head = req.method() == Method::HEAD;
         let fut = async move {
             let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
             let (ob_conn_watch_tx, ob_conn_watch_rx) = watch::channel(ConnSync::Want);
+            let is_eos = Arc::new(AtomicBool::new(false));
 
             let req_uri = req.uri().clone();
             let msg = WorkerRequestMsg {
@@ -98,10 +127,19 @@ impl Service<Request<Body>> for WorkerService {
 
             tokio::spawn({
                 let cancel = cancel.clone();
+                let is_eos = is_eos.clone();
                 async move {
                     tokio::select! {
                         _ = cancel.cancelled() => {
-                            if let Err(ex) = ob_conn_watch_tx.send(ConnSync::Recv) {
+                            // the connection closed before the response was
+                            // sent in full, so the client went away
+                            let sync = if is_eos.load(Ordering::Acquire) {
+                                ConnSync::Recv
+                            } else {
+                                ConnSync::Abort
+                            };
+
+                            if let Err(ex) = ob_conn_watch_tx.send(sync) {
                                 error!("can't update connection watcher: {}", ex.to_string());
                             }
                         }
@@ -127,17 +165,55 @@ impl Service<Request<Body>> for WorkerService {
                         .body(Body::wrap_stream(NotifyOnEos {
                             inner: Body::empty(),
                             cancel: Some(cancel.clone()),
+                            is_eos,
                         }))
                         .unwrap());
                 }
             };
 
-            let (parts, body) = res.into_parts();
+            let (mut parts, body) = res.into_parts();
+
+            // hyper drops these bodies without polling them
+            if is_head
+                || matches!(
+                    parts.status,
+                    StatusCode::SWITCHING_PROTOCOLS
+                        | StatusCode::NO_CONTENT
+                        | StatusCode::NOT_MODIFIED
+                )
+            {
+                is_eos.store(true, Ordering::Release);
+            }
+
+            if parts.status == StatusCode::SWITCHING_PROTOCOLS {
+                if let Some(OnUpgradedStream(upgraded_rx)) =
+                    parts.extensions.remove::<OnUpgradedStream>()
+                {
+                    // splice the client connection to the upgraded one of the
+                    // worker once hyper hands it over
+                    tokio::spawn(async move {
+                        let result = async {
+                            let conn = on_client_upgrade.await?;
+                            let upgraded = upgraded_rx.await?;
+
+                            splice(conn, upgraded).await?;
+                            Ok::<_, Error>(())
+                        }
+                        .await;
+
+                        if let Err(e) = result {
+                            debug!("upgraded connection closed ({:?})", e);
+                        }
+                    });
+                }
+            }
+
             let res = Response::from_parts(
                 parts,
                 Body::wrap_stream(NotifyOnEos {
                     inner: body,
                     cancel: Some(cancel.clone()),
+                    is_eos,
                 }),
             );
 
@@ -149,84 +225,374 @@ impl Service<Request<Body>> for WorkerService {
     }
 }
 
+#[derive(Default)]
 pub struct WorkerEntrypoints {
     pub main: Option<String>,
     pub events: Option<String>,
 }
 
+/// How to start the server, usually filled from the config file and the flags
+/// overriding it.
+pub struct ServerOptions {
+    pub ip: String,
+    pub port: u16,
+    pub main_service_path: String,
+    pub maybe_events_service_path: Option<String>,
+    pub maybe_user_worker_policy: Option<WorkerPoolPolicy>,
+    pub maybe_scheduler_opts: Option<SchedulerOpts>,
+    pub import_map_path: Option<String>,
+    pub no_module_cache: bool,
+    pub cached_only: bool,
+    pub maybe_lockfile: Option<LockfileOpts>,
+    pub maybe_trusted_keys: Option<EszipTrustedKeys>,
+    /// Reload the workers when their local files change.
+    pub watch: bool,
+    pub maybe_inspector_option: Option<InspectorOption>,
+    pub no_signal_handler: bool,
+    pub callback_tx: Option<Sender<ServerCodes>>,
+    pub entrypoints: WorkerEntrypoints,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            ip: String::from("0.0.0.0"),
+            port: 9000,
+            main_service_path: String::from("examples/main"),
+            maybe_events_service_path: None,
+            maybe_user_worker_policy: None,
+            maybe_scheduler_opts: None,
+            import_map_path: None,
+            no_module_cache: false,
+            cached_only: false,
+            maybe_lockfile: None,
+            maybe_trusted_keys: None,
+            watch: false,
+            maybe_inspector_option: None,
+            no_signal_handler: false,
+            callback_tx: None,
+            entrypoints: WorkerEntrypoints::default(),
+        }
+    }
+}
+
+struct ReloadableWorker {
+    service_path: PathBuf,
+    maybe_entrypoint: Option<String>,
+    local_module_paths: Vec<PathBuf>,
+    /// Terminates the worker once it is replaced.
+    _guard: DropGuard,
+}
+
+impl ReloadableWorker {
+    fn is_affected_by(&self, changed_paths: &[PathBuf]) -> bool {
+        self.local_module_paths
+            .iter()
+            .any(|it| changed_paths.contains(it))
+    }
+}
+
+/// Everything needed to reboot the workers when their files change.
+struct WatchState {
+    watcher: Arc<FileWatcher>,
+    changes_rx: mpsc::UnboundedReceiver<Vec<PathBuf>>,
+    import_map_path: Option<String>,
+    no_module_cache: bool,
+    cached_only: bool,
+    maybe_lockfile: Option<LockfileOpts>,
+    maybe_trusted_keys: Option<EszipTrustedKeys>,
+    user_worker_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    user_worker_limits: Arc<UserWorkerLimitsConfig>,
+    scheduler_tx: mpsc::UnboundedSender<SchedulerMsgs>,
+    maybe_inspector: Option<Inspector>,
+    main: ReloadableWorker,
+    main_worker_tx: watch::Sender<mpsc::UnboundedSender<WorkerRequestMsg>>,
+    callback_tx: Option<Sender<ServerCodes>>,
+    // The user worker pool holds on to the sender it was given, so events are
+    // forwarded to whichever events worker is the current one.
+    maybe_events: Option<(
+        ReloadableWorker,
+        watch::Sender<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+    )>,
+}
+
+impl WatchState {
+    async fn reload(&mut self, changed_paths: Vec<PathBuf>) {
+        if self.main.is_affected_by(&changed_paths) {
+            let token = CancellationToken::new();
+
+            match create_main_worker(
+                self.main.service_path.clone(),
+                self.import_map_path.clone(),
+                self.no_module_cache,
+                self.cached_only,
+                self.maybe_lockfile.clone(),
+                self.maybe_trusted_keys.clone(),
+                self.user_worker_msgs_tx.clone(),
+                self.user_worker_limits.clone(),
+                Some(self.scheduler_tx.clone()),
+                self.main.maybe_entrypoint.clone(),
+                self.maybe_inspector.clone(),
+                token.clone(),
+            )
+            .await
+            {
+                Ok((main_worker_req_tx, local_module_paths)) => {
+                    info!("reloaded main worker");
+                    self.watcher.watch(&local_module_paths);
+                    self.main.local_module_paths = local_module_paths;
+                    let _ = self.main_worker_tx.send_replace(main_worker_req_tx);
+                    self.main._guard = token.drop_guard();
+
+                    if let Some(callback) = self.callback_tx.as_ref() {
+                        let _ = callback.try_send(ServerCodes::Reloaded);
+                    }
+                }
+
+                // keep serving with the previous worker until the error is fixed
+                Err(err) => error!("failed to reload main worker: {}", err),
+            }
+        }
+
+        if let Some((events, current_tx)) = self.maybe_events.as_mut() {
+            if events.is_affected_by(&changed_paths) {
+                let token = CancellationToken::new();
+
+                match create_events_worker(
+                    events.service_path.clone(),
+                    self.import_map_path.clone(),
+                    self.no_module_cache,
+                    self.cached_only,
+                    self.maybe_lockfile.clone(),
+                    self.maybe_trusted_keys.clone(),
+                    events.maybe_entrypoint.clone(),
+                    token.clone(),
+                )
+                .await
+                {
+                    Ok((events_tx, local_module_paths)) => {
+                        info!("reloaded events worker");
+                        self.watcher.watch(&local_module_paths);
+                        events.local_module_paths = local_module_paths;
+                        let _ = current_tx.send_replace(events_tx);
+                        events._guard = token.drop_guard();
+                    }
+
+                    Err(err) => error!("failed to reload events worker: {}", err),
+                }
+            }
+        }
+
+        if self
+            .user_worker_msgs_tx
+            .send(UserWorkerMsgs::FilesChanged(changed_paths))
+            .is_err()
+        {
+            error!("user worker pool receiver dropped");
+        }
+    }
+}
+
 pub struct Server {
     ip: Ipv4Addr,
     port: u16,
-    main_worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    main_worker_req_rx: watch::Receiver<mpsc::UnboundedSender<WorkerRequestMsg>>,
     callback_tx: Option<Sender<ServerCodes>>,
+    maybe_watch_state: Option<WatchState>,
+    /// Terminate the workers with the server when they are not reloadable.
+    _worker_guards: Vec<DropGuard>,
 }
 
 impl Server {
-    #[allow(clippy::too_many_arguments)]
-    pub async fn new(
-        ip: &str,
-        port: u16,
-        main_service_path: String,
-        maybe_events_service_path: Option<String>,
-        maybe_user_worker_policy: Option<WorkerPoolPolicy>,
-        import_map_path: Option<String>,
-        no_module_cache: bool,
-        no_signal_handler: bool,
-        callback_tx: Option<Sender<ServerCodes>>,
-        entrypoints: WorkerEntrypoints,
-    ) -> Result<Self, Error> {
+    pub async fn new(opts: ServerOptions) -> Result<Self, Error> {
+        let ServerOptions {
+            ip,
+            port,
+            main_service_path,
+            maybe_events_service_path,
+            maybe_user_worker_policy,
+            maybe_scheduler_opts,
+            import_map_path,
+            no_module_cache,
+            cached_only,
+            maybe_lockfile,
+            maybe_trusted_keys,
+            watch,
+            maybe_inspector_option,
+            no_signal_handler,
+            callback_tx,
+            entrypoints,
+        } = opts;
         let mut worker_events_sender: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>> = None;
+        let mut maybe_events_worker = None;
+        let mut worker_guards = vec![];
         let maybe_events_entrypoint = entrypoints.events;
         let maybe_main_entrypoint = entrypoints.main;
 
+        let maybe_inspector = maybe_inspector_option
+            .map(Inspector::from_option)
+            .transpose()?;
+
+        // the scratch directories of user workers outlive a crashed server
+        remove_stale_host_dirs();
+
+        let maybe_watcher_and_changes = if watch {
+            let (watcher, changes_rx) = FileWatcher::new()?;
+            Some((Arc::new(watcher), changes_rx))
+        } else {
+            None
+        };
+
         // Create Event Worker
         if let Some(events_service_path) = maybe_events_service_path {
             let events_path = Path::new(&events_service_path);
             let events_path_buf = events_path.to_path_buf();
+            let events_token = CancellationToken::new();
 
-            let events_worker = create_events_worker(
-                events_path_buf,
+            let (events_worker, local_module_paths) = create_events_worker(
+                events_path_buf.clone(),
                 import_map_path.clone(),
                 no_module_cache,
-                maybe_events_entrypoint,
+                cached_only,
+                maybe_lockfile.clone(),
+                maybe_trusted_keys.clone(),
+                maybe_events_entrypoint.clone(),
+                events_token.clone(),
             )
             .await?;
 
-            worker_events_sender = Some(events_worker);
+            if maybe_watcher_and_changes.is_some() {
+                let (front_tx, mut front_rx) = mpsc::unbounded_channel();
+                let (current_tx, current_rx) = watch::channel(events_worker);
+
+                drop(tokio::spawn(async move {
+                    while let Some(event) = front_rx.recv().await {
+                        let _ = current_rx.borrow().send(event);
+                    }
+                }));
+
+                worker_events_sender = Some(front_tx);
+                maybe_events_worker = Some((
+                    ReloadableWorker {
+                        service_path: events_path_buf,
+                        maybe_entrypoint: maybe_events_entrypoint,
+                        local_module_paths,
+                        _guard: events_token.drop_guard(),
+                    },
+                    current_tx,
+                ));
+            } else {
+                worker_events_sender = Some(events_worker);
+                worker_guards.push(events_token.drop_guard());
+            }
         }
 
         // Create a user worker pool
+        let user_worker_policy = maybe_user_worker_policy.unwrap_or_default();
+        let user_worker_limits = user_worker_policy.user_worker_limits();
         let user_worker_msgs_tx = create_user_worker_pool(
-            maybe_user_worker_policy.unwrap_or_default(),
-            worker_events_sender,
+            user_worker_policy,
+            worker_events_sender.clone(),
+            maybe_watcher_and_changes
+                .as_ref()
+                .map(|(watcher, _)| watcher.clone()),
+            maybe_inspector.clone(),
+            maybe_trusted_keys.clone(),
+            cached_only,
         )
         .await?;
 
+        let scheduler_tx = create_scheduler(
+            maybe_scheduler_opts.unwrap_or_default(),
+            user_worker_msgs_tx.clone(),
+            worker_events_sender,
+        )?;
+
         // create main worker
         let main_worker_path = Path::new(&main_service_path).to_path_buf();
-        let main_worker_req_tx = create_main_worker(
-            main_worker_path,
+        let main_token = CancellationToken::new();
+        let (main_worker_req_tx, local_module_paths) = create_main_worker(
+            main_worker_path.clone(),
             import_map_path.clone(),
             no_module_cache,
-            user_worker_msgs_tx,
-            maybe_main_entrypoint,
+            cached_only,
+            maybe_lockfile.clone(),
+            maybe_trusted_keys.clone(),
+            user_worker_msgs_tx.clone(),
+            user_worker_limits.clone(),
+            Some(scheduler_tx.clone()),
+            maybe_main_entrypoint.clone(),
+            maybe_inspector.clone(),
+            main_token.clone(),
         )
         .await?;
 
+        let (main_worker_tx, main_worker_req_rx) = watch::channel(main_worker_req_tx);
+        let maybe_watch_state = if let Some((watcher, changes_rx)) = maybe_watcher_and_changes {
+            let main = ReloadableWorker {
+                service_path: main_worker_path,
+                maybe_entrypoint: maybe_main_entrypoint,
+                local_module_paths,
+                _guard: main_token.drop_guard(),
+            };
+
+            watcher.watch(&main.local_module_paths);
+            if let Some((events, _)) = maybe_events_worker.as_ref() {
+                watcher.watch(&events.local_module_paths);
+            }
+
+            Some(WatchState {
+                watcher,
+                changes_rx,
+                import_map_path,
+                no_module_cache,
+                cached_only,
+                maybe_lockfile,
+                maybe_trusted_keys,
+                user_worker_msgs_tx,
+                user_worker_limits,
+                scheduler_tx,
+                maybe_inspector,
+                main,
+                main_worker_tx,
+                callback_tx: callback_tx.clone(),
+                maybe_events: maybe_events_worker,
+            })
+        } else {
+            worker_guards.push(main_token.drop_guard());
+            None
+        };
+
         if !no_signal_handler {
             // register alarm signal handler
             cpu_timer::register_alarm()?;
         }
 
-        let ip = Ipv4Addr::from_str(ip)?;
+        let ip = Ipv4Addr::from_str(&ip)?;
         Ok(Self {
             ip,
             port,
-            main_worker_req_tx,
+            main_worker_req_rx,
             callback_tx,
+            maybe_watch_state,
+            _worker_guards: worker_guards,
         })
     }
 
+    /// Reloads the workers whenever their files change. Never resolves, as it
+    /// owns the reloadable workers.
+    async fn reload_on_changes(maybe_watch_state: Option<WatchState>) {
+        let Some(mut state) = maybe_watch_state else {
+            return std::future::pending().await;
+        };
+
+        while let Some(changed_paths) = state.changes_rx.recv().await {
+            state.reload(changed_paths).await;
+        }
+
+        std::future::pending().await
+    }
+
     pub async fn listen(&mut self) -> Result<(), Error> {
         let addr = SocketAddr::new(IpAddr::V4(self.ip), self.port);
         let listener = TcpListener::bind(&addr).await?;
@@ -236,19 +602,25 @@ impl Server {
             let _ = callback.send(ServerCodes::Listening).await;
         }
 
-        loop {
-            let main_worker_req_tx = self.main_worker_req_tx.clone();
+        // Polled along with the accept loop, so connections keep being served
+        // by the previous workers while the new ones boot.
+        let reloader = Self::reload_on_changes(self.maybe_watch_state.take());
+        tokio::pin!(reloader);
 
+        loop {
             tokio::select! {
                 msg = listener.accept() => {
                     match msg {
                         Ok((conn, _)) => {
+                            let main_worker_req_rx = self.main_worker_req_rx.clone();
+
                             tokio::task::spawn(async move {
-                                let (service, cancel) = WorkerService::new(main_worker_req_tx);
+                                let (service, cancel) = WorkerService::new(main_worker_req_rx);
                                 let _guard = cancel.drop_guard();
 
                                 let conn_fut = Http::new()
-                                    .serve_connection(conn, service);
+                                    .serve_connection(conn, service)
+                                    .with_upgrades();
 
                                 if let Err(e) = conn_fut.await {
                                     // Most common cause for these errors are
@@ -265,6 +637,7 @@ impl Server {
                         Err(e) => error!("socket error: {}", e)
                     }
                 }
+                () = &mut reloader => {}
                 // wait for shutdown signal...
                 _ = tokio::signal::ctrl_c() => {
                     info!("shutdown signal received");
@@ -0,0 +1,108 @@
+use anyhow::Error;
+use log::{debug, error};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Editors usually touch a file more than once when saving it, so changes are
+/// batched within this window before being reported.
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(100);
+
+/// Watches the local files used by the workers when running with `--watch`.
+pub struct FileWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    watched_dirs: Mutex<HashSet<PathBuf>>,
+}
+
+impl FileWatcher {
+    /// Creates a watcher along with a receiver that yields the (canonical)
+    /// paths that changed, one batch at a time.
+    pub fn new() -> Result<(Self, mpsc::UnboundedReceiver<Vec<PathBuf>>), Error> {
+        let (raw_tx, mut raw_rx) = mpsc::unbounded_channel::<Vec<PathBuf>>();
+        let (changes_tx, changes_rx) = mpsc::unbounded_channel::<Vec<PathBuf>>();
+
+        let watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) => {
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) {
+                        let _ = raw_tx.send(event.paths);
+                    }
+                }
+
+                Err(err) => error!("file watcher error: {}", err),
+            })?;
+
+        drop(tokio::spawn(async move {
+            while let Some(paths) = raw_rx.recv().await {
+                let mut batch = paths.into_iter().collect::<HashSet<_>>();
+                let debounce = tokio::time::sleep(DEBOUNCE_DURATION);
+
+                tokio::pin!(debounce);
+                loop {
+                    tokio::select! {
+                        Some(paths) = raw_rx.recv() => batch.extend(paths),
+                        () = &mut debounce => break,
+                    }
+                }
+
+                let batch = batch
+                    .into_iter()
+                    .map(|it| it.canonicalize().unwrap_or(it))
+                    .collect::<Vec<_>>();
+
+                if changes_tx.send(batch).is_err() {
+                    break;
+                }
+            }
+        }));
+
+        Ok((
+            Self {
+                watcher: Mutex::new(watcher),
+                watched_dirs: Mutex::default(),
+            },
+            changes_rx,
+        ))
+    }
+
+    /// Starts watching `paths`. Files are watched through their parent
+    /// directory so that editors replacing the file on save are noticed too.
+    pub fn watch<P: AsRef<Path>>(&self, paths: impl IntoIterator<Item = P>) {
+        let mut watcher = self.watcher.lock().unwrap();
+        let mut watched_dirs = self.watched_dirs.lock().unwrap();
+
+        for path in paths {
+            let path = path.as_ref();
+            let (dir, mode) = if path.is_dir() {
+                (path, RecursiveMode::Recursive)
+            } else if let Some(parent) = path.parent() {
+                (parent, RecursiveMode::NonRecursive)
+            } else {
+                continue;
+            };
+
+            let Ok(dir) = dir.canonicalize() else {
+                continue;
+            };
+
+            if watched_dirs.contains(&dir) {
+                continue;
+            }
+
+            match watcher.watch(&dir, mode) {
+                Ok(_) => {
+                    debug!("watching {}", dir.display());
+                    watched_dirs.insert(dir);
+                }
+
+                Err(err) => error!("failed to watch {}: {}", dir.display(), err),
+            }
+        }
+    }
+}
@@ -0,0 +1,8 @@
+Deno.serve(async () => {
+    const res = await EdgeRuntime.bindings.greeter.fetch("http://greeter/", {
+        method: "POST",
+        body: "bob",
+    });
+
+    return new Response(await res.text());
+});
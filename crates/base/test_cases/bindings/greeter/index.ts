@@ -0,0 +1,5 @@
+Deno.serve(async (req: Request) => {
+    const bindings = Object.keys(EdgeRuntime.bindings);
+
+    return new Response(`hello ${await req.text()} (${bindings.length} bindings)`);
+});
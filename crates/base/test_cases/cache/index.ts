@@ -0,0 +1,31 @@
+Deno.serve(async () => {
+    const cache = await caches.open("v1");
+    const url = "https://example.com/greeting";
+
+    await cache.put(
+        new Request(url, { headers: { "accept-language": "en" } }),
+        new Response("hello", { headers: { "vary": "Accept-Language" } }),
+    );
+    await cache.put(
+        "https://example.com/stale",
+        new Response("stale", { headers: { "cache-control": "max-age=0" } }),
+    );
+
+    const en = await cache.match(new Request(url, { headers: { "accept-language": "en" } }));
+    const fr = await cache.match(new Request(url, { headers: { "accept-language": "fr" } }));
+    const anyLanguage = await cache.match(url, { ignoreVary: true });
+    const stale = await cache.match("https://example.com/stale");
+    const deleted = await cache.delete(url, { ignoreVary: true });
+
+    return new Response(
+        JSON.stringify({
+            en: await en?.text(),
+            fr: fr === undefined,
+            anyLanguage: await anyLanguage?.text(),
+            stale: stale === undefined,
+            deleted,
+            keys: await caches.keys(),
+        }),
+        { status: 200, headers: { "Content-Type": "application/json" } },
+    )
+})
@@ -0,0 +1,41 @@
+Deno.serve(async () => {
+    const kv = await Deno.openKv();
+
+    const alice = await kv.set(["users", 1], { name: "alice" });
+    await kv.set(["users", 2], { name: "bob" });
+    await kv.set(["users", 3], { name: "carol" }, { expireIn: 1 });
+    await kv.set(["config"], new Uint8Array([1, 2]));
+
+    const conflict = await kv.atomic()
+        .check({ key: ["users", 1], versionstamp: null })
+        .set(["users", 1], { name: "mallory" })
+        .commit();
+    const renamed = await kv.atomic()
+        .check({ key: ["users", 1], versionstamp: alice.versionstamp })
+        .set(["users", 1], { name: "alice2" })
+        .sum(["visits"], 2n)
+        .commit();
+    await kv.atomic().sum(["visits"], 3n).commit();
+
+    // let the entry of carol expire
+    await new Promise((resolve) => setTimeout(resolve, 10));
+
+    const names = [];
+    for await (const entry of kv.list({ prefix: ["users"] })) {
+        names.push(entry.value.name);
+    }
+
+    const memory = await Deno.openKv(":memory:");
+
+    return new Response(
+        JSON.stringify({
+            conflict: conflict.ok,
+            renamed: renamed.ok,
+            names,
+            visits: (await kv.get(["visits"])).value.toString(),
+            config: Array.from((await kv.get(["config"])).value),
+            inMemory: (await memory.get(["users", 1])).versionstamp,
+        }),
+        { status: 200, headers: { "Content-Type": "application/json" } },
+    )
+})
@@ -0,0 +1,24 @@
+Deno.serve(async (req: Request) => {
+    const caller = await EdgeRuntime.userWorkers.create({
+        servicePath: "./test_cases/bindings/caller",
+        bindings: { greeter: "./test_cases/bindings/greeter" },
+    });
+    const greeting = await (await caller.fetch(req)).text();
+
+    let loop = null;
+    try {
+        await EdgeRuntime.userWorkers.create({
+            servicePath: "./test_cases/bindings/caller",
+            bindings: {
+                greeter: {
+                    servicePath: "./test_cases/bindings/greeter",
+                    bindings: { caller: "./test_cases/bindings/caller" },
+                },
+            },
+        });
+    } catch (e) {
+        loop = e.toString();
+    }
+
+    return Response.json({ greeting, loop });
+});
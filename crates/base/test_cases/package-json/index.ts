@@ -0,0 +1,8 @@
+import isEven from "is-even";
+
+Deno.serve(() => {
+    return new Response(
+        JSON.stringify({ is_even: isEven(10) }),
+        { status: 200, headers: { "Content-Type": "application/json" } },
+    )
+})
@@ -0,0 +1,11 @@
+interface Greeting {
+  message: string;
+}
+
+type Loud<T> = T & { loud: true };
+
+function fail(greeting: Loud<Greeting>): never {
+  throw new Error(greeting.message);
+}
+
+fail({ message: "thrown from typescript", loud: true });
@@ -0,0 +1,15 @@
+Deno.serve(async () => {
+    const text = await Deno.readTextFile(new URL("./static/hello.txt", import.meta.url));
+
+    let error = null;
+    try {
+        await Deno.readTextFile(new URL("./index.ts", import.meta.url));
+    } catch (e) {
+        error = e.name;
+    }
+
+    return new Response(
+        JSON.stringify({ text, error }),
+        { status: 200, headers: { "Content-Type": "application/json" } },
+    )
+})
@@ -0,0 +1,24 @@
+Deno.serve(async () => {
+    const path = await Deno.makeTempFile();
+    await Deno.writeTextFile(path, "hello from /tmp");
+    const text = await Deno.readTextFile(path);
+
+    let error = null;
+    try {
+        await Deno.writeTextFile(new URL("./out.txt", import.meta.url), "nope");
+    } catch (e) {
+        error = e.name;
+    }
+
+    let exceeded = false;
+    try {
+        await Deno.writeFile(path, new Uint8Array(2 * 1024 * 1024));
+    } catch {
+        exceeded = true;
+    }
+
+    return new Response(
+        JSON.stringify({ inTmp: path.startsWith("/tmp/"), text, error, exceeded }),
+        { status: 200, headers: { "Content-Type": "application/json" } },
+    )
+})
@@ -0,0 +1,23 @@
+// A main service using the globals only the main worker has.
+Deno.serve(async (req: Request) => {
+    const worker = await EdgeRuntime.userWorkers.create({
+        servicePath: "./test_cases/type_check_ok",
+        memoryLimitMb: 150,
+        importMapPath: null,
+        envVars: [["NAME", "world"]],
+        backgroundTimeoutMs: 1000,
+        bindings: { payments: "./test_cases/type_check_ok" },
+    });
+
+    await EdgeRuntime.schedule("*/15 * * * *", "./test_cases/type_check_ok", {
+        name: "cleanup",
+        overlap: "queue",
+    });
+    await EdgeRuntime.unschedule("cleanup");
+
+    const forwarded = new Request(req);
+    EdgeRuntime.applyConnectionWatcher(req, forwarded);
+
+    const controller = new AbortController();
+    return await worker.fetch(forwarded, { signal: controller.signal });
+});
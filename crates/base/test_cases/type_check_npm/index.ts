@@ -0,0 +1,8 @@
+import { z } from "npm:zod@3.22.4";
+import { Buffer } from "node:buffer";
+
+// both would pass if the modules were typed as `any`
+const name: number = z.string().parse("world");
+const encoded: number = Buffer.from(name.toString()).toString("base64");
+
+Deno.serve(() => new Response(String(encoded)));
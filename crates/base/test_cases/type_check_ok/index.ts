@@ -0,0 +1,6 @@
+import { greet } from "./greet.ts";
+
+Deno.serve((req: Request) => {
+    const name = new URL(req.url).searchParams.get("name") ?? "world";
+    return new Response(greet(name));
+});
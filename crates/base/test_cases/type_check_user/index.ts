@@ -0,0 +1,21 @@
+// A user worker using the globals only user workers have.
+Deno.serve(async (_req: Request) => {
+    EdgeRuntime.waitUntil(new Promise((resolve) => setTimeout(resolve, 100)));
+
+    const kv = await Deno.openKv();
+    const { versionstamp } = await kv.set(["visits"], new Deno.KvU64(1n));
+    await kv.atomic()
+        .check({ key: ["visits"], versionstamp })
+        .sum(["visits"], 1n)
+        .commit();
+    const payments = EdgeRuntime.bindings["payments"];
+    if (payments) {
+        await payments.fetch("http://payments/charge", { method: "POST" });
+    }
+
+    for await (const entry of kv.list<Deno.KvU64>({ prefix: ["visits"] })) {
+        console.log(entry.value.value);
+    }
+
+    return new Response("ok");
+});
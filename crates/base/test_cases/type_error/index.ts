@@ -0,0 +1,3 @@
+const greeting: string = 42;
+
+Deno.serve(() => new Response(greeting));
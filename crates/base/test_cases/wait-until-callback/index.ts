@@ -0,0 +1,13 @@
+const callbackUrl = Deno.env.get("CALLBACK_URL")!;
+const delayMs = Number(Deno.env.get("DELAY_MS"));
+
+Deno.serve(() => {
+    // the callback is only called after the response was sent
+    EdgeRuntime.waitUntil(
+        new Promise((resolve) => setTimeout(resolve, delayMs))
+            .then(() => fetch(callbackUrl, { method: "POST" }))
+            .then((res) => res.body?.cancel()),
+    );
+
+    return new Response("sent");
+})
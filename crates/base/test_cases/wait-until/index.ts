@@ -0,0 +1,8 @@
+Deno.serve(() => {
+    EdgeRuntime.waitUntil(new Promise((resolve) => setTimeout(resolve, 100)));
+
+    return new Response(
+        JSON.stringify({ wait_until: typeof EdgeRuntime.waitUntil }),
+        { status: 200, headers: { "Content-Type": "application/json" } },
+    )
+})
@@ -0,0 +1,7 @@
+Deno.serve((req: Request) => {
+    const { socket, response } = Deno.upgradeWebSocket(req);
+
+    socket.onmessage = (e) => socket.send(e.data);
+
+    return response;
+})
@@ -15,16 +15,20 @@ async fn test_import_map_file_path() {
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/with_import_map".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: Some("./test_cases/with_import_map/import_map.json".to_string()),
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
@@ -72,16 +76,20 @@ async fn test_import_map_inline() {
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/with_import_map".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: Some(inline_import_map),
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
@@ -9,25 +9,32 @@ use tokio::sync::oneshot;
 #[tokio::test]
 async fn test_main_worker_options_request() {
     // create a user worker pool
-    let user_worker_msgs_tx = create_user_worker_pool(Default::default(), None)
-        .await
-        .unwrap();
+    let user_worker_msgs_tx =
+        create_user_worker_pool(Default::default(), None, None, None, None, false)
+            .await
+            .unwrap();
 
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/main".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
             worker_pool_tx: user_worker_msgs_tx,
+            user_worker_limits: Default::default(),
+            scheduler_tx: None,
         }),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
@@ -60,25 +67,32 @@ async fn test_main_worker_options_request() {
 #[tokio::test]
 async fn test_main_worker_post_request() {
     // create a user worker pool
-    let user_worker_msgs_tx = create_user_worker_pool(Default::default(), None)
-        .await
-        .unwrap();
+    let user_worker_msgs_tx =
+        create_user_worker_pool(Default::default(), None, None, None, None, false)
+            .await
+            .unwrap();
 
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/main".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
             worker_pool_tx: user_worker_msgs_tx,
+            user_worker_limits: Default::default(),
+            scheduler_tx: None,
         }),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let body_chunk = "{ \"name\": \"bar\"}";
@@ -115,22 +129,29 @@ async fn test_main_worker_post_request() {
 #[tokio::test]
 async fn test_main_worker_boot_error() {
     // create a user worker pool
-    let user_worker_msgs_tx = create_user_worker_pool(Default::default(), None)
-        .await
-        .unwrap();
+    let user_worker_msgs_tx =
+        create_user_worker_pool(Default::default(), None, None, None, None, false)
+            .await
+            .unwrap();
 
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/main".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: Some("./non-existing-import-map.json".to_string()),
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
             worker_pool_tx: user_worker_msgs_tx,
+            user_worker_limits: Default::default(),
+            scheduler_tx: None,
         }),
     };
     let result = create_worker(opts).await;
@@ -142,25 +163,32 @@ async fn test_main_worker_boot_error() {
 #[tokio::test]
 async fn test_main_worker_abort_request() {
     // create a user worker pool
-    let user_worker_msgs_tx = create_user_worker_pool(Default::default(), None)
-        .await
-        .unwrap();
+    let user_worker_msgs_tx =
+        create_user_worker_pool(Default::default(), None, None, None, None, false)
+            .await
+            .unwrap();
 
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/main_with_abort".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
             worker_pool_tx: user_worker_msgs_tx,
+            user_worker_limits: Default::default(),
+            scheduler_tx: None,
         }),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let body_chunk = "{ \"name\": \"bar\"}";
@@ -197,6 +225,63 @@ async fn test_main_worker_abort_request() {
     );
 }
 
+#[tokio::test]
+async fn test_main_worker_service_bindings() {
+    // create a user worker pool
+    let user_worker_msgs_tx =
+        create_user_worker_pool(Default::default(), None, None, None, None, false)
+            .await
+            .unwrap();
+
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/main_with_bindings".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+            worker_pool_tx: user_worker_msgs_tx,
+            user_worker_limits: Default::default(),
+            scheduler_tx: None,
+        }),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    // the caller reaches the greeter through its binding, which has none
+    assert_eq!(
+        body_bytes,
+        r#"{"greeting":"hello bob (0 bindings)","loop":"TypeError: binding 'caller' loops back to ./test_cases/bindings/caller"}"#
+    );
+}
+
 //#[tokio::test]
 //async fn test_main_worker_user_worker_mod_evaluate_exception() {
 //    // create a user worker pool
@@ -210,7 +295,7 @@ async fn test_main_worker_abort_request() {
 //            worker_pool_tx: user_worker_msgs_tx,
 //        }),
 //    };
-//    let worker_req_tx = create_worker(opts).await.unwrap();
+//    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
 //    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 //
 //    let req = Request::builder()
@@ -246,7 +331,7 @@ async fn test_main_worker_abort_request() {
 //            worker_pool_tx: user_worker_msgs_tx,
 //        }),
 //    };
-//    let worker_req_tx = create_worker(opts).await.unwrap();
+//    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
 //    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 //
 //    let chunks: Vec<Result<_, std::io::Error>> = vec![Ok("{\\"name\\":"), Ok("\\"bar\\"}")];
@@ -12,16 +12,20 @@ async fn test_null_body_with_204_status() {
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/empty-response".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
@@ -55,16 +59,20 @@ async fn test_null_body_with_204_status_post() {
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/empty-response".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
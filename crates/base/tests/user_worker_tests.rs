@@ -1,10 +1,21 @@
-use base::rt_worker::worker_ctx::create_worker;
-use hyper::{Body, Request, Response};
+use base::rt_worker::worker_ctx::{create_user_worker_pool, create_worker};
+use base::rt_worker::worker_pool::{SupervisorPolicy, WorkerPoolPolicy};
+use event_worker::events::{ShutdownReason, WorkerEventWithMetadata, WorkerEvents};
+use fastwebsockets::{Frame, OpCode, Role, WebSocket};
+use hyper::header::{CONNECTION, UPGRADE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use sb_core::upgrade::OnUpgradedStream;
 use sb_workers::context::{
-    UserWorkerRuntimeOpts, WorkerContextInitOpts, WorkerRequestMsg, WorkerRuntimeOpts,
+    CreateUserWorkerResult, SendRequestResult, UserWorkerMsgs, UserWorkerRuntimeOpts,
+    WorkerContextInitOpts, WorkerRequestMsg, WorkerRuntimeOpts,
 };
 use std::collections::HashMap;
-use tokio::sync::oneshot;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
 
 #[tokio::test]
 async fn test_user_worker_json_imports() {
@@ -12,16 +23,20 @@ async fn test_user_worker_json_imports() {
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/json_import".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
@@ -52,16 +67,20 @@ async fn test_user_imports_npm() {
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/npm".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
-    let worker_req_tx = create_worker(opts).await.unwrap();
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
     let req = Request::builder()
@@ -88,3 +107,511 @@ async fn test_user_imports_npm() {
         r#"{"is_even":true,"hello":"","numbers":{"Uno":1,"Dos":2}}"#
     );
 }
+
+#[tokio::test]
+async fn test_user_imports_package_json_deps() {
+    let user_rt_opts = UserWorkerRuntimeOpts::default();
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/package-json".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    assert_eq!(body_bytes, r#"{"is_even":true}"#);
+}
+
+#[tokio::test]
+async fn test_user_worker_wait_until() {
+    let user_rt_opts = UserWorkerRuntimeOpts::default();
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/wait-until".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    assert_eq!(body_bytes, r#"{"wait_until":"function"}"#);
+}
+
+/// Serves the callback of `wait-until-callback`, and notifies the receiver of
+/// each call.
+fn start_callback_server() -> (String, mpsc::UnboundedReceiver<()>) {
+    let (callback_tx, callback_rx) = mpsc::unbounded_channel::<()>();
+    let make_svc = make_service_fn(move |_| {
+        let callback_tx = callback_tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_: Request<Body>| {
+                let _ = callback_tx.send(());
+                async { Ok::<_, Infallible>(Response::new(Body::empty())) }
+            }))
+        }
+    });
+
+    let server = hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+    let callback_url = format!("http://{}/", server.local_addr());
+
+    tokio::spawn(server);
+    (callback_url, callback_rx)
+}
+
+/// Creates a worker of `wait-until-callback` through the pool. Its background
+/// task calls `callback_url` `delay_ms` after each request.
+async fn create_wait_until_worker(
+    worker_pool_tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+    callback_url: String,
+    delay_ms: u64,
+    background_timeout_ms: u64,
+) -> Uuid {
+    let user_rt_opts = UserWorkerRuntimeOpts {
+        background_timeout_ms,
+        ..Default::default()
+    };
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/wait-until-callback".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::from([
+            ("CALLBACK_URL".to_string(), callback_url),
+            ("DELAY_MS".to_string(), delay_ms.to_string()),
+        ]),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+
+    let (result_tx, result_rx) =
+        oneshot::channel::<Result<CreateUserWorkerResult, anyhow::Error>>();
+    worker_pool_tx
+        .send(UserWorkerMsgs::Create(opts, result_tx))
+        .unwrap();
+
+    result_rx.await.unwrap().unwrap().key
+}
+
+/// Sends a request to the worker through the pool like the main worker would,
+/// and ends it once the whole response was read.
+async fn send_pool_request(
+    worker_pool_tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+    key: Uuid,
+) -> hyper::body::Bytes {
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let (result_tx, result_rx) = oneshot::channel::<Result<SendRequestResult, anyhow::Error>>();
+    worker_pool_tx
+        .send(UserWorkerMsgs::SendRequest(key, req, result_tx, None))
+        .unwrap();
+
+    let (res, req_end_tx) = result_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let _ = req_end_tx.send(());
+
+    body_bytes
+}
+
+#[tokio::test]
+async fn test_user_worker_wait_until_outlives_response() {
+    let (callback_url, mut callback_rx) = start_callback_server();
+    let worker_pool_tx = create_user_worker_pool(
+        WorkerPoolPolicy::new(SupervisorPolicy::PerRequest { oneshot: false }, 1, None),
+        None,
+        None,
+        None,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let key = create_wait_until_worker(&worker_pool_tx, callback_url, 500, 10 * 1000).await;
+    let body_bytes = send_pool_request(&worker_pool_tx, key).await;
+
+    assert_eq!(body_bytes, "sent");
+
+    // the task is still sleeping when the response arrives, and keeps running
+    // after the request ended
+    assert!(callback_rx.try_recv().is_err());
+
+    tokio::time::timeout(Duration::from_secs(10), callback_rx.recv())
+        .await
+        .expect("the background task was not run to completion")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_user_worker_wait_until_background_deadline() {
+    let (callback_url, mut callback_rx) = start_callback_server();
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel::<WorkerEventWithMetadata>();
+
+    // the isolate of a oneshot worker is terminated once the request ends, so
+    // the background task can't outlive the deadline
+    let worker_pool_tx = create_user_worker_pool(
+        WorkerPoolPolicy::new(SupervisorPolicy::PerRequest { oneshot: true }, 1, None),
+        Some(events_tx),
+        None,
+        None,
+        None,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let key = create_wait_until_worker(&worker_pool_tx, callback_url, 3000, 300).await;
+    let body_bytes = send_pool_request(&worker_pool_tx, key).await;
+
+    assert_eq!(body_bytes, "sent");
+
+    let reason = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            if let WorkerEvents::Shutdown(ev) = events_rx.recv().await.unwrap().event {
+                break ev.reason;
+            }
+        }
+    })
+    .await
+    .expect("the worker was not terminated at the background deadline");
+
+    assert!(matches!(reason, ShutdownReason::EarlyDrop));
+    assert!(
+        tokio::time::timeout(Duration::from_secs(4), callback_rx.recv())
+            .await
+            .is_err(),
+        "the background task ran past the deadline"
+    );
+}
+
+#[tokio::test]
+async fn test_user_worker_static_files() {
+    let user_rt_opts = UserWorkerRuntimeOpts {
+        static_patterns: vec!["static/*".to_string()],
+        ..Default::default()
+    };
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/static-files".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    // the entrypoint isn't matched by the patterns, so it can't be read
+    assert_eq!(
+        body_bytes,
+        r#"{"text":"hello from a static file\n","error":"PermissionDenied"}"#
+    );
+}
+
+#[tokio::test]
+async fn test_user_worker_tmp_files() {
+    let user_rt_opts = UserWorkerRuntimeOpts {
+        tmp_quota_mb: Some(1),
+        ..Default::default()
+    };
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/tmp-files".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    // only /tmp is writable, and writing 2 MiB exceeds the quota of 1 MiB
+    assert_eq!(
+        body_bytes,
+        r#"{"inTmp":true,"text":"hello from /tmp","error":"PermissionDenied","exceeded":true}"#
+    );
+}
+
+#[tokio::test]
+async fn test_user_worker_cache() {
+    let user_rt_opts = UserWorkerRuntimeOpts::default();
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/cache".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    // the stale entry expired as soon as it was stored
+    assert_eq!(
+        body_bytes,
+        r#"{"en":"hello","fr":true,"anyLanguage":"hello","stale":true,"deleted":true,"keys":["v1"]}"#
+    );
+}
+
+#[tokio::test]
+async fn test_user_worker_kv() {
+    let kv_dir = std::env::temp_dir().join(format!("sb-kv-{}", uuid::Uuid::new_v4()));
+    let user_rt_opts = UserWorkerRuntimeOpts {
+        kv_dir: Some(kv_dir.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/kv".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let res = res_rx.await.unwrap().unwrap();
+    assert!(res.status().as_u16() == 200);
+
+    let body_bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    // the check that alice doesn't exist fails, and carol expired
+    assert_eq!(
+        body_bytes,
+        r#"{"conflict":false,"renamed":true,"names":["alice2","bob"],"visits":"5","config":[1,2],"inMemory":null}"#
+    );
+
+    let _ = std::fs::remove_dir_all(kv_dir);
+}
+
+#[tokio::test]
+async fn test_user_worker_websocket_upgrade() {
+    let user_rt_opts = UserWorkerRuntimeOpts::default();
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/websocket".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
+    };
+    let (worker_req_tx, _) = create_worker(opts).await.unwrap();
+    let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .header(CONNECTION, "Upgrade")
+        .header(UPGRADE, "websocket")
+        .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("Sec-WebSocket-Version", "13")
+        .body(Body::empty())
+        .unwrap();
+
+    let msg = WorkerRequestMsg {
+        req,
+        res_tx,
+        conn_watch: None,
+    };
+
+    let _ = worker_req_tx.send(msg);
+
+    let mut res = res_rx.await.unwrap().unwrap();
+    assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+    let OnUpgradedStream(upgraded_rx) = res.extensions_mut().remove::<OnUpgradedStream>().unwrap();
+    let upgraded = upgraded_rx.await.unwrap();
+    let mut ws = WebSocket::after_handshake(upgraded.io, Role::Client);
+
+    ws.write_frame(Frame::text(b"hello".to_vec().into()))
+        .await
+        .unwrap();
+
+    let frame = ws.read_frame().await.unwrap();
+
+    assert_eq!(frame.opcode, OpCode::Text);
+    assert_eq!(frame.payload.to_vec(), b"hello");
+}
@@ -0,0 +1,140 @@
+use base::rt_worker::worker_ctx::create_user_worker_pool;
+use base::server::{Server, ServerCodes, ServerOptions};
+use sb_workers::context::{
+    CreateUserWorkerResult, UserWorkerMsgs, UserWorkerRuntimeOpts, WorkerContextInitOpts,
+    WorkerRuntimeOpts,
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+async fn create_user_worker(
+    user_worker_msgs_tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+) -> uuid::Uuid {
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/json_import".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts::default()),
+    };
+    let (result_tx, result_rx) = oneshot::channel::<Result<CreateUserWorkerResult, _>>();
+
+    user_worker_msgs_tx
+        .send(UserWorkerMsgs::Create(opts, result_tx))
+        .unwrap();
+
+    result_rx.await.unwrap().unwrap().key
+}
+
+#[tokio::test]
+async fn test_files_changed_retires_user_workers() {
+    let user_worker_msgs_tx =
+        create_user_worker_pool(Default::default(), None, None, None, None, false)
+            .await
+            .unwrap();
+
+    let key = create_user_worker(&user_worker_msgs_tx).await;
+    assert_eq!(create_user_worker(&user_worker_msgs_tx).await, key);
+
+    // a file the worker wasn't built from leaves it alone
+    user_worker_msgs_tx
+        .send(UserWorkerMsgs::FilesChanged(vec![PathBuf::from(
+            "./test_cases/main/index.ts",
+        )
+        .canonicalize()
+        .unwrap()]))
+        .unwrap();
+    assert_eq!(create_user_worker(&user_worker_msgs_tx).await, key);
+
+    user_worker_msgs_tx
+        .send(UserWorkerMsgs::FilesChanged(vec![PathBuf::from(
+            "./test_cases/json_import/index.ts",
+        )
+        .canonicalize()
+        .unwrap()]))
+        .unwrap();
+    assert_ne!(create_user_worker(&user_worker_msgs_tx).await, key);
+}
+
+/// Removes the directory even if the test panics.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn test_main_worker_reloads_when_files_change() {
+    let dir =
+        TempDir(std::env::temp_dir().join(format!("sb-main-reload-{}", uuid::Uuid::new_v4())));
+    let index = dir.0.join("index.ts");
+    std::fs::create_dir_all(&dir.0).unwrap();
+    std::fs::write(&index, "Deno.serve(() => new Response('before'));").unwrap();
+
+    let port = free_port();
+    let (callback_tx, mut callback_rx) = mpsc::channel::<ServerCodes>(1);
+    let mut server = Server::new(ServerOptions {
+        ip: String::from("127.0.0.1"),
+        port,
+        main_service_path: dir.0.to_string_lossy().to_string(),
+        watch: true,
+        no_signal_handler: true,
+        callback_tx: Some(callback_tx),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let client = async {
+        assert!(matches!(
+            callback_rx.recv().await,
+            Some(ServerCodes::Listening)
+        ));
+
+        // one client, so that the connection is kept alive across the reload
+        let client = reqwest::Client::new();
+        let get = || async {
+            client
+                .get(format!("http://127.0.0.1:{}/", port))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        };
+
+        assert_eq!(get().await, "before");
+        std::fs::write(&index, "Deno.serve(() => new Response('after'));").unwrap();
+
+        let reloaded = tokio::time::timeout(Duration::from_secs(10), callback_rx.recv()).await;
+        assert!(matches!(reloaded, Ok(Some(ServerCodes::Reloaded))));
+        assert_eq!(get().await, "after");
+    };
+
+    tokio::select! {
+        () = client => {}
+        _ = server.listen() => panic!("server stopped"),
+    }
+}
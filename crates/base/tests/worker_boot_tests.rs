@@ -1,20 +1,58 @@
 use std::collections::HashMap;
 
 use base::rt_worker::worker_ctx::create_worker;
+use deno_core::serde_json::json;
+use sb_graph::emitter::LockfileOpts;
 use sb_workers::context::{UserWorkerRuntimeOpts, WorkerContextInitOpts, WorkerRuntimeOpts};
 
+async fn boot_npm_worker_with_lockfile(lockfile: deno_core::serde_json::Value) -> bool {
+    let dir = std::env::temp_dir().join(format!("sb-lockfile-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("deno.lock");
+    std::fs::write(&path, lockfile.to_string()).unwrap();
+
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/npm".into(),
+        no_module_cache: false,
+        cached_only: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: Some(LockfileOpts {
+            path,
+            overwrite: false,
+            frozen: false,
+        }),
+        maybe_trusted_keys: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts::default()),
+    };
+    let booted = create_worker(opts).await.is_ok();
+
+    std::fs::remove_dir_all(dir).unwrap();
+    booted
+}
+
 #[tokio::test]
 async fn test_worker_boot_invalid_imports() {
     let user_rt_opts = UserWorkerRuntimeOpts::default();
     let opts = WorkerContextInitOpts {
         service_path: "./test_cases/invalid_imports".into(),
         no_module_cache: false,
+        cached_only: false,
         import_map_path: None,
         env_vars: HashMap::new(),
         events_rx: None,
         timing: None,
         maybe_eszip: None,
         maybe_entrypoint: None,
+        maybe_function_name: None,
+        maybe_lockfile: None,
+        maybe_trusted_keys: None,
         maybe_module_code: None,
         conf: WorkerRuntimeOpts::UserWorker(user_rt_opts),
     };
@@ -23,3 +61,40 @@ async fn test_worker_boot_invalid_imports() {
     assert!(result.is_err());
     assert_eq!(result.unwrap_err().to_string(), "worker boot error");
 }
+
+#[tokio::test]
+async fn test_worker_boot_changed_remote_module() {
+    let booted = boot_npm_worker_with_lockfile(json!({
+        "version": "3",
+        "remote": { "https://deno.land/std@0.131.0/http/server.ts": "0".repeat(64) }
+    }))
+    .await;
+
+    assert!(!booted);
+}
+
+#[tokio::test]
+async fn test_worker_boot_changed_npm_package() {
+    let booted = boot_npm_worker_with_lockfile(json!({
+        "version": "3",
+        "packages": {
+            "npm": {
+                "is-even@1.0.0": {
+                    "integrity": "sha512-invalid",
+                    "dependencies": { "is-odd": "is-odd@0.1.2" }
+                }
+            }
+        },
+        "remote": {}
+    }))
+    .await;
+
+    assert!(!booted);
+}
+
+#[tokio::test]
+async fn test_worker_boot_with_lockfile() {
+    let booted = boot_npm_worker_with_lockfile(json!({ "version": "3", "remote": {} })).await;
+
+    assert!(booted);
+}
@@ -0,0 +1,696 @@
+use anyhow::{anyhow, bail, Context, Error};
+use base::scheduler::cron::Cron;
+use base::scheduler::SchedulerOpts;
+use deno_core::serde_json::{self, Value};
+use sb_graph::emitter::LockfileOpts;
+use sb_workers::context::{
+    OverlapPolicy, ScheduleOpts, UserWorkerLimits, UserWorkerLimitsConfig, UserWorkerRuntimeOpts,
+    UserWorkerTemplate,
+};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const SUPERVISOR_POLICIES: [&str; 3] = ["per_worker", "per_request", "oneshot"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(|it| it.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("json") => Ok(Self::Json),
+            _ => bail!(
+                "unsupported config file format, expected a .toml or .json file ({})",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Server configuration read from the file passed to `start --config`.
+///
+/// Every value is optional; flags given on the command line take precedence
+/// over the values in the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub main: ServiceConfig,
+    pub events: ServiceConfig,
+    pub modules: ModulesConfig,
+    pub pool: PoolConfig,
+    pub worker_limits: WorkerLimitsConfig,
+    pub scheduler: SchedulerConfig,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub ip: Option<String>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServiceConfig {
+    pub service_path: Option<String>,
+    /// Entrypoint of the service (only for eszips).
+    pub entrypoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModulesConfig {
+    pub import_map: Option<String>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub disable_cache: Option<bool>,
+    /// Fail instead of downloading anything that isn't cached.
+    #[serde(deserialize_with = "option_string_or")]
+    pub cached_only: Option<bool>,
+    pub lock: Option<LockConfig>,
+    /// Public keys that eszips must be signed with.
+    pub trusted_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockConfig {
+    pub path: String,
+    #[serde(deserialize_with = "string_or")]
+    pub write: bool,
+    #[serde(deserialize_with = "string_or")]
+    pub frozen: bool,
+}
+
+impl From<LockConfig> for LockfileOpts {
+    fn from(value: LockConfig) -> Self {
+        LockfileOpts {
+            path: PathBuf::from(value.path),
+            overwrite: value.write,
+            frozen: value.frozen,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub policy: Option<String>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub max_parallelism: Option<usize>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub request_wait_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerLimitsConfig {
+    pub default: LimitsConfig,
+    /// Limits keyed by the service path of the user worker.
+    pub services: HashMap<String, LimitsConfig>,
+}
+
+impl From<WorkerLimitsConfig> for UserWorkerLimitsConfig {
+    fn from(value: WorkerLimitsConfig) -> Self {
+        UserWorkerLimitsConfig {
+            default: value.default.into(),
+            services: value
+                .services
+                .into_iter()
+                .map(|(path, limits)| (PathBuf::from(path), limits.into()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    #[serde(deserialize_with = "option_string_or")]
+    pub memory_limit_mb: Option<u64>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub low_memory_multiplier: Option<u64>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub worker_timeout_ms: Option<u64>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub cpu_time_soft_limit_ms: Option<u64>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub cpu_time_hard_limit_ms: Option<u64>,
+}
+
+impl From<LimitsConfig> for UserWorkerLimits {
+    fn from(value: LimitsConfig) -> Self {
+        UserWorkerLimits {
+            memory_limit_mb: value.memory_limit_mb,
+            low_memory_multiplier: value.low_memory_multiplier,
+            worker_timeout_ms: value.worker_timeout_ms,
+            cpu_time_soft_limit_ms: value.cpu_time_soft_limit_ms,
+            cpu_time_hard_limit_ms: value.cpu_time_hard_limit_ms,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// Where the last run of each schedule is kept across restarts.
+    pub state_path: Option<String>,
+    /// Schedules keyed by their name.
+    pub schedules: HashMap<String, ScheduleConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub cron: String,
+    pub service_path: String,
+    #[serde(default)]
+    pub overlap: OverlapPolicy,
+    /// Path of the request sent to the worker (`/` by default).
+    pub path: Option<String>,
+    #[serde(default)]
+    pub env_vars: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: Option<LogLevel>,
+    #[serde(deserialize_with = "option_string_or")]
+    pub include_source: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn to_level(self) -> Option<log::Level> {
+        match self {
+            Self::Off => None,
+            Self::Error => Some(log::Level::Error),
+            Self::Warn => Some(log::Level::Warn),
+            Self::Info => Some(log::Level::Info),
+            Self::Debug => Some(log::Level::Debug),
+            Self::Trace => Some(log::Level::Trace),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let format = ConfigFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file ({})", path.display()))?;
+
+        Self::parse(&text, format, |key| std::env::var(key).ok())
+            .with_context(|| format!("invalid config file ({})", path.display()))
+    }
+
+    /// Parses a config file, replacing `${VAR}` and `${VAR:-default}` in
+    /// string values with the variables returned by `get_env`.
+    pub fn parse(
+        text: &str,
+        format: ConfigFormat,
+        get_env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Error> {
+        let mut value = match format {
+            ConfigFormat::Toml => toml::from_str::<Value>(text)?,
+            ConfigFormat::Json => serde_json::from_str::<Value>(text)?,
+        };
+
+        interpolate_env_vars(&mut value, &mut Vec::new(), &get_env)?;
+
+        let config: Config = serde_path_to_error::deserialize(value)
+            .map_err(|err| anyhow!("`{}`: {}", err.path(), err.inner()))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(ip) = self.server.ip.as_ref() {
+            if ip.parse::<Ipv4Addr>().is_err() {
+                bail!("`server.ip`: invalid IPv4 address `{}`", ip);
+            }
+        }
+
+        if let Some(policy) = self.pool.policy.as_ref() {
+            if !SUPERVISOR_POLICIES.contains(&policy.as_str()) {
+                bail!(
+                    "`pool.policy`: unknown policy `{}`, expected one of {}",
+                    policy,
+                    SUPERVISOR_POLICIES.join(", ")
+                );
+            }
+        }
+
+        if let Some(max_parallelism) = self.pool.max_parallelism {
+            // NOTE: Keep the bounds in sync with the `--max-parallelism` flag.
+            if !(1..9999).contains(&max_parallelism) {
+                bail!("`pool.max_parallelism`: must be between 1 and 9998");
+            }
+        }
+
+        if let Some(lock) = self.modules.lock.as_ref() {
+            if lock.path.is_empty() {
+                bail!("`modules.lock.path`: must not be empty");
+            }
+
+            if lock.write && lock.frozen {
+                bail!("`modules.lock`: `write` and `frozen` can't be used together");
+            }
+        }
+
+        validate_limits("worker_limits.default", &self.worker_limits.default)?;
+        for (path, limits) in self.worker_limits.services.iter() {
+            validate_limits(&format!("worker_limits.services.{}", path), limits)?;
+        }
+
+        for (name, schedule) in self.scheduler.schedules.iter() {
+            if let Err(err) = Cron::parse(&schedule.cron) {
+                bail!("`scheduler.schedules.{}.cron`: {}", name, err);
+            }
+
+            if let Some(path) = schedule.path.as_ref() {
+                if !path.starts_with('/') {
+                    bail!("`scheduler.schedules.{}.path`: must start with `/`", name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn user_worker_limits(&self) -> UserWorkerLimitsConfig {
+        self.worker_limits.clone().into()
+    }
+
+    pub fn lockfile_opts(&self) -> Option<LockfileOpts> {
+        self.modules.lock.clone().map(Into::into)
+    }
+
+    /// The scheduled workers are created with the limits configured for their
+    /// service, like the ones the main worker leaves the limits of unset. They
+    /// resolve modules with the same import map and lockfile as the server,
+    /// which may come from the command line rather than the config.
+    pub fn scheduler_opts(
+        &self,
+        import_map_path: Option<String>,
+        no_module_cache: bool,
+        maybe_lockfile: Option<LockfileOpts>,
+    ) -> SchedulerOpts {
+        let limits = self.user_worker_limits();
+        let schedules = self
+            .scheduler
+            .schedules
+            .iter()
+            .map(|(name, schedule)| {
+                let service_path = PathBuf::from(&schedule.service_path);
+                let mut conf = UserWorkerRuntimeOpts {
+                    requested_limits: limits.resolve(&service_path, Default::default()),
+                    ..Default::default()
+                };
+                conf.apply_default_limits(&Default::default());
+
+                ScheduleOpts {
+                    name: name.clone(),
+                    cron: schedule.cron.clone(),
+                    overlap: schedule.overlap,
+                    path: schedule.path.clone().unwrap_or_else(|| "/".to_string()),
+                    worker: UserWorkerTemplate {
+                        service_path,
+                        no_module_cache,
+                        import_map_path: import_map_path.clone(),
+                        env_vars: schedule.env_vars.clone(),
+                        maybe_entrypoint: None,
+                        maybe_function_name: None,
+                        maybe_lockfile: maybe_lockfile.clone(),
+                        conf,
+                    },
+                }
+            })
+            .collect();
+
+        SchedulerOpts {
+            state_path: self.scheduler.state_path.as_ref().map(PathBuf::from),
+            schedules,
+        }
+    }
+}
+
+fn validate_limits(key: &str, limits: &LimitsConfig) -> Result<(), Error> {
+    if let Some(0) = limits.memory_limit_mb {
+        bail!("`{}.memory_limit_mb`: must be greater than 0", key);
+    }
+
+    if let (Some(soft), Some(hard)) = (limits.cpu_time_soft_limit_ms, limits.cpu_time_hard_limit_ms)
+    {
+        if soft > hard {
+            bail!(
+                "`{}.cpu_time_soft_limit_ms`: must not be greater than `cpu_time_hard_limit_ms`",
+                key
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn interpolate_env_vars(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    get_env: &impl Fn(&str) -> Option<String>,
+) -> Result<(), Error> {
+    match value {
+        Value::String(text) => {
+            *text = interpolate_str(text, get_env)
+                .map_err(|err| anyhow!("`{}`: {}", path.join("."), err))?;
+        }
+
+        Value::Array(values) => {
+            for (idx, value) in values.iter_mut().enumerate() {
+                path.push(idx.to_string());
+                interpolate_env_vars(value, path, get_env)?;
+                path.pop();
+            }
+        }
+
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                path.push(key.clone());
+                interpolate_env_vars(value, path, get_env)?;
+                path.pop();
+            }
+        }
+
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// A number or bool which may also be given as a string, so that it can come
+/// from the environment (e.g. `port = "${PORT}"`).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOr<T> {
+    Value(T),
+    String(String),
+}
+
+impl<T> StringOr<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn into_value<E: de::Error>(self) -> Result<T, E> {
+        match self {
+            Self::Value(value) => Ok(value),
+            Self::String(text) => text
+                .parse()
+                .map_err(|err| E::custom(format!("invalid value `{}`: {}", text, err))),
+        }
+    }
+}
+
+fn string_or<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    StringOr::<T>::deserialize(deserializer)?.into_value()
+}
+
+fn option_string_or<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    Option::<StringOr<T>>::deserialize(deserializer)?
+        .map(StringOr::into_value)
+        .transpose()
+}
+
+fn interpolate_str(text: &str, get_env: &impl Fn(&str) -> Option<String>) -> Result<String, Error> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        // `$${` escapes a literal `${`.
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start]);
+            result.push('{');
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        result.push_str(&rest[..start]);
+
+        let Some(end) = rest[start..].find('}') else {
+            bail!("unterminated environment variable reference");
+        };
+
+        let reference = &rest[start + 2..start + end];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+
+        if name.is_empty() {
+            bail!("empty environment variable reference");
+        }
+
+        match get_env(name).or_else(|| default.map(str::to_string)) {
+            Some(value) => result.push_str(&value),
+            None => bail!("environment variable `{}` is not set", name),
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{Config, ConfigFormat, LogLevel};
+    use sb_graph::emitter::LockfileOpts;
+    use sb_workers::context::OverlapPolicy;
+    use std::path::{Path, PathBuf};
+
+    fn env(key: &str) -> Option<String> {
+        match key {
+            "PORT" => Some("8000".to_string()),
+            "IMPORT_MAP" => Some("./import_map.json".to_string()),
+            "CACHED_ONLY" => Some("true".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_toml_config() {
+        let config = Config::parse(
+            r#"
+            [server]
+            port = "${PORT}"
+
+            [modules]
+            import_map = "${IMPORT_MAP}"
+            lock = { path = "${LOCK_PATH:-deno.lock}", frozen = true }
+
+            [pool]
+            policy = "per_request"
+
+            [worker_limits.default]
+            memory_limit_mb = 256
+
+            [worker_limits.services."./examples/hello-world"]
+            cpu_time_hard_limit_ms = 200
+
+            [logging]
+            level = "debug"
+            "#,
+            ConfigFormat::Toml,
+            env,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.port, Some(8000));
+        assert_eq!(
+            config.modules.import_map.as_deref(),
+            Some("./import_map.json")
+        );
+        assert_eq!(config.pool.policy.as_deref(), Some("per_request"));
+        assert_eq!(config.logging.level, Some(LogLevel::Debug));
+
+        let lock = config.lockfile_opts().unwrap();
+        assert_eq!(lock.path, Path::new("deno.lock"));
+        assert!(lock.frozen);
+
+        let limits = config.user_worker_limits();
+        assert_eq!(limits.default.memory_limit_mb, Some(256));
+
+        let resolved = limits.resolve(Path::new("examples/hello-world"), Default::default());
+        assert_eq!(resolved.memory_limit_mb, Some(256));
+        assert_eq!(resolved.cpu_time_hard_limit_ms, Some(200));
+    }
+
+    #[test]
+    fn test_parse_json_config() {
+        let config = Config::parse(
+            r#"{ "server": { "ip": "127.0.0.1" }, "main": { "service_path": "$${literal}" } }"#,
+            ConfigFormat::Json,
+            env,
+        )
+        .unwrap();
+
+        assert_eq!(config.server.ip.as_deref(), Some("127.0.0.1"));
+        assert_eq!(config.main.service_path.as_deref(), Some("${literal}"));
+    }
+
+    #[test]
+    fn test_env_vars_are_typed_after_the_field() {
+        let config = Config::parse(
+            r#"
+            [main]
+            service_path = "${PORT}"
+
+            [modules]
+            cached_only = "${CACHED_ONLY}"
+            lock = { path = "deno.lock", write = "${CACHED_ONLY}" }
+
+            [worker_limits.default]
+            worker_timeout_ms = "${PORT}"
+            "#,
+            ConfigFormat::Toml,
+            env,
+        )
+        .unwrap();
+
+        assert_eq!(config.main.service_path.as_deref(), Some("8000"));
+        assert_eq!(config.modules.cached_only, Some(true));
+        assert!(config.lockfile_opts().unwrap().overwrite);
+        assert_eq!(config.worker_limits.default.worker_timeout_ms, Some(8000));
+
+        let err = Config::parse(
+            "[modules]\ncached_only = \"${PORT}\"",
+            ConfigFormat::Toml,
+            env,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("`modules.cached_only`"), "{}", err);
+    }
+
+    #[test]
+    fn test_config_errors_point_at_key() {
+        let err = Config::parse(
+            "[pool]\nmax_parallelism = \"many\"",
+            ConfigFormat::Toml,
+            env,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("`pool.max_parallelism`"),
+            "{}",
+            err
+        );
+
+        let err =
+            Config::parse("[server]\nhost = \"0.0.0.0\"", ConfigFormat::Toml, env).unwrap_err();
+        assert!(err.to_string().contains("`server`"), "{}", err);
+
+        let err =
+            Config::parse("[server]\nip = \"${MISSING}\"", ConfigFormat::Toml, env).unwrap_err();
+        assert!(err.to_string().contains("`server.ip`"), "{}", err);
+        assert!(err.to_string().contains("`MISSING`"), "{}", err);
+
+        let err =
+            Config::parse("[pool]\npolicy = \"round_robin\"", ConfigFormat::Toml, env).unwrap_err();
+        assert!(err.to_string().contains("`pool.policy`"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_scheduler_config() {
+        let config = Config::parse(
+            r#"
+            [worker_limits.services."./examples/cleanup"]
+            worker_timeout_ms = 30000
+
+            [scheduler]
+            state_path = "./scheduler.json"
+
+            [scheduler.schedules.cleanup]
+            cron = "*/5 * * * *"
+            service_path = "./examples/cleanup"
+            overlap = "queue"
+            env_vars = { ORIGIN = "http://localhost:${PORT}" }
+            "#,
+            ConfigFormat::Toml,
+            env,
+        )
+        .unwrap();
+
+        let opts = config.scheduler_opts(
+            Some("./import_map.json".to_string()),
+            false,
+            Some(LockfileOpts {
+                path: PathBuf::from("./deno.lock"),
+                overwrite: false,
+                frozen: true,
+            }),
+        );
+        assert_eq!(
+            opts.state_path.as_deref(),
+            Some(Path::new("./scheduler.json"))
+        );
+
+        let schedule = &opts.schedules[0];
+        assert_eq!(schedule.name, "cleanup");
+        assert_eq!(schedule.overlap, OverlapPolicy::Queue);
+        assert_eq!(schedule.path, "/");
+        assert_eq!(schedule.worker.conf.worker_timeout_ms, 30000);
+        assert_eq!(
+            schedule.worker.import_map_path.as_deref(),
+            Some("./import_map.json")
+        );
+        assert!(schedule.worker.maybe_lockfile.as_ref().unwrap().frozen);
+        assert_eq!(
+            schedule.worker.env_vars.get("ORIGIN").map(String::as_str),
+            Some("http://localhost:8000")
+        );
+
+        let err = Config::parse(
+            "[scheduler.schedules.cleanup]\ncron = \"* * *\"\nservice_path = \"./cleanup\"",
+            ConfigFormat::Toml,
+            env,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`scheduler.schedules.cleanup.cron`"),
+            "{}",
+            err
+        );
+    }
+}
@@ -50,13 +50,7 @@ impl log::Log for CliLogger {
     }
 }
 
-pub fn init(verbose: bool, include_source: bool) {
-    let log_level = if verbose {
-        log::Level::Debug
-    } else {
-        log::Level::Info
-    };
-
+pub fn init(log_level: log::Level, include_source: bool) {
     let cli_logger = CliLogger::new(log_level, include_source);
     let max_level = cli_logger.filter();
     let r = log::set_boxed_logger(Box::new(cli_logger));
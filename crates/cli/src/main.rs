@@ -1,19 +1,153 @@
+mod config;
 mod logger;
 
 use anyhow::{anyhow, bail, Error};
 use base::commands::start_server;
+use base::inspector_server::InspectorOption;
 use base::rt_worker::worker_pool::{SupervisorPolicy, WorkerPoolPolicy};
-use base::server::WorkerEntrypoints;
+use base::server::{ServerOptions, WorkerEntrypoints};
 use clap::builder::{FalseyValueParser, TypedValueParser};
-use clap::{arg, crate_version, value_parser, ArgAction, Command};
+use clap::parser::ValueSource;
+use clap::{arg, crate_version, value_parser, Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use deno_core::serde_json;
 use deno_core::url::Url;
-use sb_graph::emitter::EmitterFactory;
+use sb_core::cache::CacheSetting;
+use sb_graph::check::check_file;
+use sb_graph::emitter::{EmitterFactory, LockfileOpts};
+use sb_graph::extract::extract_from_file;
 use sb_graph::import_map::load_import_map;
-use sb_graph::{extract_from_file, generate_binary_eszip};
+use sb_graph::inspect::inspect_eszip;
+use sb_graph::package_json::load_package_json;
+use sb_graph::signature::{sign_eszip, EszipSigningKey, EszipTrustedKeys};
+use sb_graph::vendor::vendor;
+use sb_graph::{
+    add_static_files, eszip_source_hash, generate_binary_eszip,
+    generate_binary_eszip_for_functions, EszipPayloadKind,
+};
+use sb_module_loader::metadata::{Metadata, MetadataLimits, SerializablePackageJsonDeps};
+use sb_npm::ResolvedNpmRc;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn inspector_args() -> [Arg; 2] {
+    [
+        arg!(--"inspect" [HOST_PORT] "Activate the inspector for the main and user workers on host:port (default: 127.0.0.1:9229)")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("127.0.0.1:9229")
+            .value_parser(value_parser!(SocketAddr)),
+        arg!(--"inspect-brk" [HOST_PORT] "Like --inspect, but wait for a debugger to attach and break on the first statement of every worker")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("127.0.0.1:9229")
+            .value_parser(value_parser!(SocketAddr))
+            .conflicts_with("inspect"),
+    ]
+}
+
+fn get_inspector_option(sub_matches: &ArgMatches) -> Option<InspectorOption> {
+    if let Some(addr) = sub_matches.get_one::<SocketAddr>("inspect-brk") {
+        return Some(InspectorOption::WithBreak(*addr));
+    }
+
+    sub_matches
+        .get_one::<SocketAddr>("inspect")
+        .map(|addr| InspectorOption::Inspect(*addr))
+}
+
+fn cached_only_arg() -> Arg {
+    arg!(--"cached-only" "Fail instead of downloading remote modules and npm packages that aren't cached")
+        .action(ArgAction::SetTrue)
+}
+
+fn lockfile_args() -> [Arg; 3] {
+    [
+        arg!(--"lock" <Path> "Check the integrity of remote modules and npm packages against a lockfile"),
+        arg!(--"lock-write" "Write the lockfile from scratch instead of checking it")
+            .requires("lock")
+            .action(ArgAction::SetTrue),
+        arg!(--"frozen" "Error out if the lockfile is out of date instead of updating it")
+            .requires("lock")
+            .conflicts_with("lock-write")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+fn metadata_args() -> [Arg; 7] {
+    [
+        arg!(--"env" <KEY_VALUE> "Default environment variable of the worker, as KEY=VALUE (can be repeated)")
+            .action(ArgAction::Append),
+        arg!(--"memory-limit-mb" <MB> "Default memory limit of a user worker running the eszip")
+            .value_parser(value_parser!(u64)),
+        arg!(--"worker-timeout-ms" <MILLISECONDS> "Default wall clock limit of a user worker running the eszip")
+            .value_parser(value_parser!(u64)),
+        arg!(--"cpu-time-soft-limit-ms" <MILLISECONDS> "Default CPU time soft limit of a user worker running the eszip")
+            .value_parser(value_parser!(u64)),
+        arg!(--"cpu-time-hard-limit-ms" <MILLISECONDS> "Default CPU time hard limit of a user worker running the eszip")
+            .value_parser(value_parser!(u64)),
+        arg!(--"ca-store" <STORE> "Certificate store used to fetch npm packages (mozilla or system, can be repeated)")
+            .action(ArgAction::Append)
+            .value_parser(["mozilla", "system"]),
+        arg!(--"cert" <Path> "Load a PEM certificate file used to fetch npm packages"),
+    ]
+}
+
+/// Builds the metadata `bundle` embeds in the eszip, except for the
+/// entrypoints and the source hash which depend on the eszip itself.
+fn get_metadata(sub_matches: &ArgMatches) -> Result<Metadata, Error> {
+    let mut env_vars = HashMap::new();
+    for env in sub_matches.get_many::<String>("env").into_iter().flatten() {
+        let Some((key, value)) = env.split_once('=') else {
+            bail!("invalid environment variable, expected KEY=VALUE ({})", env);
+        };
+
+        env_vars.insert(key.to_string(), value.to_string());
+    }
+
+    let limits = MetadataLimits {
+        memory_limit_mb: sub_matches.get_one::<u64>("memory-limit-mb").copied(),
+        worker_timeout_ms: sub_matches.get_one::<u64>("worker-timeout-ms").copied(),
+        cpu_time_soft_limit_ms: sub_matches
+            .get_one::<u64>("cpu-time-soft-limit-ms")
+            .copied(),
+        cpu_time_hard_limit_ms: sub_matches
+            .get_one::<u64>("cpu-time-hard-limit-ms")
+            .copied(),
+    };
+    let has_limits = limits.memory_limit_mb.is_some()
+        || limits.worker_timeout_ms.is_some()
+        || limits.cpu_time_soft_limit_ms.is_some()
+        || limits.cpu_time_hard_limit_ms.is_some();
+
+    let ca_data = match sub_matches.get_one::<String>("cert") {
+        Some(path) => Some(
+            std::fs::read(path)
+                .map_err(|e| anyhow!("failed to read certificate file {} ({})", path, e))?,
+        ),
+        None => None,
+    };
+
+    Ok(Metadata {
+        ca_stores: sub_matches
+            .get_many::<String>("ca-store")
+            .map(|stores| stores.cloned().collect()),
+        ca_data,
+        bundler_version: Some(format!("edge-runtime {}", crate_version!())),
+        build_timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|it| it.as_millis() as u64),
+        env_vars: (!env_vars.is_empty()).then_some(env_vars),
+        limits: has_limits.then_some(limits),
+        ..Default::default()
+    })
+}
 
 fn cli() -> Command {
     Command::new("edge-runtime")
@@ -46,6 +180,7 @@ fn cli() -> Command {
         .subcommand(
             Command::new("start")
                 .about("Start the server")
+                .arg(arg!(--"config" <Path> "Path to a TOML or JSON config file. Flags override the values in the file"))
                 .arg(arg!(-i --ip <HOST> "Host IP address to listen on").default_value("0.0.0.0"))
                 .arg(
                     arg!(-p --port <PORT> "Port to listen on")
@@ -54,10 +189,11 @@ fn cli() -> Command {
                 )
                 .arg(arg!(--"main-service" <DIR> "Path to main service directory or eszip").default_value("examples/main"))
                 .arg(arg!(--"disable-module-cache" "Disable using module cache").default_value("false").value_parser(FalseyValueParser::new()))
+                .arg(cached_only_arg())
                 .arg(arg!(--"import-map" <Path> "Path to import map file"))
                 .arg(arg!(--"event-worker" <Path> "Path to event worker directory"))
-                .arg(arg!(--"main-entrypoint" <Path> "Path to entrypoint in main service (only for eszips)"))
-                .arg(arg!(--"events-entrypoint" <Path> "Path to entrypoint in events worker (only for eszips)"))
+                .arg(arg!(--"main-entrypoint" <Path> "Path to entrypoint in main service (only for eszips, defaults to the one it was bundled from)"))
+                .arg(arg!(--"events-entrypoint" <Path> "Path to entrypoint in events worker (only for eszips, defaults to the one it was bundled from)"))
                 .arg(
                     arg!(--"policy" <POLICY> "Policy to enforce in the worker pool")
                         .default_value("per_worker")
@@ -76,18 +212,68 @@ fn cli() -> Command {
                     arg!(--"request-wait-timeout" <MILLISECONDS> "Maximum time in milliseconds that can wait to establish a connection with a worker")
                     .value_parser(value_parser!(u64))
                 )
+                .arg(arg!(--"watch" "Restart the workers when their local files change").action(ArgAction::SetTrue))
+                .arg(
+                    arg!(--"trusted-key" <Path> "Only run eszips signed with this Ed25519 public key (can be repeated)")
+                        .action(ArgAction::Append)
+                )
+                .args(inspector_args())
+                .args(lockfile_args())
         )
         .subcommand(
             Command::new("bundle")
                 .about("Creates an 'eszip' file that can be executed by the EdgeRuntime. Such file contains all the modules in contained in a single binary.")
                 .arg(arg!(--"output" <DIR> "Path to output eszip file").default_value("bin.eszip"))
-                .arg(arg!(--"entrypoint" <Path> "Path to entrypoint to bundle as an eszip").required(true))
+                .arg(arg!(--"entrypoint" <Path> "Path to entrypoint to bundle as an eszip").required_unless_present("function"))
+                .arg(
+                    arg!(--"function" <NAME_PATH> "Bundle the function at PATH as NAME, as NAME=PATH (can be repeated)")
+                        .action(ArgAction::Append)
+                        .conflicts_with("entrypoint")
+                )
                 .arg(arg!(--"import-map" <Path> "Path to import map file"))
+                .arg(arg!(--"package-json" <Path> "Path to the package.json bare specifiers resolve through (defaults to the one next to the entrypoint)"))
+                .arg(arg!(--"check" "Type-check the entrypoints before bundling").action(ArgAction::SetTrue))
+                .arg(arg!(--"sign-key" <Path> "Sign the eszip with this Ed25519 private key (PKCS#8)"))
+                .arg(
+                    arg!(--"static" <GLOB> "Bundle the files matching GLOB, relative to the current directory, so the function can read them (can be repeated)")
+                        .action(ArgAction::Append)
+                )
+                .arg(cached_only_arg())
+                .arg(
+                    arg!(--"node-modules-dir" [DIR] "Install npm packages in a node_modules directory and bundle it instead of the global npm cache")
+                        .num_args(0..=1)
+                        .default_missing_value("node_modules")
+                )
+                .args(metadata_args())
+                .args(lockfile_args())
+        ).subcommand(
+            Command::new("vendor")
+                .about("Downloads the remote modules and npm packages of a function, so it can be bundled and served without network access")
+                .arg(arg!(--"entrypoint" <Path> "Path to entrypoint to vendor the dependencies of (can be repeated)").required(true).action(ArgAction::Append))
+                .arg(arg!(--"import-map" <Path> "Path to import map file"))
+                .arg(arg!(--"output" <DIR> "Path to the vendor directory").default_value("vendor"))
+        ).subcommand(
+            Command::new("check")
+                .about("Type-checks a function without bundling or running it")
+                .arg(arg!(--"entrypoint" <Path> "Path to entrypoint to type-check").required(true))
+                .arg(arg!(--"import-map" <Path> "Path to import map file"))
+                .arg(cached_only_arg())
+                .args(lockfile_args())
         ).subcommand(
         Command::new("unbundle")
             .about("Unbundles an .eszip file into the specified directory")
             .arg(arg!(--"output" <DIR> "Path to extract the ESZIP content").default_value("./"))
             .arg(arg!(--"eszip" <DIR> "Path of eszip to extract").required(true))
+    ).subcommand(
+        Command::new("eszip")
+            .about("Tools for working with .eszip files")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("inspect")
+                    .about("Lists the modules, npm packages, import map and sizes of an .eszip file")
+                    .arg(arg!(--"eszip" <Path> "Path of eszip to inspect").required(true))
+                    .arg(arg!(--"json" "Print the result as JSON").action(ArgAction::SetTrue))
+            )
     )
 }
 
@@ -101,6 +287,93 @@ fn cli() -> Command {
 //    }
 //}
 
+fn get_entrypoint_path(entry_point_path: &str) -> Result<PathBuf, Error> {
+    let path = PathBuf::from(entry_point_path);
+    if !path.exists() {
+        bail!("entrypoint path does not exist ({})", path.display());
+    }
+
+    Ok(path)
+}
+
+/// Returns the value of `id` if it was given on the command line, then `value`
+/// (usually coming from the config file) and the default of the flag last.
+fn get_arg_or<T: Clone + Send + Sync + 'static>(
+    sub_matches: &ArgMatches,
+    id: &str,
+    value: Option<T>,
+) -> Option<T> {
+    if let Some(ValueSource::CommandLine) = sub_matches.value_source(id) {
+        return sub_matches.get_one::<T>(id).cloned();
+    }
+
+    value.or_else(|| sub_matches.get_one::<T>(id).cloned())
+}
+
+fn get_lockfile_opts(sub_matches: &ArgMatches) -> Option<LockfileOpts> {
+    sub_matches
+        .get_one::<String>("lock")
+        .map(|path| LockfileOpts {
+            path: PathBuf::from(path),
+            overwrite: sub_matches.get_flag("lock-write"),
+            frozen: sub_matches.get_flag("frozen"),
+        })
+}
+
+/// Applies `--lock` and `--cached-only`, so that `check` resolves the same
+/// graph as `bundle`.
+fn set_module_fetching_opts(
+    emitter_factory: &mut EmitterFactory,
+    sub_matches: &ArgMatches,
+) -> Result<(), Error> {
+    emitter_factory.set_lockfile(get_lockfile_opts(sub_matches))?;
+    if sub_matches.get_flag("cached-only") {
+        emitter_factory.set_file_fetcher_cache_strategy(CacheSetting::Only);
+    }
+
+    Ok(())
+}
+
+fn create_emitter_factory(
+    import_map_path: Option<String>,
+) -> Result<(EmitterFactory, Option<String>), Error> {
+    let mut emitter_factory = EmitterFactory::new();
+    let maybe_import_map = load_import_map(import_map_path.clone())
+        .map_err(|e| anyhow!("import map path is invalid ({})", e))?;
+    let mut maybe_import_map_url = None;
+    if maybe_import_map.is_some() {
+        let abs_import_map_path =
+            std::env::current_dir().map(|p| p.join(import_map_path.unwrap()))?;
+        maybe_import_map_url = Some(
+            Url::from_file_path(abs_import_map_path)
+                .map_err(|_| anyhow!("failed get import map url"))?
+                .to_string(),
+        );
+    }
+    emitter_factory.set_import_map(maybe_import_map);
+    emitter_factory.set_npmrc(ResolvedNpmRc::discover(
+        std::env::current_dir().ok().as_deref(),
+    )?);
+
+    Ok((emitter_factory, maybe_import_map_url))
+}
+
+async fn type_check(path: PathBuf, emitter_factory: Arc<EmitterFactory>) -> Result<(), Error> {
+    let diagnostics = check_file(path.clone(), emitter_factory).await?;
+
+    if !diagnostics.is_empty() {
+        eprintln!("{}", diagnostics);
+    }
+
+    if diagnostics.has_errors() {
+        bail!("type check failed ({})", path.display());
+    }
+
+    eprintln!("Type check passed ({})", path.display());
+
+    Ok(())
+}
+
 fn main() -> Result<(), anyhow::Error> {
     let runtime = tokio::runtime::Builder::new_current_thread()
         .enable_all()
@@ -111,107 +384,226 @@ fn main() -> Result<(), anyhow::Error> {
     let local = tokio::task::LocalSet::new();
     let res: Result<(), Error> = local.block_on(&runtime, async {
         let matches = cli().get_matches();
+        let config = match matches.subcommand() {
+            Some(("start", sub_matches)) => match sub_matches.get_one::<String>("config") {
+                Some(path) => Config::load(Path::new(path))?,
+                None => Config::default(),
+            },
+            _ => Config::default(),
+        };
 
-        if !matches.get_flag("quiet") {
-            let verbose = matches.get_flag("verbose");
-            let include_source = matches.get_flag("log-source");
-            logger::init(verbose, include_source);
+        let maybe_log_level = if matches.get_flag("quiet") {
+            None
+        } else if matches.get_flag("verbose") {
+            Some(log::Level::Debug)
+        } else {
+            config
+                .logging
+                .level
+                .map_or(Some(log::Level::Info), |it| it.to_level())
+        };
+
+        if let Some(log_level) = maybe_log_level {
+            let include_source =
+                matches.get_flag("log-source") || config.logging.include_source.unwrap_or_default();
+            logger::init(log_level, include_source);
         }
 
         #[allow(clippy::single_match)]
         #[allow(clippy::arc_with_non_send_sync)]
         match matches.subcommand() {
             Some(("start", sub_matches)) => {
-                let ip = sub_matches.get_one::<String>("ip").cloned().unwrap();
-                let port = sub_matches.get_one::<u16>("port").copied().unwrap();
+                let user_worker_limits = config.user_worker_limits();
+                let import_map_path =
+                    get_arg_or(sub_matches, "import-map", config.modules.import_map.clone());
+                let no_module_cache = get_arg_or(
+                    sub_matches,
+                    "disable-module-cache",
+                    config.modules.disable_cache,
+                )
+                .unwrap();
+                let maybe_lockfile = get_lockfile_opts(sub_matches).or(config.lockfile_opts());
+                let scheduler_opts = config.scheduler_opts(
+                    import_map_path.clone(),
+                    no_module_cache,
+                    maybe_lockfile.clone(),
+                );
+                let Config {
+                    server,
+                    main,
+                    events,
+                    modules,
+                    pool,
+                    ..
+                } = config;
 
-                let main_service_path = sub_matches
-                    .get_one::<String>("main-service")
-                    .cloned()
-                    .unwrap();
-                let import_map_path = sub_matches.get_one::<String>("import-map").cloned();
-                let no_module_cache = sub_matches
-                    .get_one::<bool>("disable-module-cache")
-                    .cloned()
-                    .unwrap();
-                let event_service_manager_path =
-                    sub_matches.get_one::<String>("event-worker").cloned();
+                let ip = get_arg_or(sub_matches, "ip", server.ip).unwrap();
+                let port = get_arg_or(sub_matches, "port", server.port).unwrap();
+
+                let main_service_path =
+                    get_arg_or(sub_matches, "main-service", main.service_path).unwrap();
+                let cached_only =
+                    sub_matches.get_flag("cached-only") || modules.cached_only.unwrap_or_default();
+                let maybe_events_service_path =
+                    get_arg_or(sub_matches, "event-worker", events.service_path);
                 let maybe_main_entrypoint =
-                    sub_matches.get_one::<String>("main-entrypoint").cloned();
+                    get_arg_or(sub_matches, "main-entrypoint", main.entrypoint);
                 let maybe_events_entrypoint =
-                    sub_matches.get_one::<String>("events-entrypoint").cloned();
-                let maybe_supervisor_policy = sub_matches
-                    .get_one::<String>("policy")
+                    get_arg_or(sub_matches, "events-entrypoint", events.entrypoint);
+                let maybe_supervisor_policy = get_arg_or(sub_matches, "policy", pool.policy)
                     .map(|it| it.parse::<SupervisorPolicy>().unwrap());
                 let maybe_max_parallelism =
-                    sub_matches.get_one::<usize>("max-parallelism").cloned();
-                let maybe_request_wait_timeout =
-                    sub_matches.get_one::<u64>("request-wait-timeout").cloned();
+                    get_arg_or(sub_matches, "max-parallelism", pool.max_parallelism);
+                let maybe_request_wait_timeout = get_arg_or(
+                    sub_matches,
+                    "request-wait-timeout",
+                    pool.request_wait_timeout_ms,
+                );
+                let watch = sub_matches.get_flag("watch");
+                let maybe_inspector_option = get_inspector_option(sub_matches);
+                let trusted_key_paths = match sub_matches.get_many::<String>("trusted-key") {
+                    Some(paths) => paths.cloned().collect(),
+                    None => modules.trusted_keys,
+                };
+                let maybe_trusted_keys = if trusted_key_paths.is_empty() {
+                    None
+                } else {
+                    Some(EszipTrustedKeys::from_files(&trusted_key_paths)?)
+                };
+
+                let mut user_worker_policy = WorkerPoolPolicy::new(
+                    maybe_supervisor_policy,
+                    if let Some(true) = maybe_supervisor_policy
+                        .as_ref()
+                        .map(SupervisorPolicy::is_oneshot)
+                    {
+                        Some(1)
+                    } else {
+                        maybe_max_parallelism
+                    },
+                    maybe_request_wait_timeout,
+                );
+                user_worker_policy.set_user_worker_limits(user_worker_limits);
 
-                start_server(
-                    ip.as_str(),
+                start_server(ServerOptions {
+                    ip,
                     port,
                     main_service_path,
-                    event_service_manager_path,
-                    Some(WorkerPoolPolicy::new(
-                        maybe_supervisor_policy,
-                        if let Some(true) = maybe_supervisor_policy
-                            .as_ref()
-                            .map(SupervisorPolicy::is_oneshot)
-                        {
-                            Some(1)
-                        } else {
-                            maybe_max_parallelism
-                        },
-                        maybe_request_wait_timeout,
-                    )),
+                    maybe_events_service_path,
+                    maybe_user_worker_policy: Some(user_worker_policy),
+                    maybe_scheduler_opts: Some(scheduler_opts),
                     import_map_path,
                     no_module_cache,
-                    cfg!(not(target_os = "linux")),
-                    None,
-                    WorkerEntrypoints {
+                    cached_only,
+                    maybe_lockfile,
+                    maybe_trusted_keys,
+                    watch,
+                    maybe_inspector_option,
+                    no_signal_handler: cfg!(not(target_os = "linux")),
+                    callback_tx: None,
+                    entrypoints: WorkerEntrypoints {
                         main: maybe_main_entrypoint,
                         events: maybe_events_entrypoint,
                     },
-                )
+                })
                 .await?;
             }
             Some(("bundle", sub_matches)) => {
                 let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
                 let import_map_path = sub_matches.get_one::<String>("import-map").cloned();
 
-                let entry_point_path = sub_matches
+                let functions = sub_matches
+                    .get_many::<String>("function")
+                    .into_iter()
+                    .flatten()
+                    .map(|function| {
+                        let Some((name, path)) = function.split_once('=') else {
+                            bail!("invalid function, expected NAME=PATH ({})", function);
+                        };
+                        if name.is_empty() {
+                            bail!("function name must not be empty ({})", function);
+                        }
+
+                        Ok((name.to_string(), get_entrypoint_path(path)?))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                let (mut emitter_factory, maybe_import_map_url) =
+                    create_emitter_factory(import_map_path)?;
+                set_module_fetching_opts(&mut emitter_factory, sub_matches)?;
+                if let Some(node_modules_dir) = sub_matches.get_one::<String>("node-modules-dir") {
+                    emitter_factory.set_node_modules_dir(PathBuf::from(node_modules_dir))?;
+                }
+                let package_json_dir = sub_matches
                     .get_one::<String>("entrypoint")
+                    .and_then(|it| Path::new(it).parent())
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                if let Some(package_json) = load_package_json(
+                    sub_matches.get_one::<String>("package-json").cloned(),
+                    &package_json_dir,
+                )? {
+                    emitter_factory.init_package_json_deps(&package_json);
+                }
+                let emitter_factory = Arc::new(emitter_factory);
+                let mut metadata = get_metadata(sub_matches)?;
+                metadata.package_json_deps = emitter_factory
+                    .package_json_deps()
                     .cloned()
-                    .unwrap();
+                    .map(SerializablePackageJsonDeps::from_deps);
 
-                let path = PathBuf::from(entry_point_path.as_str());
-                if !path.exists() {
-                    bail!("entrypoint path does not exist ({})", path.display());
-                }
+                let mut eszip = if functions.is_empty() {
+                    let entry_point_path = sub_matches
+                        .get_one::<String>("entrypoint")
+                        .cloned()
+                        .unwrap();
 
-                let mut emitter_factory = EmitterFactory::new();
-                let maybe_import_map = load_import_map(import_map_path.clone())
-                    .map_err(|e| anyhow!("import map path is invalid ({})", e))?;
-                let mut maybe_import_map_url = None;
-                if maybe_import_map.is_some() {
-                    let abs_import_map_path =
-                        std::env::current_dir().map(|p| p.join(import_map_path.unwrap()))?;
-                    maybe_import_map_url = Some(
-                        Url::from_file_path(abs_import_map_path)
-                            .map_err(|_| anyhow!("failed get import map url"))?
-                            .to_string(),
-                    );
+                    let path = get_entrypoint_path(entry_point_path.as_str())?;
+
+                    if sub_matches.get_flag("check") {
+                        type_check(path.clone(), emitter_factory.clone()).await?;
+                    }
+
+                    let path = path.canonicalize().unwrap();
+                    let entrypoint = Url::from_file_path(&path)
+                        .map_err(|_| anyhow!("failed get entrypoint url ({})", path.display()))?;
+
+                    metadata.entrypoint = Some(entrypoint.to_string());
+                    generate_binary_eszip(path, emitter_factory, None, maybe_import_map_url).await?
+                } else {
+                    if sub_matches.get_flag("check") {
+                        for (_, path) in &functions {
+                            type_check(path.clone(), emitter_factory.clone()).await?;
+                        }
+                    }
+
+                    let (eszip, entrypoints) = generate_binary_eszip_for_functions(
+                        functions,
+                        emitter_factory,
+                        maybe_import_map_url,
+                    )
+                    .await?;
+
+                    metadata.functions = Some(entrypoints);
+                    eszip
+                };
+
+                let static_patterns = sub_matches
+                    .get_many::<String>("static")
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                add_static_files(&mut eszip, &std::env::current_dir()?, &static_patterns)?;
+
+                metadata.source_hash = Some(eszip_source_hash(&mut eszip).await);
+                metadata.add_to_eszip(&mut eszip)?;
+
+                if let Some(key_path) = sub_matches.get_one::<String>("sign-key") {
+                    let key = EszipSigningKey::from_file(Path::new(key_path))?;
+                    sign_eszip(&mut eszip, &key).await?;
                 }
-                emitter_factory.set_import_map(maybe_import_map.clone());
 
-                let eszip = generate_binary_eszip(
-                    path.canonicalize().unwrap(),
-                    Arc::new(emitter_factory),
-                    None,
-                    maybe_import_map_url,
-                )
-                .await?;
                 let bin = eszip.into_bytes();
 
                 if output_path == "-" {
@@ -224,6 +616,56 @@ fn main() -> Result<(), anyhow::Error> {
                     file.write_all(&bin)?
                 }
             }
+            Some(("check", sub_matches)) => {
+                let import_map_path = sub_matches.get_one::<String>("import-map").cloned();
+                let entry_point_path = sub_matches
+                    .get_one::<String>("entrypoint")
+                    .cloned()
+                    .unwrap();
+
+                let path = get_entrypoint_path(entry_point_path.as_str())?;
+                let (mut emitter_factory, _) = create_emitter_factory(import_map_path)?;
+                set_module_fetching_opts(&mut emitter_factory, sub_matches)?;
+
+                type_check(path, Arc::new(emitter_factory)).await?;
+            }
+            Some(("vendor", sub_matches)) => {
+                let import_map_path = sub_matches.get_one::<String>("import-map").cloned();
+                let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
+                let entrypoints = sub_matches
+                    .get_many::<String>("entrypoint")
+                    .into_iter()
+                    .flatten()
+                    .map(|it| get_entrypoint_path(it))
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                let (emitter_factory, _) = create_emitter_factory(import_map_path)?;
+                let vendored =
+                    vendor(entrypoints, emitter_factory, PathBuf::from(output_path)).await?;
+
+                println!(
+                    "Vendored {} remote modules inside path {}",
+                    vendored.remote_modules,
+                    vendored.import_map.display()
+                );
+
+                let mut bundle_cmd = format!(
+                    "edge-runtime bundle --cached-only --import-map {}",
+                    vendored.import_map.display()
+                );
+                if let Some(lockfile) = &vendored.lockfile {
+                    bundle_cmd.push_str(&format!(" --lock {} --frozen", lockfile.display()));
+                }
+                if let Some(npm_cache) = &vendored.npm_cache {
+                    bundle_cmd = format!(
+                        "DENO_DIR={} {}",
+                        npm_cache.parent().unwrap_or(npm_cache).display(),
+                        bundle_cmd
+                    );
+                }
+
+                println!("To bundle offline, run: {} --entrypoint <PATH>", bundle_cmd);
+            }
             Some(("unbundle", sub_matches)) => {
                 let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
                 let eszip_path = sub_matches.get_one::<String>("eszip").cloned().unwrap();
@@ -231,12 +673,51 @@ fn main() -> Result<(), anyhow::Error> {
                 let output_path = PathBuf::from(output_path.as_str());
                 let eszip_path = PathBuf::from(eszip_path.as_str());
 
-                extract_from_file(eszip_path, output_path.clone()).await;
+                let extracted = extract_from_file(eszip_path, output_path.clone()).await?;
 
                 println!(
                     "Eszip extracted successfully inside path {}",
                     output_path.to_str().unwrap()
                 );
+
+                let mut bundle_cmd = String::from("edge-runtime bundle");
+                if extracted.functions.is_empty() {
+                    bundle_cmd
+                        .push_str(&format!(" --entrypoint {}", extracted.entrypoint.display()));
+                }
+                for (name, entrypoint) in &extracted.functions {
+                    bundle_cmd.push_str(&format!(" --function {}={}", name, entrypoint.display()));
+                }
+                if let Some(import_map) = &extracted.import_map {
+                    bundle_cmd.push_str(&format!(" --import-map {}", import_map.display()));
+                }
+                if let Some(lockfile) = &extracted.lockfile {
+                    bundle_cmd.push_str(&format!(" --lock {} --frozen", lockfile.display()));
+                }
+                if let Some(node_modules_dir) = &extracted.node_modules_dir {
+                    bundle_cmd.push_str(&format!(
+                        " --node-modules-dir {}",
+                        node_modules_dir.display()
+                    ));
+                }
+
+                println!("To bundle it again, run: {}", bundle_cmd);
+            }
+            Some(("eszip", sub_matches)) => {
+                if let Some(("inspect", sub_matches)) = sub_matches.subcommand() {
+                    let eszip_path = sub_matches.get_one::<String>("eszip").cloned().unwrap();
+                    let as_json = sub_matches.get_flag("json");
+
+                    let bytes = std::fs::read(&eszip_path)
+                        .map_err(|err| anyhow!("failed to read {}: {}", eszip_path, err))?;
+                    let info = inspect_eszip(EszipPayloadKind::VecKind(bytes)).await?;
+
+                    if as_json {
+                        println!("{}", serde_json::to_string_pretty(&info)?);
+                    } else {
+                        print!("{}", info);
+                    }
+                }
             }
             _ => {
                 // unrecognized command
@@ -55,6 +55,25 @@ pub enum LogLevel {
     Error,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduledRunOutcome {
+    Completed,
+    Failed,
+    /// The previous run was still going.
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScheduledRunEvent {
+    pub name: String,
+    /// When the run was due, in milliseconds since the Unix epoch.
+    pub scheduled_time: u64,
+    pub outcome: ScheduledRunOutcome,
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum WorkerEvents {
     Boot(BootEvent),
@@ -63,6 +82,7 @@ pub enum WorkerEvents {
     Shutdown(ShutdownEvent),
     EventLoopCompleted(PseudoEvent),
     Log(LogEvent),
+    ScheduledRun(ScheduledRunEvent),
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -23,6 +23,7 @@ use sb_core::util::fs::{canonicalize_path, hard_link_dir_recursive};
 use sb_core::util::http_util::HttpClient;
 use sb_core::util::path::root_url_to_safe_local_dirname;
 
+use super::npmrc::ResolvedNpmRc;
 use super::tarball::verify_and_extract_tarball;
 
 const NPM_PACKAGE_SYNC_LOCK_FILENAME: &str = ".deno_sync_lock";
@@ -249,6 +250,7 @@ pub struct NpmCache {
     cache_setting: CacheSetting,
     fs: Arc<dyn deno_fs::FileSystem>,
     http_client: Arc<HttpClient>,
+    npmrc: Arc<ResolvedNpmRc>,
     /// ensures a package is only downloaded once per run
     previously_reloaded_packages: Mutex<HashSet<PackageNv>>,
 }
@@ -259,12 +261,14 @@ impl NpmCache {
         cache_setting: CacheSetting,
         fs: Arc<dyn deno_fs::FileSystem>,
         http_client: Arc<HttpClient>,
+        npmrc: Arc<ResolvedNpmRc>,
     ) -> Self {
         Self {
             cache_dir,
             cache_setting,
             fs,
             http_client,
+            npmrc,
             previously_reloaded_packages: Default::default(),
         }
     }
@@ -335,9 +339,12 @@ impl NpmCache {
             bail!("Tarball URL was empty.");
         }
 
+        let tarball_url = Url::parse(&dist.tarball)
+            .with_context(|| format!("Invalid tarball URL: {}", dist.tarball))?;
+        let maybe_auth_header = self.npmrc.get_auth_header(&tarball_url)?;
         let maybe_bytes = self
             .http_client
-            .download_with_progress(&dist.tarball)
+            .download_with_progress(tarball_url, maybe_auth_header)
             .await?;
         match maybe_bytes {
             Some(bytes) => verify_and_extract_tarball(package, &bytes, dist, &package_folder),
@@ -2,6 +2,7 @@
 
 pub mod cache;
 pub mod installer;
+pub mod npmrc;
 pub mod package_json;
 pub mod registry;
 pub mod resolution;
@@ -11,6 +12,7 @@ pub mod tarball;
 pub use cache::NpmCache;
 pub use cache::NpmCacheDir;
 pub use installer::PackageJsonDepsInstaller;
+pub use npmrc::ResolvedNpmRc;
 pub use registry::CliNpmRegistryApi;
 pub use resolution::NpmResolution;
 pub use resolvers::create_npm_fs_resolver;
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use deno_core::anyhow::bail;
+use deno_core::anyhow::Context;
+use deno_core::error::AnyError;
+use deno_core::url::Url;
+
+use super::registry::CliNpmRegistryApi;
+
+const NPMRC_FILE: &str = ".npmrc";
+
+/// Credentials of a registry, from the `//host/path/:<key>=` entries of an
+/// `.npmrc`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct RegistryConfig {
+    auth_token: Option<String>,
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// The registries and credentials of an `.npmrc`.
+///
+/// Only where packages are downloaded from depends on it: they are still
+/// cached under the folder of the default registry, so the cache layout (and
+/// the one of bundled eszips) doesn't change with the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedNpmRc {
+    default_registry: Url,
+    scope_registries: HashMap<String, Url>,
+    registry_configs: HashMap<String, RegistryConfig>,
+}
+
+impl Default for ResolvedNpmRc {
+    fn default() -> Self {
+        Self {
+            default_registry: CliNpmRegistryApi::default_url().clone(),
+            scope_registries: Default::default(),
+            registry_configs: Default::default(),
+        }
+    }
+}
+
+impl ResolvedNpmRc {
+    /// Parses the content of an `.npmrc`. `${VAR}` in values is replaced with
+    /// the result of `get_env`.
+    pub fn parse(text: &str, get_env: impl Fn(&str) -> Option<String>) -> Result<Self, AnyError> {
+        let mut npmrc = Self::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            let value = expand_env_vars(unquote(value.trim()), &get_env)
+                .with_context(|| format!("invalid value for {key} in .npmrc"))?;
+
+            if key == "registry" {
+                npmrc.default_registry = parse_registry_url(&value)?;
+            } else if let Some(scope) = key.strip_suffix(":registry") {
+                if !scope.starts_with('@') {
+                    continue;
+                }
+                npmrc
+                    .scope_registries
+                    .insert(scope.to_string(), parse_registry_url(&value)?);
+            } else if let Some(rest) = key.strip_prefix("//") {
+                let Some((registry, config_key)) = rest.rsplit_once(':') else {
+                    continue;
+                };
+                let config = npmrc
+                    .registry_configs
+                    .entry(normalize_registry_key(registry))
+                    .or_default();
+
+                match config_key {
+                    "_authToken" => config.auth_token = Some(value),
+                    "_auth" => config.auth = Some(value),
+                    "username" => config.username = Some(value),
+                    "_password" => config.password = Some(value),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(npmrc)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, AnyError> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        Self::parse(&text, |name| std::env::var(name).ok())
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Loads the `.npmrc` of `dir`, falling back to the one in the home
+    /// directory, then to the default registry without credentials.
+    pub fn discover(dir: Option<&Path>) -> Result<Self, AnyError> {
+        let candidates = dir
+            .map(|dir| dir.join(NPMRC_FILE))
+            .into_iter()
+            .chain(home_dir().map(|home| home.join(NPMRC_FILE)));
+
+        for path in candidates {
+            if path.is_file() {
+                return Self::from_file(&path);
+            }
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn default_registry_url(&self) -> &Url {
+        &self.default_registry
+    }
+
+    /// The registry the package is downloaded from.
+    pub fn get_registry_url(&self, package_name: &str) -> &Url {
+        package_name
+            .split_once('/')
+            .filter(|(scope, _)| scope.starts_with('@'))
+            .and_then(|(scope, _)| self.scope_registries.get(scope))
+            .unwrap_or(&self.default_registry)
+    }
+
+    /// The `Authorization` header to send with a request to `url`, from the
+    /// credentials of the most specific registry `url` is under.
+    pub fn get_auth_header(&self, url: &Url) -> Result<Option<String>, AnyError> {
+        let Some(host) = url.host_str() else {
+            return Ok(None);
+        };
+        let url_key = match url.port() {
+            Some(port) => format!("{}:{}{}", host, port, url.path()),
+            None => format!("{}{}", host, url.path()),
+        };
+        let Some(config) = self
+            .registry_configs
+            .iter()
+            .filter(|(key, _)| url_key.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, config)| config)
+        else {
+            return Ok(None);
+        };
+
+        if let Some(token) = &config.auth_token {
+            return Ok(Some(format!("Bearer {}", token)));
+        }
+        if let Some(auth) = &config.auth {
+            return Ok(Some(format!("Basic {}", auth)));
+        }
+
+        match (&config.username, &config.password) {
+            (Some(username), Some(password)) => {
+                let password = base64::decode(password)
+                    .ok()
+                    .and_then(|it| String::from_utf8(it).ok())
+                    .with_context(|| format!("_password of {} must be base64 encoded", url_key))?;
+
+                Ok(Some(format!(
+                    "Basic {}",
+                    base64::encode(format!("{}:{}", username, password))
+                )))
+            }
+            (Some(_), None) | (None, Some(_)) => {
+                bail!(
+                    "both username and _password must be set for {} in .npmrc",
+                    url_key
+                )
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|it| it.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|it| it.strip_suffix('\''))
+        })
+        .unwrap_or(value)
+}
+
+fn expand_env_vars(
+    value: &str,
+    get_env: &impl Fn(&str) -> Option<String>,
+) -> Result<String, AnyError> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + end];
+        let Some(env_value) = get_env(name) else {
+            bail!("environment variable {} is not set", name);
+        };
+
+        result.push_str(&rest[..start]);
+        result.push_str(&env_value);
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn parse_registry_url(value: &str) -> Result<Url, AnyError> {
+    // ensure there is a trailing slash for the directory
+    let url = format!("{}/", value.trim_end_matches('/'));
+    Url::parse(&url).with_context(|| format!("invalid registry url ({})", value))
+}
+
+fn normalize_registry_key(registry: &str) -> String {
+    format!("{}/", registry.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResolvedNpmRc;
+    use deno_core::url::Url;
+
+    fn parse(text: &str) -> ResolvedNpmRc {
+        ResolvedNpmRc::parse(text, |name| match name {
+            "NPM_TOKEN" => Some("secret".to_string()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scoped_registry() {
+        let npmrc = parse(
+            r#"
+            # comment
+            @internal:registry=https://npm.example.com/packages
+            //npm.example.com/packages/:_authToken=${NPM_TOKEN}
+            "#,
+        );
+
+        assert_eq!(
+            npmrc.get_registry_url("@internal/utils").as_str(),
+            "https://npm.example.com/packages/"
+        );
+        assert_eq!(
+            npmrc.get_registry_url("@other/utils"),
+            npmrc.default_registry_url()
+        );
+        assert_eq!(
+            npmrc.get_registry_url("internal"),
+            npmrc.default_registry_url()
+        );
+
+        let url = Url::parse("https://npm.example.com/packages/@internal%2futils").unwrap();
+        assert_eq!(
+            npmrc.get_auth_header(&url).unwrap().as_deref(),
+            Some("Bearer secret")
+        );
+
+        let url = Url::parse("https://registry.npmjs.org/is-even").unwrap();
+        assert_eq!(npmrc.get_auth_header(&url).unwrap(), None);
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        // `_password` is base64 encoded, as `npm login` writes it
+        let npmrc = parse(
+            r#"
+            registry=https://npm.example.com/
+            //npm.example.com/:username=user
+            //npm.example.com/:_password="cGFzcw=="
+            "#,
+        );
+        let url = Url::parse("https://npm.example.com/is-even/-/is-even-1.0.0.tgz").unwrap();
+
+        assert_eq!(
+            npmrc.get_registry_url("is-even").as_str(),
+            "https://npm.example.com/"
+        );
+        assert_eq!(
+            npmrc.get_auth_header(&url).unwrap().as_deref(),
+            Some("Basic dXNlcjpwYXNz")
+        );
+    }
+
+    #[test]
+    fn test_missing_env_var() {
+        let result = ResolvedNpmRc::parse("//npm.example.com/:_authToken=${MISSING}", |_| None);
+
+        assert!(result.is_err());
+    }
+}
@@ -30,6 +30,7 @@ use sb_core::util::http_util::HttpClient;
 use sb_core::util::sync::AtomicFlag;
 
 use super::cache::NpmCache;
+use super::npmrc::ResolvedNpmRc;
 
 static NPM_REGISTRY_DEFAULT_URL: Lazy<Url> = Lazy::new(|| {
     let env_var_name = "NPM_CONFIG_REGISTRY";
@@ -57,10 +58,16 @@ impl CliNpmRegistryApi {
         &NPM_REGISTRY_DEFAULT_URL
     }
 
-    pub fn new(base_url: Url, cache: Arc<NpmCache>, http_client: Arc<HttpClient>) -> Self {
+    pub fn new(
+        base_url: Url,
+        cache: Arc<NpmCache>,
+        http_client: Arc<HttpClient>,
+        npmrc: Arc<ResolvedNpmRc>,
+    ) -> Self {
         Self(Some(Arc::new(CliNpmRegistryApiInner {
             base_url,
             cache,
+            npmrc,
             force_reload_flag: Default::default(),
             mem_cache: Default::default(),
             previously_reloaded_packages: Default::default(),
@@ -137,8 +144,10 @@ enum CacheItem {
 
 #[derive(Debug)]
 struct CliNpmRegistryApiInner {
+    /// Where package information is cached, whichever registry it comes from.
     base_url: Url,
     cache: Arc<NpmCache>,
+    npmrc: Arc<ResolvedNpmRc>,
     force_reload_flag: AtomicFlag,
     mem_cache: Mutex<HashMap<String, CacheItem>>,
     previously_reloaded_packages: Mutex<HashSet<String>>,
@@ -300,8 +309,12 @@ impl CliNpmRegistryApiInner {
         }
 
         let package_url = self.get_package_url(name);
+        let maybe_auth_header = self.npmrc.get_auth_header(&package_url)?;
 
-        let maybe_bytes = self.http_client.download_with_progress(package_url).await?;
+        let maybe_bytes = self
+            .http_client
+            .download_with_progress(package_url, maybe_auth_header)
+            .await?;
         match maybe_bytes {
             Some(bytes) => {
                 let package_info = serde_json::from_slice(&bytes)?;
@@ -328,7 +341,10 @@ impl CliNpmRegistryApiInner {
             .remove(b'_')
             .remove(b'~');
         let name = percent_encoding::utf8_percent_encode(name, &ASCII_SET);
-        self.base_url.join(&name.to_string()).unwrap()
+        self.npmrc
+            .get_registry_url(name)
+            .join(&name.to_string())
+            .unwrap()
     }
 
     fn get_package_file_cache_path(&self, name: &str) -> PathBuf {
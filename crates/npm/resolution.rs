@@ -339,7 +339,7 @@ fn get_npm_pending_resolver(
     })
 }
 
-fn populate_lockfile_from_snapshot(
+pub fn populate_lockfile_from_snapshot(
     lockfile: &mut Lockfile,
     snapshot: &NpmResolutionSnapshot,
 ) -> Result<(), AnyError> {
@@ -0,0 +1,214 @@
+const primordials = globalThis.__bootstrap.primordials;
+const {
+	ArrayPrototypeForEach,
+	ArrayPrototypeMap,
+	ArrayPrototypeSome,
+	PromiseAll,
+	SafeArrayIterator,
+	String,
+	StringPrototypeSplit,
+	StringPrototypeTrim,
+	Symbol,
+	TypeError,
+	Uint8Array,
+} = primordials;
+
+import { Request } from 'ext:deno_fetch/23_request.js';
+import { Response } from 'ext:deno_fetch/23_response.js';
+import { fetch } from 'ext:deno_fetch/26_fetch.js';
+import { URL } from 'ext:deno_url/00_url.js';
+
+const core = globalThis.Deno.core;
+
+const illegalConstructorKey = Symbol('illegalConstructorKey');
+
+function nullBodyStatus(status) {
+	return status === 101 || status === 204 || status === 205 || status === 304;
+}
+
+function toRequest(request) {
+	return request instanceof Request ? request : new Request(request);
+}
+
+function toCacheRequest(request) {
+	return { url: request.url, headers: [...new SafeArrayIterator(request.headers)] };
+}
+
+function assertCacheableRequest(request) {
+	const { protocol } = new URL(request.url);
+	if (protocol !== 'http:' && protocol !== 'https:') {
+		throw new TypeError(`Request scheme '${protocol}' is not supported by the cache`);
+	}
+	if (request.method !== 'GET') {
+		throw new TypeError(`Request method '${request.method}' is not supported by the cache`);
+	}
+}
+
+function assertCacheableResponse(response) {
+	if (response.status < 200 || response.status === 206) {
+		throw new TypeError(`Responses with status ${response.status} can't be cached`);
+	}
+
+	const vary = response.headers.get('vary');
+	if (
+		vary !== null &&
+		ArrayPrototypeSome(
+			StringPrototypeSplit(vary, ','),
+			(field) => StringPrototypeTrim(field) === '*',
+		)
+	) {
+		throw new TypeError("Responses with 'Vary: *' can't be cached");
+	}
+	if (response.bodyUsed) {
+		throw new TypeError('Response body is already used');
+	}
+}
+
+// Returns null when nothing can match the request.
+function toQuery(cacheName, request, options) {
+	const query = {
+		cacheName,
+		request: null,
+		ignoreSearch: !!options?.ignoreSearch,
+		ignoreVary: !!options?.ignoreVary,
+	};
+	if (request === undefined) {
+		return query;
+	}
+
+	request = toRequest(request);
+	if (request.method !== 'GET' && !options?.ignoreMethod) {
+		return null;
+	}
+
+	query.request = toCacheRequest(request);
+	return query;
+}
+
+function toResponse({ status, statusText, headers, body }) {
+	return new Response(nullBodyStatus(status) ? null : body, { status, statusText, headers });
+}
+
+async function matchAll(cacheName, request, options, all) {
+	const query = toQuery(cacheName, request, options);
+	if (query === null) {
+		return [];
+	}
+
+	const responses = await core.opAsync('op_cache_match', query, all);
+	return ArrayPrototypeMap(responses, toResponse);
+}
+
+class Cache {
+	#name;
+
+	constructor(key, name) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError('Illegal constructor');
+		}
+		this.#name = name;
+	}
+
+	async match(request, options) {
+		const { 0: response } = await matchAll(this.#name, request, options, false);
+		return response;
+	}
+
+	async matchAll(request, options) {
+		return matchAll(this.#name, request, options, true);
+	}
+
+	async add(request) {
+		await this.addAll([request]);
+	}
+
+	async addAll(requests) {
+		requests = ArrayPrototypeMap([...new SafeArrayIterator(requests)], toRequest);
+		ArrayPrototypeForEach(requests, assertCacheableRequest);
+
+		const responses = await PromiseAll(ArrayPrototypeMap(requests, (request) => fetch(request)));
+		ArrayPrototypeForEach(responses, (response) => {
+			if (!response.ok) {
+				throw new TypeError(`Response for ${response.url} has status ${response.status}`);
+			}
+		});
+
+		await PromiseAll(
+			ArrayPrototypeMap(requests, (request, i) => this.put(request, responses[i])),
+		);
+	}
+
+	async put(request, response) {
+		request = toRequest(request);
+		assertCacheableRequest(request);
+		assertCacheableResponse(response);
+
+		const body = new Uint8Array(await response.arrayBuffer());
+
+		await core.opAsync('op_cache_put', {
+			cacheName: this.#name,
+			request: toCacheRequest(request),
+			responseStatus: response.status,
+			responseStatusText: response.statusText,
+			responseHeaders: [...new SafeArrayIterator(response.headers)],
+		}, body);
+	}
+
+	async delete(request, options) {
+		const query = toQuery(this.#name, request, options);
+		if (query === null) {
+			return false;
+		}
+
+		return await core.opAsync('op_cache_delete', query);
+	}
+
+	async keys(request, options) {
+		const query = toQuery(this.#name, request, options);
+		if (query === null) {
+			return [];
+		}
+
+		const requests = await core.opAsync('op_cache_keys', query);
+		return ArrayPrototypeMap(requests, ({ url, headers }) => new Request(url, { headers }));
+	}
+}
+
+class CacheStorage {
+	constructor(key) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError('Illegal constructor');
+		}
+	}
+
+	async open(cacheName) {
+		cacheName = String(cacheName);
+		await core.opAsync('op_cache_storage_open', cacheName);
+
+		return new Cache(illegalConstructorKey, cacheName);
+	}
+
+	async has(cacheName) {
+		return await core.opAsync('op_cache_storage_has', String(cacheName));
+	}
+
+	async delete(cacheName) {
+		return await core.opAsync('op_cache_storage_delete', String(cacheName));
+	}
+
+	async keys() {
+		return await core.opAsync('op_cache_storage_keys');
+	}
+
+	// Looks in the caches in creation order, unless `options.cacheName` is set.
+	async match(request, options) {
+		const cacheName = options?.cacheName === undefined ? null : String(options.cacheName);
+		const { 0: response } = await matchAll(cacheName, request, options, false);
+
+		return response;
+	}
+}
+
+const caches = new CacheStorage(illegalConstructorKey);
+
+export { Cache, caches, CacheStorage };
@@ -0,0 +1,426 @@
+mod memory;
+mod semantics;
+mod sqlite;
+
+pub use memory::MemoryCacheBackend;
+pub use sqlite::SqliteCacheBackend;
+
+use deno_core::error::{custom_error, type_error, AnyError};
+use deno_core::op2;
+use deno_core::parking_lot::Mutex;
+use deno_core::unsync::spawn_blocking;
+use deno_core::{JsBuffer, OpState, ToJsBuffer};
+use once_cell::sync::Lazy;
+use sb_core::cache::common::FastInsecureHasher;
+use sb_core::cache::deno_dir::DenoDir;
+use semantics::{base_url, CacheQuery, Freshness};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+deno_core::extension!(
+    sb_cache,
+    ops = [
+        op_cache_storage_open,
+        op_cache_storage_has,
+        op_cache_storage_delete,
+        op_cache_storage_keys,
+        op_cache_put,
+        op_cache_match,
+        op_cache_delete,
+        op_cache_keys,
+    ],
+    esm_entry_point = "ext:sb_cache/cache.js",
+    esm = ["cache.js"],
+    options = { backend: Option<Arc<dyn CacheBackend>> },
+    state = |state, options| {
+        if let Some(backend) = options.backend {
+            state.put::<Arc<dyn CacheBackend>>(backend);
+        }
+    }
+);
+
+/// A request and its response, as stored by `cache.put`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub request_url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub response_status: u16,
+    pub response_status_text: String,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: Vec<u8>,
+    /// Unix time in milliseconds after which the entry isn't served anymore.
+    pub expires_at: Option<i64>,
+}
+
+impl CacheEntry {
+    pub fn base_url(&self) -> &str {
+        base_url(&self.request_url)
+    }
+
+    /// What the entry counts for against the quota.
+    pub fn size(&self) -> u64 {
+        let headers_size = |headers: &[(String, String)]| {
+            headers
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>()
+        };
+
+        (self.request_url.len()
+            + headers_size(&self.request_headers)
+            + self.response_status_text.len()
+            + headers_size(&self.response_headers)
+            + self.response_body.len()) as u64
+    }
+
+    fn is_expired(&self, now_ms: i64) -> bool {
+        self.expires_at.map_or(false, |it| it <= now_ms)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredEntry {
+    pub id: u64,
+    pub entry: Arc<CacheEntry>,
+}
+
+/// Where the caches of a service are kept. It's shared by all the workers of
+/// the service, and evicts the least recently used entries of all its caches
+/// to stay under the quota.
+pub trait CacheBackend: Send + Sync + 'static {
+    /// Sets the size in bytes the entries of all caches can take up.
+    fn set_quota(&self, quota: u64);
+
+    /// Creates the cache if it doesn't exist yet.
+    fn open(&self, cache_name: &str) -> Result<(), AnyError>;
+    fn has(&self, cache_name: &str) -> Result<bool, AnyError>;
+    /// Deletes the cache and its entries. Returns whether it existed.
+    fn delete(&self, cache_name: &str) -> Result<bool, AnyError>;
+    /// The names of the caches, in creation order.
+    fn keys(&self) -> Result<Vec<String>, AnyError>;
+
+    /// The entries of the cache in insertion order, only the ones whose URL
+    /// without query and fragment is `base_url` if it's given.
+    fn entries(
+        &self,
+        cache_name: &str,
+        base_url: Option<&str>,
+    ) -> Result<Vec<StoredEntry>, AnyError>;
+    /// Stores `entry` in place of the `replaced` ones, creating the cache if
+    /// it was deleted meanwhile.
+    fn put(&self, cache_name: &str, replaced: &[u64], entry: CacheEntry) -> Result<(), AnyError>;
+    fn remove(&self, cache_name: &str, ids: &[u64]) -> Result<(), AnyError>;
+    /// Marks the entries as used, so they are evicted last.
+    fn touch(&self, cache_name: &str, ids: &[u64]) -> Result<(), AnyError>;
+}
+
+/// Where user workers keep the entries of `caches`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackendKind {
+    /// In memory, shared by the workers of the service until the runtime
+    /// exits.
+    #[default]
+    Memory,
+    /// In a SQLite database of the service under the `DENO_DIR`.
+    Sqlite,
+}
+
+static BACKENDS: Lazy<Mutex<HashMap<(CacheBackendKind, String), Arc<dyn CacheBackend>>>> =
+    Lazy::new(Default::default);
+
+/// The backend of the service at `service_path`, created the first time one
+/// of its workers asks for it. `quota` replaces the one it had.
+pub fn get_or_create_backend(
+    kind: CacheBackendKind,
+    service_path: &str,
+    quota: u64,
+) -> Result<Arc<dyn CacheBackend>, AnyError> {
+    let mut backends = BACKENDS.lock();
+    let key = (kind, service_path.to_string());
+
+    if let Some(backend) = backends.get(&key) {
+        backend.set_quota(quota);
+        return Ok(backend.clone());
+    }
+
+    let backend: Arc<dyn CacheBackend> = match kind {
+        CacheBackendKind::Memory => Arc::new(MemoryCacheBackend::new(quota)),
+        CacheBackendKind::Sqlite => {
+            let path = DenoDir::new(None)?.web_cache_folder_path().join(format!(
+                "{:x}.sqlite",
+                FastInsecureHasher::hash(service_path)
+            ));
+
+            Arc::new(SqliteCacheBackend::open(&path, quota)?)
+        }
+    };
+
+    backends.insert(key, backend.clone());
+    Ok(backend)
+}
+
+fn quota_exceeded(size: u64, quota: u64) -> AnyError {
+    custom_error(
+        "DOMExceptionQuotaExceededError",
+        format!(
+            "the cache entry of {} bytes exceeds the quota of {} bytes",
+            size, quota
+        ),
+    )
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// The live entries of the cache matching `query`, or all of them without
+/// one. Expired entries are removed along the way.
+fn query_cache(
+    backend: &dyn CacheBackend,
+    cache_name: &str,
+    query: Option<&CacheQuery>,
+) -> Result<Vec<StoredEntry>, AnyError> {
+    let now = now_ms();
+    let entries = backend.entries(cache_name, query.map(|it| base_url(&it.request_url)))?;
+    let (expired, entries): (Vec<_>, Vec<_>) =
+        entries.into_iter().partition(|it| it.entry.is_expired(now));
+
+    if !expired.is_empty() {
+        backend.remove(cache_name, &ids(&expired))?;
+    }
+
+    Ok(entries
+        .into_iter()
+        .filter(|it| query.map_or(true, |query| query.matches(&it.entry)))
+        .collect())
+}
+
+fn ids(entries: &[StoredEntry]) -> Vec<u64> {
+    entries.iter().map(|it| it.id).collect()
+}
+
+/// Runs `f` off the JS thread, as backends may block on IO.
+async fn with_backend<T, F>(state: &Rc<RefCell<OpState>>, f: F) -> Result<T, AnyError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn CacheBackend) -> Result<T, AnyError> + Send + 'static,
+{
+    let backend = state
+        .borrow()
+        .try_borrow::<Arc<dyn CacheBackend>>()
+        .cloned()
+        .ok_or_else(|| type_error("caches are not available in this worker"))?;
+
+    spawn_blocking(move || f(&*backend)).await?
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheRequest {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheQueryArgs {
+    /// All caches, in creation order, if unset.
+    cache_name: Option<String>,
+    /// Matches all entries if unset.
+    request: Option<CacheRequest>,
+    ignore_search: bool,
+    ignore_vary: bool,
+}
+
+impl CacheQueryArgs {
+    fn into_parts(self) -> (Option<String>, Option<CacheQuery>) {
+        let query = self.request.map(|request| CacheQuery {
+            request_url: request.url,
+            request_headers: request.headers,
+            ignore_search: self.ignore_search,
+            ignore_vary: self.ignore_vary,
+        });
+
+        (self.cache_name, query)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CachePutArgs {
+    cache_name: String,
+    request: CacheRequest,
+    response_status: u16,
+    response_status_text: String,
+    response_headers: Vec<(String, String)>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheMatchResponse {
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+    body: ToJsBuffer,
+}
+
+#[op2(async)]
+pub async fn op_cache_storage_open(
+    state: Rc<RefCell<OpState>>,
+    #[string] cache_name: String,
+) -> Result<(), AnyError> {
+    with_backend(&state, move |backend| backend.open(&cache_name)).await
+}
+
+#[op2(async)]
+pub async fn op_cache_storage_has(
+    state: Rc<RefCell<OpState>>,
+    #[string] cache_name: String,
+) -> Result<bool, AnyError> {
+    with_backend(&state, move |backend| backend.has(&cache_name)).await
+}
+
+#[op2(async)]
+pub async fn op_cache_storage_delete(
+    state: Rc<RefCell<OpState>>,
+    #[string] cache_name: String,
+) -> Result<bool, AnyError> {
+    with_backend(&state, move |backend| backend.delete(&cache_name)).await
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_cache_storage_keys(state: Rc<RefCell<OpState>>) -> Result<Vec<String>, AnyError> {
+    with_backend(&state, |backend| backend.keys()).await
+}
+
+#[op2(async)]
+pub async fn op_cache_put(
+    state: Rc<RefCell<OpState>>,
+    #[serde] args: CachePutArgs,
+    #[buffer] body: JsBuffer,
+) -> Result<(), AnyError> {
+    let entry = CacheEntry {
+        request_url: args.request.url,
+        request_headers: args.request.headers,
+        response_status: args.response_status,
+        response_status_text: args.response_status_text,
+        response_headers: args.response_headers,
+        response_body: body.to_vec(),
+        expires_at: None,
+    };
+
+    with_backend(&state, move |backend| {
+        // the entries the request matches are replaced
+        let query = CacheQuery {
+            request_url: entry.request_url.clone(),
+            request_headers: entry.request_headers.clone(),
+            ignore_search: false,
+            ignore_vary: false,
+        };
+        let replaced = ids(&query_cache(backend, &args.cache_name, Some(&query))?);
+
+        match Freshness::from_headers(&entry.response_headers, now_ms()) {
+            Freshness::NotStorable => backend.remove(&args.cache_name, &replaced),
+            Freshness::Until(expires_at) => backend.put(
+                &args.cache_name,
+                &replaced,
+                CacheEntry {
+                    expires_at: Some(expires_at),
+                    ..entry
+                },
+            ),
+            Freshness::Unlimited => backend.put(&args.cache_name, &replaced, entry),
+        }
+    })
+    .await
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_cache_match(
+    state: Rc<RefCell<OpState>>,
+    #[serde] args: CacheQueryArgs,
+    all: bool,
+) -> Result<Vec<CacheMatchResponse>, AnyError> {
+    let (cache_name, query) = args.into_parts();
+    let entries = with_backend(&state, move |backend| {
+        let cache_names = match cache_name {
+            Some(cache_name) => vec![cache_name],
+            None => backend.keys()?,
+        };
+        let mut matched = vec![];
+
+        for cache_name in cache_names {
+            let entries = query_cache(backend, &cache_name, query.as_ref())?;
+            let entries = if all {
+                entries
+            } else {
+                entries.into_iter().take(1).collect()
+            };
+
+            backend.touch(&cache_name, &ids(&entries))?;
+            matched.extend(entries.into_iter().map(|it| it.entry));
+            if !all && !matched.is_empty() {
+                break;
+            }
+        }
+
+        Ok(matched)
+    })
+    .await?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| CacheMatchResponse {
+            status: entry.response_status,
+            status_text: entry.response_status_text.clone(),
+            headers: entry.response_headers.clone(),
+            body: entry.response_body.clone().into(),
+        })
+        .collect())
+}
+
+#[op2(async)]
+pub async fn op_cache_delete(
+    state: Rc<RefCell<OpState>>,
+    #[serde] args: CacheQueryArgs,
+) -> Result<bool, AnyError> {
+    let (Some(cache_name), Some(query)) = args.into_parts() else {
+        return Err(type_error("a cache and a request are required"));
+    };
+
+    with_backend(&state, move |backend| {
+        let entries = query_cache(backend, &cache_name, Some(&query))?;
+        backend.remove(&cache_name, &ids(&entries))?;
+
+        Ok(!entries.is_empty())
+    })
+    .await
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_cache_keys(
+    state: Rc<RefCell<OpState>>,
+    #[serde] args: CacheQueryArgs,
+) -> Result<Vec<CacheRequest>, AnyError> {
+    let (Some(cache_name), query) = args.into_parts() else {
+        return Err(type_error("a cache is required"));
+    };
+
+    with_backend(&state, move |backend| {
+        Ok(query_cache(backend, &cache_name, query.as_ref())?
+            .into_iter()
+            .map(|it| CacheRequest {
+                url: it.entry.request_url.clone(),
+                headers: it.entry.request_headers.clone(),
+            })
+            .collect())
+    })
+    .await
+}
@@ -0,0 +1,265 @@
+use deno_core::error::AnyError;
+use deno_core::parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{quota_exceeded, CacheBackend, CacheEntry, StoredEntry};
+
+struct MemoryEntry {
+    id: u64,
+    last_used: u64,
+    entry: Arc<CacheEntry>,
+}
+
+#[derive(Default)]
+struct MemoryCaches {
+    /// In creation order, as `caches.keys()` returns them.
+    caches: Vec<(String, Vec<MemoryEntry>)>,
+    next_id: u64,
+    clock: u64,
+    size: u64,
+}
+
+impl MemoryCaches {
+    fn get(&self, cache_name: &str) -> Option<&Vec<MemoryEntry>> {
+        self.caches
+            .iter()
+            .find(|(name, _)| name == cache_name)
+            .map(|(_, entries)| entries)
+    }
+
+    fn get_or_create(&mut self, cache_name: &str) -> &mut Vec<MemoryEntry> {
+        let index = match self.caches.iter().position(|(name, _)| name == cache_name) {
+            Some(index) => index,
+            None => {
+                self.caches.push((cache_name.to_string(), vec![]));
+                self.caches.len() - 1
+            }
+        };
+
+        &mut self.caches[index].1
+    }
+
+    fn remove(&mut self, cache_name: &str, ids: &[u64]) {
+        let mut freed = 0;
+        if let Some((_, entries)) = self.caches.iter_mut().find(|(name, _)| name == cache_name) {
+            entries.retain(|it| {
+                let keep = !ids.contains(&it.id);
+                if !keep {
+                    freed += it.entry.size();
+                }
+                keep
+            });
+        }
+
+        self.size -= freed;
+    }
+
+    /// Removes the least recently used entry of all caches.
+    fn evict(&mut self) -> bool {
+        let oldest = self
+            .caches
+            .iter()
+            .enumerate()
+            .flat_map(|(cache_index, (_, entries))| {
+                entries
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, it)| (it.last_used, cache_index, index))
+            })
+            .min();
+
+        match oldest {
+            Some((_, cache_index, index)) => {
+                let evicted = self.caches[cache_index].1.remove(index);
+                self.size -= evicted.entry.size();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Keeps the caches of a service in memory, which is the fastest but doesn't
+/// survive restarts of the runtime.
+pub struct MemoryCacheBackend {
+    quota: AtomicU64,
+    caches: Mutex<MemoryCaches>,
+}
+
+impl MemoryCacheBackend {
+    pub fn new(quota: u64) -> Self {
+        Self {
+            quota: AtomicU64::new(quota),
+            caches: Mutex::default(),
+        }
+    }
+}
+
+impl CacheBackend for MemoryCacheBackend {
+    fn set_quota(&self, quota: u64) {
+        self.quota.store(quota, Ordering::Relaxed);
+    }
+
+    fn open(&self, cache_name: &str) -> Result<(), AnyError> {
+        self.caches.lock().get_or_create(cache_name);
+        Ok(())
+    }
+
+    fn has(&self, cache_name: &str) -> Result<bool, AnyError> {
+        Ok(self.caches.lock().get(cache_name).is_some())
+    }
+
+    fn delete(&self, cache_name: &str) -> Result<bool, AnyError> {
+        let mut caches = self.caches.lock();
+        let Some(index) = caches
+            .caches
+            .iter()
+            .position(|(name, _)| name == cache_name)
+        else {
+            return Ok(false);
+        };
+
+        let (_, entries) = caches.caches.remove(index);
+        caches.size -= entries.iter().map(|it| it.entry.size()).sum::<u64>();
+        Ok(true)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, AnyError> {
+        Ok(self
+            .caches
+            .lock()
+            .caches
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
+
+    fn entries(
+        &self,
+        cache_name: &str,
+        base_url: Option<&str>,
+    ) -> Result<Vec<StoredEntry>, AnyError> {
+        let caches = self.caches.lock();
+        let Some(entries) = caches.get(cache_name) else {
+            return Ok(vec![]);
+        };
+
+        Ok(entries
+            .iter()
+            .filter(|it| base_url.map_or(true, |url| it.entry.base_url() == url))
+            .map(|it| StoredEntry {
+                id: it.id,
+                entry: it.entry.clone(),
+            })
+            .collect())
+    }
+
+    fn put(&self, cache_name: &str, replaced: &[u64], entry: CacheEntry) -> Result<(), AnyError> {
+        let quota = self.quota.load(Ordering::Relaxed);
+        let size = entry.size();
+        if size > quota {
+            return Err(quota_exceeded(size, quota));
+        }
+
+        let mut caches = self.caches.lock();
+        caches.remove(cache_name, replaced);
+        while caches.size + size > quota && caches.evict() {}
+
+        caches.next_id += 1;
+        caches.clock += 1;
+        caches.size += size;
+
+        let id = caches.next_id;
+        let last_used = caches.clock;
+        caches.get_or_create(cache_name).push(MemoryEntry {
+            id,
+            last_used,
+            entry: Arc::new(entry),
+        });
+
+        Ok(())
+    }
+
+    fn remove(&self, cache_name: &str, ids: &[u64]) -> Result<(), AnyError> {
+        self.caches.lock().remove(cache_name, ids);
+        Ok(())
+    }
+
+    fn touch(&self, cache_name: &str, ids: &[u64]) -> Result<(), AnyError> {
+        let mut caches = self.caches.lock();
+        caches.clock += 1;
+
+        let clock = caches.clock;
+        if let Some((_, entries)) = caches
+            .caches
+            .iter_mut()
+            .find(|(name, _)| name == cache_name)
+        {
+            for it in entries.iter_mut().filter(|it| ids.contains(&it.id)) {
+                it.last_used = clock;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MemoryCacheBackend;
+    use crate::{CacheBackend, CacheEntry};
+
+    fn entry(url: &str, body: &[u8]) -> CacheEntry {
+        CacheEntry {
+            request_url: url.to_string(),
+            request_headers: vec![],
+            response_status: 200,
+            response_status_text: String::new(),
+            response_headers: vec![],
+            response_body: body.to_vec(),
+            expires_at: None,
+        }
+    }
+
+    fn urls(backend: &MemoryCacheBackend, cache_name: &str) -> Vec<String> {
+        backend
+            .entries(cache_name, None)
+            .unwrap()
+            .into_iter()
+            .map(|it| it.entry.request_url.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let a = entry("https://a.com/", &[0; 10]);
+        let b = entry("https://b.com/", &[0; 10]);
+        let c = entry("https://c.com/", &[0; 10]);
+        let backend = MemoryCacheBackend::new(a.size() + b.size());
+
+        backend.put("v1", &[], a.clone()).unwrap();
+        backend.put("v2", &[], b).unwrap();
+
+        // `a` was used last, so `b` is evicted to make room for `c`
+        let ids = backend
+            .entries("v1", Some("https://a.com/"))
+            .unwrap()
+            .into_iter()
+            .map(|it| it.id)
+            .collect::<Vec<_>>();
+        backend.touch("v1", &ids).unwrap();
+        backend.put("v1", &[], c).unwrap();
+
+        assert_eq!(urls(&backend, "v1"), ["https://a.com/", "https://c.com/"]);
+        assert!(urls(&backend, "v2").is_empty());
+        assert_eq!(backend.keys().unwrap(), ["v1", "v2"]);
+
+        assert!(backend
+            .put("v1", &[], entry("https://d.com/", &[0; 100]))
+            .is_err());
+        assert!(backend.delete("v1").unwrap());
+        assert!(!backend.has("v1").unwrap());
+        backend.put("v2", &[], a).unwrap();
+    }
+}
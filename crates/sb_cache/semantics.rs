@@ -0,0 +1,217 @@
+use cache_control::{Cachability, CacheControl};
+use chrono::DateTime;
+
+use super::CacheEntry;
+
+/// How long a response can be served from the cache, according to its
+/// caching headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// `no-store` and `private` responses aren't kept.
+    NotStorable,
+    /// Unix time in milliseconds after which the response is stale.
+    Until(i64),
+    /// No caching headers, the response is kept until it's deleted or
+    /// evicted.
+    Unlimited,
+}
+
+impl Freshness {
+    pub fn from_headers(headers: &[(String, String)], now_ms: i64) -> Self {
+        if let Some(value) = get_header(headers, "cache-control") {
+            let cache_control = CacheControl::from_value(&value).unwrap_or_default();
+
+            if cache_control.no_store || cache_control.cachability == Some(Cachability::Private) {
+                return Freshness::NotStorable;
+            }
+            if let Some(max_age) = cache_control.s_max_age.or(cache_control.max_age) {
+                let age = get_header(headers, "age")
+                    .and_then(|it| it.trim().parse::<u64>().ok())
+                    .unwrap_or(0);
+                let ttl_secs = max_age.as_secs().saturating_sub(age);
+
+                return Freshness::Until(now_ms.saturating_add(ttl_secs as i64 * 1000));
+            }
+        }
+
+        if let Some(expires) = get_header(headers, "expires") {
+            // an invalid date, such as `0`, means the response already expired
+            let Ok(expires) = DateTime::parse_from_rfc2822(&expires) else {
+                return Freshness::Until(now_ms);
+            };
+            // relative to the date of the server, as its clock may differ
+            let date = get_header(headers, "date")
+                .and_then(|it| DateTime::parse_from_rfc2822(&it).ok())
+                .map_or(now_ms, |it| it.timestamp_millis());
+
+            return Freshness::Until(now_ms + (expires.timestamp_millis() - date).max(0));
+        }
+
+        Freshness::Unlimited
+    }
+}
+
+/// The value of a header, with the values of repeated headers joined with
+/// `, `.
+pub fn get_header(headers: &[(String, String)], name: &str) -> Option<String> {
+    let values = headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// The field names of the `Vary` header of the response, lowercased.
+pub fn vary_fields(headers: &[(String, String)]) -> Vec<String> {
+    get_header(headers, "vary")
+        .map(|value| {
+            value
+                .split(',')
+                .map(|it| it.trim().to_ascii_lowercase())
+                .filter(|it| !it.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The URL entries are stored under: without its query and fragment, so that
+/// queries ignoring the search find them too.
+pub fn base_url(url: &str) -> &str {
+    match url.find(|c| c == '?' || c == '#') {
+        Some(index) => &url[..index],
+        None => url,
+    }
+}
+
+fn strip_fragment(url: &str) -> &str {
+    match url.find('#') {
+        Some(index) => &url[..index],
+        None => url,
+    }
+}
+
+/// What `match`, `matchAll`, `delete` and `keys` look entries up with.
+#[derive(Debug, Clone)]
+pub struct CacheQuery {
+    pub request_url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub ignore_search: bool,
+    pub ignore_vary: bool,
+}
+
+impl CacheQuery {
+    pub fn matches(&self, entry: &CacheEntry) -> bool {
+        let url_matches = if self.ignore_search {
+            base_url(&self.request_url) == base_url(&entry.request_url)
+        } else {
+            strip_fragment(&self.request_url) == strip_fragment(&entry.request_url)
+        };
+        if !url_matches {
+            return false;
+        }
+        if self.ignore_vary {
+            return true;
+        }
+
+        vary_fields(&entry.response_headers).iter().all(|field| {
+            field != "*"
+                && get_header(&self.request_headers, field)
+                    == get_header(&entry.request_headers, field)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheQuery, Freshness};
+    use crate::CacheEntry;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn entry(url: &str, request_headers: &[(&str, &str)], vary: &str) -> CacheEntry {
+        CacheEntry {
+            request_url: url.to_string(),
+            request_headers: headers(request_headers),
+            response_status: 200,
+            response_status_text: "OK".to_string(),
+            response_headers: headers(&[("vary", vary)]),
+            response_body: b"hello".to_vec(),
+            expires_at: None,
+        }
+    }
+
+    fn query(url: &str, request_headers: &[(&str, &str)]) -> CacheQuery {
+        CacheQuery {
+            request_url: url.to_string(),
+            request_headers: headers(request_headers),
+            ignore_search: false,
+            ignore_vary: false,
+        }
+    }
+
+    #[test]
+    fn test_vary() {
+        let entry = entry(
+            "https://example.com/a?b=1",
+            &[("accept-language", "en")],
+            "Accept-Language",
+        );
+
+        assert!(query("https://example.com/a?b=1#c", &[("accept-language", "en")]).matches(&entry));
+        assert!(!query("https://example.com/a?b=1", &[("accept-language", "fr")]).matches(&entry));
+        assert!(!query("https://example.com/a?b=1", &[]).matches(&entry));
+        assert!(!query("https://example.com/a?b=2", &[("accept-language", "en")]).matches(&entry));
+
+        let mut query = query("https://example.com/a?b=2", &[]);
+        query.ignore_search = true;
+        query.ignore_vary = true;
+        assert!(query.matches(&entry));
+    }
+
+    #[test]
+    fn test_freshness() {
+        let now = 1_000_000;
+
+        assert_eq!(Freshness::from_headers(&[], now), Freshness::Unlimited);
+        assert_eq!(
+            Freshness::from_headers(&headers(&[("cache-control", "public, max-age=60")]), now),
+            Freshness::Until(now + 60_000)
+        );
+        assert_eq!(
+            Freshness::from_headers(
+                &headers(&[("cache-control", "max-age=60, s-maxage=120"), ("age", "20")]),
+                now
+            ),
+            Freshness::Until(now + 100_000)
+        );
+        assert_eq!(
+            Freshness::from_headers(
+                &headers(&[
+                    ("date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+                    ("expires", "Wed, 21 Oct 2015 07:29:00 GMT")
+                ]),
+                now
+            ),
+            Freshness::Until(now + 60_000)
+        );
+        assert_eq!(
+            Freshness::from_headers(&headers(&[("expires", "0")]), now),
+            Freshness::Until(now)
+        );
+        assert_eq!(
+            Freshness::from_headers(&headers(&[("cache-control", "no-store")]), now),
+            Freshness::NotStorable
+        );
+    }
+}
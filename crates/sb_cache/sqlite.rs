@@ -0,0 +1,328 @@
+use deno_core::error::AnyError;
+use deno_core::parking_lot::Mutex;
+use deno_core::serde_json;
+use deno_webstorage::rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{quota_exceeded, CacheBackend, CacheEntry, StoredEntry};
+
+const INIT_SQL: &str = "
+    PRAGMA journal_mode=WAL;
+    PRAGMA synchronous=NORMAL;
+
+    CREATE TABLE IF NOT EXISTS caches (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      name TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE IF NOT EXISTS entries (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      cache_id INTEGER NOT NULL,
+      base_url TEXT NOT NULL,
+      request_url TEXT NOT NULL,
+      request_headers TEXT NOT NULL,
+      response_status INTEGER NOT NULL,
+      response_status_text TEXT NOT NULL,
+      response_headers TEXT NOT NULL,
+      response_body BLOB NOT NULL,
+      expires_at INTEGER,
+      size INTEGER NOT NULL,
+      last_used INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS entries_by_url ON entries (cache_id, base_url);
+    CREATE INDEX IF NOT EXISTS entries_by_last_used ON entries (last_used);
+";
+
+/// Keeps the caches of a service in a SQLite database, so they survive
+/// restarts of the runtime.
+pub struct SqliteCacheBackend {
+    quota: AtomicU64,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteCacheBackend {
+    pub fn open(path: &Path, quota: u64) -> Result<Self, AnyError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        Self::from_connection(Connection::open(path)?, quota)
+    }
+
+    fn from_connection(conn: Connection, quota: u64) -> Result<Self, AnyError> {
+        conn.execute_batch(INIT_SQL)?;
+
+        Ok(Self {
+            quota: AtomicU64::new(quota),
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+fn cache_id(conn: &Connection, cache_name: &str) -> Result<Option<i64>, AnyError> {
+    Ok(conn
+        .query_row(
+            "SELECT id FROM caches WHERE name = ?1",
+            [cache_name],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+fn create_cache(tx: &Transaction, cache_name: &str) -> Result<i64, AnyError> {
+    tx.execute(
+        "INSERT OR IGNORE INTO caches (name) VALUES (?1)",
+        [cache_name],
+    )?;
+
+    Ok(cache_id(tx, cache_name)?.unwrap())
+}
+
+fn next_last_used(conn: &Connection) -> Result<i64, AnyError> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(last_used), 0) + 1 FROM entries",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+impl CacheBackend for SqliteCacheBackend {
+    fn set_quota(&self, quota: u64) {
+        self.quota.store(quota, Ordering::Relaxed);
+    }
+
+    fn open(&self, cache_name: &str) -> Result<(), AnyError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        create_cache(&tx, cache_name)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn has(&self, cache_name: &str) -> Result<bool, AnyError> {
+        Ok(cache_id(&self.conn.lock(), cache_name)?.is_some())
+    }
+
+    fn delete(&self, cache_name: &str) -> Result<bool, AnyError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let Some(id) = cache_id(&tx, cache_name)? else {
+            return Ok(false);
+        };
+
+        tx.execute("DELETE FROM entries WHERE cache_id = ?1", [id])?;
+        tx.execute("DELETE FROM caches WHERE id = ?1", [id])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, AnyError> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached("SELECT name FROM caches ORDER BY id")?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(names)
+    }
+
+    fn entries(
+        &self,
+        cache_name: &str,
+        base_url: Option<&str>,
+    ) -> Result<Vec<StoredEntry>, AnyError> {
+        let conn = self.conn.lock();
+        let Some(cache_id) = cache_id(&conn, cache_name)? else {
+            return Ok(vec![]);
+        };
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, request_url, request_headers, response_status, response_status_text,
+              response_headers, response_body, expires_at
+            FROM entries
+            WHERE cache_id = ?1 AND (?2 IS NULL OR base_url = ?2)
+            ORDER BY id",
+        )?;
+        let rows = stmt
+            .query_map(params![cache_id, base_url], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, u16>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Vec<u8>>(6)?,
+                    row.get::<_, Option<i64>>(7)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    id,
+                    request_url,
+                    request_headers,
+                    response_status,
+                    response_status_text,
+                    response_headers,
+                    response_body,
+                    expires_at,
+                )| {
+                    Ok::<_, AnyError>(StoredEntry {
+                        id: id as u64,
+                        entry: Arc::new(CacheEntry {
+                            request_url,
+                            request_headers: serde_json::from_str(&request_headers)?,
+                            response_status,
+                            response_status_text,
+                            response_headers: serde_json::from_str(&response_headers)?,
+                            response_body,
+                            expires_at,
+                        }),
+                    })
+                },
+            )
+            .collect()
+    }
+
+    fn put(&self, cache_name: &str, replaced: &[u64], entry: CacheEntry) -> Result<(), AnyError> {
+        let quota = self.quota.load(Ordering::Relaxed);
+        let size = entry.size();
+        if size > quota {
+            return Err(quota_exceeded(size, quota));
+        }
+
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let cache_id = create_cache(&tx, cache_name)?;
+
+        for id in replaced {
+            tx.execute("DELETE FROM entries WHERE id = ?1", [*id as i64])?;
+        }
+
+        // evict the least recently used entries of all caches until it fits
+        let mut used = tx.query_row("SELECT COALESCE(SUM(size), 0) FROM entries", [], |row| {
+            row.get::<_, i64>(0)
+        })? as u64;
+        while used + size > quota {
+            let Some((id, evicted)) = tx
+                .query_row(
+                    "SELECT id, size FROM entries ORDER BY last_used LIMIT 1",
+                    [],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+                )
+                .optional()?
+            else {
+                break;
+            };
+
+            tx.execute("DELETE FROM entries WHERE id = ?1", [id])?;
+            used -= evicted as u64;
+        }
+
+        let last_used = next_last_used(&tx)?;
+        tx.execute(
+            "INSERT INTO entries (cache_id, base_url, request_url, request_headers,
+              response_status, response_status_text, response_headers, response_body,
+              expires_at, size, last_used)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                cache_id,
+                entry.base_url(),
+                entry.request_url,
+                serde_json::to_string(&entry.request_headers)?,
+                entry.response_status,
+                entry.response_status_text,
+                serde_json::to_string(&entry.response_headers)?,
+                entry.response_body,
+                entry.expires_at,
+                size as i64,
+                last_used,
+            ],
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn remove(&self, _cache_name: &str, ids: &[u64]) -> Result<(), AnyError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        for id in ids {
+            tx.execute("DELETE FROM entries WHERE id = ?1", [*id as i64])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn touch(&self, _cache_name: &str, ids: &[u64]) -> Result<(), AnyError> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let last_used = next_last_used(&tx)?;
+
+        for id in ids {
+            tx.execute(
+                "UPDATE entries SET last_used = ?1 WHERE id = ?2",
+                [last_used, *id as i64],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SqliteCacheBackend;
+    use crate::{CacheBackend, CacheEntry};
+    use deno_webstorage::rusqlite::Connection;
+
+    #[test]
+    fn test_sqlite_backend() {
+        let entry = CacheEntry {
+            request_url: "https://example.com/a?b=1".to_string(),
+            request_headers: vec![("accept".to_string(), "text/plain".to_string())],
+            response_status: 200,
+            response_status_text: "OK".to_string(),
+            response_headers: vec![("content-type".to_string(), "text/plain".to_string())],
+            response_body: b"hello".to_vec(),
+            expires_at: Some(42),
+        };
+        let backend =
+            SqliteCacheBackend::from_connection(Connection::open_in_memory().unwrap(), 1024)
+                .unwrap();
+
+        backend.open("v1").unwrap();
+        backend.put("v2", &[], entry.clone()).unwrap();
+        assert_eq!(backend.keys().unwrap(), ["v1", "v2"]);
+
+        let stored = backend
+            .entries("v2", Some("https://example.com/a"))
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(*stored[0].entry, entry);
+        assert!(backend
+            .entries("v2", Some("https://example.com/b"))
+            .unwrap()
+            .is_empty());
+
+        // replacing the entry doesn't count it twice against the quota
+        backend.put("v2", &[stored[0].id], entry.clone()).unwrap();
+        assert_eq!(backend.entries("v2", None).unwrap().len(), 1);
+
+        let mut big = entry;
+        big.response_body = vec![0; 2048];
+        assert!(backend.put("v2", &[], big).is_err());
+
+        assert!(backend.delete("v2").unwrap());
+        assert!(!backend.has("v2").unwrap());
+        assert!(backend.has("v1").unwrap());
+    }
+}
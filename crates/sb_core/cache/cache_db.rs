@@ -16,7 +16,8 @@ use std::sync::Arc;
 /// What should the cache should do on failure?
 #[derive(Default)]
 pub enum CacheFailure {
-    /// Return errors if failure mode otherwise unspecified.
+    /// Return errors if failure mode otherwise unspecified. The database file
+    /// is never deleted, as it may hold data that can't be rebuilt.
     #[default]
     Error,
     /// Create an in-memory cache that is not persistent.
@@ -236,7 +237,9 @@ impl CacheDB {
             "Could not initialize cache database '{}', deleting and retrying... ({err:?})",
             path.to_string_lossy()
         );
-        if std::fs::remove_file(path).is_ok() {
+        if !matches!(self.config.on_failure, CacheFailure::Error)
+            && std::fs::remove_file(path).is_ok()
+        {
             // Try a third time if we successfully deleted it
             let res = self.open_connection_and_init(&self.path);
             if let Ok(conn) = res {
@@ -129,6 +129,18 @@ impl DenoDir {
         self.root.join("latest.txt")
     }
 
+    /// Folder of the databases `caches` keeps the entries of services in.
+    pub fn web_cache_folder_path(&self) -> PathBuf {
+        // bump this version name to invalidate the entire cache
+        self.root.join("web_cache_v1")
+    }
+
+    /// Folder of the databases `Deno.openKv` keeps the entries of namespaces
+    /// in.
+    pub fn kv_folder_path(&self) -> PathBuf {
+        self.root.join("kv_v1")
+    }
+
     /// Folder used for the npm cache.
     pub fn npm_folder_path(&self) -> PathBuf {
         self.root.join("npm")
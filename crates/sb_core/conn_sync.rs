@@ -5,6 +5,15 @@ use tokio::sync::watch;
 pub enum ConnSync {
     Want,
     Recv,
+    /// The client went away before the response was sent in full.
+    Abort,
+}
+
+impl ConnSync {
+    /// Whether the request is over, either way.
+    pub fn is_settled(&self) -> bool {
+        *self != ConnSync::Want
+    }
 }
 
 pub struct ConnWatcher(pub Option<watch::Receiver<ConnSync>>);
@@ -20,3 +29,54 @@ impl ConnWatcher {
         self.0.clone()
     }
 }
+
+/// Resolves once the client of the request went away. Never resolves if the
+/// response was received or the connection isn't watched.
+pub async fn wait_for_abort(maybe_watcher: Option<watch::Receiver<ConnSync>>) {
+    if let Some(mut watcher) = maybe_watcher {
+        if let Ok(sync) = watcher.wait_for(ConnSync::is_settled).await {
+            if *sync == ConnSync::Abort {
+                return;
+            }
+        }
+    }
+
+    std::future::pending().await
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::sync::watch;
+    use tokio::time::timeout;
+
+    use super::{wait_for_abort, ConnSync};
+
+    #[tokio::test]
+    async fn test_wait_for_abort() {
+        let (tx, rx) = watch::channel(ConnSync::Want);
+        tx.send(ConnSync::Abort).unwrap();
+
+        assert!(
+            timeout(Duration::from_millis(100), wait_for_abort(Some(rx)))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_abort_after_response() {
+        let (tx, rx) = watch::channel(ConnSync::Want);
+        tx.send(ConnSync::Recv).unwrap();
+
+        assert!(
+            timeout(Duration::from_millis(100), wait_for_abort(Some(rx)))
+                .await
+                .is_err()
+        );
+        assert!(timeout(Duration::from_millis(100), wait_for_abort(None))
+            .await
+            .is_err());
+    }
+}
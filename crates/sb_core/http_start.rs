@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::os::fd::AsRawFd;
 use std::os::fd::RawFd;
@@ -7,11 +8,15 @@ use std::task::Poll;
 
 use deno_core::error::bad_resource;
 use deno_core::error::bad_resource_id;
+use deno_core::error::custom_error;
 use deno_core::error::AnyError;
 use deno_core::op2;
 use deno_core::OpState;
+use deno_core::RcRef;
 use deno_core::ResourceId;
 use deno_http::http_create_conn_resource;
+use deno_http::HttpRequestReader;
+use deno_http::HttpStreamResource;
 use deno_net::io::UnixStreamResource;
 use futures::pin_mut;
 use futures::ready;
@@ -24,6 +29,8 @@ use tokio::sync::watch;
 
 use crate::conn_sync::ConnSync;
 use crate::conn_sync::ConnWatcher;
+use crate::upgrade::splice;
+use crate::upgrade::UpgradedResponseResource;
 
 struct UnixStream2(UnixStream, Option<watch::Receiver<ConnSync>>);
 
@@ -71,7 +78,7 @@ impl AsyncWrite for UnixStream2 {
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Result<(), std::io::Error>> {
         if let Some(ref mut sync) = self.1 {
-            let fut = sync.wait_for(|it| *it == ConnSync::Recv);
+            let fut = sync.wait_for(ConnSync::is_settled);
 
             pin_mut!(fut);
             ready!(fut.poll(cx).map(|it| {
@@ -125,4 +132,98 @@ fn op_http_start(
     Err(bad_resource_id())
 }
 
-deno_core::extension!(sb_core_http, ops = [op_http_start]);
+/// Resolves with whether the client went away once the request is over.
+/// Resolves with `false` right away if the connection isn't watched.
+#[op2(async)]
+async fn op_http_wait_for_disconnect(
+    state: Rc<RefCell<OpState>>,
+    #[smi] watcher_rid: ResourceId,
+) -> Result<bool, AnyError> {
+    let watcher = state
+        .borrow()
+        .resource_table
+        .get::<ConnWatcher>(watcher_rid)?
+        .get();
+
+    let Some(mut watcher) = watcher else {
+        return Ok(false);
+    };
+
+    match watcher.wait_for(ConnSync::is_settled).await {
+        Ok(sync) => Ok(*sync == ConnSync::Abort),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Answers the request of `stream_rid` with an upgraded user worker response:
+/// once the `101` response has been written, the connection of the request is
+/// spliced to the one of the user worker.
+#[op2(fast)]
+fn op_http_splice_upgraded_response(
+    state: &mut OpState,
+    #[smi] stream_rid: ResourceId,
+    #[smi] upgraded_rid: ResourceId,
+) -> Result<(), AnyError> {
+    let upgraded = state
+        .resource_table
+        .take::<UpgradedResponseResource>(upgraded_rid)?;
+    let upgraded = Rc::try_unwrap(upgraded)
+        .map_err(|_| bad_resource("upgraded response is currently in use"))?;
+    let UpgradedResponseResource {
+        on_upgraded,
+        req_end_tx,
+    } = upgraded;
+
+    let on_upgrade = state
+        .resource_table
+        .get::<HttpStreamResource>(stream_rid)
+        .and_then(|stream| {
+            let mut rd = RcRef::map(&stream, |r| &r.rd)
+                .try_borrow_mut()
+                .ok_or_else(|| bad_resource("request is currently in use"))?;
+
+            match &mut *rd {
+                HttpRequestReader::Headers(request) => Ok(hyper::upgrade::on(request)),
+                _ => Err(custom_error(
+                    "Http",
+                    "cannot upgrade because request body was used",
+                )),
+            }
+        });
+
+    let on_upgrade = match on_upgrade {
+        Ok(it) => it,
+        Err(err) => {
+            let _ = req_end_tx.send(());
+            return Err(err);
+        }
+    };
+
+    tokio::spawn(async move {
+        let result = async {
+            let conn = on_upgrade.await?;
+            let upgraded = on_upgraded.0.await?;
+
+            splice(conn, upgraded).await?;
+            Ok::<_, AnyError>(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            error!("upgraded connection closed with an error: {}", err);
+        }
+
+        let _ = req_end_tx.send(());
+    });
+
+    Ok(())
+}
+
+deno_core::extension!(
+    sb_core_http,
+    ops = [
+        op_http_start,
+        op_http_wait_for_disconnect,
+        op_http_splice_upgraded_response
+    ]
+);
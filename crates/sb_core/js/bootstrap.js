@@ -20,6 +20,8 @@ import * as response from 'ext:deno_fetch/23_response.js';
 import * as request from 'ext:deno_fetch/23_request.js';
 import * as globalInterfaces from 'ext:deno_web/04_global_interfaces.js';
 import { SUPABASE_ENV } from 'ext:sb_env/env.js';
+import { createServiceBindings } from 'ext:sb_user_workers/user_workers.js';
+import * as cache from 'ext:sb_cache/cache.js';
 import { registerErrors } from 'ext:sb_core_main_js/js/errors.js';
 import {
 	formatException,
@@ -53,9 +55,25 @@ const {
 	ObjectDefineProperties,
 	ObjectSetPrototypeOf,
 	ObjectFreeze,
+	PromisePrototypeThen,
+	PromiseResolve,
 	StringPrototypeSplit,
 } = globalThis.__bootstrap.primordials;
 
+// Keeps the worker alive after the response is sent, until `promise` settles
+// or the background deadline of the worker passes.
+function waitUntil(promise) {
+	ops.op_wait_until_add();
+	PromisePrototypeThen(
+		PromiseResolve(promise),
+		() => ops.op_wait_until_done(),
+		(err) => {
+			ops.op_wait_until_done();
+			globalThis.console.error('EdgeRuntime.waitUntil promise rejected:', err);
+		},
+	);
+}
+
 const globalScope = {
 	console: nonEnumerable(
 		new console.Console((msg, level) => core.print(msg, level > 1)),
@@ -156,6 +174,11 @@ const globalScope = {
 	// web sockets
 	WebSocket: nonEnumerable(webSocket.WebSocket),
 
+	// cache
+	Cache: nonEnumerable(cache.Cache),
+	CacheStorage: nonEnumerable(cache.CacheStorage),
+	caches: readOnly(cache.caches),
+
 	// performance
 	Performance: nonEnumerable(performance.Performance),
 	PerformanceEntry: nonEnumerable(performance.PerformanceEntry),
@@ -225,13 +248,77 @@ const globalProperties = {
 };
 ObjectDefineProperties(globalThis, globalProperties);
 
+// Read-only fs APIs user workers keep. They can only reach the static files
+// bundled with the function.
+const userWorkerFsApis = [
+	'cwd',
+	'readFile',
+	'readFileSync',
+	'readTextFile',
+	'readTextFileSync',
+	'stat',
+	'statSync',
+	'lstat',
+	'lstatSync',
+	'readDir',
+	'readDirSync',
+	'realPath',
+	'realPathSync',
+];
+
+// fs APIs user workers get on top of the read-only ones when they have a
+// writable /tmp.
+const userWorkerTmpFsApis = [
+	'writeFile',
+	'writeFileSync',
+	'writeTextFile',
+	'writeTextFileSync',
+	'makeTempFile',
+	'makeTempFileSync',
+	'makeTempDir',
+	'makeTempDirSync',
+	'mkdir',
+	'mkdirSync',
+	'remove',
+	'removeSync',
+	'rename',
+	'renameSync',
+	'copyFile',
+	'copyFileSync',
+	'truncate',
+	'truncateSync',
+	'File',
+	'FsFile',
+	'open',
+	'openSync',
+	'create',
+	'createSync',
+	'seek',
+	'seekSync',
+	'fstat',
+	'fstatSync',
+	'ftruncate',
+	'ftruncateSync',
+	'fsync',
+	'fsyncSync',
+	'fdatasync',
+	'fdatasyncSync',
+];
+
 const deleteDenoApis = (apis) => {
 	apis.forEach((key) => {
 		delete Deno[key];
 	});
 };
 
-globalThis.bootstrapSBEdge = (opts, isUserWorker, isEventsWorker, version) => {
+globalThis.bootstrapSBEdge = (
+	opts,
+	isUserWorker,
+	isEventsWorker,
+	version,
+	hasTmpDir,
+	bindingNames,
+) => {
 	// We should delete this after initialization,
 	// Deleting it during bootstrapping can backfire
 	delete globalThis.__bootstrap;
@@ -265,7 +352,7 @@ globalThis.bootstrapSBEdge = (opts, isUserWorker, isEventsWorker, version) => {
 		version: getterOnly(() => ({
 			deno: `supabase-edge-runtime-${globalThis.SUPABASE_VERSION}`,
 			v8: '11.6.189.12',
-			typescript: '5.1.6',
+			typescript: opts.tsVersion,
 		})),
 	});
 	ObjectDefineProperty(globalThis, 'Deno', readOnly(denoOverrides));
@@ -276,6 +363,11 @@ globalThis.bootstrapSBEdge = (opts, isUserWorker, isEventsWorker, version) => {
 
 	if (isUserWorker) {
 		delete globalThis.EdgeRuntime;
+		ObjectDefineProperty(
+			globalThis,
+			'EdgeRuntime',
+			readOnly(ObjectFreeze({ waitUntil, bindings: createServiceBindings(bindingNames) })),
+		);
 
 		// override console
 		ObjectDefineProperties(globalThis, {
@@ -286,8 +378,10 @@ globalThis.bootstrapSBEdge = (opts, isUserWorker, isEventsWorker, version) => {
 			),
 		});
 
-		// remove all fs APIs except Deno.cwd
-		deleteDenoApis(Object.keys(fsVars).filter((k) => k !== 'cwd'));
+		// remove all fs APIs except the read-only ones, and the ones to
+		// write to /tmp if the worker has one
+		const fsApis = hasTmpDir ? [...userWorkerFsApis, ...userWorkerTmpFsApis] : userWorkerFsApis;
+		deleteDenoApis(Object.keys(fsVars).filter((k) => !fsApis.includes(k)));
 	}
 
 	if (isEventsWorker) {
@@ -4,9 +4,11 @@ import * as timers from 'ext:deno_web/02_timers.js';
 import * as permissions from 'ext:sb_core_main_js/js/permissions.js';
 import { errors } from 'ext:sb_core_main_js/js/errors.js';
 import { serve, serveHttp } from 'ext:sb_core_main_js/js/http.js';
+import { upgradeWebSocket } from 'ext:deno_http/01_http.js';
 import * as fs from 'ext:deno_fs/30_fs.js';
 import { osCalls } from 'ext:sb_os/os.js';
 import * as io from 'ext:deno_io/12_io.js';
+import * as kv from 'ext:sb_kv/kv.js';
 
 const osCallsVars = {
 	gid: osCalls.gid,
@@ -102,6 +104,7 @@ const denoOverrides = {
 	resolveDns: net.resolveDns,
 	serveHttp: serveHttp,
 	serve: serve,
+	upgradeWebSocket: upgradeWebSocket,
 	permissions: permissions.permissions,
 	Permissions: permissions.Permissions,
 	PermissionStatus: permissions.PermissionStatus,
@@ -109,6 +112,11 @@ const denoOverrides = {
 	refTimer: timers.refTimer,
 	unrefTimer: timers.unrefTimer,
 	isatty: (arg) => false,
+	openKv: kv.openKv,
+	Kv: kv.Kv,
+	KvU64: kv.KvU64,
+	KvListIterator: kv.KvListIterator,
+	AtomicOperation: kv.AtomicOperation,
 	...ioVars,
 	...fsVars,
 	...osCallsVars,
@@ -1,7 +1,14 @@
 import { HttpConn } from 'ext:deno_http/01_http.js';
 import { RequestPrototype } from 'ext:deno_fetch/23_request.js';
+import { fromInnerResponse, newInnerResponse } from 'ext:deno_fetch/23_response.js';
+import { signalAbort } from 'ext:deno_web/03_abort_signal.js';
+import DOMException from 'ext:deno_web/01_dom_exception.js';
 
-const { ObjectPrototypeIsPrototypeOf } = globalThis.__bootstrap.primordials;
+const {
+	ArrayPrototypeAt,
+	ObjectPrototypeIsPrototypeOf,
+	SafeSetIterator,
+} = globalThis.__bootstrap.primordials;
 
 const HttpConnPrototypeNextRequest = HttpConn.prototype.nextRequest;
 const HttpConnPrototypeClose = HttpConn.prototype.close;
@@ -10,6 +17,8 @@ const core = globalThis.Deno.core;
 const ops = core.ops;
 
 const watcher = Symbol("watcher");
+const streamRid = Symbol("streamRid");
+const upgradedRid = Symbol("upgradedRid");
 
 function internalServerError() {
 	// "Internal Server Error"
@@ -41,6 +50,20 @@ function internalServerError() {
 	);
 }
 
+// Aborts `req.signal` if the client goes away before the response was sent in
+// full.
+async function abortOnDisconnect(req, watcherRid) {
+	try {
+		const isDisconnected = await core.opAsync('op_http_wait_for_disconnect', watcherRid);
+
+		if (isDisconnected) {
+			req.signal[signalAbort](new DOMException('The client disconnected', 'AbortError'));
+		}
+	} catch {
+		// the connection is closed already
+	}
+}
+
 function serveHttp(conn) {
 	const [connRid, watcherRid] = ops.op_http_start(conn.rid);
 	const httpConn = new HttpConn(connRid, conn.remoteAddr, conn.localAddr);
@@ -53,6 +76,14 @@ function serveHttp(conn) {
 		}
 
 		nextRequest.request[watcher] = watcherRid;
+		abortOnDisconnect(nextRequest.request, watcherRid);
+
+		// Each connection carries a single request, so the resource it added
+		// last is the stream of this request.
+		nextRequest.request[streamRid] = ArrayPrototypeAt(
+			[...new SafeSetIterator(httpConn.managedResources)],
+			-1,
+		);
 
 		return nextRequest;
 	};
@@ -91,6 +122,12 @@ async function serve(args1, args2) {
 		for await (const e of serve) {
 			try {
 				const res = await opts['handler'](e.request);
+				const maybeUpgradedRid = res?.[upgradedRid];
+
+				if (maybeUpgradedRid !== void 0) {
+					ops.op_http_splice_upgraded_response(e.request[streamRid], maybeUpgradedRid);
+				}
+
 				e.respondWith(res);
 			} catch (error) {
 				console.error(error);
@@ -121,6 +158,19 @@ async function serve(args1, args2) {
 	};
 }
 
+// A `101 Switching Protocols` response, which the Response constructor
+// refuses to create. Responding with it splices the connection of the request
+// to the upgraded one of `rid`.
+function upgradedResponse(rid, headerList) {
+	const innerResponse = newInnerResponse(101);
+	innerResponse.headerList = headerList;
+
+	const response = fromInnerResponse(innerResponse, 'immutable');
+	response[upgradedRid] = rid;
+
+	return response;
+}
+
 function getWatcherRid(req) {
 	return req[watcher];
 }
@@ -136,4 +186,4 @@ function applyWatcherRid(src, dest) {
 	dest[watcher] = src[watcher];
 }
 
-export { serve, serveHttp, getWatcherRid, applyWatcherRid };
+export { serve, serveHttp, getWatcherRid, applyWatcherRid, upgradedResponse };
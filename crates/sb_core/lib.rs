@@ -11,6 +11,7 @@ pub mod net;
 pub mod permissions;
 pub mod runtime;
 pub mod transpiler;
+pub mod upgrade;
 pub mod util;
 
 deno_core::extension!(
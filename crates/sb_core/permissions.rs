@@ -1,22 +1,56 @@
+use crate::util::fs::resolve_from_cwd;
 use deno_core::error::{custom_error, AnyError};
 use deno_core::url::Url;
 use deno_fs::OpenOptions;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Where a worker may write to the file system.
+#[derive(Debug, Clone)]
+pub enum FsWriteAccess {
+    All,
+    /// Only inside the directory, e.g. the `/tmp` of a user worker.
+    Within(PathBuf),
+    Denied,
+}
 
 pub struct Permissions {
     net_access_disabled: bool,
+    fs_write_access: FsWriteAccess,
 }
 
 impl Default for Permissions {
     fn default() -> Self {
-        Self::new(false)
+        Self::new(false, FsWriteAccess::All)
     }
 }
 
 impl Permissions {
-    pub fn new(net_access_disabled: bool) -> Self {
+    pub fn new(net_access_disabled: bool, fs_write_access: FsWriteAccess) -> Self {
         Self {
             net_access_disabled,
+            fs_write_access,
+        }
+    }
+
+    fn check_fs_write(&self, path: Option<&Path>) -> Result<(), AnyError> {
+        match (&self.fs_write_access, path) {
+            (FsWriteAccess::All, _) => Ok(()),
+            (FsWriteAccess::Within(dir), Some(path))
+                if resolve_from_cwd(path)?.starts_with(dir) =>
+            {
+                Ok(())
+            }
+            (FsWriteAccess::Within(dir), _) => Err(custom_error(
+                "PermissionDenied",
+                format!(
+                    "write access is limited to {} for the user worker",
+                    dir.display()
+                ),
+            )),
+            (FsWriteAccess::Denied, _) => Err(custom_error(
+                "PermissionDenied",
+                "write access to the file system is denied for the user worker",
+            )),
         }
     }
 
@@ -40,9 +74,12 @@ impl Permissions {
 
 deno_core::extension!(
     sb_core_permissions,
-    options = { net_access_disabled: bool },
+    options = { net_access_disabled: bool, fs_write_access: FsWriteAccess },
     state = |state, options| {
-        state.put::<Permissions>(Permissions::new(options.net_access_disabled));
+        state.put::<Permissions>(Permissions::new(
+            options.net_access_disabled,
+            options.fs_write_access,
+        ));
     }
 );
 
@@ -126,33 +163,42 @@ impl deno_fs::FsPermissions for Permissions {
         Ok(())
     }
 
-    fn check_write(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_write(Some(path))
     }
 
-    fn check_write_partial(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write_partial(&mut self, path: &Path, _api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_write(Some(path))
     }
 
     fn check_write_all(&mut self, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+        self.check_fs_write(None)
     }
 
     fn check_write_blind(
         &mut self,
-        _p: &Path,
+        p: &Path,
         _display: &str,
         _api_name: &str,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_fs_write(Some(p))
     }
 
     fn check(
         &mut self,
-        _open_options: &OpenOptions,
-        _path: &Path,
+        open_options: &OpenOptions,
+        path: &Path,
         _api_name: &str,
     ) -> Result<(), AnyError> {
+        if open_options.write
+            || open_options.append
+            || open_options.create
+            || open_options.create_new
+            || open_options.truncate
+        {
+            self.check_fs_write(Some(path))?;
+        }
+
         Ok(())
     }
 }
@@ -4,6 +4,11 @@ use deno_core::error::AnyError;
 use deno_core::op2;
 use deno_core::ModuleSpecifier;
 use deno_core::OpState;
+use tokio::sync::watch;
+
+/// Number of promises given to `EdgeRuntime.waitUntil` that haven't settled
+/// yet. The supervisor keeps the request open until it drops to zero.
+pub struct PendingBackgroundTasks(pub watch::Sender<usize>);
 
 #[op2]
 #[string]
@@ -23,8 +28,24 @@ fn op_main_module(state: &mut OpState) -> Result<String, AnyError> {
     Ok(main)
 }
 
+#[op2(fast)]
+fn op_wait_until_add(state: &mut OpState) {
+    if let Some(tasks) = state.try_borrow::<PendingBackgroundTasks>() {
+        tasks.0.send_modify(|count| *count += 1);
+    }
+}
+
+#[op2(fast)]
+fn op_wait_until_done(state: &mut OpState) {
+    if let Some(tasks) = state.try_borrow::<PendingBackgroundTasks>() {
+        tasks
+            .0
+            .send_modify(|count| *count = count.saturating_sub(1));
+    }
+}
+
 deno_core::extension!(sb_core_runtime,
-    ops = [op_main_module],
+    ops = [op_main_module, op_wait_until_add, op_wait_until_done],
     options = {
         main_module: Option<ModuleSpecifier>
     },
@@ -0,0 +1,57 @@
+use std::borrow::Cow;
+use std::rc::Rc;
+
+use bytes::Bytes;
+use deno_core::Resource;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+/// The connection to a worker after its response switched protocols (e.g. a
+/// WebSocket handshake). `read_buf` holds what the worker sent right after the
+/// response head.
+pub struct UpgradedStream {
+    pub io: UnixStream,
+    pub read_buf: Bytes,
+}
+
+/// Set on the `101 Switching Protocols` responses of workers. Resolves to the
+/// upgraded connection once the HTTP exchange is over.
+pub struct OnUpgradedStream(pub oneshot::Receiver<UpgradedStream>);
+
+/// Copies bytes both ways between `conn` and an upgraded worker connection
+/// until both sides are closed.
+pub async fn splice<T>(mut conn: T, upgraded: UpgradedStream) -> std::io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let UpgradedStream { mut io, read_buf } = upgraded;
+
+    if !read_buf.is_empty() {
+        conn.write_all(&read_buf).await?;
+    }
+
+    tokio::io::copy_bidirectional(&mut conn, &mut io).await?;
+    Ok(())
+}
+
+/// An upgraded user worker response the main worker hasn't answered its own
+/// request with yet. The request of the user worker lasts until the spliced
+/// connection closes, or until the resource is closed if it's never spliced.
+pub struct UpgradedResponseResource {
+    pub on_upgraded: OnUpgradedStream,
+    pub req_end_tx: mpsc::UnboundedSender<()>,
+}
+
+impl Resource for UpgradedResponseResource {
+    fn name(&self) -> Cow<str> {
+        "upgradedResponse".into()
+    }
+
+    fn close(self: Rc<Self>) {
+        let _ = self.req_end_tx.send(());
+    }
+}
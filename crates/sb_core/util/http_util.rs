@@ -9,6 +9,7 @@ use deno_core::error::AnyError;
 use deno_core::url::Url;
 use deno_fetch::create_http_client;
 use deno_fetch::reqwest;
+use deno_fetch::reqwest::header::AUTHORIZATION;
 use deno_fetch::reqwest::header::LOCATION;
 use deno_fetch::reqwest::Response;
 use deno_fetch::CreateHttpClientOptions;
@@ -277,25 +278,29 @@ impl HttpClient {
     }
 
     pub async fn download<U: reqwest::IntoUrl>(&self, url: U) -> Result<Vec<u8>, AnyError> {
-        let maybe_bytes = self.inner_download(url).await?;
+        let maybe_bytes = self.inner_download(url, None).await?;
         match maybe_bytes {
             Some(bytes) => Ok(bytes),
             None => Err(custom_error("Http", "Not found.")),
         }
     }
 
+    /// `maybe_auth_header` is sent as the `Authorization` header, but not
+    /// to redirects to another origin.
     pub async fn download_with_progress<U: reqwest::IntoUrl>(
         &self,
         url: U,
+        maybe_auth_header: Option<String>,
     ) -> Result<Option<Vec<u8>>, AnyError> {
-        self.inner_download(url).await
+        self.inner_download(url, maybe_auth_header).await
     }
 
     async fn inner_download<U: reqwest::IntoUrl>(
         &self,
         url: U,
+        maybe_auth_header: Option<String>,
     ) -> Result<Option<Vec<u8>>, AnyError> {
-        let response = self.get_redirected_response(url).await?;
+        let response = self.get_redirected_response(url, maybe_auth_header).await?;
 
         if response.status() == 404 {
             return Ok(None);
@@ -318,14 +323,26 @@ impl HttpClient {
     pub async fn get_redirected_response<U: reqwest::IntoUrl>(
         &self,
         url: U,
+        maybe_auth_header: Option<String>,
     ) -> Result<Response, AnyError> {
         let mut url = url.into_url()?;
-        let mut response = self.get_no_redirect(url.clone())?.send().await?;
+        let origin = url.origin();
+        let request = |url: &Url| -> Result<reqwest::RequestBuilder, AnyError> {
+            let request = self.get_no_redirect(url.clone())?;
+            Ok(match &maybe_auth_header {
+                Some(auth_header) if url.origin() == origin => {
+                    request.header(AUTHORIZATION, auth_header)
+                }
+                _ => request,
+            })
+        };
+
+        let mut response = request(&url)?.send().await?;
         let status = response.status();
         if status.is_redirection() {
             for _ in 0..5 {
                 let new_url = resolve_redirect_from_response(&url, &response)?;
-                let new_response = self.get_no_redirect(new_url.clone())?.send().await?;
+                let new_response = request(&new_url)?.send().await?;
                 let status = new_response.status();
                 if status.is_redirection() {
                     response = new_response;
@@ -1,12 +1,18 @@
 use crate::virtual_fs::{FileBackedVfs, VfsBuilder, VfsRoot, VirtualDirectory};
+use anyhow::{bail, Context};
 use deno_core::error::AnyError;
 use deno_core::serde_json;
 use deno_npm::NpmSystemInfo;
+use sb_core::util::fs::canonicalize_path;
 use sb_npm::{CliNpmRegistryApi, CliNpmResolver, NpmCache, NpmResolution};
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub mod file_system;
+pub mod static_fs;
+pub mod tmp_fs;
 pub mod virtual_fs;
 
 pub struct VfsOpts {
@@ -38,6 +44,8 @@ pub fn build_vfs(opts: VfsOpts) -> Result<VfsBuilder, AnyError> {
     if let Some(node_modules_path) = npm_resolver.node_modules_path() {
         let mut builder = VfsBuilder::new(node_modules_path.clone())?;
         builder.add_dir_recursive(&node_modules_path)?;
+        // it's mounted as `node_modules`, whatever the local directory is named
+        builder.set_root_dir_name("node_modules".to_string());
         Ok(builder)
     } else {
         // DO NOT include the user's registry url as it may contain credentials,
@@ -59,3 +67,61 @@ pub fn build_vfs(opts: VfsOpts) -> Result<VfsBuilder, AnyError> {
         Ok(builder)
     }
 }
+
+/// The static files bundled with a function. They're mounted at the directory
+/// they were bundled from, so the paths the modules read them by still work.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StaticFilesData {
+    pub root_path: PathBuf,
+    pub dir: VirtualDirectory,
+}
+
+/// Collects the files matching `patterns`, globs relative to `root_path`.
+/// Returns `None` if no file matches.
+pub fn build_static_vfs(
+    root_path: &Path,
+    patterns: &[String],
+) -> Result<Option<StaticFilesData>, AnyError> {
+    let root_path = canonicalize_path(root_path)?;
+    let mut paths = BTreeSet::new();
+
+    for pattern in patterns {
+        let full_pattern = root_path.join(pattern);
+        let entries = glob::glob(&full_pattern.to_string_lossy())
+            .with_context(|| format!("invalid static files pattern ({})", pattern))?;
+
+        for entry in entries {
+            let path = canonicalize_path(&entry?)?;
+            if !path.is_file() {
+                continue;
+            }
+            if !path.starts_with(&root_path) {
+                bail!(
+                    "static file {} is outside of {}",
+                    path.display(),
+                    root_path.display()
+                );
+            }
+
+            paths.insert(path);
+        }
+    }
+
+    if paths.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = VfsBuilder::new(root_path.clone())?;
+    for path in &paths {
+        builder.add_file_at_path(path)?;
+    }
+    let (dir, _files) = builder.into_dir_and_files();
+
+    Ok(Some(StaticFilesData { root_path, dir }))
+}
+
+pub fn load_static_vfs(data: &[u8]) -> Result<FileBackedVfs, AnyError> {
+    let StaticFilesData { root_path, dir } = serde_json::from_slice(data)?;
+
+    Ok(FileBackedVfs::new(VfsRoot { dir, root_path }))
+}
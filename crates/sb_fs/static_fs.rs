@@ -0,0 +1,320 @@
+use deno_fs::{FileSystem, FsDirEntry, FsFileType, OpenOptions, RealFs};
+use deno_io::fs::{File, FsError, FsResult, FsStat};
+use sb_core::util::fs::resolve_from_cwd;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::tmp_fs::{TmpFs, TMP_DIR};
+use super::virtual_fs::FileBackedVfs;
+
+/// The file system of user workers. The static files bundled with the
+/// function can be read, and the scratch directory of the worker, if it has
+/// one, can be read and written. Nothing else is reachable.
+#[derive(Debug, Clone, Default)]
+pub struct StaticFs {
+    static_vfs: Option<Arc<FileBackedVfs>>,
+    tmp: Option<TmpFs>,
+}
+
+enum Target<'a> {
+    Tmp(&'a TmpFs),
+    Static(&'a Arc<FileBackedVfs>),
+}
+
+fn permission_denied(message: String) -> FsError {
+    FsError::Io(io::Error::new(io::ErrorKind::PermissionDenied, message))
+}
+
+fn is_write(options: &OpenOptions) -> bool {
+    options.write || options.append || options.create || options.create_new || options.truncate
+}
+
+impl StaticFs {
+    pub fn new(static_vfs: Option<FileBackedVfs>, tmp: Option<TmpFs>) -> Self {
+        Self {
+            static_vfs: static_vfs.map(Arc::new),
+            tmp,
+        }
+    }
+
+    /// Where `path` is. Relative paths are resolved against the current
+    /// directory, as the real file system would.
+    fn target(&self, path: &Path) -> FsResult<(Target, PathBuf)> {
+        let path = resolve_from_cwd(path).map_err(|err| permission_denied(err.to_string()))?;
+
+        match (&self.tmp, &self.static_vfs) {
+            (Some(tmp), _) if tmp.is_path_within(&path) => Ok((Target::Tmp(tmp), path)),
+            (_, Some(vfs)) if vfs.is_path_within(&path) => Ok((Target::Static(vfs), path)),
+            _ => Err(permission_denied(format!(
+                "{} can't be accessed by the user worker",
+                path.display()
+            ))),
+        }
+    }
+
+    fn writable(&self, path: &Path) -> FsResult<(&TmpFs, PathBuf)> {
+        match self.target(path) {
+            Ok((Target::Tmp(tmp), path)) => Ok((tmp, path)),
+            _ if self.tmp.is_some() => Err(permission_denied(format!(
+                "{} can't be written to, only {} is writable",
+                path.display(),
+                TMP_DIR
+            ))),
+            _ => Err(permission_denied(format!(
+                "{} can't be written to, the user worker has no writable {}",
+                path.display(),
+                TMP_DIR
+            ))),
+        }
+    }
+
+    fn copy_static_file(
+        &self,
+        vfs: &FileBackedVfs,
+        oldpath: &Path,
+        tmp: &TmpFs,
+        newpath: &Path,
+    ) -> FsResult<()> {
+        let file = vfs.file_entry(oldpath)?;
+        let data = vfs.read_file_all(file)?;
+
+        tmp.write_file_sync(newpath, OpenOptions::write(true, false, false, None), &data)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl FileSystem for StaticFs {
+    fn cwd(&self) -> FsResult<PathBuf> {
+        RealFs.cwd()
+    }
+
+    fn tmp_dir(&self) -> FsResult<PathBuf> {
+        match &self.tmp {
+            Some(tmp) => tmp.tmp_dir(),
+            None => RealFs.tmp_dir(),
+        }
+    }
+
+    fn chdir(&self, _path: &Path) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn umask(&self, _mask: Option<u32>) -> FsResult<u32> {
+        Err(FsError::NotSupported)
+    }
+
+    fn open_sync(&self, path: &Path, options: OpenOptions) -> FsResult<Rc<dyn File>> {
+        if is_write(&options) {
+            let (tmp, path) = self.writable(path)?;
+            return tmp.open_sync(&path, options);
+        }
+
+        match self.target(path)? {
+            (Target::Tmp(tmp), path) => tmp.open_sync(&path, options),
+            (Target::Static(vfs), path) => Ok(vfs.open_file(&path)?),
+        }
+    }
+    async fn open_async(&self, path: PathBuf, options: OpenOptions) -> FsResult<Rc<dyn File>> {
+        if is_write(&options) {
+            let (tmp, path) = self.writable(&path)?;
+            return tmp.open_async(path, options).await;
+        }
+
+        match self.target(&path)? {
+            (Target::Tmp(tmp), path) => tmp.open_async(path, options).await,
+            (Target::Static(vfs), path) => Ok(vfs.open_file(&path)?),
+        }
+    }
+
+    fn mkdir_sync(&self, path: &Path, recursive: bool, mode: u32) -> FsResult<()> {
+        let (tmp, path) = self.writable(path)?;
+        tmp.mkdir_sync(&path, recursive, mode)
+    }
+    async fn mkdir_async(&self, path: PathBuf, recursive: bool, mode: u32) -> FsResult<()> {
+        let (tmp, path) = self.writable(&path)?;
+        tmp.mkdir_async(path, recursive, mode).await
+    }
+
+    fn chmod_sync(&self, path: &Path, mode: u32) -> FsResult<()> {
+        let (tmp, path) = self.writable(path)?;
+        tmp.chmod_sync(&path, mode)
+    }
+    async fn chmod_async(&self, path: PathBuf, mode: u32) -> FsResult<()> {
+        let (tmp, path) = self.writable(&path)?;
+        tmp.chmod_async(path, mode).await
+    }
+
+    fn chown_sync(&self, _path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+    async fn chown_async(
+        &self,
+        _path: PathBuf,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn remove_sync(&self, path: &Path, recursive: bool) -> FsResult<()> {
+        let (tmp, path) = self.writable(path)?;
+        tmp.remove_sync(&path, recursive)
+    }
+    async fn remove_async(&self, path: PathBuf, recursive: bool) -> FsResult<()> {
+        let (tmp, path) = self.writable(&path)?;
+        tmp.remove_async(path, recursive).await
+    }
+
+    fn copy_file_sync(&self, oldpath: &Path, newpath: &Path) -> FsResult<()> {
+        let (tmp, newpath) = self.writable(newpath)?;
+
+        match self.target(oldpath)? {
+            (Target::Tmp(_), oldpath) => tmp.copy_file_sync(&oldpath, &newpath),
+            (Target::Static(vfs), oldpath) => self.copy_static_file(vfs, &oldpath, tmp, &newpath),
+        }
+    }
+    async fn copy_file_async(&self, oldpath: PathBuf, newpath: PathBuf) -> FsResult<()> {
+        let (tmp, newpath) = self.writable(&newpath)?;
+
+        match self.target(&oldpath)? {
+            (Target::Tmp(_), oldpath) => tmp.copy_file_async(oldpath, newpath).await,
+            (Target::Static(vfs), oldpath) => self.copy_static_file(vfs, &oldpath, tmp, &newpath),
+        }
+    }
+
+    fn stat_sync(&self, path: &Path) -> FsResult<FsStat> {
+        match self.target(path)? {
+            (Target::Tmp(tmp), path) => tmp.stat_sync(&path),
+            (Target::Static(vfs), path) => Ok(vfs.stat(&path)?),
+        }
+    }
+    async fn stat_async(&self, path: PathBuf) -> FsResult<FsStat> {
+        match self.target(&path)? {
+            (Target::Tmp(tmp), path) => tmp.stat_async(path).await,
+            (Target::Static(vfs), path) => Ok(vfs.stat(&path)?),
+        }
+    }
+
+    fn lstat_sync(&self, path: &Path) -> FsResult<FsStat> {
+        match self.target(path)? {
+            (Target::Tmp(tmp), path) => tmp.lstat_sync(&path),
+            (Target::Static(vfs), path) => Ok(vfs.lstat(&path)?),
+        }
+    }
+    async fn lstat_async(&self, path: PathBuf) -> FsResult<FsStat> {
+        match self.target(&path)? {
+            (Target::Tmp(tmp), path) => tmp.lstat_async(path).await,
+            (Target::Static(vfs), path) => Ok(vfs.lstat(&path)?),
+        }
+    }
+
+    fn realpath_sync(&self, path: &Path) -> FsResult<PathBuf> {
+        match self.target(path)? {
+            (Target::Tmp(tmp), path) => tmp.realpath_sync(&path),
+            (Target::Static(vfs), path) => Ok(vfs.canonicalize(&path)?),
+        }
+    }
+    async fn realpath_async(&self, path: PathBuf) -> FsResult<PathBuf> {
+        match self.target(&path)? {
+            (Target::Tmp(tmp), path) => tmp.realpath_async(path).await,
+            (Target::Static(vfs), path) => Ok(vfs.canonicalize(&path)?),
+        }
+    }
+
+    fn read_dir_sync(&self, path: &Path) -> FsResult<Vec<FsDirEntry>> {
+        match self.target(path)? {
+            (Target::Tmp(tmp), path) => tmp.read_dir_sync(&path),
+            (Target::Static(vfs), path) => Ok(vfs.read_dir(&path)?),
+        }
+    }
+    async fn read_dir_async(&self, path: PathBuf) -> FsResult<Vec<FsDirEntry>> {
+        match self.target(&path)? {
+            (Target::Tmp(tmp), path) => tmp.read_dir_async(path).await,
+            (Target::Static(vfs), path) => Ok(vfs.read_dir(&path)?),
+        }
+    }
+
+    fn rename_sync(&self, oldpath: &Path, newpath: &Path) -> FsResult<()> {
+        let (tmp, oldpath) = self.writable(oldpath)?;
+        let (_, newpath) = self.writable(newpath)?;
+        tmp.rename_sync(&oldpath, &newpath)
+    }
+    async fn rename_async(&self, oldpath: PathBuf, newpath: PathBuf) -> FsResult<()> {
+        let (tmp, oldpath) = self.writable(&oldpath)?;
+        let (_, newpath) = self.writable(&newpath)?;
+        tmp.rename_async(oldpath, newpath).await
+    }
+
+    fn link_sync(&self, _oldpath: &Path, _newpath: &Path) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+    async fn link_async(&self, _oldpath: PathBuf, _newpath: PathBuf) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn symlink_sync(
+        &self,
+        _oldpath: &Path,
+        _newpath: &Path,
+        _file_type: Option<FsFileType>,
+    ) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+    async fn symlink_async(
+        &self,
+        _oldpath: PathBuf,
+        _newpath: PathBuf,
+        _file_type: Option<FsFileType>,
+    ) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn read_link_sync(&self, path: &Path) -> FsResult<PathBuf> {
+        match self.target(path)? {
+            (Target::Tmp(tmp), path) => tmp.read_link_sync(&path),
+            (Target::Static(vfs), path) => Ok(vfs.read_link(&path)?),
+        }
+    }
+    async fn read_link_async(&self, path: PathBuf) -> FsResult<PathBuf> {
+        match self.target(&path)? {
+            (Target::Tmp(tmp), path) => tmp.read_link_async(path).await,
+            (Target::Static(vfs), path) => Ok(vfs.read_link(&path)?),
+        }
+    }
+
+    fn truncate_sync(&self, path: &Path, len: u64) -> FsResult<()> {
+        let (tmp, path) = self.writable(path)?;
+        tmp.truncate_sync(&path, len)
+    }
+    async fn truncate_async(&self, path: PathBuf, len: u64) -> FsResult<()> {
+        let (tmp, path) = self.writable(&path)?;
+        tmp.truncate_async(path, len).await
+    }
+
+    fn utime_sync(
+        &self,
+        path: &Path,
+        atime_secs: i64,
+        atime_nanos: u32,
+        mtime_secs: i64,
+        mtime_nanos: u32,
+    ) -> FsResult<()> {
+        let (tmp, path) = self.writable(path)?;
+        tmp.utime_sync(&path, atime_secs, atime_nanos, mtime_secs, mtime_nanos)
+    }
+    async fn utime_async(
+        &self,
+        path: PathBuf,
+        atime_secs: i64,
+        atime_nanos: u32,
+        mtime_secs: i64,
+        mtime_nanos: u32,
+    ) -> FsResult<()> {
+        let (tmp, path) = self.writable(&path)?;
+        tmp.utime_async(path, atime_secs, atime_nanos, mtime_secs, mtime_nanos)
+            .await
+    }
+}
@@ -0,0 +1,583 @@
+use deno_core::parking_lot::Mutex;
+use deno_core::{BufMutView, BufView, ResourceHandleFd, WriteOutcome};
+use deno_fs::{FileSystem, FsDirEntry, FsFileType, OpenOptions, RealFs};
+use deno_io::fs::{File, FsError, FsResult, FsStat};
+use sb_core::util::fs::{canonicalize_path, dir_size};
+use std::io;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Where user workers see their scratch directory.
+pub const TMP_DIR: &str = "/tmp";
+
+/// Prefix of the host directories the scratch directories are kept in. There
+/// is one per server process, named after its pid.
+const HOST_DIR_PREFIX: &str = "sb-user-worker-tmp-";
+
+/// The host directory the scratch directories of this process are kept in.
+pub fn host_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("{}{}", HOST_DIR_PREFIX, std::process::id()))
+}
+
+fn is_process_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Removes the scratch directories left behind by server processes that
+/// didn't get to drop their workers, such as after a crash.
+pub fn remove_stale_host_dirs() {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|it| it.strip_prefix(HOST_DIR_PREFIX))
+            .and_then(|it| it.parse::<libc::pid_t>().ok())
+        else {
+            continue;
+        };
+        if pid <= 0 || pid as u32 == std::process::id() || is_process_alive(pid) {
+            continue;
+        }
+
+        if let Err(err) = std::fs::remove_dir_all(entry.path()) {
+            log::error!("failed to remove {}: {}", entry.path().display(), err);
+        }
+    }
+}
+
+/// Bytes written to the scratch directory of a worker, counted against its
+/// quota.
+#[derive(Debug)]
+struct TmpQuota {
+    root: PathBuf,
+    limit: u64,
+    used: Mutex<u64>,
+}
+
+impl TmpQuota {
+    /// Writes are counted as they come, so overwriting or deleting data isn't
+    /// noticed until the directory is measured again. That's only done when
+    /// the quota seems exceeded, and after the operations that free space.
+    fn reserve(&self, len: u64) -> FsResult<()> {
+        let mut used = self.used.lock();
+
+        if *used + len > self.limit {
+            *used = dir_size(&self.root).unwrap_or(*used);
+        }
+        if *used + len > self.limit {
+            return Err(FsError::Io(io::Error::new(
+                io::ErrorKind::Other,
+                format!("the {} quota of {} bytes is exceeded", TMP_DIR, self.limit),
+            )));
+        }
+
+        *used += len;
+        Ok(())
+    }
+
+    fn recount(&self) {
+        let mut used = self.used.lock();
+        *used = dir_size(&self.root).unwrap_or(*used);
+    }
+}
+
+impl Drop for TmpQuota {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.root) {
+            log::error!("failed to remove {}: {}", self.root.display(), err);
+        }
+    }
+}
+
+/// The scratch directory of a user worker, seen as [`TMP_DIR`]. It's backed
+/// by a directory of the host that is removed once the worker is dropped, and
+/// writes fail when the files in it would exceed the quota.
+///
+/// The files are on the disk of the host, so the quota is a budget of its own
+/// rather than part of the memory limit of the worker. The directories of a
+/// process that crashed are removed by [`remove_stale_host_dirs`].
+///
+/// Paths given to it must be absolute, normalized and inside [`TMP_DIR`].
+#[derive(Debug, Clone)]
+pub struct TmpFs {
+    quota: Arc<TmpQuota>,
+}
+
+impl TmpFs {
+    pub fn new(root: PathBuf, limit: u64) -> io::Result<Self> {
+        std::fs::create_dir_all(&root)?;
+        let root = canonicalize_path(&root)?;
+
+        Ok(Self {
+            quota: Arc::new(TmpQuota {
+                root,
+                limit,
+                used: Mutex::new(0),
+            }),
+        })
+    }
+
+    pub fn is_path_within(&self, path: &Path) -> bool {
+        path.starts_with(TMP_DIR)
+    }
+
+    fn real_path(&self, path: &Path) -> FsResult<PathBuf> {
+        match path.strip_prefix(TMP_DIR) {
+            Ok(relative) => Ok(self.quota.root.join(relative)),
+            Err(_) => Err(FsError::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is outside of {}", path.display(), TMP_DIR),
+            ))),
+        }
+    }
+
+    fn virtual_path(&self, real_path: &Path) -> FsResult<PathBuf> {
+        match real_path.strip_prefix(&self.quota.root) {
+            Ok(relative) => Ok(Path::new(TMP_DIR).join(relative)),
+            Err(_) => Err(FsError::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} resolves outside of {}", real_path.display(), TMP_DIR),
+            ))),
+        }
+    }
+
+    fn wrap_file(&self, inner: Rc<dyn File>) -> Rc<dyn File> {
+        Rc::new(TmpFile {
+            inner,
+            quota: self.quota.clone(),
+        })
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl FileSystem for TmpFs {
+    fn cwd(&self) -> FsResult<PathBuf> {
+        RealFs.cwd()
+    }
+
+    fn tmp_dir(&self) -> FsResult<PathBuf> {
+        Ok(PathBuf::from(TMP_DIR))
+    }
+
+    fn chdir(&self, _path: &Path) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn umask(&self, _mask: Option<u32>) -> FsResult<u32> {
+        Err(FsError::NotSupported)
+    }
+
+    fn open_sync(&self, path: &Path, options: OpenOptions) -> FsResult<Rc<dyn File>> {
+        let file = RealFs.open_sync(&self.real_path(path)?, options)?;
+        if options.truncate {
+            self.quota.recount();
+        }
+
+        Ok(self.wrap_file(file))
+    }
+    async fn open_async(&self, path: PathBuf, options: OpenOptions) -> FsResult<Rc<dyn File>> {
+        let file = RealFs.open_async(self.real_path(&path)?, options).await?;
+        if options.truncate {
+            self.quota.recount();
+        }
+
+        Ok(self.wrap_file(file))
+    }
+
+    fn mkdir_sync(&self, path: &Path, recursive: bool, mode: u32) -> FsResult<()> {
+        RealFs.mkdir_sync(&self.real_path(path)?, recursive, mode)
+    }
+    async fn mkdir_async(&self, path: PathBuf, recursive: bool, mode: u32) -> FsResult<()> {
+        RealFs
+            .mkdir_async(self.real_path(&path)?, recursive, mode)
+            .await
+    }
+
+    fn chmod_sync(&self, path: &Path, mode: u32) -> FsResult<()> {
+        RealFs.chmod_sync(&self.real_path(path)?, mode)
+    }
+    async fn chmod_async(&self, path: PathBuf, mode: u32) -> FsResult<()> {
+        RealFs.chmod_async(self.real_path(&path)?, mode).await
+    }
+
+    fn chown_sync(&self, _path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+    async fn chown_async(
+        &self,
+        _path: PathBuf,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn remove_sync(&self, path: &Path, recursive: bool) -> FsResult<()> {
+        RealFs.remove_sync(&self.real_path(path)?, recursive)?;
+        self.quota.recount();
+        Ok(())
+    }
+    async fn remove_async(&self, path: PathBuf, recursive: bool) -> FsResult<()> {
+        RealFs
+            .remove_async(self.real_path(&path)?, recursive)
+            .await?;
+        self.quota.recount();
+        Ok(())
+    }
+
+    fn copy_file_sync(&self, oldpath: &Path, newpath: &Path) -> FsResult<()> {
+        let oldpath = self.real_path(oldpath)?;
+        self.quota.reserve(RealFs.stat_sync(&oldpath)?.size)?;
+        RealFs.copy_file_sync(&oldpath, &self.real_path(newpath)?)?;
+        self.quota.recount();
+        Ok(())
+    }
+    async fn copy_file_async(&self, oldpath: PathBuf, newpath: PathBuf) -> FsResult<()> {
+        let oldpath = self.real_path(&oldpath)?;
+        self.quota
+            .reserve(RealFs.stat_async(oldpath.clone()).await?.size)?;
+        RealFs
+            .copy_file_async(oldpath, self.real_path(&newpath)?)
+            .await?;
+        self.quota.recount();
+        Ok(())
+    }
+
+    fn stat_sync(&self, path: &Path) -> FsResult<FsStat> {
+        RealFs.stat_sync(&self.real_path(path)?)
+    }
+    async fn stat_async(&self, path: PathBuf) -> FsResult<FsStat> {
+        RealFs.stat_async(self.real_path(&path)?).await
+    }
+
+    fn lstat_sync(&self, path: &Path) -> FsResult<FsStat> {
+        RealFs.lstat_sync(&self.real_path(path)?)
+    }
+    async fn lstat_async(&self, path: PathBuf) -> FsResult<FsStat> {
+        RealFs.lstat_async(self.real_path(&path)?).await
+    }
+
+    fn realpath_sync(&self, path: &Path) -> FsResult<PathBuf> {
+        self.virtual_path(&RealFs.realpath_sync(&self.real_path(path)?)?)
+    }
+    async fn realpath_async(&self, path: PathBuf) -> FsResult<PathBuf> {
+        self.virtual_path(&RealFs.realpath_async(self.real_path(&path)?).await?)
+    }
+
+    fn read_dir_sync(&self, path: &Path) -> FsResult<Vec<FsDirEntry>> {
+        RealFs.read_dir_sync(&self.real_path(path)?)
+    }
+    async fn read_dir_async(&self, path: PathBuf) -> FsResult<Vec<FsDirEntry>> {
+        RealFs.read_dir_async(self.real_path(&path)?).await
+    }
+
+    fn rename_sync(&self, oldpath: &Path, newpath: &Path) -> FsResult<()> {
+        RealFs.rename_sync(&self.real_path(oldpath)?, &self.real_path(newpath)?)?;
+        self.quota.recount();
+        Ok(())
+    }
+    async fn rename_async(&self, oldpath: PathBuf, newpath: PathBuf) -> FsResult<()> {
+        RealFs
+            .rename_async(self.real_path(&oldpath)?, self.real_path(&newpath)?)
+            .await?;
+        self.quota.recount();
+        Ok(())
+    }
+
+    // Links could point outside of the directory, or share data the quota
+    // would count twice.
+    fn link_sync(&self, _oldpath: &Path, _newpath: &Path) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+    async fn link_async(&self, _oldpath: PathBuf, _newpath: PathBuf) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn symlink_sync(
+        &self,
+        _oldpath: &Path,
+        _newpath: &Path,
+        _file_type: Option<FsFileType>,
+    ) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+    async fn symlink_async(
+        &self,
+        _oldpath: PathBuf,
+        _newpath: PathBuf,
+        _file_type: Option<FsFileType>,
+    ) -> FsResult<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn read_link_sync(&self, path: &Path) -> FsResult<PathBuf> {
+        self.virtual_path(&RealFs.read_link_sync(&self.real_path(path)?)?)
+    }
+    async fn read_link_async(&self, path: PathBuf) -> FsResult<PathBuf> {
+        self.virtual_path(&RealFs.read_link_async(self.real_path(&path)?).await?)
+    }
+
+    fn truncate_sync(&self, path: &Path, len: u64) -> FsResult<()> {
+        self.quota.reserve(len)?;
+        RealFs.truncate_sync(&self.real_path(path)?, len)?;
+        self.quota.recount();
+        Ok(())
+    }
+    async fn truncate_async(&self, path: PathBuf, len: u64) -> FsResult<()> {
+        self.quota.reserve(len)?;
+        RealFs.truncate_async(self.real_path(&path)?, len).await?;
+        self.quota.recount();
+        Ok(())
+    }
+
+    fn utime_sync(
+        &self,
+        path: &Path,
+        atime_secs: i64,
+        atime_nanos: u32,
+        mtime_secs: i64,
+        mtime_nanos: u32,
+    ) -> FsResult<()> {
+        RealFs.utime_sync(
+            &self.real_path(path)?,
+            atime_secs,
+            atime_nanos,
+            mtime_secs,
+            mtime_nanos,
+        )
+    }
+    async fn utime_async(
+        &self,
+        path: PathBuf,
+        atime_secs: i64,
+        atime_nanos: u32,
+        mtime_secs: i64,
+        mtime_nanos: u32,
+    ) -> FsResult<()> {
+        RealFs
+            .utime_async(
+                self.real_path(&path)?,
+                atime_secs,
+                atime_nanos,
+                mtime_secs,
+                mtime_nanos,
+            )
+            .await
+    }
+}
+
+/// A file of the scratch directory. Writes are counted against the quota
+/// before they happen.
+struct TmpFile {
+    inner: Rc<dyn File>,
+    quota: Arc<TmpQuota>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl File for TmpFile {
+    fn read_sync(self: Rc<Self>, buf: &mut [u8]) -> FsResult<usize> {
+        self.inner.clone().read_sync(buf)
+    }
+    async fn read_byob(self: Rc<Self>, buf: BufMutView) -> FsResult<(usize, BufMutView)> {
+        self.inner.clone().read_byob(buf).await
+    }
+
+    fn write_sync(self: Rc<Self>, buf: &[u8]) -> FsResult<usize> {
+        self.quota.reserve(buf.len() as u64)?;
+        self.inner.clone().write_sync(buf)
+    }
+    async fn write(self: Rc<Self>, buf: BufView) -> FsResult<WriteOutcome> {
+        self.quota.reserve(buf.len() as u64)?;
+        self.inner.clone().write(buf).await
+    }
+
+    fn write_all_sync(self: Rc<Self>, buf: &[u8]) -> FsResult<()> {
+        self.quota.reserve(buf.len() as u64)?;
+        self.inner.clone().write_all_sync(buf)
+    }
+    async fn write_all(self: Rc<Self>, buf: BufView) -> FsResult<()> {
+        self.quota.reserve(buf.len() as u64)?;
+        self.inner.clone().write_all(buf).await
+    }
+
+    fn read_all_sync(self: Rc<Self>) -> FsResult<Vec<u8>> {
+        self.inner.clone().read_all_sync()
+    }
+    async fn read_all_async(self: Rc<Self>) -> FsResult<Vec<u8>> {
+        self.inner.clone().read_all_async().await
+    }
+
+    fn chmod_sync(self: Rc<Self>, pathmode: u32) -> FsResult<()> {
+        self.inner.clone().chmod_sync(pathmode)
+    }
+    async fn chmod_async(self: Rc<Self>, mode: u32) -> FsResult<()> {
+        self.inner.clone().chmod_async(mode).await
+    }
+
+    fn seek_sync(self: Rc<Self>, pos: SeekFrom) -> FsResult<u64> {
+        self.inner.clone().seek_sync(pos)
+    }
+    async fn seek_async(self: Rc<Self>, pos: SeekFrom) -> FsResult<u64> {
+        self.inner.clone().seek_async(pos).await
+    }
+
+    fn datasync_sync(self: Rc<Self>) -> FsResult<()> {
+        self.inner.clone().datasync_sync()
+    }
+    async fn datasync_async(self: Rc<Self>) -> FsResult<()> {
+        self.inner.clone().datasync_async().await
+    }
+
+    fn sync_sync(self: Rc<Self>) -> FsResult<()> {
+        self.inner.clone().sync_sync()
+    }
+    async fn sync_async(self: Rc<Self>) -> FsResult<()> {
+        self.inner.clone().sync_async().await
+    }
+
+    fn stat_sync(self: Rc<Self>) -> FsResult<FsStat> {
+        self.inner.clone().stat_sync()
+    }
+    async fn stat_async(self: Rc<Self>) -> FsResult<FsStat> {
+        self.inner.clone().stat_async().await
+    }
+
+    fn lock_sync(self: Rc<Self>, exclusive: bool) -> FsResult<()> {
+        self.inner.clone().lock_sync(exclusive)
+    }
+    async fn lock_async(self: Rc<Self>, exclusive: bool) -> FsResult<()> {
+        self.inner.clone().lock_async(exclusive).await
+    }
+
+    fn unlock_sync(self: Rc<Self>) -> FsResult<()> {
+        self.inner.clone().unlock_sync()
+    }
+    async fn unlock_async(self: Rc<Self>) -> FsResult<()> {
+        self.inner.clone().unlock_async().await
+    }
+
+    fn truncate_sync(self: Rc<Self>, len: u64) -> FsResult<()> {
+        self.quota.reserve(len)?;
+        self.inner.clone().truncate_sync(len)?;
+        self.quota.recount();
+        Ok(())
+    }
+    async fn truncate_async(self: Rc<Self>, len: u64) -> FsResult<()> {
+        self.quota.reserve(len)?;
+        self.inner.clone().truncate_async(len).await?;
+        self.quota.recount();
+        Ok(())
+    }
+
+    fn utime_sync(
+        self: Rc<Self>,
+        atime_secs: i64,
+        atime_nanos: u32,
+        mtime_secs: i64,
+        mtime_nanos: u32,
+    ) -> FsResult<()> {
+        self.inner
+            .clone()
+            .utime_sync(atime_secs, atime_nanos, mtime_secs, mtime_nanos)
+    }
+    async fn utime_async(
+        self: Rc<Self>,
+        atime_secs: i64,
+        atime_nanos: u32,
+        mtime_secs: i64,
+        mtime_nanos: u32,
+    ) -> FsResult<()> {
+        self.inner
+            .clone()
+            .utime_async(atime_secs, atime_nanos, mtime_secs, mtime_nanos)
+            .await
+    }
+
+    // lower level functionality
+    fn as_stdio(self: Rc<Self>) -> FsResult<std::process::Stdio> {
+        Err(FsError::NotSupported)
+    }
+    fn backing_fd(self: Rc<Self>) -> Option<ResourceHandleFd> {
+        self.inner.clone().backing_fd()
+    }
+    fn try_clone_inner(self: Rc<Self>) -> FsResult<Rc<dyn File>> {
+        Ok(Rc::new(TmpFile {
+            inner: self.inner.clone().try_clone_inner()?,
+            quota: self.quota.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{host_dir, remove_stale_host_dirs, TmpFs, HOST_DIR_PREFIX, TMP_DIR};
+    use deno_fs::{FileSystem, OpenOptions};
+    use std::path::Path;
+
+    #[test]
+    fn test_remove_stale_host_dirs() {
+        // no process can have this pid, as it's above the maximum
+        let stale = std::env::temp_dir().join(format!("{}{}", HOST_DIR_PREFIX, i32::MAX));
+        let own = host_dir().join("test-remove-stale-host-dirs");
+        std::fs::create_dir_all(&stale).unwrap();
+        std::fs::create_dir_all(&own).unwrap();
+
+        remove_stale_host_dirs();
+
+        assert!(!stale.exists());
+        assert!(own.exists());
+        std::fs::remove_dir_all(own).unwrap();
+    }
+
+    #[test]
+    fn test_tmp_quota() {
+        let root = std::env::temp_dir().join(format!("sb-tmp-fs-test-{}", std::process::id()));
+        let fs = TmpFs::new(root.clone(), 10).unwrap();
+        let path = Path::new(TMP_DIR).join("a.txt");
+
+        fs.write_file_sync(
+            &path,
+            OpenOptions::write(true, false, false, None),
+            b"12345678",
+        )
+        .unwrap();
+        assert_eq!(fs.read_file_sync(&path).unwrap(), b"12345678");
+
+        // overwriting the file frees what it held
+        fs.write_file_sync(
+            &path,
+            OpenOptions::write(true, false, false, None),
+            b"87654321",
+        )
+        .unwrap();
+
+        let other = Path::new(TMP_DIR).join("b.txt");
+        assert!(fs
+            .write_file_sync(
+                &other,
+                OpenOptions::write(true, false, false, None),
+                b"1234"
+            )
+            .is_err());
+
+        fs.remove_sync(&path, false).unwrap();
+        fs.write_file_sync(
+            &other,
+            OpenOptions::write(true, false, false, None),
+            b"1234",
+        )
+        .unwrap();
+
+        // the directory is removed along with the last handle to it
+        drop(fs);
+        assert!(!root.exists());
+    }
+}
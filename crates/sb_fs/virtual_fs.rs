@@ -120,6 +120,13 @@ impl VfsBuilder {
         Ok(())
     }
 
+    pub fn add_file_at_path(&mut self, path: &Path) -> Result<(), AnyError> {
+        let path = canonicalize_path(path)?;
+        let file_bytes =
+            std::fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+        self.add_file(&path, file_bytes)
+    }
+
     fn add_dir(&mut self, path: &Path) -> Result<&mut VirtualDirectory, StripRootError> {
         log::debug!("Ensuring directory '{}'", path.display());
         let path = self.path_relative_root(path)?;
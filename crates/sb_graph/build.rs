@@ -0,0 +1,165 @@
+// build script
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha512};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The version of the TypeScript compiler bundled for type checking.
+const TYPESCRIPT_VERSION: &str = "5.1.6";
+/// The `dist.integrity` npm publishes for the archive of the compiler, as
+/// printed by `npm view typescript@<version> dist.integrity`.
+const TYPESCRIPT_INTEGRITY: &str =
+    "sha512-zaWCozRZ6DLEWAWFrVDz1H6FVXzUSfTy5FUMWsQlU8Ym5JP9eO4xkTIROFCQvhQf61z6O/G6ugw3SgAnvvm+HA==";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=TYPESCRIPT_ARCHIVE");
+    println!("cargo:rustc-env=TYPESCRIPT_VERSION={}", TYPESCRIPT_VERSION);
+
+    let o = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    // Offline builds can point to the npm tarball of the compiler instead
+    let archive_path = match env::var_os("TYPESCRIPT_ARCHIVE") {
+        Some(path) => PathBuf::from(path),
+        None => download_typescript(&o),
+    };
+
+    verify_integrity(&archive_path);
+    extract_typescript(&archive_path, &o.join("tsc"));
+}
+
+/// Checks that the archive is the one published to npm, since the compiler
+/// in it is embedded in the binary.
+fn verify_integrity(archive_path: &Path) {
+    let bytes = fs::read(archive_path).unwrap_or_else(|err| {
+        panic!(
+            "failed to read the TypeScript archive ({}): {}",
+            archive_path.display(),
+            err
+        )
+    });
+    let integrity = format!("sha512-{}", base64::encode(Sha512::digest(&bytes)));
+
+    if integrity != TYPESCRIPT_INTEGRITY {
+        panic!(
+            "{} is not the archive of typescript@{} (expected {}, got {})",
+            archive_path.display(),
+            TYPESCRIPT_VERSION,
+            TYPESCRIPT_INTEGRITY,
+            integrity
+        );
+    }
+}
+
+fn download_typescript(out_dir: &Path) -> PathBuf {
+    let archive_path = out_dir.join(format!("typescript-{}.tgz", TYPESCRIPT_VERSION));
+    if archive_path.exists() {
+        return archive_path;
+    }
+
+    let url = format!(
+        "https://registry.npmjs.org/typescript/-/typescript-{}.tgz",
+        TYPESCRIPT_VERSION
+    );
+    let tmp_path = archive_path.with_extension("tmp");
+    let status = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--output",
+        ])
+        .arg(&tmp_path)
+        .arg(&url)
+        .status()
+        .expect("failed to run curl");
+
+    if !status.success() {
+        panic!(
+            "failed to download {} (set TYPESCRIPT_ARCHIVE to build offline)",
+            url
+        );
+    }
+
+    // a corrupt download isn't kept around for the next build
+    verify_integrity(&tmp_path);
+    fs::rename(&tmp_path, &archive_path).unwrap();
+    archive_path
+}
+
+/// Writes the compiler and its lib files to `dir`, along with `libs.rs` which
+/// embeds the lib files in the crate.
+fn extract_typescript(archive_path: &Path, dir: &Path) {
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+
+    let file = fs::File::open(archive_path).unwrap_or_else(|err| {
+        panic!(
+            "failed to open the TypeScript archive ({}): {}",
+            archive_path.display(),
+            err
+        )
+    });
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut package_json = None;
+    let mut libs = Vec::new();
+
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_path_buf();
+
+        if path == Path::new("package/package.json") {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            package_json = Some(contents);
+            continue;
+        }
+
+        let Ok(name) = path.strip_prefix("package/lib") else {
+            continue;
+        };
+        let name = name.to_string_lossy().to_string();
+        let is_lib = name.starts_with("lib.") && name.ends_with(".d.ts");
+
+        if name == "typescript.js" || is_lib {
+            entry.unpack(dir.join(&name)).unwrap();
+        }
+        if is_lib {
+            libs.push(name);
+        }
+    }
+
+    let expected = format!("\"version\": \"{}\"", TYPESCRIPT_VERSION);
+    if !package_json.is_some_and(|it| it.contains(&expected)) {
+        panic!(
+            "{} is not the archive of typescript@{}",
+            archive_path.display(),
+            TYPESCRIPT_VERSION
+        );
+    }
+    if !dir.join("typescript.js").exists() {
+        panic!("{} has no lib/typescript.js", archive_path.display());
+    }
+
+    libs.sort();
+
+    let mut libs_rs = String::from("static TYPESCRIPT_LIBS: &[(&str, &str)] = &[\n");
+    for name in libs {
+        let path = dir.join(&name);
+        writeln!(
+            libs_rs,
+            "    ({:?}, include_str!({:?})),",
+            name,
+            path.to_string_lossy()
+        )
+        .unwrap();
+    }
+    libs_rs.push_str("];\n");
+
+    fs::write(dir.join("libs.rs"), libs_rs).unwrap();
+}
@@ -0,0 +1,170 @@
+// Drives the TypeScript compiler over a module graph that lives on the Rust
+// side. Sources and module resolution are provided through ops, so the
+// compiler never touches the real file system.
+
+((globalThis) => {
+	const core = globalThis.Deno.core;
+	const ops = core.ops;
+	const ts = globalThis.ts;
+
+	const ASSET_PREFIX = 'asset:///';
+
+	// Diagnostics that are either irrelevant for ES modules loaded by URL or
+	// that are already reported while building the module graph.
+	const IGNORED_DIAGNOSTICS = [
+		// TS1208: All files must be modules when the '--isolatedModules' flag is
+		// provided.
+		1208,
+		// TS2691: An import path cannot end with a '.ts' extension.
+		2691,
+		// TS2792: Cannot find module. Did you mean to set the 'moduleResolution'
+		// option to 'node', or to add aliases to the 'paths' option?
+		2792,
+		// TS5009: Cannot find the common subdirectory path for the input files.
+		5009,
+	];
+
+	const compilerOptions = {
+		allowImportingTsExtensions: true,
+		allowJs: true,
+		checkJs: false,
+		esModuleInterop: true,
+		isolatedModules: true,
+		jsx: ts.JsxEmit.React,
+		lib: [
+			'lib.esnext.d.ts',
+			'lib.dom.d.ts',
+			'lib.dom.iterable.d.ts',
+			'lib.edge-runtime.d.ts',
+		],
+		module: ts.ModuleKind.ESNext,
+		moduleDetection: ts.ModuleDetectionKind.Force,
+		noEmit: true,
+		resolveJsonModule: true,
+		strict: true,
+		target: ts.ScriptTarget.ESNext,
+		useDefineForClassFields: true,
+	};
+
+	const sourceFileCache = new Map();
+
+	function getScriptKind(fileName) {
+		if (fileName.endsWith('.tsx')) return ts.ScriptKind.TSX;
+		if (fileName.endsWith('.jsx')) return ts.ScriptKind.JSX;
+		if (fileName.endsWith('.json')) return ts.ScriptKind.JSON;
+		if (
+			fileName.endsWith('.js') || fileName.endsWith('.mjs') ||
+			fileName.endsWith('.cjs')
+		) {
+			return ts.ScriptKind.JS;
+		}
+
+		return ts.ScriptKind.TS;
+	}
+
+	const host = {
+		fileExists(fileName) {
+			return sourceFileCache.has(fileName) || ops.op_check_load(fileName) !== null;
+		},
+		readFile(fileName) {
+			return ops.op_check_load(fileName) ?? undefined;
+		},
+		getSourceFile(fileName, languageVersion) {
+			let sourceFile = sourceFileCache.get(fileName);
+			if (sourceFile) {
+				return sourceFile;
+			}
+
+			const data = ops.op_check_load(fileName);
+			if (data === null || data === undefined) {
+				return undefined;
+			}
+
+			sourceFile = ts.createSourceFile(
+				fileName,
+				data,
+				languageVersion,
+				false,
+				getScriptKind(fileName),
+			);
+			sourceFile.moduleName = fileName;
+			sourceFileCache.set(fileName, sourceFile);
+
+			return sourceFile;
+		},
+		getDefaultLibFileName() {
+			return `${ASSET_PREFIX}lib.esnext.d.ts`;
+		},
+		getDefaultLibLocation() {
+			return ASSET_PREFIX;
+		},
+		writeFile() {
+			throw new Error('the type checker does not emit files');
+		},
+		getCurrentDirectory() {
+			return '/';
+		},
+		getCanonicalFileName(fileName) {
+			return fileName;
+		},
+		useCaseSensitiveFileNames() {
+			return true;
+		},
+		getNewLine() {
+			return '\n';
+		},
+		resolveModuleNameLiterals(moduleLiterals, base) {
+			const resolved = ops.op_check_resolve({
+				base,
+				specifiers: moduleLiterals.map((it) => it.text),
+			});
+
+			return resolved.map((it) => ({
+				resolvedModule: it
+					? {
+						resolvedFileName: it[0],
+						extension: it[1],
+						isExternalLibraryImport: false,
+					}
+					: undefined,
+			}));
+		},
+	};
+
+	function fromTsDiagnostic(diagnostic) {
+		const result = {
+			code: diagnostic.code,
+			category: diagnostic.category,
+			message: ts.flattenDiagnosticMessageText(diagnostic.messageText, '\n'),
+		};
+
+		const { file, start } = diagnostic;
+		if (file && start !== undefined) {
+			const { line, character } = file.getLineAndCharacterOfPosition(start);
+			const lineStart = file.getPositionOfLineAndCharacter(line, 0);
+			const lineEnd = file.getLineEndOfPosition(start);
+
+			result.fileName = file.moduleName ?? file.fileName;
+			result.line = line + 1;
+			result.column = character + 1;
+			result.sourceLine = file.text.slice(lineStart, lineEnd);
+		}
+
+		return result;
+	}
+
+	globalThis.exec = ({ rootNames }) => {
+		const program = ts.createProgram({
+			rootNames,
+			options: compilerOptions,
+			host,
+		});
+
+		return ts.getPreEmitDiagnostics(program)
+			.filter(({ code, file }) =>
+				!IGNORED_DIAGNOSTICS.includes(code) &&
+				!file?.fileName.startsWith(ASSET_PREFIX)
+			)
+			.map(fromTsDiagnostic);
+	};
+})(globalThis);
@@ -0,0 +1,585 @@
+use crate::emitter::EmitterFactory;
+use crate::graph_util::{graph_valid, GraphValidOptions, ModuleGraphBuilder};
+use deno_ast::MediaType;
+use deno_core::error::{generic_error, AnyError};
+use deno_core::url::Url;
+use deno_core::{
+    op2, serde_v8, v8, FastString, JsRuntime, ModuleSpecifier, OpState, RuntimeOptions,
+};
+use deno_semver::npm::NpmPackageReqReference;
+use eszip::deno_graph::{Module, ModuleGraph};
+use sb_core::cache::check::TypeCheckCache;
+use sb_core::cache::common::FastInsecureHasher;
+use sb_node::{AllowAllNodePermissions, NodeResolution, NodeResolutionMode, NodeResolver};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The version of the TypeScript compiler used for type checking, which is also
+/// the one `Deno.version.typescript` reports.
+pub const TYPESCRIPT_VERSION: &str = env!("TYPESCRIPT_VERSION");
+
+const ASSET_PREFIX: &str = "asset:///";
+const MISSING_DEPENDENCY_SPECIFIER: &str = "asset:///missing_dependency.d.ts";
+
+static CHECK_JS: &str = include_str!("check.js");
+// The compiler is bundled at build time (see build.rs), so checking doesn't
+// need network access.
+static TYPESCRIPT_JS: &str = include_str!(concat!(env!("OUT_DIR"), "/tsc/typescript.js"));
+include!(concat!(env!("OUT_DIR"), "/tsc/libs.rs"));
+static EDGE_RUNTIME_LIB: &str = include_str!("edge_runtime.d.ts");
+
+/// Types for modules the checker can't see through (external modules and npm
+/// packages without declarations) are loosened to `any`.
+static MISSING_DEPENDENCY_LIB: &str = "declare const mod: any;\nexport = mod;\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(from = "u8")]
+pub enum DiagnosticCategory {
+    Warning,
+    Error,
+    Suggestion,
+    Message,
+}
+
+impl From<u8> for DiagnosticCategory {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Warning,
+            1 => Self::Error,
+            2 => Self::Suggestion,
+            _ => Self::Message,
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "WARN"),
+            Self::Error => write!(f, "ERROR"),
+            Self::Suggestion => write!(f, "SUGGESTION"),
+            Self::Message => write!(f, "INFO"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub code: u64,
+    pub category: DiagnosticCategory,
+    pub message: String,
+    pub file_name: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+    pub source_line: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TS{} [{}]: {}", self.code, self.category, self.message)?;
+
+        if let Some(source_line) = self.source_line.as_ref() {
+            write!(f, "\n{}", source_line)?;
+
+            if let Some(column) = self.column {
+                let padding = " ".repeat(column.saturating_sub(1) as usize);
+                write!(f, "\n{}^", padding)?;
+            }
+        }
+
+        if let Some(file_name) = self.file_name.as_ref() {
+            write!(
+                f,
+                "\n    at {}:{}:{}",
+                file_name,
+                self.line.unwrap_or(1),
+                self.column.unwrap_or(1)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.0
+            .iter()
+            .any(|it| it.category == DiagnosticCategory::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|it| it.category == DiagnosticCategory::Error)
+            .count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, diagnostic) in self.0.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "\n\n")?;
+            }
+
+            write!(f, "{}", diagnostic)?;
+        }
+
+        let count = self.error_count();
+        if count > 0 {
+            write!(
+                f,
+                "\n\nFound {} error{}.",
+                count,
+                if count == 1 { "" } else { "s" }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+struct CheckState {
+    graph: Arc<ModuleGraph>,
+    node_resolver: Arc<NodeResolver>,
+    assets: HashMap<String, String>,
+    file_names: HashMap<String, ModuleSpecifier>,
+}
+
+impl CheckState {
+    fn specifier_for(&self, file_name: &str) -> Option<ModuleSpecifier> {
+        self.file_names
+            .get(file_name)
+            .cloned()
+            .or_else(|| ModuleSpecifier::parse(file_name).ok())
+    }
+
+    fn resolve(&mut self, base: &str, specifier: &str) -> Option<(String, String)> {
+        let referrer = self.specifier_for(base)?;
+
+        // the declaration files of npm packages aren't in the graph, they
+        // import each other the way node does
+        if self.node_resolver.in_npm_package(&referrer) {
+            let resolution = self.node_resolver.resolve(
+                specifier,
+                &referrer,
+                NodeResolutionMode::Types,
+                &AllowAllNodePermissions,
+            );
+
+            return self.resolve_node(resolution);
+        }
+
+        let dependency = match self.graph.get(&referrer) {
+            Some(Module::Esm(module)) => module.dependencies.get(specifier)?,
+            _ => return None,
+        };
+
+        let resolved = dependency
+            .maybe_type
+            .maybe_specifier()
+            .or_else(|| dependency.maybe_code.maybe_specifier())?;
+
+        let mut resolved = self.graph.resolve(resolved);
+        if let Some(Module::Esm(module)) = self.graph.get(&resolved) {
+            if let Some(types) = module
+                .maybe_types_dependency
+                .as_ref()
+                .and_then(|it| it.dependency.maybe_specifier())
+            {
+                resolved = self.graph.resolve(types);
+            }
+        }
+
+        let media_type = match self.graph.get(&resolved) {
+            Some(Module::Esm(module)) => module.media_type,
+            Some(Module::Json(module)) => module.media_type,
+            Some(Module::Npm(module)) => {
+                let resolution = self.node_resolver.resolve_npm_reference(
+                    &module.nv_reference,
+                    NodeResolutionMode::Types,
+                    &AllowAllNodePermissions,
+                );
+
+                return self.resolve_node(resolution);
+            }
+            // declared by the ambient modules of @types/node
+            Some(Module::Node(_)) => return None,
+            _ => return Some(missing_dependency()),
+        };
+
+        Some(self.add_file_name(resolved, media_type))
+    }
+
+    fn resolve_node(
+        &mut self,
+        resolution: Result<Option<NodeResolution>, AnyError>,
+    ) -> Option<(String, String)> {
+        match resolution {
+            Ok(Some(NodeResolution::Esm(specifier)))
+            | Ok(Some(NodeResolution::CommonJs(specifier))) => {
+                let media_type = MediaType::from_specifier(&specifier);
+                Some(self.add_file_name(specifier, media_type))
+            }
+            Ok(Some(NodeResolution::BuiltIn(_))) => None,
+            // packages without types are loosened to `any`
+            Ok(None) => Some(missing_dependency()),
+            Err(err) => {
+                log::debug!("failed to resolve the types of an npm module: {}", err);
+                Some(missing_dependency())
+            }
+        }
+    }
+
+    fn add_file_name(
+        &mut self,
+        specifier: ModuleSpecifier,
+        media_type: MediaType,
+    ) -> (String, String) {
+        let extension = media_type.as_ts_extension();
+        let file_name = if specifier.path().ends_with(extension) {
+            specifier.to_string()
+        } else {
+            // the compiler infers the kind of a script from its file name,
+            // so extensionless specifiers get one appended.
+            format!("{}{}", specifier, extension)
+        };
+
+        self.file_names.insert(file_name.clone(), specifier);
+
+        (file_name, extension.to_string())
+    }
+
+    fn load(&self, file_name: &str) -> Option<String> {
+        if file_name.starts_with(ASSET_PREFIX) {
+            return self.assets.get(file_name).cloned();
+        }
+
+        let specifier = self.specifier_for(file_name)?;
+        match self.graph.get(&specifier) {
+            Some(Module::Esm(module)) => Some(module.source.to_string()),
+            Some(Module::Json(module)) => Some(module.source.to_string()),
+            _ if self.node_resolver.in_npm_package(&specifier) => {
+                std::fs::read_to_string(specifier.to_file_path().ok()?).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+fn missing_dependency() -> (String, String) {
+    (
+        MISSING_DEPENDENCY_SPECIFIER.to_string(),
+        MediaType::Dts.as_ts_extension().to_string(),
+    )
+}
+
+/// The entry of @types/node, which declares the `node:` modules.
+fn types_node_file_name(node_resolver: &NodeResolver) -> Option<String> {
+    let reference = NpmPackageReqReference::from_str("npm:@types/node").ok()?;
+    let resolution = node_resolver
+        .resolve_npm_req_reference(
+            &reference,
+            NodeResolutionMode::Types,
+            &AllowAllNodePermissions,
+        )
+        .ok()??;
+
+    Some(resolution.into_url().to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResolveArgs {
+    base: String,
+    specifiers: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecRequest {
+    root_names: Vec<String>,
+}
+
+#[op2]
+#[serde]
+fn op_check_load(state: &mut OpState, #[string] file_name: String) -> Option<String> {
+    state.borrow::<CheckState>().load(&file_name)
+}
+
+#[op2]
+#[serde]
+fn op_check_resolve(
+    state: &mut OpState,
+    #[serde] args: ResolveArgs,
+) -> Vec<Option<(String, String)>> {
+    let check_state = state.borrow_mut::<CheckState>();
+
+    args.specifiers
+        .iter()
+        .map(|it| check_state.resolve(&args.base, it))
+        .collect()
+}
+
+deno_core::extension!(
+    sb_graph_check,
+    ops = [op_check_load, op_check_resolve],
+    options = { state: CheckState },
+    state = |state, options| {
+        state.put::<CheckState>(options.state);
+    }
+);
+
+pub struct TypeChecker {
+    emitter_factory: Arc<EmitterFactory>,
+}
+
+impl TypeChecker {
+    pub fn new(emitter_factory: Arc<EmitterFactory>) -> Self {
+        Self { emitter_factory }
+    }
+
+    /// Type checks the modules reachable from the roots of `graph`.
+    ///
+    /// The graph should be built with type dependencies (see
+    /// [`ModuleGraphBuilder::new`]) so declaration files are visible to the
+    /// checker.
+    pub async fn check(&self, graph: Arc<ModuleGraph>) -> Result<Diagnostics, AnyError> {
+        let type_check_cache =
+            TypeCheckCache::new(self.emitter_factory.caches()?.type_checking_cache_db());
+
+        let check_hash = get_check_hash(&graph);
+        if type_check_cache.has_check_hash(check_hash) {
+            log::debug!("already type checked");
+            return Ok(Diagnostics::default());
+        }
+
+        let mut assets = TYPESCRIPT_LIBS
+            .iter()
+            .map(|(name, source)| (format!("{}{}", ASSET_PREFIX, name), source.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        assets.insert(
+            format!("{}lib.edge-runtime.d.ts", ASSET_PREFIX),
+            EDGE_RUNTIME_LIB.to_string(),
+        );
+        assets.insert(
+            MISSING_DEPENDENCY_SPECIFIER.to_string(),
+            MISSING_DEPENDENCY_LIB.to_string(),
+        );
+
+        let node_resolver = self.emitter_factory.node_resolver().clone();
+        let mut root_names = graph
+            .roots
+            .iter()
+            .map(|it| it.to_string())
+            .collect::<Vec<_>>();
+
+        // the graph builder adds @types/node to the npm packages when there are
+        // `node:` imports
+        if graph.has_node_specifier {
+            root_names.extend(types_node_file_name(&node_resolver));
+        }
+
+        let mut js_runtime = JsRuntime::new(RuntimeOptions {
+            extensions: vec![sb_graph_check::init_ops(CheckState {
+                graph: graph.clone(),
+                node_resolver: node_resolver.clone(),
+                assets,
+                file_names: HashMap::new(),
+            })],
+            ..Default::default()
+        });
+
+        js_runtime.execute_script(
+            "ext:sb_graph/typescript.js",
+            FastString::from_static(TYPESCRIPT_JS),
+        )?;
+        js_runtime.execute_script("ext:sb_graph/check.js", FastString::from_static(CHECK_JS))?;
+
+        let request = deno_core::serde_json::to_string(&ExecRequest { root_names })?;
+        let result = js_runtime.execute_script(
+            "ext:sb_graph/exec.js",
+            FastString::from(format!("globalThis.exec({})", request)),
+        )?;
+
+        let diagnostics = {
+            let scope = &mut js_runtime.handle_scope();
+            let value = v8::Local::new(scope, result);
+            serde_v8::from_v8::<Vec<Diagnostic>>(scope, value)?
+        };
+
+        // the declarations of npm packages are theirs to get right
+        let diagnostics = Diagnostics(
+            diagnostics
+                .into_iter()
+                .filter(|it| {
+                    it.file_name
+                        .as_ref()
+                        .and_then(|it| ModuleSpecifier::parse(it).ok())
+                        .map_or(true, |it| !node_resolver.in_npm_package(&it))
+                })
+                .collect(),
+        );
+        if !diagnostics.has_errors() {
+            type_check_cache.add_check_hash(check_hash);
+        }
+
+        Ok(diagnostics)
+    }
+}
+
+/// Builds the module graph for `file` including type dependencies and runs the
+/// type checker over it.
+pub async fn check_file(
+    file: PathBuf,
+    emitter_factory: Arc<EmitterFactory>,
+) -> Result<Diagnostics, AnyError> {
+    let path = file.canonicalize()?;
+    let specifier = Url::from_file_path(&path)
+        .map_err(|_| generic_error(format!("invalid entrypoint path ({})", path.display())))?;
+
+    let builder = ModuleGraphBuilder::new(emitter_factory.clone(), true);
+    let graph = builder
+        .create_graph_and_maybe_check(vec![specifier.clone()])
+        .await?;
+
+    graph_valid(
+        &graph,
+        &[specifier],
+        GraphValidOptions {
+            check_js: false,
+            follow_type_only: true,
+            is_vendoring: false,
+        },
+    )?;
+
+    TypeChecker::new(emitter_factory)
+        .check(Arc::new(graph))
+        .await
+}
+
+fn get_check_hash(graph: &ModuleGraph) -> u64 {
+    let mut hasher = FastInsecureHasher::new();
+    hasher.write_str(TYPESCRIPT_VERSION);
+    hasher.write_str(EDGE_RUNTIME_LIB);
+    hasher.write_str(CHECK_JS);
+
+    let mut roots = graph.roots.iter().map(|it| it.as_str()).collect::<Vec<_>>();
+    roots.sort();
+    for root in roots {
+        hasher.write_str(root);
+    }
+
+    for package in &graph.npm_packages {
+        hasher.write_str(&package.to_string());
+    }
+
+    let mut modules = graph
+        .modules()
+        .filter_map(|module| match module {
+            Module::Esm(module) => Some((module.specifier.as_str(), module.source.as_ref())),
+            Module::Json(module) => Some((module.specifier.as_str(), module.source.as_ref())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    modules.sort_by(|a, b| a.0.cmp(b.0));
+    for (specifier, source) in modules {
+        hasher.write_str(specifier);
+        hasher.write_str(source);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::check::check_file;
+    use crate::emitter::EmitterFactory;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_check_reports_type_errors() {
+        let diagnostics = check_file(
+            PathBuf::from("../base/test_cases/type_error/index.ts"),
+            Arc::new(EmitterFactory::new()),
+        )
+        .await
+        .unwrap();
+
+        assert!(diagnostics.has_errors());
+
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!(diagnostic.code, 2322);
+        assert_eq!(diagnostic.line, Some(1));
+        assert!(diagnostic
+            .file_name
+            .as_ref()
+            .unwrap()
+            .ends_with("type_error/index.ts"));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_check_passes_valid_module() {
+        let diagnostics = check_file(
+            PathBuf::from("../base/test_cases/type_check_ok/index.ts"),
+            Arc::new(EmitterFactory::new()),
+        )
+        .await
+        .unwrap();
+
+        assert!(!diagnostics.has_errors(), "{}", diagnostics);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_check_resolves_npm_and_node_types() {
+        let diagnostics = check_file(
+            PathBuf::from("../base/test_cases/type_check_npm/index.ts"),
+            Arc::new(EmitterFactory::new()),
+        )
+        .await
+        .unwrap();
+
+        let lines = diagnostics
+            .iter()
+            .map(|it| (it.code, it.line))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![(2322, Some(5)), (2322, Some(6))]);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_check_knows_edge_runtime_globals() {
+        for path in [
+            "../base/test_cases/type_check_main/index.ts",
+            "../base/test_cases/type_check_user/index.ts",
+        ] {
+            let diagnostics = check_file(PathBuf::from(path), Arc::new(EmitterFactory::new()))
+                .await
+                .unwrap();
+
+            assert!(!diagnostics.has_errors(), "{}", diagnostics);
+        }
+    }
+}
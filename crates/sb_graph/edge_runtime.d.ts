@@ -0,0 +1,730 @@
+// Ambient declarations for the globals available inside Edge Runtime workers.
+// These are only used by the type checker and mirror the surface set up by
+// `bootstrap.js`, `denoOverrides.js`, `main_worker.js` and `user_workers.js`,
+// so they have to be updated along with those.
+
+declare namespace Deno {
+	export interface Env {
+		get(key: string): string | undefined;
+		set(key: string, value: string): void;
+		delete(key: string): void;
+		has(key: string): boolean;
+		toObject(): { [key: string]: string };
+	}
+
+	export const env: Env;
+
+	export const version: {
+		deno: string;
+		v8: string;
+		typescript: string;
+	};
+
+	export const build: {
+		target: string;
+		arch: 'x86_64' | 'aarch64';
+		os:
+			| 'darwin'
+			| 'linux'
+			| 'windows'
+			| 'freebsd'
+			| 'netbsd'
+			| 'aix'
+			| 'solaris'
+			| 'illumos';
+		vendor: string;
+		env?: string;
+	};
+
+	export const pid: number;
+	export const noColor: boolean;
+	export const args: string[];
+	export const mainModule: string;
+
+	export function cwd(): string;
+	export function memoryUsage(): {
+		rss: number;
+		heapTotal: number;
+		heapUsed: number;
+		external: number;
+	};
+
+	export function isatty(rid: number): boolean;
+	export function refTimer(id: number): void;
+	export function unrefTimer(id: number): void;
+
+	// os
+
+	export function gid(): number | null;
+	export function uid(): number | null;
+	export function hostname(): string;
+	export function loadavg(): number[];
+	export function osUptime(): number;
+	export function osRelease(): string;
+	export function execPath(): string;
+	export function consoleSize(): { columns: number; rows: number };
+
+	export interface SystemMemoryInfo {
+		total: number;
+		free: number;
+		available: number;
+		buffers: number;
+		cached: number;
+		swapTotal: number;
+		swapFree: number;
+	}
+
+	export function systemMemoryInfo(): SystemMemoryInfo;
+
+	export interface NetworkInterfaceInfo {
+		family: 'IPv4' | 'IPv6';
+		name: string;
+		address: string;
+		netmask: string;
+		scopeid: number | null;
+		cidr: string;
+		mac: string;
+	}
+
+	export function networkInterfaces(): NetworkInterfaceInfo[];
+
+	export interface CommandOptions {
+		args?: string[];
+		cwd?: string | URL;
+		env?: Record<string, string>;
+	}
+
+	/** Spawning subprocesses is not allowed, so every method throws. */
+	export class Command {
+		constructor(command: string | URL, options?: CommandOptions);
+		output(): Promise<never>;
+		outputSync(): never;
+		spawn(): never;
+	}
+
+	// permissions
+
+	export type PermissionName =
+		| 'read'
+		| 'write'
+		| 'net'
+		| 'env'
+		| 'sys'
+		| 'run'
+		| 'ffi'
+		| 'hrtime';
+
+	export type PermissionState = 'granted' | 'denied' | 'prompt';
+
+	export interface PermissionDescriptor {
+		name: PermissionName;
+		path?: string | URL;
+		host?: string;
+		variable?: string;
+		kind?: string;
+		command?: string | URL;
+	}
+
+	export class PermissionStatus extends EventTarget {
+		readonly state: PermissionState;
+		onchange: ((this: PermissionStatus, ev: Event) => any) | null;
+	}
+
+	export class Permissions {
+		query(desc: PermissionDescriptor): Promise<PermissionStatus>;
+		querySync(desc: PermissionDescriptor): PermissionStatus;
+		revoke(desc: PermissionDescriptor): Promise<PermissionStatus>;
+		revokeSync(desc: PermissionDescriptor): PermissionStatus;
+		request(desc: PermissionDescriptor): Promise<PermissionStatus>;
+		requestSync(desc: PermissionDescriptor): PermissionStatus;
+	}
+
+	export const permissions: Permissions;
+
+	// io
+
+	export interface Reader {
+		read(p: Uint8Array): Promise<number | null>;
+	}
+
+	export interface Writer {
+		write(p: Uint8Array): Promise<number>;
+	}
+
+	export interface Closer {
+		close(): void;
+	}
+
+	export const stdin: Reader & {
+		readonly rid: number;
+		readonly readable: ReadableStream<Uint8Array>;
+		readSync(p: Uint8Array): number | null;
+	};
+	export const stdout: Writer & {
+		readonly rid: number;
+		readonly writable: WritableStream<Uint8Array>;
+		writeSync(p: Uint8Array): number;
+	};
+	export const stderr: Writer & {
+		readonly rid: number;
+		readonly writable: WritableStream<Uint8Array>;
+		writeSync(p: Uint8Array): number;
+	};
+
+	// fs
+
+	export enum SeekMode {
+		Start = 0,
+		Current = 1,
+		End = 2,
+	}
+
+	export interface FileInfo {
+		isFile: boolean;
+		isDirectory: boolean;
+		isSymlink: boolean;
+		size: number;
+		mtime: Date | null;
+		atime: Date | null;
+		birthtime: Date | null;
+		dev: number;
+		ino: number | null;
+		mode: number | null;
+		nlink: number | null;
+		uid: number | null;
+		gid: number | null;
+		rdev: number | null;
+		blksize: number | null;
+		blocks: number | null;
+	}
+
+	export interface DirEntry {
+		name: string;
+		isFile: boolean;
+		isDirectory: boolean;
+		isSymlink: boolean;
+	}
+
+	export interface OpenOptions {
+		read?: boolean;
+		write?: boolean;
+		append?: boolean;
+		truncate?: boolean;
+		create?: boolean;
+		createNew?: boolean;
+		mode?: number;
+	}
+
+	export interface ReadFileOptions {
+		signal?: AbortSignal;
+	}
+
+	export interface WriteFileOptions {
+		append?: boolean;
+		create?: boolean;
+		createNew?: boolean;
+		mode?: number;
+		signal?: AbortSignal;
+	}
+
+	export interface MkdirOptions {
+		recursive?: boolean;
+		mode?: number;
+	}
+
+	export interface RemoveOptions {
+		recursive?: boolean;
+	}
+
+	export interface MakeTempOptions {
+		dir?: string;
+		prefix?: string;
+		suffix?: string;
+	}
+
+	export interface SymlinkOptions {
+		type: 'file' | 'dir';
+	}
+
+	export class FsFile implements Reader, Writer, Closer {
+		readonly rid: number;
+		readonly readable: ReadableStream<Uint8Array>;
+		readonly writable: WritableStream<Uint8Array>;
+		write(p: Uint8Array): Promise<number>;
+		writeSync(p: Uint8Array): number;
+		truncate(len?: number): Promise<void>;
+		truncateSync(len?: number): void;
+		read(p: Uint8Array): Promise<number | null>;
+		readSync(p: Uint8Array): number | null;
+		seek(offset: number | bigint, whence: SeekMode): Promise<number>;
+		seekSync(offset: number | bigint, whence: SeekMode): number;
+		stat(): Promise<FileInfo>;
+		statSync(): FileInfo;
+		close(): void;
+	}
+
+	/** @deprecated Use `Deno.FsFile` instead. */
+	export const File: typeof FsFile;
+
+	export function open(path: string | URL, options?: OpenOptions): Promise<FsFile>;
+	export function openSync(path: string | URL, options?: OpenOptions): FsFile;
+	export function create(path: string | URL): Promise<FsFile>;
+	export function createSync(path: string | URL): FsFile;
+	export function seek(rid: number, offset: number | bigint, whence: SeekMode): Promise<number>;
+	export function seekSync(rid: number, offset: number | bigint, whence: SeekMode): number;
+	export function fstat(rid: number): Promise<FileInfo>;
+	export function fstatSync(rid: number): FileInfo;
+	export function ftruncate(rid: number, len?: number): Promise<void>;
+	export function ftruncateSync(rid: number, len?: number): void;
+	export function fsync(rid: number): Promise<void>;
+	export function fsyncSync(rid: number): void;
+	export function fdatasync(rid: number): Promise<void>;
+	export function fdatasyncSync(rid: number): void;
+	export function futime(rid: number, atime: number | Date, mtime: number | Date): Promise<void>;
+	export function futimeSync(rid: number, atime: number | Date, mtime: number | Date): void;
+
+	export function readFile(path: string | URL, options?: ReadFileOptions): Promise<Uint8Array>;
+	export function readFileSync(path: string | URL): Uint8Array;
+	export function readTextFile(path: string | URL, options?: ReadFileOptions): Promise<string>;
+	export function readTextFileSync(path: string | URL): string;
+	export function writeFile(
+		path: string | URL,
+		data: Uint8Array | ReadableStream<Uint8Array>,
+		options?: WriteFileOptions,
+	): Promise<void>;
+	export function writeFileSync(path: string | URL, data: Uint8Array, options?: WriteFileOptions): void;
+	export function writeTextFile(
+		path: string | URL,
+		data: string | ReadableStream<string>,
+		options?: WriteFileOptions,
+	): Promise<void>;
+	export function writeTextFileSync(path: string | URL, data: string, options?: WriteFileOptions): void;
+
+	export function stat(path: string | URL): Promise<FileInfo>;
+	export function statSync(path: string | URL): FileInfo;
+	export function lstat(path: string | URL): Promise<FileInfo>;
+	export function lstatSync(path: string | URL): FileInfo;
+	export function readDir(path: string | URL): AsyncIterable<DirEntry>;
+	export function readDirSync(path: string | URL): Iterable<DirEntry>;
+	export function realPath(path: string | URL): Promise<string>;
+	export function realPathSync(path: string | URL): string;
+	export function readLink(path: string | URL): Promise<string>;
+	export function readLinkSync(path: string | URL): string;
+
+	export function mkdir(path: string | URL, options?: MkdirOptions): Promise<void>;
+	export function mkdirSync(path: string | URL, options?: MkdirOptions): void;
+	export function makeTempDir(options?: MakeTempOptions): Promise<string>;
+	export function makeTempDirSync(options?: MakeTempOptions): string;
+	export function makeTempFile(options?: MakeTempOptions): Promise<string>;
+	export function makeTempFileSync(options?: MakeTempOptions): string;
+	export function remove(path: string | URL, options?: RemoveOptions): Promise<void>;
+	export function removeSync(path: string | URL, options?: RemoveOptions): void;
+	export function rename(oldpath: string | URL, newpath: string | URL): Promise<void>;
+	export function renameSync(oldpath: string | URL, newpath: string | URL): void;
+	export function copyFile(fromPath: string | URL, toPath: string | URL): Promise<void>;
+	export function copyFileSync(fromPath: string | URL, toPath: string | URL): void;
+	export function truncate(name: string, len?: number): Promise<void>;
+	export function truncateSync(name: string, len?: number): void;
+	export function chmod(path: string | URL, mode: number): Promise<void>;
+	export function chmodSync(path: string | URL, mode: number): void;
+	export function chown(path: string | URL, uid: number | null, gid: number | null): Promise<void>;
+	export function chownSync(path: string | URL, uid: number | null, gid: number | null): void;
+	export function chdir(directory: string | URL): void;
+	export function link(oldpath: string, newpath: string): Promise<void>;
+	export function linkSync(oldpath: string, newpath: string): void;
+	export function symlink(
+		oldpath: string | URL,
+		newpath: string | URL,
+		options?: SymlinkOptions,
+	): Promise<void>;
+	export function symlinkSync(oldpath: string | URL, newpath: string | URL, options?: SymlinkOptions): void;
+	export function utime(path: string | URL, atime: number | Date, mtime: number | Date): Promise<void>;
+	export function utimeSync(path: string | URL, atime: number | Date, mtime: number | Date): void;
+
+	export namespace errors {
+		class NotFound extends Error {}
+		class PermissionDenied extends Error {}
+		class ConnectionRefused extends Error {}
+		class ConnectionReset extends Error {}
+		class ConnectionAborted extends Error {}
+		class NotConnected extends Error {}
+		class AddrInUse extends Error {}
+		class AddrNotAvailable extends Error {}
+		class BrokenPipe extends Error {}
+		class AlreadyExists extends Error {}
+		class InvalidData extends Error {}
+		class TimedOut extends Error {}
+		class Interrupted extends Error {}
+		class WriteZero extends Error {}
+		class UnexpectedEof extends Error {}
+		class BadResource extends Error {}
+		class Http extends Error {}
+		class Busy extends Error {}
+		class NotSupported extends Error {}
+	}
+
+	// net
+
+	export interface NetAddr {
+		transport: 'tcp' | 'udp';
+		hostname: string;
+		port: number;
+	}
+
+	export interface UnixAddr {
+		transport: 'unix' | 'unixpacket';
+		path: string;
+	}
+
+	export type Addr = NetAddr | UnixAddr;
+
+	export interface Conn extends Reader, Writer, Closer {
+		readonly localAddr: Addr;
+		readonly remoteAddr: Addr;
+		readonly rid: number;
+		readonly readable: ReadableStream<Uint8Array>;
+		readonly writable: WritableStream<Uint8Array>;
+		closeWrite(): Promise<void>;
+		ref(): void;
+		unref(): void;
+	}
+
+	export interface TlsHandshakeInfo {
+		alpnProtocol: string | null;
+	}
+
+	export interface TlsConn extends Conn {
+		handshake(): Promise<TlsHandshakeInfo>;
+	}
+
+	export interface Listener extends AsyncIterable<Conn> {
+		readonly addr: Addr;
+		readonly rid: number;
+		accept(): Promise<Conn>;
+		close(): void;
+		ref(): void;
+		unref(): void;
+	}
+
+	export interface ListenOptions {
+		port: number;
+		hostname?: string;
+	}
+
+	export interface ConnectOptions {
+		port: number;
+		hostname?: string;
+		transport?: 'tcp';
+	}
+
+	export interface ConnectTlsOptions {
+		port: number;
+		hostname?: string;
+		caCerts?: string[];
+		alpnProtocols?: string[];
+	}
+
+	export interface StartTlsOptions {
+		hostname?: string;
+		caCerts?: string[];
+		alpnProtocols?: string[];
+	}
+
+	export function listen(options: ListenOptions & { transport?: 'tcp' }): Listener;
+	export function connect(options: ConnectOptions): Promise<Conn>;
+	export function connectTls(options: ConnectTlsOptions): Promise<TlsConn>;
+	export function startTls(conn: Conn, options?: StartTlsOptions): Promise<TlsConn>;
+
+	export type RecordType =
+		| 'A'
+		| 'AAAA'
+		| 'ANAME'
+		| 'CAA'
+		| 'CNAME'
+		| 'MX'
+		| 'NAPTR'
+		| 'NS'
+		| 'PTR'
+		| 'SOA'
+		| 'SRV'
+		| 'TXT';
+
+	export interface ResolveDnsOptions {
+		nameServer?: { ipAddr: string; port?: number };
+		signal?: AbortSignal;
+	}
+
+	export function resolveDns(
+		query: string,
+		recordType: RecordType,
+		options?: ResolveDnsOptions,
+	): Promise<unknown[]>;
+
+	// http
+
+	export interface ServeHandlerInfo {
+		remoteAddr: NetAddr;
+	}
+
+	export type ServeHandler = (
+		request: Request,
+		info: ServeHandlerInfo,
+	) => Response | Promise<Response>;
+
+	export interface ServeOptions {
+		port?: number;
+		hostname?: string;
+		signal?: AbortSignal;
+		reusePort?: boolean;
+		onError?: (error: unknown) => Response | Promise<Response>;
+		onListen?: (params: { hostname: string; port: number }) => void;
+	}
+
+	export interface ServeInit {
+		handler: ServeHandler;
+	}
+
+	export interface Server {
+		readonly finished: Promise<void>;
+		shutdown(): Promise<void>;
+		ref(): void;
+		unref(): void;
+	}
+
+	export function serve(handler: ServeHandler): Server;
+	export function serve(
+		options: ServeOptions,
+		handler: ServeHandler,
+	): Server;
+	export function serve(options: ServeInit & ServeOptions): Server;
+
+	export interface RequestEvent {
+		readonly request: Request;
+		respondWith(r: Response | PromiseLike<Response>): Promise<void>;
+	}
+
+	export interface HttpConn extends AsyncIterable<RequestEvent> {
+		readonly rid: number;
+		nextRequest(): Promise<RequestEvent | null>;
+		close(): void;
+	}
+
+	export function serveHttp(conn: Conn): HttpConn;
+
+	export interface WebSocketUpgrade {
+		response: Response;
+		socket: WebSocket;
+	}
+
+	export function upgradeWebSocket(
+		request: Request,
+		options?: { protocol?: string; idleTimeout?: number },
+	): WebSocketUpgrade;
+
+	// kv
+
+	export type KvKeyPart = Uint8Array | string | number | bigint | boolean;
+	export type KvKey = readonly KvKeyPart[];
+
+	export type KvListSelector =
+		| { prefix: KvKey }
+		| { prefix: KvKey; start: KvKey }
+		| { prefix: KvKey; end: KvKey }
+		| { start: KvKey; end: KvKey };
+
+	export interface KvEntry<T> {
+		key: KvKey;
+		value: T;
+		versionstamp: string;
+	}
+
+	export type KvEntryMaybe<T> = KvEntry<T> | {
+		key: KvKey;
+		value: null;
+		versionstamp: null;
+	};
+
+	export interface KvListOptions {
+		limit?: number;
+		cursor?: string;
+		reverse?: boolean;
+		batchSize?: number;
+	}
+
+	export interface AtomicCheck {
+		key: KvKey;
+		versionstamp: string | null;
+	}
+
+	export interface KvCommitResult {
+		ok: true;
+		versionstamp: string;
+	}
+
+	export interface KvCommitError {
+		ok: false;
+	}
+
+	export class KvU64 {
+		constructor(value: bigint);
+		readonly value: bigint;
+	}
+
+	export class KvListIterator<T> implements AsyncIterableIterator<KvEntry<T>> {
+		get cursor(): string;
+		next(): Promise<IteratorResult<KvEntry<T>, undefined>>;
+		[Symbol.asyncIterator](): AsyncIterableIterator<KvEntry<T>>;
+	}
+
+	export class AtomicOperation {
+		check(...checks: AtomicCheck[]): this;
+		set(key: KvKey, value: unknown, options?: { expireIn?: number }): this;
+		delete(key: KvKey): this;
+		sum(key: KvKey, n: bigint): this;
+		min(key: KvKey, n: bigint): this;
+		max(key: KvKey, n: bigint): this;
+		commit(): Promise<KvCommitResult | KvCommitError>;
+	}
+
+	export class Kv {
+		get<T = unknown>(key: KvKey): Promise<KvEntryMaybe<T>>;
+		getMany<T extends readonly unknown[]>(
+			keys: readonly [...{ [K in keyof T]: KvKey }],
+		): Promise<{ [K in keyof T]: KvEntryMaybe<T[K]> }>;
+		set(key: KvKey, value: unknown, options?: { expireIn?: number }): Promise<KvCommitResult>;
+		delete(key: KvKey): Promise<void>;
+		list<T = unknown>(selector: KvListSelector, options?: KvListOptions): KvListIterator<T>;
+		atomic(): AtomicOperation;
+		close(): void;
+	}
+
+	/** Opens the store of the worker, or a new in-memory one for `:memory:`. */
+	export function openKv(path?: string): Promise<Kv>;
+}
+
+declare namespace EdgeRuntime {
+	/** The options of `EdgeRuntime.userWorkers.create`. */
+	export interface UserWorkerOptions {
+		/** Required unless the worker is loaded from an eszip. */
+		servicePath?: string;
+		memoryLimitMb?: number | null;
+		lowMemoryMultiplier?: number | null;
+		workerTimeoutMs?: number | null;
+		cpuTimeSoftLimitMs?: number | null;
+		cpuTimeHardLimitMs?: number | null;
+		backgroundTimeoutMs?: number | null;
+		/** Gives the worker a writable /tmp of this size. */
+		tmpQuotaMb?: number | null;
+		cacheBackend?: 'memory' | 'sqlite' | null;
+		cacheQuotaMb?: number | null;
+		kvDir?: string | null;
+		kvNamespace?: string | null;
+		kvQuotaMb?: number | null;
+		noModuleCache?: boolean;
+		importMapPath?: string | null;
+		envVars?: [string, string][];
+		forceCreate?: boolean;
+		netAccessDisabled?: boolean;
+		allowRemoteModules?: boolean;
+		customModuleRoot?: string;
+		maybeEszip?: Uint8Array | null;
+		/** Shorthand for `maybeEszip`. */
+		eszip?: Uint8Array | null;
+		maybeEntrypoint?: string | null;
+		/** Picks a function of an eszip bundled with several. */
+		functionName?: string | null;
+		maybeModuleCode?: string | null;
+		lockfilePath?: string | null;
+		npmrcPath?: string | null;
+		nodeModulesDir?: boolean;
+		packageJsonPath?: string | null;
+		/** Globs of the files next to the service the worker can read. */
+		staticPatterns?: string[];
+		/** Also exchanges BroadcastChannel messages with the main worker. */
+		broadcastWithMain?: boolean;
+		/** Services the worker can call through `EdgeRuntime.bindings`. */
+		bindings?: Record<string, string | UserWorkerOptions>;
+	}
+
+	export interface UserWorkerFetchOptions {
+		signal?: AbortSignal;
+	}
+
+	export interface UserWorker {
+		readonly key: string;
+		fetch(request: Request, options?: UserWorkerFetchOptions): Promise<Response>;
+	}
+
+	/**
+	 * Keeps the worker alive after the response is sent, until `promise`
+	 * settles or the background timeout passes. Only available in user
+	 * workers.
+	 */
+	export function waitUntil(promise: Promise<unknown>): void;
+
+	/** A service the main worker bound to the user worker. */
+	export interface ServiceBinding {
+		fetch(input: RequestInfo | URL, init?: RequestInit): Promise<Response>;
+	}
+
+	/**
+	 * The services the worker was created with `bindings` for. Only available
+	 * in user workers.
+	 */
+	export const bindings: Readonly<Record<string, ServiceBinding>>;
+
+	/** Only available in the main worker. */
+	export const userWorkers: {
+		/** Reuses the active worker of the service unless `forceCreate` is set. */
+		create(options: UserWorkerOptions): Promise<UserWorker>;
+	};
+
+	/** The options of `EdgeRuntime.schedule`, on top of the worker ones. */
+	export interface ScheduleOptions extends Omit<UserWorkerOptions, 'servicePath'> {
+		/** Defaults to the service path. */
+		name?: string;
+		/** What to do when a run is due while the last one is still going. */
+		overlap?: 'skip' | 'allow' | 'queue';
+		/** The path the request of a run is sent to. */
+		path?: string;
+	}
+
+	/**
+	 * Sends a request to the worker of `servicePath` whenever `cron` (in UTC)
+	 * matches. Scheduling again with the same name replaces the schedule. Only
+	 * available in the main worker.
+	 */
+	export function schedule(
+		cron: string,
+		servicePath: string,
+		options?: ScheduleOptions,
+	): Promise<void>;
+
+	/**
+	 * Resolves whether there was a schedule with the name. Only available in
+	 * the main worker.
+	 */
+	export function unschedule(name: string): Promise<boolean>;
+
+	/** Only available in the main worker. */
+	export function applyConnectionWatcher(
+		src: Request,
+		dest: Request,
+	): void;
+}
+
+/** An event the event worker receives from the other workers. */
+interface EdgeRuntimeEvent {
+	timestamp: string;
+	event_type: string;
+	event: any;
+	metadata: {
+		service_path: string | null;
+		execution_id: string | null;
+	};
+}
+
+/** Only available in the event worker. */
+declare var EventManager: {
+	new (): AsyncIterable<EdgeRuntimeEvent | undefined>;
+};
@@ -1,7 +1,9 @@
 use crate::graph_resolver::{CliGraphResolver, CliGraphResolverOptions};
+use anyhow::{bail, Context};
 use deno_ast::EmitOptions;
 use deno_core::error::AnyError;
 use deno_core::parking_lot::Mutex;
+use deno_core::serde_json;
 use deno_lockfile::Lockfile;
 use deno_npm::resolution::ValidSerializedNpmResolutionSnapshot;
 use deno_npm::NpmSystemInfo;
@@ -16,6 +18,7 @@ use sb_core::cache::parsed_source::ParsedSourceCache;
 use sb_core::cache::{CacheSetting, GlobalHttpCache, HttpCache, RealDenoCacheEnv};
 use sb_core::emit::Emitter;
 use sb_core::file_fetcher::{FileCache, FileFetcher};
+use sb_core::util::fs::canonicalize_path_maybe_not_exists;
 use sb_core::util::http_util::HttpClient;
 use sb_node::{NodeResolver, PackageJson};
 use sb_npm::package_json::{
@@ -23,13 +26,17 @@ use sb_npm::package_json::{
 };
 use sb_npm::{
     create_npm_fs_resolver, CliNpmRegistryApi, CliNpmResolver, NpmCache, NpmCacheDir,
-    NpmPackageFsResolver, NpmResolution, PackageJsonDepsInstaller,
+    NpmPackageFsResolver, NpmResolution, PackageJsonDepsInstaller, ResolvedNpmRc,
 };
 use std::collections::HashMap;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+/// Serialises the writes of lockfiles, as the workers sharing one each keep
+/// their own copy of it in memory.
+static LOCKFILE_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
 struct Deferred<T>(once_cell::unsync::OnceCell<T>);
 
 impl<T> Default for Deferred<T> {
@@ -39,7 +46,6 @@ impl<T> Default for Deferred<T> {
 }
 
 impl<T> Deferred<T> {
-    #[allow(dead_code)]
     pub fn get_or_try_init(
         &self,
         create: impl FnOnce() -> Result<T, AnyError>,
@@ -67,10 +73,13 @@ impl<T> Deferred<T> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct LockfileOpts {
-    path: PathBuf,
-    overwrite: bool,
+    pub path: PathBuf,
+    /// Regenerates the entries of the lockfile instead of verifying them.
+    pub overwrite: bool,
+    /// Fails if resolving the module graph would change the lockfile.
+    pub frozen: bool,
 }
 
 pub struct EmitterFactory {
@@ -89,6 +98,9 @@ pub struct EmitterFactory {
     resolver: Deferred<Arc<CliGraphResolver>>,
     file_fetcher_cache_strategy: Option<CacheSetting>,
     file_fetcher_allow_remote: bool,
+    npm_cache_dir: Option<PathBuf>,
+    npmrc: Option<Arc<ResolvedNpmRc>>,
+    maybe_node_modules_dir: Option<PathBuf>,
     pub maybe_import_map: Option<Arc<ImportMap>>,
     file_cache: Deferred<Arc<FileCache>>,
 }
@@ -119,6 +131,9 @@ impl EmitterFactory {
             resolver: Default::default(),
             file_fetcher_cache_strategy: None,
             file_fetcher_allow_remote: true,
+            npm_cache_dir: None,
+            npmrc: None,
+            maybe_node_modules_dir: None,
             maybe_import_map: None,
             file_cache: Default::default(),
         }
@@ -132,6 +147,12 @@ impl EmitterFactory {
         self.file_fetcher_allow_remote = allow_remote;
     }
 
+    /// Downloads npm packages to `npm_cache_dir` instead of the `npm` folder
+    /// of the `DENO_DIR`.
+    pub fn set_npm_cache_dir(&mut self, npm_cache_dir: PathBuf) {
+        self.npm_cache_dir = Some(npm_cache_dir);
+    }
+
     pub fn set_import_map(&mut self, import_map: Option<ImportMap>) {
         self.maybe_import_map = import_map
             .map(|import_map| Some(Arc::new(import_map)))
@@ -142,6 +163,19 @@ impl EmitterFactory {
         self.maybe_package_json_deps = Some(get_local_package_json_version_reqs(package));
     }
 
+    pub fn package_json_deps(&self) -> Option<&PackageJsonDeps> {
+        self.maybe_package_json_deps.as_ref()
+    }
+
+    /// Sets the lockfile and loads it, so that a lockfile which cannot be
+    /// read is reported here.
+    pub fn set_lockfile(&mut self, lockfile: Option<LockfileOpts>) -> Result<(), AnyError> {
+        self.maybe_lockfile = lockfile;
+        self.lockfile = Default::default();
+        self.get_lock_file_deferred()?;
+        Ok(())
+    }
+
     pub fn set_npm_snapshot(&mut self, npm_snapshot: Option<ValidSerializedNpmResolutionSnapshot>) {
         self.npm_snapshot = npm_snapshot;
     }
@@ -206,13 +240,45 @@ impl EmitterFactory {
         Arc::new(deno_fs::RealFs)
     }
 
+    /// Sets the registries and credentials npm packages are downloaded with.
+    pub fn set_npmrc(&mut self, npmrc: ResolvedNpmRc) {
+        self.npmrc = Some(Arc::new(npmrc));
+    }
+
+    pub fn npmrc(&self) -> Arc<ResolvedNpmRc> {
+        self.npmrc.clone().unwrap_or_default()
+    }
+
+    /// Installs npm packages in `node_modules_dir`, and resolves them from
+    /// there, instead of using the global npm cache directly.
+    pub fn set_node_modules_dir(&mut self, node_modules_dir: PathBuf) -> Result<(), AnyError> {
+        self.maybe_node_modules_dir = Some(canonicalize_path_maybe_not_exists(&node_modules_dir)?);
+        Ok(())
+    }
+
+    pub fn node_modules_dir(&self) -> Option<&PathBuf> {
+        self.maybe_node_modules_dir.as_ref()
+    }
+
     pub fn npm_cache(&self) -> &Arc<NpmCache> {
         self.npm_cache.get_or_init(|| {
+            let npm_cache_dir = self
+                .npm_cache_dir
+                .clone()
+                .unwrap_or_else(|| self.deno_dir.npm_folder_path());
+            // npm packages are never reloaded, but `--cached-only` must not
+            // download them either.
+            let cache_setting = match self.file_fetcher_cache_strategy {
+                Some(CacheSetting::Only) => CacheSetting::Only,
+                _ => CacheSetting::Use,
+            };
+
             Arc::new(NpmCache::new(
-                NpmCacheDir::new(self.deno_dir.npm_folder_path().clone()),
-                CacheSetting::Use, // TODO: Maybe ?,
+                NpmCacheDir::new(npm_cache_dir),
+                cache_setting,
                 self.real_fs(),
                 self.http_client(),
+                self.npmrc(),
             ))
         })
     }
@@ -223,6 +289,7 @@ impl EmitterFactory {
                 CliNpmRegistryApi::default_url().to_owned(),
                 self.npm_cache().clone(),
                 self.http_client(),
+                self.npmrc(),
             ))
         })
     }
@@ -242,25 +309,79 @@ impl EmitterFactory {
         self.file_cache.get_or_init(Default::default)
     }
 
-    pub fn get_lock_file_deferred(&self) -> &Option<Arc<Mutex<Lockfile>>> {
-        self.lockfile.get_or_init(|| {
-            if let Some(lockfile_data) = self.maybe_lockfile.clone() {
-                Some(Arc::new(Mutex::new(
-                    Lockfile::new(lockfile_data.path.clone(), lockfile_data.overwrite).unwrap(),
-                )))
+    pub fn get_lock_file_deferred(&self) -> Result<&Option<Arc<Mutex<Lockfile>>>, AnyError> {
+        self.lockfile.get_or_try_init(|| {
+            let lockfile = if let Some(lockfile_data) = self.maybe_lockfile.as_ref() {
+                Lockfile::new(lockfile_data.path.clone(), lockfile_data.overwrite).with_context(
+                    || {
+                        format!(
+                            "failed to read the lockfile ({})",
+                            lockfile_data.path.display()
+                        )
+                    },
+                )?
             } else {
+                // Only kept in memory, it is never written
                 let default_lockfile_path = std::env::current_dir()
-                    .map(|p| p.join(".supabase.lock"))
-                    .unwrap();
-                Some(Arc::new(Mutex::new(
-                    Lockfile::new(default_lockfile_path, true).unwrap(),
-                )))
-            }
+                    .unwrap_or_default()
+                    .join(".supabase.lock");
+                Lockfile::new(default_lockfile_path, true)?
+            };
+
+            Ok(Some(Arc::new(Mutex::new(lockfile))))
         })
     }
 
     pub fn get_lock_file(&self) -> Option<Arc<Mutex<Lockfile>>> {
-        self.get_lock_file_deferred().as_ref().cloned()
+        // A lockfile that cannot be read has already failed `set_lockfile`
+        self.get_lock_file_deferred().ok().and_then(Option::clone)
+    }
+
+    /// Persists the lockfile if one was explicitly set and either its content
+    /// changed or `--lock-write` was given. A frozen lockfile is never written;
+    /// instead, an error is returned if its content changed.
+    pub fn write_lock_file_if_necessary(&self) -> Result<(), AnyError> {
+        let Some(lockfile_opts) = self.maybe_lockfile.as_ref() else {
+            return Ok(());
+        };
+        let Some(lockfile) = self.get_lock_file() else {
+            return Ok(());
+        };
+
+        let lockfile = lockfile.lock();
+        if lockfile_opts.frozen {
+            if lockfile.has_content_changed {
+                bail!(
+                    "the lockfile is out of date, run without `--frozen` to update it ({})",
+                    lockfile_opts.path.display()
+                );
+            }
+
+            return Ok(());
+        }
+        if !lockfile.overwrite && !lockfile.has_content_changed {
+            return Ok(());
+        }
+
+        let _guard = LOCKFILE_WRITE_LOCK.lock();
+        let existing = std::fs::read_to_string(&lockfile.filename).ok();
+        let contents = match existing.as_deref() {
+            // Keeps the entries other workers added since this one was loaded
+            Some(existing) if !lockfile.overwrite => {
+                merge_lockfile_contents(&lockfile.filename, existing, &lockfile.as_json_string())?
+            }
+            _ => lockfile.as_json_string(),
+        };
+        if existing.as_deref() == Some(contents.as_str()) {
+            return Ok(());
+        }
+
+        write_atomically(&lockfile.filename, &contents).with_context(|| {
+            format!(
+                "failed to write the lockfile ({})",
+                lockfile.filename.display()
+            )
+        })
     }
 
     pub fn node_resolver(&self) -> &Arc<NodeResolver> {
@@ -277,7 +398,7 @@ impl EmitterFactory {
             self.npm_cache().clone(),
             CliNpmRegistryApi::default_url().to_owned(),
             self.npm_resolution().clone(),
-            None,
+            self.maybe_node_modules_dir.clone(),
             NpmSystemInfo::default(),
         )
     }
@@ -367,3 +488,43 @@ impl EmitterFactory {
         ))
     }
 }
+
+/// Adds the entries of `contents` to the ones of `existing`, the entries of
+/// `contents` winning.
+fn merge_lockfile_contents(
+    path: &Path,
+    existing: &str,
+    contents: &str,
+) -> Result<String, AnyError> {
+    fn merge(into: &mut serde_json::Value, from: serde_json::Value) {
+        match (into, from) {
+            (serde_json::Value::Object(into), serde_json::Value::Object(from)) => {
+                for (key, value) in from {
+                    match into.get_mut(&key) {
+                        Some(existing) => merge(existing, value),
+                        None => {
+                            into.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (into, from) => *into = from,
+        }
+    }
+
+    let Ok(mut merged) = serde_json::from_str::<serde_json::Value>(existing) else {
+        return Ok(contents.to_string());
+    };
+
+    merge(&mut merged, serde_json::from_str(contents)?);
+
+    // Loaded again to sort the entries
+    let merged = serde_json::to_string(&merged)?;
+    Ok(Lockfile::with_lockfile_content(path.to_path_buf(), &merged, false)?.as_json_string())
+}
+
+fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension(format!("tmp-{}", std::process::id()));
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,709 @@
+use crate::inspect::parse_import_map;
+use crate::{
+    payload_to_eszip, EszipPayloadKind, METADATA_ESZIP_KEY, NODE_MODULES_ESZIP_KEY,
+    SOURCE_CODE_ESZIP_KEY, VFS_ESZIP_KEY,
+};
+use anyhow::{anyhow, bail, Context};
+use deno_ast::MediaType;
+use deno_core::error::AnyError;
+use deno_core::serde_json::{self, Map, Value};
+use deno_core::url::Url;
+use deno_lockfile::Lockfile;
+use deno_npm::resolution::NpmResolutionSnapshot;
+use eszip::ModuleKind;
+use log::warn;
+use sb_core::util::path::{is_banned_path_char, root_url_to_safe_local_dirname};
+use sb_fs::virtual_fs::{VfsEntry, VirtualDirectory};
+use sb_npm::resolution::populate_lockfile_from_snapshot;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+const VENDOR_DIR: &str = "vendor";
+pub(crate) const IMPORT_MAP_FILE: &str = "import_map.json";
+pub(crate) const LOCKFILE_FILE: &str = "deno.lock";
+
+pub struct ExtractEszipPayload {
+    pub data: EszipPayloadKind,
+    pub folder: PathBuf,
+}
+
+/// The files `extract_eszip` wrote that are needed to bundle the project again.
+#[derive(Debug)]
+pub struct ExtractedEszip {
+    pub entrypoint: PathBuf,
+    /// The entrypoint of each function, if the eszip bundles several.
+    pub functions: Vec<(String, PathBuf)>,
+    pub import_map: Option<PathBuf>,
+    pub lockfile: Option<PathBuf>,
+    /// Set when the npm packages were bundled from a `node_modules` directory.
+    pub node_modules_dir: Option<PathBuf>,
+}
+
+/// The part of the metadata written by `bundle` that tells the functions of a
+/// multi-function eszip apart.
+#[derive(Deserialize)]
+struct BundledFunctions {
+    #[serde(default)]
+    functions: Option<BTreeMap<String, String>>,
+}
+
+struct EszipModule {
+    url: Url,
+    kind: ModuleKind,
+    source: Arc<[u8]>,
+}
+
+/// Where modules are written to. Local modules keep their layout relative to
+/// the directory they have in common, and remote modules go to
+/// `<vendor_folder>/<host>/<path>`.
+pub(crate) struct OutputLayout {
+    /// Local modules are moved from this directory into the output folder.
+    /// When `None`, they're left where they are.
+    pub(crate) local_root: Option<PathBuf>,
+    pub(crate) output_folder: PathBuf,
+    pub(crate) vendor_folder: PathBuf,
+    pub(crate) module_paths: HashMap<String, PathBuf>,
+}
+
+impl OutputLayout {
+    pub(crate) fn local_path(&self, url: &Url) -> Option<PathBuf> {
+        match url.scheme() {
+            "file" => {
+                let path = url.to_file_path().ok()?;
+                let Some(root) = self.local_root.as_ref() else {
+                    return Some(path);
+                };
+                let relative = path.strip_prefix(root).ok()?;
+
+                Some(self.output_folder.join(relative))
+            }
+
+            "http" | "https" => Some(self.vendor_folder.join(root_url_to_safe_local_dirname(url))),
+
+            _ => None,
+        }
+    }
+
+    /// Returns the path of `url` relative to the output folder, prefixed with
+    /// `./` (or `../` when it's outside of it).
+    fn relative_specifier(&self, url: &Url) -> Option<String> {
+        let path = match self.module_paths.get(url.as_str()) {
+            Some(path) => path.clone(),
+            None => self.local_path(url)?,
+        };
+
+        let mut base = self.output_folder.components().peekable();
+        let mut target = path.components().peekable();
+
+        while let (Some(a), Some(b)) = (base.peek(), target.peek()) {
+            if a != b {
+                break;
+            }
+            base.next();
+            target.next();
+        }
+
+        let mut specifier = String::from(".");
+
+        if base.peek().is_some() {
+            specifier.push('.');
+            for _ in base.skip(1) {
+                specifier.push_str("/..");
+            }
+        }
+
+        for component in target {
+            specifier.push('/');
+            specifier.push_str(&component.as_os_str().to_string_lossy());
+        }
+
+        if url.path().ends_with('/') && !specifier.ends_with('/') {
+            specifier.push('/');
+        }
+
+        Some(specifier)
+    }
+
+    /// Rewrites a specifier of the original import map so that it points into
+    /// the extracted project. Bare specifiers are left as is.
+    fn rewrite(&self, specifier: &str) -> String {
+        Url::parse(specifier)
+            .ok()
+            .and_then(|url| self.relative_specifier(&url))
+            .unwrap_or_else(|| specifier.to_string())
+    }
+}
+
+fn common_ancestor<'a>(mut paths: impl Iterator<Item = &'a PathBuf>) -> Option<PathBuf> {
+    let mut ancestor = paths.next()?.parent()?.to_path_buf();
+
+    for path in paths {
+        while !path.starts_with(&ancestor) {
+            if !ancestor.pop() {
+                return None;
+            }
+        }
+    }
+
+    Some(ancestor)
+}
+
+fn sanitize_file_name(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if matches!(c, '/' | '\\' | '&' | '=') || is_banned_path_char(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Remote modules may have a query string or no file name at all, neither of
+/// which survive as a file name. The query is folded into the file name and
+/// `index` is used for directories.
+pub(crate) fn vendor_file_stem(mut path: PathBuf, url: &Url) -> PathBuf {
+    if url.path().ends_with('/') || path.file_name().is_none() {
+        path.push("index");
+    }
+
+    if let Some(query) = url.query() {
+        let stem = path
+            .file_stem()
+            .map(|it| it.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file_name = match path.extension() {
+            Some(ext) => format!(
+                "{}_{}.{}",
+                stem,
+                sanitize_file_name(query),
+                ext.to_string_lossy()
+            ),
+            None => format!("{}_{}", stem, sanitize_file_name(query)),
+        };
+
+        path.set_file_name(file_name);
+    }
+
+    path
+}
+
+pub(crate) fn push_extension(path: &mut PathBuf, extension: &str) {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+
+    file_name.push(".");
+    file_name.push(extension.trim_start_matches('.'));
+    path.set_file_name(file_name);
+}
+
+/// Like [`vendor_file_stem`], also adding an extension matching what the
+/// eszip stores when needed.
+fn vendor_file_path(path: PathBuf, url: &Url, kind: ModuleKind) -> PathBuf {
+    let mut path = vendor_file_stem(path, url);
+
+    let media_type = MediaType::from_path(&path);
+    let extension = match kind {
+        ModuleKind::Json | ModuleKind::Jsonc if media_type != MediaType::Json => Some("json"),
+        ModuleKind::JavaScript if matches!(media_type, MediaType::Unknown | MediaType::Json) => {
+            Some("js")
+        }
+        _ => None,
+    };
+
+    if let Some(extension) = extension {
+        push_extension(&mut path, extension);
+    }
+
+    path
+}
+
+pub(crate) fn write_file(path: &Path, content: &[u8]) -> Result<(), AnyError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+
+    fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The names in the virtual file system come from the eszip, so they're
+/// checked to not escape the directory they're written to.
+fn vfs_entry_path(parent: &Path, name: &str) -> Result<PathBuf, AnyError> {
+    let mut components = Path::new(name).components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(parent.join(name)),
+        _ => bail!("invalid name in the npm virtual file system: {:?}", name),
+    }
+}
+
+fn write_vfs_dir(dir: &VirtualDirectory, path: &Path, root: &Path) -> Result<(), AnyError> {
+    fs::create_dir_all(path).with_context(|| format!("failed to create {}", path.display()))?;
+
+    for entry in &dir.entries {
+        match entry {
+            VfsEntry::Dir(dir) => write_vfs_dir(dir, &vfs_entry_path(path, &dir.name)?, root)?,
+            VfsEntry::File(file) => {
+                let content = file
+                    .content
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("npm file {} is missing its content", file.name))?;
+
+                write_file(&vfs_entry_path(path, &file.name)?, content)?;
+            }
+            VfsEntry::Symlink(symlink) => {
+                let link = vfs_entry_path(path, &symlink.name)?;
+                let dest = symlink.resolve_dest_from_root(root);
+
+                if !dest.starts_with(root) || symlink.dest_parts.iter().any(|it| it == "..") {
+                    bail!(
+                        "npm symlink {} points outside of node_modules",
+                        symlink.name
+                    );
+                }
+
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&dest, &link)
+                    .with_context(|| format!("failed to create {}", link.display()))?;
+
+                #[cfg(not(unix))]
+                warn!(
+                    "skipping npm symlink {} -> {}",
+                    link.display(),
+                    dest.display()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the import map of the extracted project: the entries of the
+/// original import map are rewritten to point into the output folder, and
+/// remote modules are mapped to their vendored copies.
+///
+/// Remote modules often import each other with absolute paths (`/v135/...`),
+/// which resolve to `file:///v135/...` once vendored, so every host gets a
+/// scope mapping those paths back into its vendor directory.
+pub(crate) fn build_import_map(
+    layout: &OutputLayout,
+    maybe_original: Option<Value>,
+    remote_modules: &[(String, Url)],
+) -> Value {
+    let mut imports = Map::new();
+    let mut scopes = Map::new();
+
+    if let Some(Value::Object(original)) = maybe_original {
+        if let Some(Value::Object(original_imports)) = original.get("imports") {
+            for (key, value) in original_imports {
+                let value = match value {
+                    Value::String(value) => Value::String(layout.rewrite(value)),
+                    other => other.clone(),
+                };
+
+                imports.insert(layout.rewrite(key), value);
+            }
+        }
+
+        if let Some(Value::Object(original_scopes)) = original.get("scopes") {
+            for (scope, entries) in original_scopes {
+                let mut rewritten = Map::new();
+
+                if let Value::Object(entries) = entries {
+                    for (key, value) in entries {
+                        let value = match value {
+                            Value::String(value) => Value::String(layout.rewrite(value)),
+                            other => other.clone(),
+                        };
+
+                        rewritten.insert(layout.rewrite(key), value);
+                    }
+                }
+
+                scopes.insert(layout.rewrite(scope), Value::Object(rewritten));
+            }
+        }
+    }
+
+    for (specifier, target) in remote_modules {
+        let Some(local) = layout.relative_specifier(target) else {
+            continue;
+        };
+        let Ok(url) = Url::parse(specifier) else {
+            continue;
+        };
+
+        imports
+            .entry(specifier.clone())
+            .or_insert_with(|| Value::String(local.clone()));
+
+        let mut host_url = url.clone();
+        host_url.set_path("/");
+        host_url.set_query(None);
+
+        let Some(scope) = layout.relative_specifier(&host_url) else {
+            continue;
+        };
+        let mut absolute_path = url.path().to_string();
+
+        if let Some(query) = url.query() {
+            absolute_path.push('?');
+            absolute_path.push_str(query);
+        }
+
+        if let Value::Object(entries) = scopes
+            .entry(scope)
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            entries
+                .entry(absolute_path)
+                .or_insert_with(|| Value::String(local));
+        }
+    }
+
+    let mut import_map = Map::new();
+
+    import_map.insert("imports".to_string(), Value::Object(imports));
+    if !scopes.is_empty() {
+        import_map.insert("scopes".to_string(), Value::Object(scopes));
+    }
+
+    Value::Object(import_map)
+}
+
+/// Writes the content of an eszip out as a project that `bundle` can turn
+/// back into an equivalent eszip. Besides the local modules, this writes:
+///
+/// * remote modules into `vendor/`,
+/// * an `import_map.json` mapping remote specifiers to their vendored copies
+///   (and carrying over the import map stored in the eszip, if any),
+/// * the npm packages of the virtual file system into `node_modules/`,
+/// * a `deno.lock` pinning the npm packages to the versions in the eszip.
+///
+/// Note that modules are stored transpiled inside an eszip, so the extracted
+/// TypeScript files contain plain JavaScript.
+pub async fn extract_eszip(payload: ExtractEszipPayload) -> Result<ExtractedEszip, AnyError> {
+    let mut eszip = payload_to_eszip(payload.data)
+        .await
+        .context("failed to read the eszip")?;
+    let output_folder = payload.folder;
+
+    let mut modules = vec![];
+    let mut redirects = vec![];
+    let mut maybe_import_map = None;
+    let mut maybe_vfs = None;
+    let mut maybe_functions = None;
+    let mut is_node_modules_dir = false;
+
+    for (idx, specifier) in eszip.specifiers().into_iter().enumerate() {
+        if specifier == SOURCE_CODE_ESZIP_KEY {
+            // The entrypoint is stored as a regular module as well.
+            continue;
+        }
+
+        let Some(module) = eszip
+            .get_module(&specifier)
+            .or_else(|| eszip.get_import_map(&specifier))
+        else {
+            continue;
+        };
+
+        if module.specifier != specifier {
+            redirects.push((specifier, module.specifier));
+            continue;
+        }
+
+        let source = module
+            .source()
+            .await
+            .ok_or_else(|| anyhow!("module {} has no source", specifier))?;
+
+        if specifier == VFS_ESZIP_KEY {
+            maybe_vfs = serde_json::from_slice::<Option<VirtualDirectory>>(&source)
+                .context("failed to parse the npm virtual file system")?;
+            continue;
+        }
+
+        if specifier == METADATA_ESZIP_KEY {
+            maybe_functions = serde_json::from_slice::<BundledFunctions>(&source)
+                .ok()
+                .and_then(|it| it.functions);
+            continue;
+        }
+
+        if specifier == NODE_MODULES_ESZIP_KEY {
+            is_node_modules_dir = true;
+            continue;
+        }
+
+        if matches!(module.kind, ModuleKind::OpaqueData) {
+            // e.g. the signature of the eszip.
+            continue;
+        }
+
+        if idx == 0 && matches!(module.kind, ModuleKind::Json | ModuleKind::Jsonc) {
+            if let Some(import_map) = parse_import_map(&source) {
+                maybe_import_map = Some(import_map);
+                continue;
+            }
+        }
+
+        let url = Url::parse(&specifier)
+            .with_context(|| format!("invalid module specifier {}", specifier))?;
+
+        modules.push(EszipModule {
+            url,
+            kind: module.kind,
+            source,
+        });
+    }
+
+    let local_paths = modules
+        .iter()
+        .filter(|it| it.url.scheme() == "file")
+        .filter_map(|it| it.url.to_file_path().ok())
+        .collect::<Vec<_>>();
+    let Some(entrypoint) = local_paths.first().cloned() else {
+        bail!("the eszip doesn't contain any local module");
+    };
+    let Some(root) = common_ancestor(local_paths.iter()) else {
+        bail!("the local modules of the eszip have no directory in common");
+    };
+
+    let mut layout = OutputLayout {
+        local_root: Some(root.clone()),
+        output_folder: output_folder.clone(),
+        vendor_folder: output_folder.join(VENDOR_DIR),
+        module_paths: HashMap::new(),
+    };
+
+    for module in &modules {
+        let Some(path) = layout.local_path(&module.url) else {
+            warn!("skipping module {}", module.url);
+            continue;
+        };
+        let path = if module.url.scheme() == "file" {
+            path
+        } else {
+            vendor_file_path(path, &module.url, module.kind)
+        };
+
+        write_file(&path, &module.source)?;
+        layout
+            .module_paths
+            .insert(module.url.as_str().to_string(), path);
+    }
+
+    let functions = maybe_functions
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(name, specifier)| {
+            let path = layout.module_paths.get(&specifier)?;
+            Some((name, path.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    let mut remote_modules = modules
+        .iter()
+        .filter(|it| matches!(it.url.scheme(), "http" | "https"))
+        .map(|it| (it.url.as_str().to_string(), it.url.clone()))
+        .collect::<Vec<_>>();
+
+    for (from, to) in redirects {
+        let Ok(to) = Url::parse(&to) else {
+            continue;
+        };
+
+        if let Some(path) = layout.module_paths.get(to.as_str()).cloned() {
+            layout.module_paths.insert(from.clone(), path);
+        }
+
+        if from.starts_with("http:") || from.starts_with("https:") {
+            remote_modules.push((from, to));
+        }
+    }
+
+    let import_map_path = if maybe_import_map.is_some() || !remote_modules.is_empty() {
+        let import_map = build_import_map(&layout, maybe_import_map, &remote_modules);
+        let path = output_folder.join(IMPORT_MAP_FILE);
+
+        write_file(&path, serde_json::to_string_pretty(&import_map)?.as_bytes())?;
+        Some(path)
+    } else {
+        None
+    };
+
+    let node_modules_dir = if let Some(vfs) = maybe_vfs {
+        let vfs_root = vfs_entry_path(&output_folder, &vfs.name)?;
+        write_vfs_dir(&vfs, &vfs_root, &vfs_root)?;
+        Some(vfs_root).filter(|_| is_node_modules_dir)
+    } else {
+        None
+    };
+
+    let lockfile_path = if let Some(snapshot) = eszip.take_npm_snapshot() {
+        let path = output_folder.join(LOCKFILE_FILE);
+        let mut lockfile = Lockfile::new(path.clone(), true)?;
+
+        populate_lockfile_from_snapshot(&mut lockfile, &NpmResolutionSnapshot::new(snapshot))?;
+        lockfile.write()?;
+        Some(path)
+    } else {
+        None
+    };
+
+    Ok(ExtractedEszip {
+        entrypoint: output_folder.join(entrypoint.strip_prefix(&root)?),
+        functions,
+        import_map: import_map_path,
+        lockfile: lockfile_path,
+        node_modules_dir,
+    })
+}
+
+pub async fn extract_from_file(
+    eszip_file: PathBuf,
+    output_path: PathBuf,
+) -> Result<ExtractedEszip, AnyError> {
+    let eszip_content = fs::read(&eszip_file)
+        .with_context(|| format!("failed to read {}", eszip_file.display()))?;
+
+    extract_eszip(ExtractEszipPayload {
+        data: EszipPayloadKind::VecKind(eszip_content),
+        folder: output_path,
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::extract::{extract_eszip, ExtractEszipPayload};
+    use crate::{
+        generate_binary_eszip, generate_binary_eszip_for_functions, EmitterFactory,
+        EszipPayloadKind, METADATA_ESZIP_KEY,
+    };
+    use deno_core::serde_json::{self, json};
+    use std::fs::{read_to_string, remove_dir_all};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn output_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sb-extract-{}-{}", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_extract_npm_eszip() {
+        let eszip = generate_binary_eszip(
+            PathBuf::from("../base/test_cases/npm/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await;
+        let eszip = eszip.unwrap();
+        let output = output_dir("npm");
+        let extracted = extract_eszip(ExtractEszipPayload {
+            data: EszipPayloadKind::Eszip(eszip),
+            folder: output.clone(),
+        })
+        .await
+        .unwrap();
+
+        assert!(output.join("hello.js").exists());
+        assert!(output.join("folder1/folder2/numbers.ts").exists());
+        assert_eq!(extracted.entrypoint, output.join("index.ts"));
+
+        assert!(output
+            .join("vendor/deno.land/std@0.131.0/http/server.ts")
+            .exists());
+        let import_map = read_to_string(extracted.import_map.unwrap()).unwrap();
+        assert!(import_map.contains(
+            "\"https://deno.land/std@0.131.0/http/server.ts\": \"./vendor/deno.land/std@0.131.0/http/server.ts\""
+        ));
+
+        assert!(output.join("node_modules").is_dir());
+        let lockfile = read_to_string(extracted.lockfile.unwrap()).unwrap();
+        assert!(lockfile.contains("is-even"));
+
+        remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_extract_multi_function_eszip() {
+        let (mut eszip, entrypoints) = generate_binary_eszip_for_functions(
+            vec![
+                (
+                    String::from("json"),
+                    PathBuf::from("../base/test_cases/json_import/index.ts"),
+                ),
+                (
+                    String::from("empty"),
+                    PathBuf::from("../base/test_cases/empty-response/index.ts"),
+                ),
+            ],
+            Arc::new(EmitterFactory::new()),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(entrypoints.len(), 2);
+        for specifier in entrypoints.values() {
+            assert!(eszip.get_module(specifier).is_some());
+        }
+
+        eszip.add_opaque_data(
+            String::from(METADATA_ESZIP_KEY),
+            Arc::from(
+                serde_json::to_vec(&json!({ "functions": entrypoints }))
+                    .unwrap()
+                    .into_boxed_slice(),
+            ),
+        );
+
+        let output = output_dir("multi-function");
+        let extracted = extract_eszip(ExtractEszipPayload {
+            data: EszipPayloadKind::Eszip(eszip),
+            folder: output.clone(),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            extracted.functions,
+            vec![
+                (
+                    String::from("empty"),
+                    output.join("empty-response/index.ts")
+                ),
+                (String::from("json"), output.join("json_import/index.ts")),
+            ]
+        );
+        assert!(output.join("json_import/version.json").exists());
+        assert!(output
+            .join("vendor/deno.land/std@0.131.0/http/server.ts")
+            .exists());
+
+        remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_extract_invalid_eszip() {
+        let output = output_dir("invalid");
+        let result = extract_eszip(ExtractEszipPayload {
+            data: EszipPayloadKind::VecKind(b"not an eszip".to_vec()),
+            folder: output.clone(),
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert!(!output.exists());
+    }
+}
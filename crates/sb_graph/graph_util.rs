@@ -1,5 +1,6 @@
 use crate::emitter::EmitterFactory;
 use crate::graph_resolver::CliGraphResolver;
+use anyhow::{bail, Context};
 use deno_ast::MediaType;
 use deno_core::error::{custom_error, AnyError};
 use deno_core::parking_lot::Mutex;
@@ -9,13 +10,13 @@ use deno_graph::ResolutionError;
 use deno_lockfile::Lockfile;
 use deno_semver::package::{PackageNv, PackageReq};
 use eszip::deno_graph::source::Loader;
-use eszip::deno_graph::{GraphKind, ModuleGraph, ModuleGraphError};
+use eszip::deno_graph::{GraphKind, Module, ModuleGraph, ModuleGraphError};
 use eszip::{deno_graph, EszipV2};
 use sb_core::cache::parsed_source::ParsedSourceCache;
 use sb_core::errors_rt::get_error_class_name;
 use sb_core::file_fetcher::File;
 use sb_npm::CliNpmResolver;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 #[derive(Clone, Copy)]
@@ -203,7 +204,11 @@ impl ModuleGraphBuilder {
         let graph_resolver = cli_resolver.as_graph_resolver();
         let graph_npm_resolver = cli_resolver.as_graph_npm_resolver();
         let analyzer = self.parsed_source_cache.as_analyzer();
-        let graph_kind = deno_graph::GraphKind::CodeOnly;
+        let graph_kind = if self.type_check {
+            deno_graph::GraphKind::All
+        } else {
+            deno_graph::GraphKind::CodeOnly
+        };
         let mut graph = ModuleGraph::new(graph_kind);
 
         self.build_graph_with_npm_resolution(
@@ -222,10 +227,42 @@ impl ModuleGraphBuilder {
         )
         .await?;
 
+        if let Some(lockfile) = &self.lockfile {
+            graph_lock(&graph, &mut lockfile.lock())?;
+        }
+
+        self.emitter_factory.write_lock_file_if_necessary()?;
+
         Ok(graph)
     }
 }
 
+/// Verifies the remote modules of `graph` against the hashes stored in the
+/// lockfile, adding the ones that are not there yet.
+pub fn graph_lock(graph: &ModuleGraph, lockfile: &mut Lockfile) -> Result<(), AnyError> {
+    for module in graph.modules() {
+        let source = match module {
+            Module::Esm(module) if module.media_type.is_declaration() => continue,
+            Module::Esm(module) => &module.source,
+            Module::Json(module) => &module.source,
+            _ => continue,
+        };
+
+        if !lockfile.check_or_insert_remote(module.specifier().as_str(), source) {
+            return Err(custom_error(
+                "InvalidData",
+                format!(
+                    "The source code is invalid, as it does not match the expected hash in the lock file.\n  Specifier: {}\n  Lock file: {}",
+                    module.specifier(),
+                    lockfile.filename.display()
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 /// Check if `roots` and their deps are available. Returns `Ok(())` if
 /// so. Returns `Err(_)` if there is a known module graph or resolution
 /// error statically reachable from `roots`.
@@ -305,11 +342,23 @@ pub async fn create_eszip_from_graph_raw(
     eszip::EszipV2::from_graph(graph, &parser, Default::default())
 }
 
+/// The specifier `create_graph` gives to the module at `file`.
+pub fn file_specifier(file: &Path) -> Result<ModuleSpecifier, AnyError> {
+    let binding = std::fs::canonicalize(file)
+        .with_context(|| format!("failed to resolve module {}", file.display()))?;
+    let Some(specifier) = binding.to_str() else {
+        bail!("module path {} is not valid unicode", binding.display());
+    };
+    let format_specifier = format!("file:///{}", specifier);
+
+    Ok(ModuleSpecifier::parse(&format_specifier)?)
+}
+
 pub async fn create_graph(
     file: PathBuf,
     emitter_factory: Arc<EmitterFactory>,
     maybe_code: &Option<FastString>,
-) -> ModuleGraph {
+) -> Result<ModuleGraph, AnyError> {
     let module_specifier = if let Some(code) = maybe_code {
         let specifier = ModuleSpecifier::parse("file:///src/index.ts").unwrap();
 
@@ -326,17 +375,13 @@ pub async fn create_graph(
 
         specifier
     } else {
-        let binding = std::fs::canonicalize(&file).unwrap();
-        let specifier = binding.to_str().unwrap();
-        let format_specifier = format!("file:///{}", specifier);
-
-        ModuleSpecifier::parse(&format_specifier).unwrap()
+        file_specifier(&file)?
     };
 
     let builder = ModuleGraphBuilder::new(emitter_factory, false);
 
     let create_module_graph_task = builder.create_graph_and_maybe_check(vec![module_specifier]);
-    create_module_graph_task.await.unwrap()
+    create_module_graph_task.await
 }
 
 pub async fn create_graph_from_specifiers(
@@ -348,3 +393,172 @@ pub async fn create_graph_from_specifiers(
     let create_module_graph_task = builder.create_graph_and_maybe_check(specifiers);
     create_module_graph_task.await
 }
+
+#[cfg(test)]
+mod test {
+    use crate::emitter::{EmitterFactory, LockfileOpts};
+    use crate::generate_binary_eszip;
+    use crate::graph_util::file_specifier;
+    use deno_core::serde_json::{self, json, Value};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const STD_SERVER: &str = "https://deno.land/std@0.131.0/http/server.ts";
+
+    fn lockfile_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sb-lockfile-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("deno.lock")
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn bundle_npm_test_case(
+        lockfile: LockfileOpts,
+    ) -> Result<(), deno_core::error::AnyError> {
+        let mut emitter_factory = EmitterFactory::new();
+        emitter_factory.set_lockfile(Some(lockfile))?;
+
+        generate_binary_eszip(
+            PathBuf::from("../base/test_cases/npm/index.ts"),
+            Arc::new(emitter_factory),
+            None,
+            None,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    #[test]
+    fn test_file_specifier_of_missing_module_is_an_error() {
+        let err = file_specifier(&PathBuf::from("../base/test_cases/missing/index.ts"))
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("failed to resolve module"), "{}", err);
+        assert!(err.contains("missing/index.ts"), "{}", err);
+    }
+
+    #[test]
+    fn test_corrupt_lockfile_is_an_error() {
+        let path = lockfile_path("corrupt");
+        fs::write(&path, "{").unwrap();
+
+        let result = EmitterFactory::new().set_lockfile(Some(LockfileOpts {
+            path: path.clone(),
+            overwrite: false,
+            frozen: false,
+        }));
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("failed to read the lockfile"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_rejects_changed_remote_module() {
+        let path = lockfile_path("remote");
+        let lockfile = json!({ "version": "3", "remote": { STD_SERVER: "0".repeat(64) } });
+        fs::write(&path, lockfile.to_string()).unwrap();
+
+        let result = bundle_npm_test_case(LockfileOpts {
+            path: path.clone(),
+            overwrite: false,
+            frozen: false,
+        })
+        .await;
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("does not match the expected hash"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lockfile_rejects_changed_npm_package() {
+        let path = lockfile_path("npm");
+        let lockfile = json!({
+            "version": "3",
+            "packages": {
+                "npm": {
+                    "is-even@1.0.0": {
+                        "integrity": "sha512-invalid",
+                        "dependencies": { "is-odd": "is-odd@0.1.2" }
+                    }
+                }
+            },
+            "remote": {}
+        });
+        fs::write(&path, lockfile.to_string()).unwrap();
+
+        let result = bundle_npm_test_case(LockfileOpts {
+            path: path.clone(),
+            overwrite: false,
+            frozen: false,
+        })
+        .await;
+
+        assert!(format!("{:#}", result.unwrap_err()).contains("Integrity check failed"));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_frozen_lockfile_rejects_changes() {
+        let path = lockfile_path("frozen");
+        let lockfile = json!({ "version": "3", "remote": {} }).to_string();
+        fs::write(&path, &lockfile).unwrap();
+
+        let result = bundle_npm_test_case(LockfileOpts {
+            path: path.clone(),
+            overwrite: false,
+            frozen: true,
+        })
+        .await;
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("lockfile is out of date"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), lockfile);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lock_write_updates_lockfile() {
+        let path = lockfile_path("write");
+        let lockfile = json!({ "version": "3", "remote": { STD_SERVER: "0".repeat(64) } });
+        fs::write(&path, lockfile.to_string()).unwrap();
+
+        bundle_npm_test_case(LockfileOpts {
+            path: path.clone(),
+            overwrite: true,
+            frozen: false,
+        })
+        .await
+        .unwrap();
+
+        let lockfile: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let hash = lockfile["remote"][STD_SERVER].as_str().unwrap();
+
+        assert_ne!(hash, "0".repeat(64));
+        assert!(lockfile["packages"]["npm"]["is-even@1.0.0"].is_object());
+
+        // The entries are now verified instead of overwritten
+        bundle_npm_test_case(LockfileOpts {
+            path: path.clone(),
+            overwrite: false,
+            frozen: true,
+        })
+        .await
+        .unwrap();
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
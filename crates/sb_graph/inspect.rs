@@ -0,0 +1,464 @@
+use crate::{
+    payload_to_eszip, EszipPayloadKind, METADATA_ESZIP_KEY, NODE_MODULES_ESZIP_KEY,
+    SOURCE_CODE_ESZIP_KEY, STATIC_FILES_ESZIP_KEY, VFS_ESZIP_KEY,
+};
+use deno_core::error::AnyError;
+use deno_core::serde_json;
+use eszip::ModuleKind;
+use sb_fs::virtual_fs::{VfsEntry, VirtualDirectory};
+use sb_fs::StaticFilesData;
+use serde::Serialize;
+use std::fmt;
+
+/// A summary of everything stored in an eszip, as printed by
+/// `edge-runtime eszip inspect`.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EszipInfo {
+    pub modules: Vec<ModuleInfo>,
+    pub redirects: Vec<RedirectInfo>,
+    pub import_map: Option<ImportMapInfo>,
+    pub npm_packages: Vec<NpmPackageInfo>,
+    pub vfs: Option<VfsEntryInfo>,
+    /// Whether `vfs` is a `node_modules` directory rather than a copy of the
+    /// global npm cache.
+    pub node_modules_dir: bool,
+    /// The files bundled besides the modules, under the directory they were
+    /// bundled from.
+    pub static_files: Option<StaticFilesInfo>,
+    /// The build metadata written by `bundle`, if any.
+    pub metadata: Option<serde_json::Value>,
+    pub sizes: SizeInfo,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleInfo {
+    pub specifier: String,
+    pub kind: &'static str,
+    pub size: usize,
+    pub source_map_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedirectInfo {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportMapInfo {
+    pub specifier: String,
+    pub size: usize,
+    pub content: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NpmPackageInfo {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VfsEntryInfo {
+    pub name: String,
+    pub kind: &'static str,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<VfsEntryInfo>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaticFilesInfo {
+    pub root_path: String,
+    pub dir: VfsEntryInfo,
+}
+
+/// Sizes in bytes. `archive` is only known when the eszip was given as bytes.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeInfo {
+    pub modules: usize,
+    pub source_maps: usize,
+    pub import_map: usize,
+    pub npm_vfs: usize,
+    pub static_files: usize,
+    pub source_code: usize,
+    pub archive: Option<usize>,
+}
+
+fn module_kind_name(kind: ModuleKind) -> &'static str {
+    match kind {
+        ModuleKind::JavaScript => "javascript",
+        ModuleKind::Json => "json",
+        ModuleKind::Jsonc => "jsonc",
+        ModuleKind::OpaqueData => "opaque",
+    }
+}
+
+/// `add_import_map` always puts the import map at the top of the archive, but
+/// it's stored like any other JSON module, so it's told apart by its shape.
+pub(crate) fn parse_import_map(source: &[u8]) -> Option<serde_json::Value> {
+    let value = serde_json::from_slice::<serde_json::Value>(source).ok()?;
+    let object = value.as_object()?;
+
+    if object.contains_key("imports") || object.contains_key("scopes") {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn vfs_entry_info(entry: &VfsEntry) -> VfsEntryInfo {
+    match entry {
+        VfsEntry::Dir(dir) => vfs_dir_info(dir),
+        VfsEntry::File(file) => VfsEntryInfo {
+            name: file.name.clone(),
+            kind: "file",
+            size: file
+                .content
+                .as_ref()
+                .map_or(file.len, |content| content.len() as u64),
+            target: None,
+            entries: vec![],
+        },
+        VfsEntry::Symlink(symlink) => VfsEntryInfo {
+            name: symlink.name.clone(),
+            kind: "symlink",
+            size: 0,
+            target: Some(symlink.dest_parts.join("/")),
+            entries: vec![],
+        },
+    }
+}
+
+fn vfs_dir_info(dir: &VirtualDirectory) -> VfsEntryInfo {
+    let entries = dir.entries.iter().map(vfs_entry_info).collect::<Vec<_>>();
+
+    VfsEntryInfo {
+        name: dir.name.clone(),
+        kind: "dir",
+        size: entries.iter().map(|it| it.size).sum(),
+        target: None,
+        entries,
+    }
+}
+
+pub async fn inspect_eszip(payload: EszipPayloadKind) -> Result<EszipInfo, AnyError> {
+    let archive_size = match &payload {
+        EszipPayloadKind::JsBufferKind(buf) => Some(buf.len()),
+        EszipPayloadKind::VecKind(vec) => Some(vec.len()),
+        EszipPayloadKind::Eszip(_) => None,
+    };
+
+    let mut eszip = payload_to_eszip(payload).await?;
+    let mut info = EszipInfo {
+        sizes: SizeInfo {
+            archive: archive_size,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    for (idx, specifier) in eszip.specifiers().into_iter().enumerate() {
+        let Some(module) = eszip
+            .get_module(&specifier)
+            .or_else(|| eszip.get_import_map(&specifier))
+        else {
+            continue;
+        };
+
+        if module.specifier != specifier {
+            info.redirects.push(RedirectInfo {
+                from: specifier,
+                to: module.specifier,
+            });
+            continue;
+        }
+
+        let source = module.source().await.unwrap_or_else(|| Vec::new().into());
+        let source_map_size = module.source_map().await.map_or(0, |it| it.len());
+
+        match specifier.as_str() {
+            VFS_ESZIP_KEY => {
+                let vfs = serde_json::from_slice::<Option<VirtualDirectory>>(&source)?;
+
+                info.vfs = vfs.as_ref().map(vfs_dir_info);
+                info.sizes.npm_vfs += source.len();
+            }
+
+            STATIC_FILES_ESZIP_KEY => {
+                let static_files = serde_json::from_slice::<StaticFilesData>(&source)?;
+
+                info.static_files = Some(StaticFilesInfo {
+                    root_path: static_files.root_path.display().to_string(),
+                    dir: vfs_dir_info(&static_files.dir),
+                });
+                info.sizes.static_files += source.len();
+            }
+
+            SOURCE_CODE_ESZIP_KEY => info.sizes.source_code += source.len(),
+
+            METADATA_ESZIP_KEY => info.metadata = serde_json::from_slice(&source).ok(),
+
+            NODE_MODULES_ESZIP_KEY => info.node_modules_dir = true,
+
+            _ => {
+                if idx == 0 && matches!(module.kind, ModuleKind::Json | ModuleKind::Jsonc) {
+                    if let Some(content) = parse_import_map(&source) {
+                        info.sizes.import_map += source.len();
+                        info.import_map = Some(ImportMapInfo {
+                            specifier,
+                            size: source.len(),
+                            content,
+                        });
+                        continue;
+                    }
+                }
+
+                info.sizes.modules += source.len();
+                info.sizes.source_maps += source_map_size;
+            }
+        }
+
+        info.modules.push(ModuleInfo {
+            specifier,
+            kind: module_kind_name(module.kind),
+            size: source.len(),
+            source_map_size,
+        });
+    }
+
+    if let Some(snapshot) = eszip.take_npm_snapshot() {
+        let mut packages = snapshot
+            .into_serialized()
+            .packages
+            .into_iter()
+            .map(|pkg| {
+                let mut dependencies = pkg
+                    .dependencies
+                    .values()
+                    .map(|id| id.as_serialized())
+                    .collect::<Vec<_>>();
+
+                dependencies.sort();
+
+                NpmPackageInfo {
+                    id: pkg.id.as_serialized(),
+                    name: pkg.id.nv.name.clone(),
+                    version: pkg.id.nv.version.to_string(),
+                    dependencies,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        packages.sort_by(|a, b| a.id.cmp(&b.id));
+        info.npm_packages = packages;
+    }
+
+    Ok(info)
+}
+
+fn fmt_vfs_entry(f: &mut fmt::Formatter<'_>, entry: &VfsEntryInfo, depth: usize) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+
+    match entry.kind {
+        "dir" => writeln!(f, "{}{}/ ({} B)", indent, entry.name, entry.size)?,
+        "symlink" => writeln!(
+            f,
+            "{}{} -> {}",
+            indent,
+            entry.name,
+            entry.target.as_deref().unwrap_or_default()
+        )?,
+        _ => writeln!(f, "{}{} ({} B)", indent, entry.name, entry.size)?,
+    }
+
+    for child in &entry.entries {
+        fmt_vfs_entry(f, child, depth + 1)?;
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for EszipInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Modules ({}):", self.modules.len())?;
+        for module in &self.modules {
+            write!(
+                f,
+                "  {:<10} {:>10} B  {}",
+                module.kind, module.size, module.specifier
+            )?;
+            if module.source_map_size > 0 {
+                write!(f, " (source map: {} B)", module.source_map_size)?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.redirects.is_empty() {
+            writeln!(f, "\nRedirects ({}):", self.redirects.len())?;
+            for redirect in &self.redirects {
+                writeln!(f, "  {} -> {}", redirect.from, redirect.to)?;
+            }
+        }
+
+        if let Some(import_map) = &self.import_map {
+            writeln!(
+                f,
+                "\nImport map: {} ({} B)",
+                import_map.specifier, import_map.size
+            )?;
+            let content =
+                serde_json::to_string_pretty(&import_map.content).map_err(|_| fmt::Error)?;
+            for line in content.lines() {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+
+        if !self.npm_packages.is_empty() {
+            writeln!(f, "\nnpm packages ({}):", self.npm_packages.len())?;
+            for pkg in &self.npm_packages {
+                writeln!(f, "  {}", pkg.id)?;
+            }
+        }
+
+        if let Some(vfs) = &self.vfs {
+            if self.node_modules_dir {
+                writeln!(f, "\nVirtual file system (node_modules):")?;
+            } else {
+                writeln!(f, "\nVirtual file system:")?;
+            }
+            fmt_vfs_entry(f, vfs, 1)?;
+        }
+
+        if let Some(static_files) = &self.static_files {
+            writeln!(f, "\nStatic files ({}):", static_files.root_path)?;
+            for entry in &static_files.dir.entries {
+                fmt_vfs_entry(f, entry, 1)?;
+            }
+        }
+
+        if let Some(metadata) = &self.metadata {
+            writeln!(f, "\nMetadata:")?;
+            let content = serde_json::to_string_pretty(metadata).map_err(|_| fmt::Error)?;
+            for line in content.lines() {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+
+        let sizes = &self.sizes;
+        writeln!(f, "\nSize:")?;
+        writeln!(f, "  modules       {:>10} B", sizes.modules)?;
+        writeln!(f, "  source maps   {:>10} B", sizes.source_maps)?;
+        writeln!(f, "  import map    {:>10} B", sizes.import_map)?;
+        writeln!(f, "  npm vfs       {:>10} B", sizes.npm_vfs)?;
+        writeln!(f, "  static files  {:>10} B", sizes.static_files)?;
+        writeln!(f, "  source code   {:>10} B", sizes.source_code)?;
+        if let Some(archive) = sizes.archive {
+            writeln!(f, "  archive       {:>10} B", archive)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::inspect::inspect_eszip;
+    use crate::{add_static_files, generate_binary_eszip, EmitterFactory, EszipPayloadKind};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_inspect_npm_eszip() {
+        let eszip = generate_binary_eszip(
+            PathBuf::from("../base/test_cases/npm/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let info = inspect_eszip(EszipPayloadKind::Eszip(eszip)).await.unwrap();
+
+        assert!(info
+            .modules
+            .iter()
+            .any(|it| it.specifier.ends_with("test_cases/npm/index.ts")));
+        assert!(!info.npm_packages.is_empty());
+        assert!(info.vfs.is_some());
+        assert!(info.sizes.npm_vfs > 0);
+        assert!(info.sizes.source_code > 0);
+        assert!(info.import_map.is_none());
+        assert!(info.metadata.is_none());
+        assert!(!info.node_modules_dir);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_inspect_node_modules_dir_eszip() {
+        let node_modules_dir = std::env::temp_dir()
+            .join(format!("sb-inspect-npm-{}", std::process::id()))
+            .join("node_modules");
+        let mut emitter_factory = EmitterFactory::new();
+        emitter_factory
+            .set_node_modules_dir(node_modules_dir.clone())
+            .unwrap();
+
+        let eszip = generate_binary_eszip(
+            PathBuf::from("../base/test_cases/npm/index.ts"),
+            Arc::new(emitter_factory),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let info = inspect_eszip(EszipPayloadKind::Eszip(eszip)).await.unwrap();
+        let vfs = info.vfs.unwrap();
+
+        assert!(info.node_modules_dir);
+        assert_eq!(vfs.name, "node_modules");
+        assert!(vfs.entries.iter().any(|it| it.name == ".deno"));
+
+        std::fs::remove_dir_all(node_modules_dir.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_inspect_static_files() {
+        let mut eszip = generate_binary_eszip(
+            PathBuf::from("../base/test_cases/static-files/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        add_static_files(
+            &mut eszip,
+            Path::new("../base/test_cases/static-files"),
+            &["static/*.txt".to_string(), "static/hello.*".to_string()],
+        )
+        .unwrap();
+
+        let info = inspect_eszip(EszipPayloadKind::Eszip(eszip)).await.unwrap();
+        let static_files = info.static_files.unwrap();
+
+        assert!(static_files.root_path.ends_with("static-files"));
+        assert_eq!(static_files.dir.entries.len(), 1);
+        assert_eq!(static_files.dir.entries[0].name, "static");
+        assert_eq!(static_files.dir.entries[0].entries.len(), 1);
+        assert_eq!(static_files.dir.entries[0].entries[0].name, "hello.txt");
+        assert!(info.sizes.static_files > 0);
+    }
+}
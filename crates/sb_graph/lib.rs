@@ -1,5 +1,8 @@
 use crate::emitter::EmitterFactory;
-use crate::graph_util::{create_eszip_from_graph_raw, create_graph};
+use crate::graph_util::{
+    create_eszip_from_graph_raw, create_graph, create_graph_from_specifiers, file_specifier,
+};
+use anyhow::{bail, Context};
 use deno_ast::MediaType;
 use deno_core::error::AnyError;
 use deno_core::futures::io::{AllowStdIo, BufReader};
@@ -7,20 +10,31 @@ use deno_core::{serde_json, FastString, JsBuffer, ModuleSpecifier};
 use deno_fs::{FileSystem, RealFs};
 use deno_npm::NpmSystemInfo;
 use eszip::{EszipV2, ModuleKind};
-use sb_fs::{build_vfs, VfsOpts};
-use std::fs;
-use std::fs::{create_dir_all, File};
-use std::io::Write;
+use sb_fs::{build_static_vfs, build_vfs, VfsOpts};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+pub mod check;
 pub mod emitter;
+pub mod extract;
 pub mod graph_resolver;
 pub mod graph_util;
 pub mod import_map;
+pub mod inspect;
+pub mod package_json;
+pub mod signature;
+pub mod vendor;
 
 pub const VFS_ESZIP_KEY: &str = "---SUPABASE-VFS-DATA-ESZIP---";
 pub const SOURCE_CODE_ESZIP_KEY: &str = "---SUPABASE-SOURCE-CODE-ESZIP---";
+pub const SIGNATURE_ESZIP_KEY: &str = "---SUPABASE-SIGNATURE-ESZIP---";
+pub const METADATA_ESZIP_KEY: &str = "---SUPABASE-METADATA-ESZIP---";
+/// Present when the npm virtual file system is a `node_modules` directory
+/// instead of a copy of the global npm cache.
+pub const NODE_MODULES_ESZIP_KEY: &str = "---SUPABASE-NODE-MODULES-ESZIP---";
+/// The files bundled besides the modules, see [`add_static_files`].
+pub const STATIC_FILES_ESZIP_KEY: &str = "---SUPABASE-STATIC-FILES-ESZIP---";
 
 #[derive(Debug)]
 pub enum EszipPayloadKind {
@@ -29,9 +43,9 @@ pub enum EszipPayloadKind {
     Eszip(EszipV2),
 }
 
-pub async fn payload_to_eszip(eszip_payload_kind: EszipPayloadKind) -> EszipV2 {
+pub async fn payload_to_eszip(eszip_payload_kind: EszipPayloadKind) -> Result<EszipV2, AnyError> {
     match eszip_payload_kind {
-        EszipPayloadKind::Eszip(data) => data,
+        EszipPayloadKind::Eszip(data) => Ok(data),
         _ => {
             let bytes = match eszip_payload_kind {
                 EszipPayloadKind::JsBufferKind(js_buffer) => Vec::from(&*js_buffer),
@@ -40,191 +54,161 @@ pub async fn payload_to_eszip(eszip_payload_kind: EszipPayloadKind) -> EszipV2 {
             };
 
             let bufreader = BufReader::new(AllowStdIo::new(bytes.as_slice()));
-            let (eszip, loader) = eszip::EszipV2::parse(bufreader).await.unwrap();
+            let (eszip, loader) = eszip::EszipV2::parse(bufreader).await?;
 
-            loader.await.unwrap();
+            loader.await?;
 
-            eszip
+            Ok(eszip)
         }
     }
 }
 
+/// Hex encoded SHA-256 of the modules and npm packages of the eszip. The
+/// metadata and the signature are left out, so the hash can be stored in the
+/// former and covered by the latter.
+pub async fn eszip_source_hash(eszip: &mut EszipV2) -> String {
+    signature::digest_eszip_content(eszip, &[METADATA_ESZIP_KEY, SIGNATURE_ESZIP_KEY])
+        .await
+        .as_ref()
+        .iter()
+        .map(|it| format!("{:02x}", it))
+        .collect()
+}
+
 pub async fn generate_binary_eszip(
     file: PathBuf,
     emitter_factory: Arc<EmitterFactory>,
     maybe_module_code: Option<FastString>,
     maybe_import_map_url: Option<String>,
 ) -> Result<EszipV2, AnyError> {
-    let graph = create_graph(file.clone(), emitter_factory.clone(), &maybe_module_code).await;
-    let eszip = create_eszip_from_graph_raw(graph, Some(emitter_factory.clone())).await;
-
-    if let Ok(mut eszip) = eszip {
-        let fs_path = file.clone();
-        let source_code: Arc<str> = if let Some(code) = maybe_module_code {
-            code.as_str().into()
-        } else {
-            let entry_content = RealFs.read_file_sync(fs_path.clone().as_path()).unwrap();
-            String::from_utf8(entry_content.clone())?.into()
-        };
-        let emit_source = emitter_factory.emitter().unwrap().emit_parsed_source(
-            &ModuleSpecifier::parse("http://localhost").unwrap(),
-            MediaType::from_path(fs_path.clone().as_path()),
-            &source_code,
-        )?;
-
-        let bin_code: Arc<[u8]> = emit_source.as_bytes().into();
-
-        let npm_res = emitter_factory.npm_resolution();
-
-        let (npm_vfs, _npm_files) = if npm_res.has_packages() {
-            let (root_dir, files) = build_vfs(VfsOpts {
-                npm_resolver: emitter_factory.npm_resolver().clone(),
-                npm_registry_api: emitter_factory.npm_api().clone(),
-                npm_cache: emitter_factory.npm_cache().clone(),
-                npm_resolution: emitter_factory.npm_resolution().clone(),
-            })?
-            .into_dir_and_files();
-
-            let snapshot = npm_res.serialized_valid_snapshot_for_system(&NpmSystemInfo::default());
-            eszip.add_npm_snapshot(snapshot);
-            (Some(root_dir), files)
-        } else {
-            (None, Vec::new())
-        };
-
-        let npm_vfs = serde_json::to_string(&npm_vfs)?.as_bytes().to_vec();
-        let boxed_slice = npm_vfs.into_boxed_slice();
-
-        eszip.add_opaque_data(String::from(VFS_ESZIP_KEY), Arc::from(boxed_slice));
-        eszip.add_opaque_data(String::from(SOURCE_CODE_ESZIP_KEY), bin_code);
-
-        // add import map
-        if emitter_factory.maybe_import_map.is_some() {
-            eszip.add_import_map(
-                ModuleKind::Json,
-                maybe_import_map_url.unwrap(),
-                Arc::from(
-                    emitter_factory
-                        .maybe_import_map
-                        .as_ref()
-                        .unwrap()
-                        .to_json()
-                        .as_bytes(),
-                ),
-            );
-        };
-
-        Ok(eszip)
+    let graph = create_graph(file.clone(), emitter_factory.clone(), &maybe_module_code).await?;
+    let mut eszip = create_eszip_from_graph_raw(graph, Some(emitter_factory.clone())).await?;
+
+    let fs_path = file.clone();
+    let source_code: Arc<str> = if let Some(code) = maybe_module_code {
+        code.as_str().into()
     } else {
-        eszip
-    }
-}
+        let entry_content = RealFs.read_file_sync(fs_path.clone().as_path()).unwrap();
+        String::from_utf8(entry_content.clone())?.into()
+    };
+    let emit_source = emitter_factory.emitter().unwrap().emit_parsed_source(
+        &ModuleSpecifier::parse("http://localhost").unwrap(),
+        MediaType::from_path(fs_path.clone().as_path()),
+        &source_code,
+    )?;
 
-fn extract_file_specifiers(eszip: &EszipV2) -> Vec<String> {
-    eszip
-        .specifiers()
-        .iter()
-        .filter(|specifier| specifier.starts_with("file:"))
-        .cloned()
-        .collect()
-}
+    let bin_code: Arc<[u8]> = emit_source.as_bytes().into();
+
+    add_npm_and_import_map(&mut eszip, &emitter_factory, maybe_import_map_url)?;
+    eszip.add_opaque_data(String::from(SOURCE_CODE_ESZIP_KEY), bin_code);
 
-pub struct ExtractEszipPayload {
-    pub data: EszipPayloadKind,
-    pub folder: PathBuf,
+    Ok(eszip)
 }
 
-fn create_module_path(global_specifier: &str, entry_path: &Path, output_folder: &Path) -> PathBuf {
-    let cleaned_specifier = global_specifier.replace(entry_path.to_str().unwrap(), "");
-    let module_path = PathBuf::from(cleaned_specifier);
-
-    if let Some(parent) = module_path.parent() {
-        if parent.parent().is_some() {
-            let output_folder_and_mod_folder =
-                output_folder.join(parent.strip_prefix("/").unwrap());
-            if !output_folder_and_mod_folder.exists() {
-                create_dir_all(&output_folder_and_mod_folder).unwrap();
-            }
+/// Bundles several functions into one eszip, so the modules they share are
+/// only stored once. Returns the eszip and the entrypoint specifier of each
+/// function. There's no single entrypoint, so unlike [`generate_binary_eszip`]
+/// the emitted source code of the entrypoint isn't stored.
+pub async fn generate_binary_eszip_for_functions(
+    functions: Vec<(String, PathBuf)>,
+    emitter_factory: Arc<EmitterFactory>,
+    maybe_import_map_url: Option<String>,
+) -> Result<(EszipV2, BTreeMap<String, String>), AnyError> {
+    let mut entrypoints = BTreeMap::new();
+
+    for (name, file) in &functions {
+        let specifier =
+            file_specifier(file).with_context(|| format!("failed to bundle function {}", name))?;
+
+        if entrypoints.insert(name.clone(), specifier).is_some() {
+            bail!("function {} is given more than once", name);
         }
     }
 
-    output_folder.join(module_path.strip_prefix("/").unwrap())
-}
+    let mut roots = entrypoints.values().cloned().collect::<Vec<_>>();
+    roots.sort();
+    roots.dedup();
 
-async fn extract_modules(
-    eszip: &EszipV2,
-    specifiers: &[String],
-    lowest_path: &str,
-    output_folder: &Path,
-) {
-    let main_path = PathBuf::from(lowest_path);
-    let entry_path = main_path.parent().unwrap();
-    for global_specifier in specifiers {
-        let module_path = create_module_path(global_specifier, entry_path, output_folder);
-        let module_content = eszip
-            .get_module(global_specifier)
-            .unwrap()
-            .take_source()
-            .await
-            .unwrap();
-
-        let mut file = File::create(&module_path).unwrap();
-        file.write_all(module_content.as_ref()).unwrap();
-    }
-}
+    let graph = create_graph_from_specifiers(roots, false, emitter_factory.clone()).await?;
+    let mut eszip = create_eszip_from_graph_raw(graph, Some(emitter_factory.clone())).await?;
 
-pub async fn extract_eszip(payload: ExtractEszipPayload) {
-    let eszip = payload_to_eszip(payload.data).await;
-    let output_folder = payload.folder;
+    add_npm_and_import_map(&mut eszip, &emitter_factory, maybe_import_map_url)?;
 
-    if !output_folder.exists() {
-        create_dir_all(&output_folder).unwrap();
-    }
+    let entrypoints = entrypoints
+        .into_iter()
+        .map(|(name, specifier)| (name, specifier.to_string()))
+        .collect();
 
-    let file_specifiers = extract_file_specifiers(&eszip);
-    if let Some(lowest_path) = sb_core::util::path::find_lowest_path(&file_specifiers) {
-        extract_modules(&eszip, &file_specifiers, &lowest_path, &output_folder).await;
-    } else {
-        panic!("Path seems to be invalid");
-    }
+    Ok((eszip, entrypoints))
 }
 
-pub async fn extract_from_file(eszip_file: PathBuf, output_path: PathBuf) {
-    let eszip_content = fs::read(eszip_file).expect("File does not exist");
-    extract_eszip(ExtractEszipPayload {
-        data: EszipPayloadKind::VecKind(eszip_content),
-        folder: output_path,
-    })
-    .await;
+/// Bundles the files under `root_path` matching `patterns`, so that user
+/// workers can read them. They can't read any other file.
+pub fn add_static_files(
+    eszip: &mut EszipV2,
+    root_path: &Path,
+    patterns: &[String],
+) -> Result<(), AnyError> {
+    let Some(static_files) = build_static_vfs(root_path, patterns)? else {
+        return Ok(());
+    };
+    let bytes = serde_json::to_vec(&static_files)?;
+
+    eszip.add_opaque_data(
+        String::from(STATIC_FILES_ESZIP_KEY),
+        Arc::from(bytes.into_boxed_slice()),
+    );
+
+    Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    use crate::{
-        extract_eszip, generate_binary_eszip, EmitterFactory, EszipPayloadKind, ExtractEszipPayload,
+fn add_npm_and_import_map(
+    eszip: &mut EszipV2,
+    emitter_factory: &EmitterFactory,
+    maybe_import_map_url: Option<String>,
+) -> Result<(), AnyError> {
+    let npm_res = emitter_factory.npm_resolution();
+
+    let (npm_vfs, _npm_files) = if npm_res.has_packages() {
+        let (root_dir, files) = build_vfs(VfsOpts {
+            npm_resolver: emitter_factory.npm_resolver().clone(),
+            npm_registry_api: emitter_factory.npm_api().clone(),
+            npm_cache: emitter_factory.npm_cache().clone(),
+            npm_resolution: emitter_factory.npm_resolution().clone(),
+        })?
+        .into_dir_and_files();
+
+        let snapshot = npm_res.serialized_valid_snapshot_for_system(&NpmSystemInfo::default());
+        eszip.add_npm_snapshot(snapshot);
+
+        if emitter_factory.node_modules_dir().is_some() {
+            eszip.add_opaque_data(String::from(NODE_MODULES_ESZIP_KEY), Arc::from(&[][..]));
+        }
+        (Some(root_dir), files)
+    } else {
+        (None, Vec::new())
+    };
+
+    let npm_vfs = serde_json::to_string(&npm_vfs)?.as_bytes().to_vec();
+    let boxed_slice = npm_vfs.into_boxed_slice();
+
+    eszip.add_opaque_data(String::from(VFS_ESZIP_KEY), Arc::from(boxed_slice));
+
+    // add import map
+    if emitter_factory.maybe_import_map.is_some() {
+        eszip.add_import_map(
+            ModuleKind::Json,
+            maybe_import_map_url.unwrap(),
+            Arc::from(
+                emitter_factory
+                    .maybe_import_map
+                    .as_ref()
+                    .unwrap()
+                    .to_json()
+                    .as_bytes(),
+            ),
+        );
     };
-    use std::fs::remove_dir_all;
-    use std::path::PathBuf;
-    use std::sync::Arc;
-
-    #[tokio::test]
-    #[allow(clippy::arc_with_non_send_sync)]
-    async fn test_module_code_no_eszip() {
-        let eszip = generate_binary_eszip(
-            PathBuf::from("../base/test_cases/npm/index.ts"),
-            Arc::new(EmitterFactory::new()),
-            None,
-            None,
-        )
-        .await;
-        let eszip = eszip.unwrap();
-        extract_eszip(ExtractEszipPayload {
-            data: EszipPayloadKind::Eszip(eszip),
-            folder: PathBuf::from("../base/test_cases/extracted-npm/"),
-        })
-        .await;
-
-        assert!(PathBuf::from("../base/test_cases/extracted-npm/hello.js").exists());
-        remove_dir_all(PathBuf::from("../base/test_cases/extracted-npm/")).unwrap();
-    }
+
+    Ok(())
 }
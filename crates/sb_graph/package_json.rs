@@ -0,0 +1,34 @@
+use anyhow::Context;
+use deno_core::error::AnyError;
+use sb_node::PackageJson;
+use std::fs;
+use std::path::Path;
+
+const PACKAGE_JSON_FILE: &str = "package.json";
+
+/// Loads the `package.json` at `maybe_path`, or else the one in `dir` if
+/// there's one. Its dependencies let bare specifiers such as `express` resolve
+/// to npm packages.
+pub fn load_package_json(
+    maybe_path: Option<String>,
+    dir: &Path,
+) -> Result<Option<PackageJson>, AnyError> {
+    let path = match maybe_path {
+        Some(path) => std::env::current_dir()?.join(path),
+        None => {
+            let path = std::env::current_dir()?.join(dir).join(PACKAGE_JSON_FILE);
+            if !path.is_file() {
+                return Ok(None);
+            }
+
+            path
+        }
+    };
+
+    let source =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let package_json = PackageJson::load_from_string(path.clone(), source)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    Ok(Some(package_json))
+}
@@ -0,0 +1,350 @@
+use crate::SIGNATURE_ESZIP_KEY;
+use anyhow::{anyhow, bail, Context};
+use deno_core::error::AnyError;
+use deno_core::serde_json;
+use eszip::{EszipV2, ModuleKind};
+use ring::digest;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Prepended to the digest of the eszip before signing it, so a signature
+/// can't be replayed for anything else signed with the same key.
+const SIGNATURE_CONTEXT: &[u8] = b"supabase-eszip-signature-v1\0";
+
+/// DER prefix of an Ed25519 `SubjectPublicKeyInfo`, as written by
+/// `openssl pkey -pubout`. The raw 32-byte key follows it.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum EszipSignatureError {
+    #[error("eszip is not signed")]
+    Unsigned,
+    #[error("eszip is signed with an untrusted key")]
+    UntrustedKey,
+    #[error("eszip signature does not match its content")]
+    InvalidSignature,
+    #[error("eszip signature is malformed: {0}")]
+    Malformed(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EszipSignature {
+    public_key: String,
+    signature: String,
+}
+
+/// Reads the base64 body of a PEM file, or a file containing base64 only.
+fn read_key_file(path: &Path) -> Result<Vec<u8>, AnyError> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read key file {}", path.display()))?;
+    let body = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect::<String>();
+
+    base64::decode(body).with_context(|| format!("invalid key file {}", path.display()))
+}
+
+fn parse_public_key(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() == ED25519_PUBLIC_KEY_LEN {
+        Some(bytes.to_vec())
+    } else {
+        bytes
+            .strip_prefix(&ED25519_SPKI_PREFIX[..])
+            .and_then(|key| (key.len() == ED25519_PUBLIC_KEY_LEN).then(|| key.to_vec()))
+    }
+}
+
+/// An Ed25519 private key used by `bundle --sign-key`.
+pub struct EszipSigningKey(Ed25519KeyPair);
+
+impl EszipSigningKey {
+    /// Loads a PKCS#8 private key, such as the one written by
+    /// `openssl genpkey -algorithm ed25519`.
+    pub fn from_file(path: &Path) -> Result<Self, AnyError> {
+        let der = read_key_file(path)?;
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+            .map_err(|err| anyhow!("invalid Ed25519 private key {}: {}", path.display(), err))?;
+
+        Ok(Self(key_pair))
+    }
+}
+
+/// Public keys of the signers whose eszips the server accepts.
+#[derive(Debug, Clone, Default)]
+pub struct EszipTrustedKeys(Arc<Vec<Vec<u8>>>);
+
+impl EszipTrustedKeys {
+    /// Loads Ed25519 public keys, either PEM encoded (`openssl pkey -pubout`)
+    /// or as the base64 of the raw 32 bytes.
+    pub fn from_files<P: AsRef<Path>>(
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<Self, AnyError> {
+        let mut keys = vec![];
+
+        for path in paths {
+            let path = path.as_ref();
+            let bytes = read_key_file(path)?;
+            let Some(key) = parse_public_key(&bytes) else {
+                bail!("invalid Ed25519 public key {}", path.display());
+            };
+
+            keys.push(key);
+        }
+
+        Ok(Self(Arc::new(keys)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.0.iter().any(|it| it == key)
+    }
+}
+
+fn update_with_len_prefixed(ctx: &mut digest::Context, bytes: &[u8]) {
+    ctx.update(&(bytes.len() as u64).to_le_bytes());
+    ctx.update(bytes);
+}
+
+/// Hashes everything the runtime reads from the eszip, except for the entries
+/// in `excluded`: every module (and redirect) with its kind, source and source
+/// map, and the npm snapshot. Specifiers are sorted so the digest doesn't
+/// depend on the module order.
+pub(crate) async fn digest_eszip_content(eszip: &mut EszipV2, excluded: &[&str]) -> digest::Digest {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    let mut specifiers = eszip
+        .specifiers()
+        .into_iter()
+        .filter(|it| !excluded.contains(&it.as_str()))
+        .collect::<Vec<_>>();
+
+    specifiers.sort();
+
+    for specifier in specifiers {
+        let Some(module) = eszip
+            .get_module(&specifier)
+            .or_else(|| eszip.get_import_map(&specifier))
+        else {
+            continue;
+        };
+
+        let kind: u8 = match module.kind {
+            ModuleKind::JavaScript => 0,
+            ModuleKind::Json => 1,
+            ModuleKind::Jsonc => 2,
+            ModuleKind::OpaqueData => 3,
+        };
+        let source = module.source().await.unwrap_or_else(|| Vec::new().into());
+        let source_map = module
+            .source_map()
+            .await
+            .unwrap_or_else(|| Vec::new().into());
+
+        update_with_len_prefixed(&mut ctx, specifier.as_bytes());
+        update_with_len_prefixed(&mut ctx, module.specifier.as_bytes());
+        ctx.update(&[kind]);
+        update_with_len_prefixed(&mut ctx, &source);
+        update_with_len_prefixed(&mut ctx, &source_map);
+    }
+
+    if let Some(snapshot) = eszip.take_npm_snapshot() {
+        let serialized = snapshot.as_serialized();
+        let mut root_packages = serialized
+            .root_packages
+            .iter()
+            .map(|(req, id)| format!("{}={}", req, id.as_serialized()))
+            .collect::<Vec<_>>();
+        let mut packages = serialized
+            .packages
+            .iter()
+            .map(|pkg| {
+                let mut dependencies = pkg
+                    .dependencies
+                    .iter()
+                    .map(|(name, id)| format!("{}={}", name, id.as_serialized()))
+                    .collect::<Vec<_>>();
+
+                dependencies.sort();
+                format!("{}:{}", pkg.id.as_serialized(), dependencies.join(","))
+            })
+            .collect::<Vec<_>>();
+
+        root_packages.sort();
+        packages.sort();
+
+        for entry in root_packages.iter().chain(packages.iter()) {
+            update_with_len_prefixed(&mut ctx, entry.as_bytes());
+        }
+
+        eszip.add_npm_snapshot(snapshot);
+    }
+
+    ctx.finish()
+}
+
+async fn signature_message(eszip: &mut EszipV2) -> Vec<u8> {
+    let digest = digest_eszip_content(eszip, &[SIGNATURE_ESZIP_KEY]).await;
+    let mut message = SIGNATURE_CONTEXT.to_vec();
+
+    message.extend_from_slice(digest.as_ref());
+    message
+}
+
+/// Signs the content of the eszip, storing the signature in the eszip itself.
+/// Nothing must be added to the eszip afterwards.
+pub async fn sign_eszip(eszip: &mut EszipV2, key: &EszipSigningKey) -> Result<(), AnyError> {
+    let message = signature_message(eszip).await;
+    let signature = EszipSignature {
+        public_key: base64::encode(key.0.public_key().as_ref()),
+        signature: base64::encode(key.0.sign(&message).as_ref()),
+    };
+
+    eszip.add_opaque_data(
+        String::from(SIGNATURE_ESZIP_KEY),
+        Arc::from(serde_json::to_vec(&signature)?.into_boxed_slice()),
+    );
+
+    Ok(())
+}
+
+/// Checks that the eszip was signed by one of `trusted_keys` and hasn't been
+/// modified since. Fails with an [`EszipSignatureError`].
+pub async fn verify_eszip_signature(
+    eszip: &mut EszipV2,
+    trusted_keys: &EszipTrustedKeys,
+) -> Result<(), AnyError> {
+    let Some(module) = eszip.get_module(SIGNATURE_ESZIP_KEY) else {
+        return Err(EszipSignatureError::Unsigned.into());
+    };
+    let Some(source) = module.source().await else {
+        return Err(EszipSignatureError::Unsigned.into());
+    };
+
+    let malformed = |err: &dyn std::fmt::Display| EszipSignatureError::Malformed(err.to_string());
+    let signature = serde_json::from_slice::<EszipSignature>(&source).map_err(|e| malformed(&e))?;
+    let public_key = base64::decode(&signature.public_key).map_err(|e| malformed(&e))?;
+    let signature_bytes = base64::decode(&signature.signature).map_err(|e| malformed(&e))?;
+
+    if !trusted_keys.contains(&public_key) {
+        return Err(EszipSignatureError::UntrustedKey.into());
+    }
+
+    let message = signature_message(eszip).await;
+
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(&message, &signature_bytes)
+        .map_err(|_| EszipSignatureError::InvalidSignature)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::signature::{
+        sign_eszip, verify_eszip_signature, EszipSignatureError, EszipSigningKey, EszipTrustedKeys,
+    };
+    use crate::{
+        eszip_source_hash, generate_binary_eszip, payload_to_eszip, EmitterFactory,
+        EszipPayloadKind, METADATA_ESZIP_KEY,
+    };
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn generate_key() -> (EszipSigningKey, EszipTrustedKeys) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref().to_vec();
+
+        (
+            EszipSigningKey(key_pair),
+            EszipTrustedKeys(Arc::new(vec![public_key])),
+        )
+    }
+
+    fn assert_signature_error(err: anyhow::Error, expected: fn(&EszipSignatureError) -> bool) {
+        let err = err.downcast::<EszipSignatureError>().unwrap();
+        assert!(expected(&err), "unexpected error: {}", err);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_eszip_signature() {
+        let (signing_key, trusted_keys) = generate_key();
+        let (_, other_trusted_keys) = generate_key();
+
+        let mut eszip = generate_binary_eszip(
+            PathBuf::from("../base/test_cases/npm/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let err = verify_eszip_signature(&mut eszip, &trusted_keys)
+            .await
+            .unwrap_err();
+        assert_signature_error(err, |it| matches!(it, EszipSignatureError::Unsigned));
+
+        sign_eszip(&mut eszip, &signing_key).await.unwrap();
+
+        // The signature survives serialization.
+        let mut eszip = payload_to_eszip(EszipPayloadKind::VecKind(eszip.into_bytes()))
+            .await
+            .unwrap();
+        verify_eszip_signature(&mut eszip, &trusted_keys)
+            .await
+            .unwrap();
+
+        let err = verify_eszip_signature(&mut eszip, &other_trusted_keys)
+            .await
+            .unwrap_err();
+        assert_signature_error(err, |it| matches!(it, EszipSignatureError::UntrustedKey));
+
+        eszip.add_opaque_data(String::from("tampered"), Arc::from(&b"tampered"[..]));
+        let err = verify_eszip_signature(&mut eszip, &trusted_keys)
+            .await
+            .unwrap_err();
+        assert_signature_error(err, |it| {
+            matches!(it, EszipSignatureError::InvalidSignature)
+        });
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_eszip_source_hash_ignores_metadata() {
+        let mut eszip = generate_binary_eszip(
+            PathBuf::from("../base/test_cases/npm/index.ts"),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let hash = eszip_source_hash(&mut eszip).await;
+        assert_eq!(hash.len(), 64);
+
+        eszip.add_opaque_data(
+            String::from(METADATA_ESZIP_KEY),
+            Arc::from(&b"{\"source_hash\":null}"[..]),
+        );
+        assert_eq!(eszip_source_hash(&mut eszip).await, hash);
+
+        eszip.add_opaque_data(String::from("tampered"), Arc::from(&b"tampered"[..]));
+        assert_ne!(eszip_source_hash(&mut eszip).await, hash);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::emitter::EmitterFactory;
+use crate::extract::{
+    build_import_map, push_extension, vendor_file_stem, write_file, OutputLayout, IMPORT_MAP_FILE,
+    LOCKFILE_FILE,
+};
+use crate::graph_util::{create_graph_from_specifiers, file_specifier};
+use anyhow::Context;
+use deno_ast::MediaType;
+use deno_core::error::AnyError;
+use deno_core::serde_json::{self, Value};
+use deno_lockfile::Lockfile;
+use eszip::deno_graph::Module;
+use sb_npm::resolution::populate_lockfile_from_snapshot;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The `npm` folder of a `DENO_DIR`, so the vendor directory can be used as
+/// one when bundling offline.
+const NPM_DIR: &str = "npm";
+
+/// The files `vendor` wrote.
+#[derive(Debug)]
+pub struct VendoredGraph {
+    pub import_map: PathBuf,
+    pub lockfile: Option<PathBuf>,
+    pub npm_cache: Option<PathBuf>,
+    pub remote_modules: usize,
+}
+
+/// Downloads everything the entrypoints depend on into `output_folder`, so
+/// they can be bundled and booted without network access:
+///
+/// * remote modules are written to `<output>/<host>/<path>`, with their
+///   original source,
+/// * an `import_map.json` maps them to their vendored copies (and carries over
+///   the import map of the emitter factory, if any),
+/// * npm packages are downloaded to `<output>/npm` and pinned in a
+///   `deno.lock`.
+///
+/// Local modules are left where they are.
+pub async fn vendor(
+    entrypoints: Vec<PathBuf>,
+    mut emitter_factory: EmitterFactory,
+    output_folder: PathBuf,
+) -> Result<VendoredGraph, AnyError> {
+    fs::create_dir_all(&output_folder)
+        .with_context(|| format!("failed to create {}", output_folder.display()))?;
+
+    let output_folder = output_folder.canonicalize()?;
+    let npm_cache_folder = output_folder.join(NPM_DIR);
+    let maybe_import_map = emitter_factory
+        .maybe_import_map
+        .as_ref()
+        .map(|import_map| serde_json::from_str::<Value>(&import_map.to_json()))
+        .transpose()?;
+
+    emitter_factory.set_npm_cache_dir(npm_cache_folder.clone());
+
+    let emitter_factory = Arc::new(emitter_factory);
+    let roots = entrypoints
+        .iter()
+        .map(|it| file_specifier(it))
+        .collect::<Result<Vec<_>, _>>()?;
+    let graph = create_graph_from_specifiers(roots, false, emitter_factory.clone()).await?;
+
+    let mut layout = OutputLayout {
+        local_root: None,
+        output_folder: output_folder.clone(),
+        vendor_folder: output_folder.clone(),
+        module_paths: HashMap::new(),
+    };
+    let mut remote_modules = vec![];
+
+    for module in graph.modules() {
+        let (specifier, source, media_type) = match module {
+            Module::Esm(module) => (&module.specifier, &module.source, module.media_type),
+            Module::Json(module) => (&module.specifier, &module.source, module.media_type),
+            _ => continue,
+        };
+
+        if !matches!(specifier.scheme(), "http" | "https") {
+            continue;
+        }
+
+        let Some(path) = layout.local_path(specifier) else {
+            continue;
+        };
+        let mut path = vendor_file_stem(path, specifier);
+
+        // The media type of a remote module comes from its headers, so the
+        // file name must say the same.
+        if MediaType::from_path(&path) != media_type {
+            push_extension(&mut path, media_type.as_ts_extension());
+        }
+
+        write_file(&path, source.as_bytes())?;
+        layout.module_paths.insert(specifier.to_string(), path);
+        remote_modules.push((specifier.to_string(), specifier.clone()));
+    }
+
+    for (from, to) in &graph.redirects {
+        if !matches!(from.scheme(), "http" | "https") {
+            continue;
+        }
+
+        if let Some(path) = layout.module_paths.get(to.as_str()).cloned() {
+            layout.module_paths.insert(from.to_string(), path);
+        }
+
+        remote_modules.push((from.to_string(), to.clone()));
+    }
+
+    let import_map = build_import_map(&layout, maybe_import_map, &remote_modules);
+    let import_map_path = output_folder.join(IMPORT_MAP_FILE);
+
+    write_file(
+        &import_map_path,
+        serde_json::to_string_pretty(&import_map)?.as_bytes(),
+    )?;
+
+    let npm_resolution = emitter_factory.npm_resolution();
+    let (lockfile_path, npm_cache) = if npm_resolution.has_packages() {
+        let path = output_folder.join(LOCKFILE_FILE);
+        let mut lockfile = Lockfile::new(path.clone(), true)?;
+
+        populate_lockfile_from_snapshot(&mut lockfile, &npm_resolution.snapshot())?;
+        lockfile.write()?;
+        (Some(path), Some(npm_cache_folder))
+    } else {
+        (None, None)
+    };
+
+    Ok(VendoredGraph {
+        import_map: import_map_path,
+        lockfile: lockfile_path,
+        npm_cache,
+        remote_modules: remote_modules.len(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::emitter::EmitterFactory;
+    use crate::vendor::vendor;
+    use std::fs::{read_to_string, remove_dir_all};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_vendor_npm() {
+        let output = std::env::temp_dir().join(format!("sb-vendor-npm-{}", std::process::id()));
+        let vendored = vendor(
+            vec![PathBuf::from("../base/test_cases/npm/index.ts")],
+            EmitterFactory::new(),
+            output.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert!(output.join("deno.land/std@0.131.0/http/server.ts").exists());
+
+        let import_map = read_to_string(vendored.import_map).unwrap();
+        assert!(import_map.contains(
+            "\"https://deno.land/std@0.131.0/http/server.ts\": \"./deno.land/std@0.131.0/http/server.ts\""
+        ));
+
+        assert!(vendored
+            .npm_cache
+            .unwrap()
+            .join("registry.npmjs.org/is-even")
+            .is_dir());
+        let lockfile = read_to_string(vendored.lockfile.unwrap()).unwrap();
+        assert!(lockfile.contains("is-even"));
+
+        remove_dir_all(output).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use deno_core::error::{type_error, AnyError};
+use serde::{Deserialize, Serialize};
+
+// Tags of the encoded key parts. They sort the parts of different types the
+// same way Deno KV does.
+const BYTES: u8 = 0x01;
+const STRING: u8 = 0x02;
+const BIGINT: u8 = 0x14;
+const NUMBER: u8 = 0x21;
+const FALSE: u8 = 0x26;
+const TRUE: u8 = 0x27;
+
+/// A part of a key, such as `"users"` or `42` in `["users", 42]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum KeyPart {
+    Bytes(Vec<u8>),
+    String(String),
+    /// In decimal, as it may not fit in a JS number.
+    BigInt(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+/// Encodes a key so that encoded keys sort like the keys they come from, and
+/// the keys starting with a prefix sort right after the encoded prefix.
+pub fn encode_key(key: &[KeyPart]) -> Result<Vec<u8>, AnyError> {
+    let mut out = vec![];
+
+    for part in key {
+        match part {
+            KeyPart::Bytes(bytes) => {
+                out.push(BYTES);
+                escape(bytes, &mut out);
+            }
+            KeyPart::String(string) => {
+                out.push(STRING);
+                escape(string.as_bytes(), &mut out);
+            }
+            KeyPart::BigInt(value) => {
+                let value = value
+                    .parse::<i64>()
+                    .map_err(|_| type_error("bigint key parts must fit in 64 bits"))?;
+
+                out.push(BIGINT);
+                out.extend_from_slice(&((value as u64) ^ (1 << 63)).to_be_bytes());
+            }
+            KeyPart::Number(value) => {
+                let bits = value.to_bits();
+                // negative numbers have their order reversed by flipping all
+                // their bits, positive ones sort after them
+                let bits = if bits >> 63 == 1 {
+                    !bits
+                } else {
+                    bits ^ (1 << 63)
+                };
+
+                out.push(NUMBER);
+                out.extend_from_slice(&bits.to_be_bytes());
+            }
+            KeyPart::Boolean(false) => out.push(FALSE),
+            KeyPart::Boolean(true) => out.push(TRUE),
+        }
+    }
+
+    Ok(out)
+}
+
+pub fn decode_key(mut bytes: &[u8]) -> Result<Vec<KeyPart>, AnyError> {
+    let mut key = vec![];
+
+    while let Some((tag, rest)) = bytes.split_first() {
+        bytes = rest;
+
+        let part = match *tag {
+            BYTES => KeyPart::Bytes(unescape(&mut bytes)?),
+            STRING => KeyPart::String(String::from_utf8(unescape(&mut bytes)?)?),
+            BIGINT => {
+                let bits = u64::from_be_bytes(take_8(&mut bytes)?);
+                KeyPart::BigInt(((bits ^ (1 << 63)) as i64).to_string())
+            }
+            NUMBER => {
+                let bits = u64::from_be_bytes(take_8(&mut bytes)?);
+                let bits = if bits >> 63 == 1 {
+                    bits ^ (1 << 63)
+                } else {
+                    !bits
+                };
+                KeyPart::Number(f64::from_bits(bits))
+            }
+            FALSE => KeyPart::Boolean(false),
+            TRUE => KeyPart::Boolean(true),
+            _ => return Err(type_error("invalid encoded key")),
+        };
+
+        key.push(part);
+    }
+
+    Ok(key)
+}
+
+/// `0x00` ends a part, so the ones in it are escaped as `0x00 0xff`.
+fn escape(bytes: &[u8], out: &mut Vec<u8>) {
+    for byte in bytes {
+        out.push(*byte);
+        if *byte == 0x00 {
+            out.push(0xff);
+        }
+    }
+
+    out.push(0x00);
+}
+
+fn unescape(bytes: &mut &[u8]) -> Result<Vec<u8>, AnyError> {
+    let mut out = vec![];
+    let mut iter = bytes.iter().enumerate();
+
+    while let Some((index, byte)) = iter.next() {
+        if *byte != 0x00 {
+            out.push(*byte);
+        } else if bytes.get(index + 1) == Some(&0xff) {
+            out.push(0x00);
+            iter.next();
+        } else {
+            *bytes = &bytes[index + 1..];
+            return Ok(out);
+        }
+    }
+
+    Err(type_error("invalid encoded key"))
+}
+
+fn take_8(bytes: &mut &[u8]) -> Result<[u8; 8], AnyError> {
+    if bytes.len() < 8 {
+        return Err(type_error("invalid encoded key"));
+    }
+
+    let (head, rest) = bytes.split_at(8);
+    *bytes = rest;
+    Ok(head.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_key, encode_key, KeyPart};
+
+    #[test]
+    fn test_key_roundtrip() {
+        let key = vec![
+            KeyPart::Bytes(vec![0, 1, 0xff]),
+            KeyPart::String("users\0".to_string()),
+            KeyPart::BigInt("-42".to_string()),
+            KeyPart::Number(-1.5),
+            KeyPart::Boolean(true),
+        ];
+        let encoded = encode_key(&key).unwrap();
+
+        assert_eq!(decode_key(&encoded).unwrap(), key);
+    }
+
+    #[test]
+    fn test_key_order() {
+        let keys = [
+            vec![KeyPart::Bytes(vec![1])],
+            vec![KeyPart::String("a".to_string())],
+            vec![KeyPart::String("a".to_string()), KeyPart::Number(1.0)],
+            vec![KeyPart::String("ab".to_string())],
+            vec![KeyPart::BigInt("-1".to_string())],
+            vec![KeyPart::BigInt("10".to_string())],
+            vec![KeyPart::Number(-10.0)],
+            vec![KeyPart::Number(-1.0)],
+            vec![KeyPart::Number(0.0)],
+            vec![KeyPart::Number(2.5)],
+            vec![KeyPart::Boolean(false)],
+            vec![KeyPart::Boolean(true)],
+        ];
+        let encoded = keys
+            .iter()
+            .map(|key| encode_key(key).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(sorted, encoded);
+    }
+}
@@ -0,0 +1,322 @@
+const primordials = globalThis.__bootstrap.primordials;
+const {
+	ArrayFrom,
+	ArrayIsArray,
+	ArrayPrototypeForEach,
+	ArrayPrototypeMap,
+	ArrayPrototypePush,
+	ArrayPrototypeShift,
+	BigInt,
+	DataView,
+	Error,
+	MathMin,
+	ObjectFreeze,
+	RangeError,
+	String,
+	Symbol,
+	SymbolAsyncIterator,
+	SymbolFor,
+	TypeError,
+	Uint8Array,
+} = primordials;
+
+const core = globalThis.Deno.core;
+const ops = core.ops;
+
+const illegalConstructorKey = Symbol('illegalConstructorKey');
+
+const MAX_U64 = 0xffffffffffffffffn;
+
+class KvU64 {
+	value;
+
+	constructor(value) {
+		if (typeof value !== 'bigint') {
+			throw new TypeError('value must be a bigint');
+		}
+		if (value < 0n || value > MAX_U64) {
+			throw new RangeError('value must fit in an unsigned 64-bit integer');
+		}
+
+		this.value = value;
+		ObjectFreeze(this);
+	}
+
+	valueOf() {
+		return this.value;
+	}
+
+	toString() {
+		return String(this.value);
+	}
+
+	get [Symbol.toStringTag]() {
+		return 'Deno.KvU64';
+	}
+
+	[SymbolFor('Deno.privateCustomInspect')](inspect, inspectOptions) {
+		return `[Deno.KvU64: ${inspect(this.value, inspectOptions)}]`;
+	}
+}
+
+function toKeyPart(part) {
+	switch (typeof part) {
+		case 'string':
+			return { type: 'string', value: part };
+		case 'number':
+			return { type: 'number', value: part };
+		case 'bigint':
+			return { type: 'bigInt', value: String(part) };
+		case 'boolean':
+			return { type: 'boolean', value: part };
+		default:
+			if (part instanceof Uint8Array) {
+				return { type: 'bytes', value: ArrayFrom(part) };
+			}
+			throw new TypeError(`Key parts of type ${typeof part} are not supported`);
+	}
+}
+
+function toKey(key) {
+	if (!ArrayIsArray(key)) {
+		throw new TypeError('A key must be an array');
+	}
+
+	return ArrayPrototypeMap(key, toKeyPart);
+}
+
+function fromKeyPart({ type, value }) {
+	switch (type) {
+		case 'bytes':
+			return new Uint8Array(value);
+		case 'bigInt':
+			return BigInt(value);
+		default:
+			return value;
+	}
+}
+
+function toValue(value) {
+	if (value instanceof KvU64) {
+		const data = new Uint8Array(8);
+		new DataView(data.buffer).setBigUint64(0, value.value, true);
+
+		return { encoding: 'u64', data };
+	}
+	if (value instanceof Uint8Array) {
+		return { encoding: 'bytes', data: value };
+	}
+
+	return { encoding: 'v8', data: core.serialize(value, { forStorage: true }) };
+}
+
+function fromValue({ encoding, data }) {
+	switch (encoding) {
+		case 'u64':
+			return new KvU64(new DataView(data.buffer, data.byteOffset, 8).getBigUint64(0, true));
+		case 'bytes':
+			return data;
+		default:
+			return core.deserialize(data, { forStorage: true });
+	}
+}
+
+function toEntry({ key, value, versionstamp }) {
+	return {
+		key: ArrayPrototypeMap(key, fromKeyPart),
+		value: value === null ? null : fromValue(value),
+		versionstamp,
+	};
+}
+
+function toSelector(selector) {
+	return {
+		prefix: selector.prefix === undefined ? null : toKey(selector.prefix),
+		start: selector.start === undefined ? null : toKey(selector.start),
+		end: selector.end === undefined ? null : toKey(selector.end),
+	};
+}
+
+class KvListIterator {
+	#rid;
+	#selector;
+	#cursor;
+	#reverse;
+	#limit;
+	#batchSize;
+	#entries = [];
+	#count = 0;
+	#done = false;
+	#lastCursor;
+
+	constructor(key, rid, selector, options) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError('Illegal constructor');
+		}
+
+		this.#rid = rid;
+		this.#selector = toSelector(selector);
+		this.#cursor = options.cursor ?? null;
+		this.#reverse = !!options.reverse;
+		this.#limit = options.limit;
+		this.#batchSize = options.batchSize ?? 100;
+
+		if (this.#batchSize < 1 || this.#batchSize > 1000) {
+			throw new RangeError('batchSize must be between 1 and 1000');
+		}
+	}
+
+	// Where a listing with the same selector continues after the last entry
+	// returned.
+	get cursor() {
+		if (this.#lastCursor === undefined) {
+			throw new Error('Cannot get the cursor before the first iteration');
+		}
+
+		return this.#lastCursor;
+	}
+
+	async next() {
+		if (this.#entries.length === 0 && !this.#done) {
+			const batchSize = this.#limit === undefined
+				? this.#batchSize
+				: MathMin(this.#batchSize, this.#limit - this.#count);
+
+			if (batchSize > 0) {
+				this.#entries = await core.opAsync(
+					'op_kv_list',
+					this.#rid,
+					this.#selector,
+					this.#cursor,
+					this.#reverse,
+					batchSize,
+				);
+			}
+			if (this.#entries.length < batchSize || batchSize <= 0) {
+				this.#done = true;
+			}
+		}
+
+		const item = ArrayPrototypeShift(this.#entries);
+		if (item === undefined) {
+			return { done: true, value: undefined };
+		}
+
+		this.#cursor = item.cursor;
+		this.#lastCursor = item.cursor;
+		this.#count++;
+
+		return { done: false, value: toEntry(item.entry) };
+	}
+
+	[SymbolAsyncIterator]() {
+		return this;
+	}
+}
+
+class AtomicOperation {
+	#rid;
+	#checks = [];
+	#mutations = [];
+
+	constructor(key, rid) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError('Illegal constructor');
+		}
+		this.#rid = rid;
+	}
+
+	check(...checks) {
+		ArrayPrototypeForEach(checks, ({ key, versionstamp }) => {
+			ArrayPrototypePush(this.#checks, { key: toKey(key), versionstamp: versionstamp ?? null });
+		});
+
+		return this;
+	}
+
+	set(key, value, options) {
+		return this.#mutate(key, 'set', toValue(value), options?.expireIn ?? null);
+	}
+
+	delete(key) {
+		return this.#mutate(key, 'delete', null, null);
+	}
+
+	sum(key, n) {
+		return this.#mutate(key, 'sum', toValue(new KvU64(n)), null);
+	}
+
+	min(key, n) {
+		return this.#mutate(key, 'min', toValue(new KvU64(n)), null);
+	}
+
+	max(key, n) {
+		return this.#mutate(key, 'max', toValue(new KvU64(n)), null);
+	}
+
+	// Resolves with `{ ok: false }` if one of the checks failed.
+	async commit() {
+		const versionstamp = await core.opAsync(
+			'op_kv_atomic_write',
+			this.#rid,
+			this.#checks,
+			this.#mutations,
+		);
+
+		return versionstamp === null ? { ok: false } : { ok: true, versionstamp };
+	}
+
+	#mutate(key, kind, value, expireIn) {
+		ArrayPrototypePush(this.#mutations, { key: toKey(key), kind, value, expireIn });
+		return this;
+	}
+}
+
+class Kv {
+	#rid;
+
+	constructor(key, rid) {
+		if (key !== illegalConstructorKey) {
+			throw new TypeError('Illegal constructor');
+		}
+		this.#rid = rid;
+	}
+
+	async get(key, _options) {
+		const { 0: entry } = await core.opAsync('op_kv_get', this.#rid, [toKey(key)]);
+		return toEntry(entry);
+	}
+
+	async getMany(keys, _options) {
+		const entries = await core.opAsync('op_kv_get', this.#rid, ArrayPrototypeMap(keys, toKey));
+		return ArrayPrototypeMap(entries, toEntry);
+	}
+
+	async set(key, value, options) {
+		return await this.atomic().set(key, value, options).commit();
+	}
+
+	async delete(key) {
+		await this.atomic().delete(key).commit();
+	}
+
+	list(selector, options = {}) {
+		return new KvListIterator(illegalConstructorKey, this.#rid, selector, options);
+	}
+
+	atomic() {
+		return new AtomicOperation(illegalConstructorKey, this.#rid);
+	}
+
+	close() {
+		core.close(this.#rid);
+	}
+}
+
+// Opens the store of the worker, or a new in-memory one for `:memory:`.
+async function openKv(path) {
+	const rid = ops.op_kv_open(path ?? null);
+	return new Kv(illegalConstructorKey, rid);
+}
+
+export { AtomicOperation, Kv, KvListIterator, KvU64, openKv };
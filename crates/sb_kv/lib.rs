@@ -0,0 +1,437 @@
+mod key;
+mod store;
+
+pub use key::{decode_key, encode_key, KeyPart};
+pub use store::{
+    KvCheck, KvEntry, KvMutation, KvMutationKind, KvRange, KvStore, KvValue, KvValueEncoding,
+};
+
+use deno_core::error::{custom_error, type_error, AnyError};
+use deno_core::op2;
+use deno_core::parking_lot::Mutex;
+use deno_core::unsync::spawn_blocking;
+use deno_core::{JsBuffer, OpState, Resource, ResourceId, ToJsBuffer};
+use once_cell::sync::Lazy;
+use sb_core::cache::common::FastInsecureHasher;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The largest encoded key Deno KV accepts.
+const MAX_KEY_SIZE: usize = 2048;
+/// The largest value Deno KV accepts.
+const MAX_VALUE_SIZE: usize = 65536;
+
+deno_core::extension!(
+    sb_kv,
+    ops = [
+        op_kv_open,
+        op_kv_get,
+        op_kv_list,
+        op_kv_atomic_write,
+    ],
+    esm_entry_point = "ext:sb_kv/kv.js",
+    esm = ["kv.js"],
+    options = { config: Option<KvConfig> },
+    state = |state, options| {
+        if let Some(config) = options.config {
+            state.put::<KvConfig>(config);
+        }
+    }
+);
+
+/// The store `Deno.openKv()` opens in a worker.
+#[derive(Debug, Clone)]
+pub struct KvConfig {
+    /// Directory the databases of the namespaces are in.
+    pub dir: PathBuf,
+    /// Workers with the same namespace share their store.
+    pub namespace: String,
+    /// The size in bytes the keys and values of the namespace can take up.
+    pub quota: u64,
+}
+
+impl KvConfig {
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(format!(
+            "{:x}.sqlite3",
+            FastInsecureHasher::hash(&self.namespace)
+        ))
+    }
+}
+
+static STORES: Lazy<Mutex<HashMap<PathBuf, Arc<KvStore>>>> = Lazy::new(Default::default);
+
+/// The store of the namespace, opened the first time one of its workers asks
+/// for it. The quota of `config` replaces the one it had.
+pub fn get_or_open_store(config: &KvConfig) -> Result<Arc<KvStore>, AnyError> {
+    let mut stores = STORES.lock();
+    let path = config.path();
+
+    if let Some(store) = stores.get(&path) {
+        store.set_quota(config.quota);
+        return Ok(store.clone());
+    }
+
+    let store = Arc::new(KvStore::open(path.clone(), config.quota)?);
+    stores.insert(path, store.clone());
+    Ok(store)
+}
+
+fn quota_exceeded(size: u64, quota: u64) -> AnyError {
+    custom_error(
+        "DOMExceptionQuotaExceededError",
+        format!(
+            "the key-value store would take up {} bytes, over the quota of {} bytes",
+            size, quota
+        ),
+    )
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+struct KvResource(Arc<KvStore>);
+
+impl Resource for KvResource {
+    fn name(&self) -> Cow<str> {
+        "kv".into()
+    }
+}
+
+/// Runs `f` off the JS thread, as the store blocks on IO.
+async fn with_store<T, F>(
+    state: &Rc<RefCell<OpState>>,
+    rid: ResourceId,
+    f: F,
+) -> Result<T, AnyError>
+where
+    T: Send + 'static,
+    F: FnOnce(&KvStore) -> Result<T, AnyError> + Send + 'static,
+{
+    let store = state
+        .borrow()
+        .resource_table
+        .get::<KvResource>(rid)?
+        .0
+        .clone();
+
+    spawn_blocking(move || f(&store)).await?
+}
+
+fn to_key(key: &[KeyPart]) -> Result<Vec<u8>, AnyError> {
+    let key = encode_key(key)?;
+    if key.len() > MAX_KEY_SIZE {
+        return Err(type_error(format!(
+            "keys can't be larger than {} bytes",
+            MAX_KEY_SIZE
+        )));
+    }
+
+    Ok(key)
+}
+
+fn to_versionstamp(version: u64) -> String {
+    format!("{:020x}", version)
+}
+
+fn from_versionstamp(versionstamp: &str) -> Result<u64, AnyError> {
+    if versionstamp.len() != 20 {
+        return Err(type_error("invalid versionstamp"));
+    }
+
+    u64::from_str_radix(versionstamp, 16).map_err(|_| type_error("invalid versionstamp"))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvValueArg {
+    encoding: KvValueEncoding,
+    data: JsBuffer,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvValueOutput {
+    encoding: KvValueEncoding,
+    data: ToJsBuffer,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvEntryOutput {
+    key: Vec<KeyPart>,
+    /// `null`, along with `versionstamp`, if there's no entry.
+    value: Option<KvValueOutput>,
+    versionstamp: Option<String>,
+}
+
+impl KvEntryOutput {
+    fn new(key: Vec<KeyPart>, entry: Option<KvEntry>) -> Self {
+        match entry {
+            Some(entry) => Self {
+                key,
+                value: Some(KvValueOutput {
+                    encoding: entry.value.encoding,
+                    data: entry.value.data.into(),
+                }),
+                versionstamp: Some(to_versionstamp(entry.version)),
+            },
+            None => Self {
+                key,
+                value: None,
+                versionstamp: None,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvListEntryOutput {
+    entry: KvEntryOutput,
+    /// Where a listing continuing after the entry starts.
+    cursor: String,
+}
+
+/// The entries of `kv.list()`: the ones starting with `prefix` (not the
+/// prefix itself), from `start` on and before `end`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvListSelector {
+    prefix: Option<Vec<KeyPart>>,
+    start: Option<Vec<KeyPart>>,
+    end: Option<Vec<KeyPart>>,
+}
+
+impl KvListSelector {
+    fn to_range(&self) -> Result<KvRange, AnyError> {
+        let prefix = self.prefix.as_deref().map(encode_key).transpose()?;
+        let with_prefix = |suffix: u8| {
+            prefix.as_ref().map(|prefix| {
+                let mut key = prefix.clone();
+                key.push(suffix);
+                key
+            })
+        };
+
+        // encoded parts start with a tag in `0x01..0xff`, so the keys
+        // starting with the prefix are between these two
+        let start = match &self.start {
+            Some(start) => Some(encode_key(start)?),
+            None => with_prefix(0x00),
+        };
+        let end = match &self.end {
+            Some(end) => Some(encode_key(end)?),
+            None => with_prefix(0xff),
+        };
+
+        match (start, end) {
+            (Some(start), Some(end)) => Ok(KvRange { start, end }),
+            _ => Err(type_error(
+                "the selector needs a prefix, or a start and an end",
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvCheckArg {
+    key: Vec<KeyPart>,
+    /// The key must not exist if it's `null`.
+    versionstamp: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KvMutationKindArg {
+    Set,
+    Delete,
+    Sum,
+    Min,
+    Max,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvMutationArg {
+    key: Vec<KeyPart>,
+    kind: KvMutationKindArg,
+    value: Option<KvValueArg>,
+    /// In milliseconds.
+    expire_in: Option<u64>,
+}
+
+impl KvMutationArg {
+    fn into_mutation(self, now_ms: i64) -> Result<KvMutation, AnyError> {
+        let key = to_key(&self.key)?;
+        let value = match self.value {
+            Some(value) if value.data.len() > MAX_VALUE_SIZE => {
+                return Err(type_error(format!(
+                    "values can't be larger than {} bytes",
+                    MAX_VALUE_SIZE
+                )))
+            }
+            Some(value) => Some(KvValue {
+                encoding: value.encoding,
+                data: value.data.to_vec(),
+            }),
+            None => None,
+        };
+        let operand = || {
+            value
+                .as_ref()
+                .filter(|it| it.encoding == KvValueEncoding::U64 && it.data.len() == 8)
+                .map(|it| u64::from_le_bytes(it.data.as_slice().try_into().unwrap()))
+                .ok_or_else(|| type_error("sum, min and max take a Deno.KvU64"))
+        };
+
+        let kind = match self.kind {
+            KvMutationKindArg::Set => KvMutationKind::Set(
+                value
+                    .clone()
+                    .ok_or_else(|| type_error("set takes a value"))?,
+            ),
+            KvMutationKindArg::Delete => KvMutationKind::Delete,
+            KvMutationKindArg::Sum => KvMutationKind::Sum(operand()?),
+            KvMutationKindArg::Min => KvMutationKind::Min(operand()?),
+            KvMutationKindArg::Max => KvMutationKind::Max(operand()?),
+        };
+
+        Ok(KvMutation {
+            key,
+            kind,
+            expires_at: self
+                .expire_in
+                .map(|it| now_ms.saturating_add(it.min(i64::MAX as u64) as i64)),
+        })
+    }
+}
+
+/// Opens the store of the worker, or a new in-memory one for `:memory:`.
+#[op2]
+#[smi]
+pub fn op_kv_open(
+    state: &mut OpState,
+    #[serde] path: Option<String>,
+) -> Result<ResourceId, AnyError> {
+    let store = match path.as_deref() {
+        None => {
+            let config = state
+                .try_borrow::<KvConfig>()
+                .ok_or_else(|| type_error("Deno.openKv is not available in this worker"))?;
+
+            get_or_open_store(config)?
+        }
+        Some(":memory:") => {
+            let quota = state
+                .try_borrow::<KvConfig>()
+                .map_or(u64::MAX, |it| it.quota);
+            Arc::new(KvStore::in_memory(quota))
+        }
+        Some(_) => {
+            return Err(type_error(
+                "Deno.openKv only opens the store of the worker, or ':memory:'",
+            ))
+        }
+    };
+
+    Ok(state.resource_table.add(KvResource(store)))
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_kv_get(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[serde] keys: Vec<Vec<KeyPart>>,
+) -> Result<Vec<KvEntryOutput>, AnyError> {
+    let encoded = keys
+        .iter()
+        .map(|key| to_key(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let entries = with_store(&state, rid, move |store| store.get(&encoded, now_ms())).await?;
+
+    Ok(keys
+        .into_iter()
+        .zip(entries)
+        .map(|(key, entry)| KvEntryOutput::new(key, entry))
+        .collect())
+}
+
+#[op2(async)]
+#[serde]
+pub async fn op_kv_list(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[serde] selector: KvListSelector,
+    #[serde] cursor: Option<String>,
+    reverse: bool,
+    #[smi] limit: u32,
+) -> Result<Vec<KvListEntryOutput>, AnyError> {
+    let range = selector.to_range()?;
+    let cursor = cursor
+        .map(|it| base64::decode_config(it, base64::URL_SAFE_NO_PAD))
+        .transpose()
+        .map_err(|_| type_error("invalid cursor"))?;
+
+    let entries = with_store(&state, rid, move |store| {
+        store.list(&range, cursor.as_deref(), reverse, limit, now_ms())
+    })
+    .await?;
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            Ok(KvListEntryOutput {
+                cursor: base64::encode_config(&entry.key, base64::URL_SAFE_NO_PAD),
+                entry: KvEntryOutput::new(decode_key(&entry.key)?, Some(entry)),
+            })
+        })
+        .collect()
+}
+
+/// Returns the versionstamp of the commit, or `null` if a check failed.
+#[op2(async)]
+#[serde]
+pub async fn op_kv_atomic_write(
+    state: Rc<RefCell<OpState>>,
+    #[smi] rid: ResourceId,
+    #[serde] checks: Vec<KvCheckArg>,
+    #[serde] mutations: Vec<KvMutationArg>,
+) -> Result<Option<String>, AnyError> {
+    let now = now_ms();
+    let checks = checks
+        .into_iter()
+        .map(|check| {
+            Ok(KvCheck {
+                key: to_key(&check.key)?,
+                version: check
+                    .versionstamp
+                    .as_deref()
+                    .map(from_versionstamp)
+                    .transpose()?,
+            })
+        })
+        .collect::<Result<Vec<_>, AnyError>>()?;
+    let mutations = mutations
+        .into_iter()
+        .map(|mutation| mutation.into_mutation(now))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let version = with_store(&state, rid, move |store| {
+        store.atomic_write(&checks, &mutations, now)
+    })
+    .await?;
+
+    Ok(version.map(to_versionstamp))
+}
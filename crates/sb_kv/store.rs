@@ -0,0 +1,461 @@
+use deno_core::error::{type_error, AnyError};
+use deno_webstorage::rusqlite::{params, Connection, OptionalExtension};
+use sb_core::cache::cache_db::{CacheDB, CacheDBConfiguration, CacheFailure};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::quota_exceeded;
+
+static KV_DB: CacheDBConfiguration = CacheDBConfiguration {
+    table_initializer: "
+      CREATE TABLE IF NOT EXISTS kv (
+        k BLOB PRIMARY KEY,
+        v BLOB NOT NULL,
+        v_encoding INTEGER NOT NULL,
+        version INTEGER NOT NULL,
+        expires_at INTEGER
+      ) WITHOUT ROWID;
+
+      CREATE INDEX IF NOT EXISTS kv_by_expires_at ON kv (expires_at)
+        WHERE expires_at IS NOT NULL;
+
+      CREATE TABLE IF NOT EXISTS kv_version (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        version INTEGER NOT NULL
+      );
+
+      INSERT OR IGNORE INTO kv_version (id, version) VALUES (0, 0);
+    ",
+    // the stores hold user data, so they are kept across upgrades
+    on_version_change: "",
+    preheat_queries: &[],
+    on_failure: CacheFailure::Error,
+};
+
+/// Bump it along with migrations in `table_initializer`.
+const KV_SCHEMA_VERSION: &str = "1";
+
+const GET_SQL: &str = "
+    SELECT v, v_encoding, version FROM kv
+    WHERE k = ?1 AND (expires_at IS NULL OR expires_at > ?2)";
+
+const LIST_SQL: &str = "
+    SELECT k, v, v_encoding, version FROM kv
+    WHERE k >= ?1 AND k < ?2 AND k > ?3 AND (expires_at IS NULL OR expires_at > ?4)
+    ORDER BY k ASC LIMIT ?5";
+
+const LIST_REVERSE_SQL: &str = "
+    SELECT k, v, v_encoding, version FROM kv
+    WHERE k >= ?1 AND k < ?2 AND k < ?3 AND (expires_at IS NULL OR expires_at > ?4)
+    ORDER BY k DESC LIMIT ?5";
+
+/// How a value is stored, so it's given back as the same type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KvValueEncoding {
+    /// Serialized with the V8 serializer.
+    V8,
+    /// A `Uint8Array`, as is.
+    Bytes,
+    /// A `Deno.KvU64`, as 8 little-endian bytes.
+    U64,
+}
+
+impl KvValueEncoding {
+    fn to_sql(self) -> i64 {
+        match self {
+            KvValueEncoding::V8 => 1,
+            KvValueEncoding::Bytes => 2,
+            KvValueEncoding::U64 => 3,
+        }
+    }
+
+    fn from_sql(value: i64) -> Result<Self, AnyError> {
+        match value {
+            1 => Ok(KvValueEncoding::V8),
+            2 => Ok(KvValueEncoding::Bytes),
+            3 => Ok(KvValueEncoding::U64),
+            _ => Err(type_error(format!("unknown value encoding {}", value))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvValue {
+    pub encoding: KvValueEncoding,
+    pub data: Vec<u8>,
+}
+
+impl KvValue {
+    pub fn u64(value: u64) -> Self {
+        Self {
+            encoding: KvValueEncoding::U64,
+            data: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self.encoding {
+            KvValueEncoding::U64 => Some(u64::from_le_bytes(self.data.as_slice().try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvEntry {
+    /// The encoded key.
+    pub key: Vec<u8>,
+    pub value: KvValue,
+    /// The commit that last wrote the entry.
+    pub version: u64,
+}
+
+/// Fails the commit unless the key was last written by `version`, or doesn't
+/// exist if it's `None`.
+#[derive(Debug, Clone)]
+pub struct KvCheck {
+    pub key: Vec<u8>,
+    pub version: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub enum KvMutationKind {
+    Set(KvValue),
+    Delete,
+    /// Adds to the `Deno.KvU64` under the key, wrapping around.
+    Sum(u64),
+    Min(u64),
+    Max(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct KvMutation {
+    pub key: Vec<u8>,
+    pub kind: KvMutationKind,
+    /// Unix time in milliseconds after which the entry is gone.
+    pub expires_at: Option<i64>,
+}
+
+/// The encoded keys `k` with `start <= k < end`.
+#[derive(Debug, Clone)]
+pub struct KvRange {
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+}
+
+/// The entries of a namespace, in a SQLite database. Commits are serialized
+/// by the connection lock of the database, so checks can't race with other
+/// writes.
+pub struct KvStore {
+    db: CacheDB,
+    quota: AtomicU64,
+}
+
+impl KvStore {
+    pub fn open(path: PathBuf, quota: u64) -> Result<Self, AnyError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        Ok(Self {
+            db: CacheDB::from_path(&KV_DB, path, KV_SCHEMA_VERSION),
+            quota: AtomicU64::new(quota),
+        })
+    }
+
+    pub fn in_memory(quota: u64) -> Self {
+        Self {
+            db: CacheDB::in_memory(&KV_DB, KV_SCHEMA_VERSION),
+            quota: AtomicU64::new(quota),
+        }
+    }
+
+    /// Sets the size in bytes the keys and values of the store can take up.
+    pub fn set_quota(&self, quota: u64) {
+        self.quota.store(quota, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, keys: &[Vec<u8>], now_ms: i64) -> Result<Vec<Option<KvEntry>>, AnyError> {
+        self.db.with_connection(|conn| {
+            keys.iter()
+                .map(|key| get_entry(conn, key, now_ms))
+                .collect()
+        })
+    }
+
+    /// The live entries in `range` in key order, after the key `cursor` (or
+    /// before it if `reverse`).
+    pub fn list(
+        &self,
+        range: &KvRange,
+        cursor: Option<&[u8]>,
+        reverse: bool,
+        limit: u32,
+        now_ms: i64,
+    ) -> Result<Vec<KvEntry>, AnyError> {
+        let (sql, cursor) = if reverse {
+            (LIST_REVERSE_SQL, cursor.unwrap_or(&range.end[..]))
+        } else {
+            // every key sorts after the empty one
+            (LIST_SQL, cursor.unwrap_or_default())
+        };
+
+        self.db.with_connection(|conn| {
+            let mut stmt = conn.prepare_cached(sql)?;
+            let rows = stmt.query_map(
+                params![range.start, range.end, cursor, now_ms, limit],
+                |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, Vec<u8>>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )?;
+
+            rows.map(|row| -> Result<KvEntry, AnyError> {
+                let (key, data, encoding, version) = row?;
+                Ok(KvEntry {
+                    key,
+                    value: KvValue {
+                        encoding: KvValueEncoding::from_sql(encoding)?,
+                        data,
+                    },
+                    version: version as u64,
+                })
+            })
+            .collect()
+        })
+    }
+
+    /// Applies the mutations if all checks pass. Returns the version of the
+    /// commit, or `None` if a check failed.
+    pub fn atomic_write(
+        &self,
+        checks: &[KvCheck],
+        mutations: &[KvMutation],
+        now_ms: i64,
+    ) -> Result<Option<u64>, AnyError> {
+        let quota = self.quota.load(Ordering::Relaxed);
+
+        self.db.with_connection(|conn| {
+            // rolled back when dropped before the commit
+            let tx = conn.unchecked_transaction()?;
+
+            for check in checks {
+                let version = get_entry(&tx, &check.key, now_ms)?.map(|it| it.version);
+                if version != check.version {
+                    return Ok(None);
+                }
+            }
+
+            tx.execute("DELETE FROM kv WHERE expires_at <= ?1", [now_ms])?;
+            let size_before = size(&tx)?;
+
+            tx.execute("UPDATE kv_version SET version = version + 1", [])?;
+            let version: i64 =
+                tx.query_row("SELECT version FROM kv_version", [], |row| row.get(0))?;
+
+            for mutation in mutations {
+                let (value, expires_at) = match &mutation.kind {
+                    KvMutationKind::Set(value) => (value.clone(), mutation.expires_at),
+                    KvMutationKind::Delete => {
+                        tx.execute("DELETE FROM kv WHERE k = ?1", [&mutation.key])?;
+                        continue;
+                    }
+                    KvMutationKind::Sum(operand)
+                    | KvMutationKind::Min(operand)
+                    | KvMutationKind::Max(operand) => {
+                        let current = match get_entry(&tx, &mutation.key, now_ms)? {
+                            Some(entry) => Some(entry.value.as_u64().ok_or_else(|| {
+                                type_error("sum, min and max only apply to Deno.KvU64 values")
+                            })?),
+                            None => None,
+                        };
+                        let value = match (&mutation.kind, current) {
+                            (_, None) => *operand,
+                            (KvMutationKind::Sum(_), Some(it)) => it.wrapping_add(*operand),
+                            (KvMutationKind::Min(_), Some(it)) => it.min(*operand),
+                            (_, Some(it)) => it.max(*operand),
+                        };
+
+                        (KvValue::u64(value), None)
+                    }
+                };
+
+                tx.execute(
+                    "INSERT OR REPLACE INTO kv (k, v, v_encoding, version, expires_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        mutation.key,
+                        value.data,
+                        value.encoding.to_sql(),
+                        version,
+                        expires_at
+                    ],
+                )?;
+            }
+
+            // a store over a lowered quota can still shrink
+            let size_after = size(&tx)?;
+            if size_after > quota && size_after > size_before {
+                return Err(quota_exceeded(size_after, quota));
+            }
+
+            tx.commit()?;
+            Ok(Some(version as u64))
+        })
+    }
+}
+
+fn get_entry(conn: &Connection, key: &[u8], now_ms: i64) -> Result<Option<KvEntry>, AnyError> {
+    let row = conn
+        .prepare_cached(GET_SQL)?
+        .query_row(params![key, now_ms], |row| {
+            Ok((
+                row.get::<_, Vec<u8>>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .optional()?;
+
+    let Some((data, encoding, version)) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(KvEntry {
+        key: key.to_vec(),
+        value: KvValue {
+            encoding: KvValueEncoding::from_sql(encoding)?,
+            data,
+        },
+        version: version as u64,
+    }))
+}
+
+/// What the entries count for against the quota.
+fn size(conn: &Connection) -> Result<u64, AnyError> {
+    let size: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(LENGTH(k) + LENGTH(v)), 0) FROM kv",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(size as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KvCheck, KvMutation, KvMutationKind, KvRange, KvStore, KvValue, KvValueEncoding};
+
+    fn set(key: &[u8], data: &[u8], expires_at: Option<i64>) -> KvMutation {
+        KvMutation {
+            key: key.to_vec(),
+            kind: KvMutationKind::Set(KvValue {
+                encoding: KvValueEncoding::Bytes,
+                data: data.to_vec(),
+            }),
+            expires_at,
+        }
+    }
+
+    fn keys(store: &KvStore, reverse: bool, cursor: Option<&[u8]>) -> Vec<Vec<u8>> {
+        let range = KvRange {
+            start: b"a".to_vec(),
+            end: b"z".to_vec(),
+        };
+
+        store
+            .list(&range, cursor, reverse, 10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|it| it.key)
+            .collect()
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let store = KvStore::in_memory(1024);
+
+        let version = store
+            .atomic_write(
+                &[KvCheck {
+                    key: b"a".to_vec(),
+                    version: None,
+                }],
+                &[set(b"a", b"1", None), set(b"b", b"2", Some(100))],
+                0,
+            )
+            .unwrap()
+            .unwrap();
+
+        // the check fails as `a` exists now
+        let check = KvCheck {
+            key: b"a".to_vec(),
+            version: None,
+        };
+        assert_eq!(
+            store
+                .atomic_write(&[check], &[set(b"c", b"3", None)], 0)
+                .unwrap(),
+            None
+        );
+
+        let check = KvCheck {
+            key: b"a".to_vec(),
+            version: Some(version),
+        };
+        let sum = |operand| KvMutation {
+            key: b"n".to_vec(),
+            kind: KvMutationKind::Sum(operand),
+            expires_at: None,
+        };
+        store
+            .atomic_write(&[check], &[sum(u64::MAX)], 0)
+            .unwrap()
+            .unwrap();
+        store.atomic_write(&[], &[sum(2)], 0).unwrap().unwrap();
+
+        let entries = store.get(&[b"n".to_vec(), b"c".to_vec()], 0).unwrap();
+        assert_eq!(entries[0].as_ref().unwrap().value, KvValue::u64(1));
+        assert_eq!(entries[1], None);
+
+        assert_eq!(keys(&store, false, None), [b"a", b"b", b"n"]);
+        assert_eq!(keys(&store, true, Some(&b"n"[..])), [b"b", b"a"]);
+        assert_eq!(keys(&store, false, Some(&b"a"[..])), [b"b", b"n"]);
+
+        // `b` expired
+        let entries = store.get(&[b"b".to_vec()], 100).unwrap();
+        assert_eq!(entries[0], None);
+
+        let sum_bytes = KvMutation {
+            key: b"a".to_vec(),
+            kind: KvMutationKind::Sum(1),
+            expires_at: None,
+        };
+        assert!(store.atomic_write(&[], &[sum_bytes], 0).is_err());
+    }
+
+    #[test]
+    fn test_quota() {
+        let store = KvStore::in_memory(16);
+
+        store
+            .atomic_write(&[], &[set(b"a", &[0; 10], None)], 0)
+            .unwrap();
+        assert!(store
+            .atomic_write(&[], &[set(b"b", &[0; 10], None)], 0)
+            .is_err());
+        assert_eq!(store.get(&[b"b".to_vec()], 0).unwrap(), [None]);
+
+        // replacing the entry with a smaller one is fine over the quota
+        store.set_quota(4);
+        store
+            .atomic_write(&[], &[set(b"a", &[0; 5], None)], 0)
+            .unwrap();
+    }
+}
@@ -1,4 +1,6 @@
-use deno_core::{FastString, ModuleLoader};
+use crate::metadata::Metadata;
+use deno_core::{FastString, ModuleLoader, SourceMapGetter};
+use sb_fs::virtual_fs::FileBackedVfs;
 use sb_npm::CliNpmResolver;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -12,5 +14,11 @@ pub struct RuntimeProviders {
     pub npm_resolver: Arc<CliNpmResolver>,
     pub module_loader: Rc<dyn ModuleLoader>,
     pub fs: Arc<dyn deno_fs::FileSystem>,
+    /// The static files bundled in the eszip, which user workers can read.
+    pub static_files: Option<FileBackedVfs>,
     pub module_code: Option<FastString>,
+    pub source_map_getter: Box<dyn SourceMapGetter>,
+    /// The metadata stored in the eszip, if any. `package_json_deps` has been
+    /// taken by the module loader.
+    pub metadata: Metadata,
 }
@@ -1,9 +1,15 @@
+use anyhow::Context;
+use deno_core::error::AnyError;
+use deno_core::serde_json;
 use deno_npm::registry::PackageDepNpmSchemeValueParseError;
 use deno_semver::package::PackageReq;
 use deno_semver::VersionReqSpecifierParseError;
+use eszip::EszipV2;
+use sb_graph::METADATA_ESZIP_KEY;
 use sb_npm::package_json::{PackageJsonDepValueParseError, PackageJsonDeps};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
 #[derive(Serialize, Deserialize)]
 enum SerializablePackageJsonDepValueParseError {
@@ -67,10 +73,64 @@ impl SerializablePackageJsonDeps {
     }
 }
 
-#[derive(Deserialize, Serialize)]
+/// Default resource limits of a user worker booted from the eszip. They only
+/// apply to the limits that neither the main worker nor the server config set.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MetadataLimits {
+    pub memory_limit_mb: Option<u64>,
+    pub worker_timeout_ms: Option<u64>,
+    pub cpu_time_soft_limit_ms: Option<u64>,
+    pub cpu_time_hard_limit_ms: Option<u64>,
+}
+
+/// Describes how an eszip was built and how it should be run. `bundle` stores
+/// it in the eszip under [`METADATA_ESZIP_KEY`].
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Metadata {
     pub ca_stores: Option<Vec<String>>,
     pub ca_data: Option<Vec<u8>>,
     pub unsafely_ignore_certificate_errors: Option<Vec<String>>,
     pub package_json_deps: Option<SerializablePackageJsonDeps>,
+    /// Specifier of the module to run when no entrypoint is given.
+    pub entrypoint: Option<String>,
+    /// Entrypoint specifier of each function of a multi-function eszip, by
+    /// function name.
+    pub functions: Option<BTreeMap<String, String>>,
+    pub bundler_version: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    pub build_timestamp: Option<u64>,
+    /// See [`sb_graph::eszip_source_hash`].
+    pub source_hash: Option<String>,
+    /// Environment variables the worker gets unless they're already set.
+    pub env_vars: Option<HashMap<String, String>>,
+    pub limits: Option<MetadataLimits>,
+}
+
+impl Metadata {
+    pub fn add_to_eszip(&self, eszip: &mut EszipV2) -> Result<(), AnyError> {
+        let bytes = serde_json::to_vec(self)?;
+
+        eszip.add_opaque_data(
+            String::from(METADATA_ESZIP_KEY),
+            Arc::from(bytes.into_boxed_slice()),
+        );
+
+        Ok(())
+    }
+
+    pub async fn from_eszip(eszip: &EszipV2) -> Result<Option<Self>, AnyError> {
+        let Some(module) = eszip.get_module(METADATA_ESZIP_KEY) else {
+            return Ok(None);
+        };
+        let Some(source) = module.source().await else {
+            return Ok(None);
+        };
+
+        let metadata =
+            serde_json::from_slice(&source).context("failed to parse the eszip metadata")?;
+
+        Ok(Some(metadata))
+    }
 }
@@ -1,7 +1,9 @@
 use crate::metadata::Metadata;
 use crate::node::cjs_code_anaylzer::CliCjsCodeAnalyzer;
 use crate::node::node_module_loader::{CjsResolutionStore, NpmModuleLoader};
-use crate::standalone::standalone_module_loader::{EmbeddedModuleLoader, SharedModuleLoaderState};
+use crate::standalone::standalone_module_loader::{
+    EmbeddedModuleLoader, EmbeddedSourceMapGetter, SharedModuleLoaderState,
+};
 use crate::RuntimeProviders;
 use anyhow::Context;
 use deno_core::error::AnyError;
@@ -18,9 +20,13 @@ use sb_core::cache::CacheSetting;
 use sb_core::cert::{get_root_cert_store, CaData};
 use sb_core::util::http_util::HttpClient;
 use sb_fs::file_system::DenoCompileFileSystem;
-use sb_fs::load_npm_vfs;
+use sb_fs::{load_npm_vfs, load_static_vfs};
 use sb_graph::graph_resolver::MappedSpecifierResolver;
-use sb_graph::{payload_to_eszip, EszipPayloadKind, SOURCE_CODE_ESZIP_KEY, VFS_ESZIP_KEY};
+use sb_graph::signature::{verify_eszip_signature, EszipTrustedKeys};
+use sb_graph::{
+    payload_to_eszip, EszipPayloadKind, NODE_MODULES_ESZIP_KEY, SOURCE_CODE_ESZIP_KEY,
+    STATIC_FILES_ESZIP_KEY, VFS_ESZIP_KEY,
+};
 use sb_node::analyze::NodeCodeTranslator;
 use sb_node::NodeResolver;
 use sb_npm::package_json::PackageJsonDepsProvider;
@@ -53,16 +59,15 @@ impl RootCertStoreProvider for StandaloneRootCertStoreProvider {
 
 pub async fn create_module_loader_for_eszip(
     mut eszip: eszip::EszipV2,
-    metadata: Metadata,
+    mut metadata: Metadata,
     maybe_import_map: Option<ImportMap>,
 ) -> Result<RuntimeProviders, AnyError> {
-    // let main_module = &metadata.entrypoint;
     let current_exe_path = std::env::current_exe().unwrap();
     let current_exe_name = current_exe_path.file_name().unwrap().to_string_lossy();
     let deno_dir_provider = Arc::new(DenoDirProvider::new(None));
     let root_cert_store_provider = Arc::new(StandaloneRootCertStoreProvider {
-        ca_stores: metadata.ca_stores,
-        ca_data: metadata.ca_data.map(CaData::Bytes),
+        ca_stores: metadata.ca_stores.clone(),
+        ca_data: metadata.ca_data.clone().map(CaData::Bytes),
         cell: Default::default(),
     });
     let http_client = Arc::new(HttpClient::new(
@@ -87,9 +92,23 @@ pub async fn create_module_loader_for_eszip(
         None
     };
 
-    let (fs, snapshot) = if let Some(snapshot) = eszip.take_npm_snapshot() {
-        // TODO: Support node_modules
-        let vfs_root_dir_path = npm_cache_dir.registry_folder(&npm_registry_url);
+    let static_files = if let Some(module) = eszip.get_module(STATIC_FILES_ESZIP_KEY) {
+        if let Some(data) = module.take_source().await {
+            Some(load_static_vfs(&data).context("Failed to load static files.")?)
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let is_node_modules_dir = eszip.get_module(NODE_MODULES_ESZIP_KEY).is_some();
+    let (fs, snapshot, node_modules_path) = if let Some(snapshot) = eszip.take_npm_snapshot() {
+        let vfs_root_dir_path = if is_node_modules_dir {
+            root_path.clone()
+        } else {
+            npm_cache_dir.registry_folder(&npm_registry_url)
+        };
 
         let vfs_data: Vec<u8> = eszip
             .get_module(VFS_ESZIP_KEY)
@@ -104,11 +123,13 @@ pub async fn create_module_loader_for_eszip(
         (
             Arc::new(DenoCompileFileSystem::new(vfs)) as Arc<dyn deno_fs::FileSystem>,
             Some(snapshot),
+            Some(root_path).filter(|_| is_node_modules_dir),
         )
     } else {
         (
             Arc::new(deno_fs::RealFs) as Arc<dyn deno_fs::FileSystem>,
             None,
+            None,
         )
     };
 
@@ -117,12 +138,14 @@ pub async fn create_module_loader_for_eszip(
         CacheSetting::Only,
         fs.clone(),
         http_client.clone(),
+        Default::default(),
     ));
 
     let npm_api = Arc::new(CliNpmRegistryApi::new(
         npm_registry_url.clone(),
         npm_cache.clone(),
         http_client.clone(),
+        Default::default(),
     ));
 
     let npm_resolution = Arc::new(NpmResolution::from_serialized(
@@ -136,7 +159,7 @@ pub async fn create_module_loader_for_eszip(
         npm_cache,
         npm_registry_url,
         npm_resolution.clone(),
-        None,
+        node_modules_path,
         NpmSystemInfo::default(),
     );
 
@@ -161,6 +184,7 @@ pub async fn create_module_loader_for_eszip(
     let package_json_deps_provider = Arc::new(PackageJsonDepsProvider::new(
         metadata
             .package_json_deps
+            .take()
             .map(|serialized| serialized.into_deps()),
     ));
     let maybe_import_map = maybe_import_map
@@ -187,9 +211,14 @@ pub async fn create_module_loader_for_eszip(
         module_loader: Rc::new(EmbeddedModuleLoader {
             shared: module_loader_factory.shared.clone(),
         }),
+        source_map_getter: Box::new(EmbeddedSourceMapGetter {
+            shared: module_loader_factory.shared.clone(),
+        }),
         npm_resolver,
         fs,
+        static_files,
         module_code: code_fs,
+        metadata,
     })
 }
 
@@ -197,8 +226,13 @@ pub async fn create_module_loader_for_standalone_from_eszip_kind(
     eszip_payload_kind: EszipPayloadKind,
     maybe_import_map_arc: Option<Arc<ImportMap>>,
     maybe_import_map_path: Option<String>,
+    maybe_trusted_keys: Option<EszipTrustedKeys>,
 ) -> Result<RuntimeProviders, AnyError> {
-    let eszip = payload_to_eszip(eszip_payload_kind).await;
+    let mut eszip = payload_to_eszip(eszip_payload_kind).await?;
+
+    if let Some(trusted_keys) = maybe_trusted_keys {
+        verify_eszip_signature(&mut eszip, &trusted_keys).await?;
+    }
 
     let mut maybe_import_map: Option<ImportMap> = None;
 
@@ -216,15 +250,7 @@ pub async fn create_module_loader_for_standalone_from_eszip_kind(
         }
     }
 
-    create_module_loader_for_eszip(
-        eszip,
-        Metadata {
-            ca_stores: None,
-            ca_data: None,
-            unsafely_ignore_certificate_errors: None,
-            package_json_deps: None,
-        },
-        maybe_import_map,
-    )
-    .await
+    let metadata = Metadata::from_eszip(&eszip).await?.unwrap_or_default();
+
+    create_module_loader_for_eszip(eszip, metadata, maybe_import_map).await
 }
@@ -10,12 +10,14 @@ use deno_core::ModuleLoader;
 use deno_core::ModuleSpecifier;
 use deno_core::ModuleType;
 use deno_core::ResolutionKind;
+use deno_core::SourceMapGetter;
 use deno_semver::npm::NpmPackageReqReference;
 use sb_core::file_fetcher::get_source_from_data_url;
+use sourcemap::SourceMap;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use crate::util::arc_u8_to_arc_str;
+use crate::util::{arc_u8_to_arc_str, source_map_from_code};
 use sb_graph::graph_resolver::MappedSpecifierResolver;
 
 pub struct SharedModuleLoaderState {
@@ -160,3 +162,47 @@ impl ModuleLoader for EmbeddedModuleLoader {
         .boxed_local()
     }
 }
+
+/// Maps the positions of the emitted code back to the original sources, using
+/// the source maps stored alongside the modules in the eszip.
+pub struct EmbeddedSourceMapGetter {
+    pub(crate) shared: Arc<SharedModuleLoaderState>,
+}
+
+impl EmbeddedSourceMapGetter {
+    fn source_map(&self, specifier: &str) -> Option<Vec<u8>> {
+        let module = self.shared.eszip.get_module(specifier)?;
+
+        // NOTE: The eszip is fully loaded before the runtime starts, so the
+        // futures below are always ready.
+        if let Some(source_map) = module.source_map().now_or_never().flatten() {
+            if !source_map.is_empty() {
+                return Some(source_map.to_vec());
+            }
+        }
+
+        // eszips built with inline source maps
+        let code = module.source().now_or_never().flatten()?;
+        source_map_from_code(&code)
+    }
+}
+
+impl SourceMapGetter for EmbeddedSourceMapGetter {
+    fn get_source_map(&self, file_name: &str) -> Option<Vec<u8>> {
+        self.source_map(file_name)
+    }
+
+    fn get_source_line(&self, file_name: &str, line_number: usize) -> Option<String> {
+        let source_map = SourceMap::from_slice(&self.source_map(file_name)?).ok()?;
+        let idx = (0..source_map.get_source_count())
+            .find(|idx| source_map.get_source(*idx) == Some(file_name))
+            .unwrap_or(0);
+
+        // Do NOT use .lines(): it skips the terminating empty line.
+        source_map
+            .get_source_contents(idx)?
+            .split('\n')
+            .nth(line_number)
+            .map(|line| line.to_string())
+    }
+}
@@ -8,3 +8,12 @@ pub fn arc_u8_to_arc_str(arc_u8: Arc<[u8]>) -> Result<Arc<str>, std::str::Utf8Er
     // standard library.
     Ok(unsafe { std::mem::transmute(arc_u8) })
 }
+
+/// Extracts the source map that the emitter inlines at the end of the code.
+pub fn source_map_from_code(code: &[u8]) -> Option<Vec<u8>> {
+    static PREFIX: &[u8] = b"//# sourceMappingURL=data:application/json;base64,";
+    let last_line = code.rsplit(|u| *u == b'\n').next()?;
+    let input = last_line.strip_prefix(PREFIX)?;
+
+    base64::decode(input).ok()
+}
@@ -0,0 +1,52 @@
+use crate::context::{UserWorkerMsgs, UserWorkerTemplate};
+use hyper::header::HeaderValue;
+use hyper::Request;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// How many service bindings a request can go through, e.g. a request the
+/// worker of `a` sends to `b`, which calls `c`, is 2 deep.
+pub const MAX_BINDING_DEPTH: usize = 8;
+
+/// Carries how deep in binding calls a request is. The request a worker
+/// forwards through a binding keeps it, and the main worker can't set it.
+pub const BINDING_DEPTH_HEADER: &str = "x-sb-binding-depth";
+
+/// The services a user worker can call through `EdgeRuntime.bindings`.
+pub struct WorkerBindings {
+    pub services: HashMap<String, UserWorkerTemplate>,
+    pub pool_msg_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+}
+
+/// How deep in binding calls `req` is, 0 if it didn't come through one.
+pub fn depth_of<T>(req: &Request<T>) -> usize {
+    req.headers()
+        .get(BINDING_DEPTH_HEADER)
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn set_depth<T>(req: &mut Request<T>, depth: usize) {
+    req.headers_mut()
+        .insert(BINDING_DEPTH_HEADER, HeaderValue::from(depth));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{depth_of, set_depth, BINDING_DEPTH_HEADER};
+    use hyper::Request;
+
+    #[test]
+    fn test_binding_depth() {
+        let mut req = Request::new(());
+        assert_eq!(depth_of(&req), 0);
+
+        set_depth(&mut req, 3);
+        assert_eq!(depth_of(&req), 3);
+
+        req.headers_mut()
+            .insert(BINDING_DEPTH_HEADER, "deep".parse().unwrap());
+        assert_eq!(depth_of(&req), 0);
+    }
+}
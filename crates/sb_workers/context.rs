@@ -3,15 +3,19 @@ use deno_core::FastString;
 use enum_as_inner::EnumAsInner;
 use event_worker::events::WorkerEventWithMetadata;
 use hyper::{Body, Request, Response};
+use sb_cache::CacheBackendKind;
 use sb_core::conn_sync::ConnSync;
 use sb_core::util::sync::AtomicFlag;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
 use std::sync::atomic::AtomicUsize;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::{mpsc, oneshot, watch, Notify, OwnedSemaphorePermit};
 use uuid::Uuid;
 
+use sb_graph::emitter::LockfileOpts;
+use sb_graph::signature::EszipTrustedKeys;
 use sb_graph::EszipPayloadKind;
 
 #[derive(Debug, Clone)]
@@ -31,10 +35,50 @@ pub struct UserWorkerRuntimeOpts {
     pub cpu_time_soft_limit_ms: u64,
     pub cpu_time_hard_limit_ms: u64,
 
+    /// How long the worker is kept alive after the response was sent, waiting
+    /// for the promises given to `EdgeRuntime.waitUntil` to settle.
+    pub background_timeout_ms: u64,
+    /// Size of the scratch `/tmp` of the worker. It has none if unset. The
+    /// files are on the disk of the host, so this doesn't count against
+    /// `memory_limit_mb`.
+    pub tmp_quota_mb: Option<u64>,
+    /// Where `caches` keeps its entries, and how much the caches of the
+    /// service can hold.
+    pub cache_backend: CacheBackendKind,
+    pub cache_quota_mb: u64,
+    /// Directory of the databases `Deno.openKv` keeps its entries in, instead
+    /// of the `DENO_DIR`.
+    pub kv_dir: Option<String>,
+    /// Workers with the same namespace share their `Deno.openKv` store. It's
+    /// the service path if unset.
+    pub kv_namespace: Option<String>,
+    pub kv_quota_mb: u64,
+    /// Also exchanges `BroadcastChannel` messages with the main worker, not
+    /// only with the other workers of the service.
+    pub broadcast_with_main: bool,
+    /// The services the worker can call through `EdgeRuntime.bindings`, by
+    /// binding name.
+    pub bindings: HashMap<String, UserWorkerTemplate>,
+
+    /// The limits above that were set by the main worker or the server config.
+    /// The others hold a default value that the eszip metadata may override.
+    pub requested_limits: UserWorkerLimits,
+
     pub force_create: bool,
     pub net_access_disabled: bool,
     pub custom_module_root: Option<String>,
     pub allow_remote_modules: bool,
+    /// The `.npmrc` to download npm packages with, instead of the one next to
+    /// the service or in the home directory.
+    pub npmrc_path: Option<String>,
+    /// Installs npm packages in the `node_modules` directory of the service
+    /// instead of resolving them from the global npm cache.
+    pub node_modules_dir: bool,
+    /// The `package.json` bare specifiers resolve through, instead of the one
+    /// next to the entrypoint.
+    pub package_json_path: Option<String>,
+    /// Globs, relative to the service path, of the files the worker can read.
+    pub static_patterns: Vec<String>,
 }
 
 impl Default for UserWorkerRuntimeOpts {
@@ -45,6 +89,16 @@ impl Default for UserWorkerRuntimeOpts {
             low_memory_multiplier: 5,
             cpu_time_soft_limit_ms: 50,
             cpu_time_hard_limit_ms: 100,
+            background_timeout_ms: 10 * 1000,
+            tmp_quota_mb: None,
+            cache_backend: CacheBackendKind::Memory,
+            cache_quota_mb: 50,
+            kv_dir: None,
+            kv_namespace: None,
+            kv_quota_mb: 10,
+            broadcast_with_main: false,
+            bindings: HashMap::new(),
+            requested_limits: UserWorkerLimits::default(),
 
             force_create: false,
             key: None,
@@ -54,11 +108,134 @@ impl Default for UserWorkerRuntimeOpts {
             net_access_disabled: false,
             allow_remote_modules: true,
             custom_module_root: None,
+            npmrc_path: None,
+            node_modules_dir: false,
+            package_json_path: None,
+            static_patterns: vec![],
             service_path: None,
         }
     }
 }
 
+impl UserWorkerRuntimeOpts {
+    /// Applies `defaults` to the limits that weren't requested.
+    pub fn apply_default_limits(&mut self, defaults: &UserWorkerLimits) {
+        let limits = self.requested_limits.clone().or(defaults);
+
+        if let Some(memory_limit_mb) = limits.memory_limit_mb {
+            self.memory_limit_mb = memory_limit_mb;
+        }
+        if let Some(low_memory_multiplier) = limits.low_memory_multiplier {
+            self.low_memory_multiplier = low_memory_multiplier;
+        }
+        if let Some(worker_timeout_ms) = limits.worker_timeout_ms {
+            self.worker_timeout_ms = worker_timeout_ms;
+        }
+        if let Some(cpu_time_soft_limit_ms) = limits.cpu_time_soft_limit_ms {
+            self.cpu_time_soft_limit_ms = cpu_time_soft_limit_ms;
+        }
+        if let Some(cpu_time_hard_limit_ms) = limits.cpu_time_hard_limit_ms {
+            self.cpu_time_hard_limit_ms = cpu_time_hard_limit_ms;
+        }
+
+        self.requested_limits = limits;
+    }
+}
+
+/// The options to create the worker of a service with whenever it's needed
+/// without the main worker, such as for service bindings and schedules.
+#[derive(Debug, Clone)]
+pub struct UserWorkerTemplate {
+    pub service_path: PathBuf,
+    pub no_module_cache: bool,
+    pub import_map_path: Option<String>,
+    pub env_vars: HashMap<String, String>,
+    pub maybe_entrypoint: Option<String>,
+    pub maybe_function_name: Option<String>,
+    pub maybe_lockfile: Option<LockfileOpts>,
+    pub conf: UserWorkerRuntimeOpts,
+}
+
+impl UserWorkerTemplate {
+    pub fn to_init_opts(&self) -> WorkerContextInitOpts {
+        WorkerContextInitOpts {
+            service_path: self.service_path.clone(),
+            no_module_cache: self.no_module_cache,
+            // set by the worker pool
+            cached_only: false,
+            import_map_path: self.import_map_path.clone(),
+            env_vars: self.env_vars.clone(),
+            events_rx: None,
+            timing: None,
+            conf: WorkerRuntimeOpts::UserWorker(self.conf.clone()),
+            maybe_eszip: None,
+            maybe_module_code: None,
+            maybe_entrypoint: self.maybe_entrypoint.clone(),
+            maybe_function_name: self.maybe_function_name.clone(),
+            maybe_lockfile: self.maybe_lockfile.clone(),
+            // set by the worker pool
+            maybe_trusted_keys: None,
+        }
+    }
+}
+
+/// Resource limits of a user worker. Unset limits fall back to the next source
+/// in line (see [`UserWorkerLimitsConfig::resolve`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserWorkerLimits {
+    pub memory_limit_mb: Option<u64>,
+    pub low_memory_multiplier: Option<u64>,
+    pub worker_timeout_ms: Option<u64>,
+    pub cpu_time_soft_limit_ms: Option<u64>,
+    pub cpu_time_hard_limit_ms: Option<u64>,
+}
+
+impl UserWorkerLimits {
+    pub fn or(self, other: &UserWorkerLimits) -> Self {
+        Self {
+            memory_limit_mb: self.memory_limit_mb.or(other.memory_limit_mb),
+            low_memory_multiplier: self.low_memory_multiplier.or(other.low_memory_multiplier),
+            worker_timeout_ms: self.worker_timeout_ms.or(other.worker_timeout_ms),
+            cpu_time_soft_limit_ms: self.cpu_time_soft_limit_ms.or(other.cpu_time_soft_limit_ms),
+            cpu_time_hard_limit_ms: self.cpu_time_hard_limit_ms.or(other.cpu_time_hard_limit_ms),
+        }
+    }
+}
+
+/// Operator-provided limits for user workers, set through the server config.
+#[derive(Debug, Clone, Default)]
+pub struct UserWorkerLimitsConfig {
+    pub default: UserWorkerLimits,
+    pub services: HashMap<PathBuf, UserWorkerLimits>,
+}
+
+impl UserWorkerLimitsConfig {
+    /// Resolves the limits of a worker for `service_path`.
+    ///
+    /// Limits configured for the service path take precedence over the ones
+    /// requested by the main worker, which in turn take precedence over the
+    /// configured defaults.
+    pub fn resolve(&self, service_path: &Path, requested: UserWorkerLimits) -> UserWorkerLimits {
+        let service_path = normalize_service_path(service_path);
+        let service_limits = self
+            .services
+            .iter()
+            .find(|(path, _)| normalize_service_path(path) == service_path)
+            .map(|(_, limits)| limits.clone())
+            .unwrap_or_default();
+
+        service_limits.or(&requested).or(&self.default)
+    }
+}
+
+fn normalize_service_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| {
+        path.components()
+            .filter(|it| !matches!(it, Component::CurDir))
+            .collect()
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct UserWorkerProfile {
     pub worker_request_msg_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
@@ -67,6 +244,7 @@ pub struct UserWorkerProfile {
         mpsc::UnboundedSender<()>,
     ),
     pub service_path: String,
+    pub local_module_paths: Vec<PathBuf>,
     pub permit: Option<Arc<OwnedSemaphorePermit>>,
     pub cancel: Arc<Notify>,
     pub status: TimingStatus,
@@ -75,6 +253,43 @@ pub struct UserWorkerProfile {
 #[derive(Debug, Clone)]
 pub struct MainWorkerRuntimeOpts {
     pub worker_pool_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    pub user_worker_limits: Arc<UserWorkerLimitsConfig>,
+    /// Takes the schedules registered with `EdgeRuntime.schedule`.
+    pub scheduler_tx: Option<mpsc::UnboundedSender<SchedulerMsgs>>,
+}
+
+/// What happens when a schedule fires while its previous run is still going.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    /// The run is skipped.
+    #[default]
+    Skip,
+    /// The runs overlap.
+    Allow,
+    /// The run starts once the previous one is done. At most one run waits.
+    Queue,
+}
+
+/// A user worker to send a request to on a cron schedule.
+#[derive(Debug, Clone)]
+pub struct ScheduleOpts {
+    /// Identifies the schedule, so that scheduling another one with the same
+    /// name replaces it.
+    pub name: String,
+    /// A cron expression, in UTC.
+    pub cron: String,
+    pub overlap: OverlapPolicy,
+    /// Path of the request sent to the worker.
+    pub path: String,
+    pub worker: UserWorkerTemplate,
+}
+
+#[derive(Debug)]
+pub enum SchedulerMsgs {
+    Schedule(ScheduleOpts, oneshot::Sender<Result<(), Error>>),
+    /// Replies whether there was a schedule with the name.
+    Unschedule(String, oneshot::Sender<bool>),
 }
 
 #[derive(Debug, Clone)]
@@ -118,6 +333,9 @@ impl Default for Timing {
 pub struct WorkerContextInitOpts {
     pub service_path: PathBuf,
     pub no_module_cache: bool,
+    /// Fails instead of downloading remote modules or npm packages that
+    /// aren't cached.
+    pub cached_only: bool,
     pub import_map_path: Option<String>,
     pub env_vars: HashMap<String, String>,
     pub events_rx: Option<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>,
@@ -126,6 +344,11 @@ pub struct WorkerContextInitOpts {
     pub maybe_eszip: Option<EszipPayloadKind>,
     pub maybe_module_code: Option<FastString>,
     pub maybe_entrypoint: Option<String>,
+    /// Name of the function to run from a multi-function `maybe_eszip`.
+    pub maybe_function_name: Option<String>,
+    pub maybe_lockfile: Option<LockfileOpts>,
+    /// When set, `maybe_eszip` must be signed by one of these keys.
+    pub maybe_trusted_keys: Option<EszipTrustedKeys>,
 }
 
 #[derive(Debug)]
@@ -143,6 +366,7 @@ pub enum UserWorkerMsgs {
     ),
     Idle(Uuid),
     Shutdown(Uuid),
+    FilesChanged(Vec<PathBuf>),
 }
 
 pub type SendRequestResult = (Response<Body>, mpsc::UnboundedSender<()>);
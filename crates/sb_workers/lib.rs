@@ -1,12 +1,17 @@
+pub mod bindings;
 pub mod context;
 
+use crate::bindings::{
+    depth_of, set_depth, WorkerBindings, BINDING_DEPTH_HEADER, MAX_BINDING_DEPTH,
+};
 use crate::context::{
-    CreateUserWorkerResult, UserWorkerMsgs, UserWorkerRuntimeOpts, WorkerContextInitOpts,
-    WorkerRuntimeOpts,
+    CreateUserWorkerResult, OverlapPolicy, ScheduleOpts, SchedulerMsgs, UserWorkerLimits,
+    UserWorkerLimitsConfig, UserWorkerMsgs, UserWorkerRuntimeOpts, UserWorkerTemplate,
+    WorkerContextInitOpts, WorkerRuntimeOpts,
 };
 use anyhow::Error;
 use context::SendRequestResult;
-use deno_core::error::{custom_error, type_error, AnyError};
+use deno_core::error::{custom_error, range_error, type_error, AnyError};
 use deno_core::futures::stream::Peekable;
 use deno_core::futures::{FutureExt, Stream, StreamExt};
 use deno_core::op2;
@@ -18,15 +23,20 @@ use hyper::body::HttpBody;
 use hyper::header::{HeaderName, HeaderValue, CONTENT_LENGTH};
 use hyper::{Body, Method, Request};
 use log::error;
-use sb_core::conn_sync::{ConnSync, ConnWatcher};
+use sb_cache::CacheBackendKind;
+use sb_core::conn_sync::{wait_for_abort, ConnSync, ConnWatcher};
+use sb_core::upgrade::{OnUpgradedStream, UpgradedResponseResource};
+use sb_core::util::fs::resolve_from_cwd;
+use sb_graph::emitter::LockfileOpts;
 use sb_graph::EszipPayloadKind;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use tokio::sync::{mpsc, oneshot, watch};
 use uuid::Uuid;
@@ -37,6 +47,9 @@ deno_core::extension!(
         op_user_worker_create,
         op_user_worker_fetch_build,
         op_user_worker_fetch_send,
+        op_user_worker_binding_fetch,
+        op_user_worker_schedule,
+        op_user_worker_unschedule,
     ],
     esm_entry_point = "ext:sb_user_workers/user_workers.js",
     esm = ["user_workers.js",]
@@ -55,13 +68,28 @@ pub struct UserWorkerCreateOptions {
     custom_module_root: Option<String>,
     maybe_eszip: Option<JsBuffer>,
     maybe_entrypoint: Option<String>,
+    function_name: Option<String>,
     maybe_module_code: Option<String>,
-
-    memory_limit_mb: u64,
-    low_memory_multiplier: u64,
-    worker_timeout_ms: u64,
-    cpu_time_soft_limit_ms: u64,
-    cpu_time_hard_limit_ms: u64,
+    lockfile_path: Option<String>,
+    npmrc_path: Option<String>,
+    node_modules_dir: bool,
+    package_json_path: Option<String>,
+    static_patterns: Vec<String>,
+    broadcast_with_main: bool,
+    bindings: HashMap<String, UserWorkerCreateOptions>,
+
+    memory_limit_mb: Option<u64>,
+    low_memory_multiplier: Option<u64>,
+    worker_timeout_ms: Option<u64>,
+    cpu_time_soft_limit_ms: Option<u64>,
+    cpu_time_hard_limit_ms: Option<u64>,
+    background_timeout_ms: Option<u64>,
+    tmp_quota_mb: Option<u64>,
+    cache_backend: Option<CacheBackendKind>,
+    cache_quota_mb: Option<u64>,
+    kv_dir: Option<String>,
+    kv_namespace: Option<String>,
+    kv_quota_mb: Option<u64>,
 }
 
 #[op2(async)]
@@ -75,58 +103,7 @@ pub async fn op_user_worker_create(
         let tx = op_state.borrow::<mpsc::UnboundedSender<UserWorkerMsgs>>();
         let (result_tx, result_rx) = oneshot::channel::<Result<CreateUserWorkerResult, Error>>();
 
-        let UserWorkerCreateOptions {
-            service_path,
-            no_module_cache,
-            import_map_path,
-            env_vars,
-            force_create,
-            net_access_disabled,
-            allow_remote_modules,
-            custom_module_root,
-            maybe_eszip,
-            maybe_entrypoint,
-            maybe_module_code,
-
-            memory_limit_mb,
-            low_memory_multiplier,
-            worker_timeout_ms,
-            cpu_time_soft_limit_ms,
-            cpu_time_hard_limit_ms,
-        } = opts;
-
-        let mut env_vars_map = HashMap::new();
-        for (key, value) in env_vars {
-            env_vars_map.insert(key, value);
-        }
-
-        let user_worker_options = WorkerContextInitOpts {
-            service_path: PathBuf::from(service_path),
-            no_module_cache,
-            import_map_path,
-            env_vars: env_vars_map,
-            events_rx: None,
-            timing: None,
-            maybe_eszip: maybe_eszip.map(EszipPayloadKind::JsBufferKind),
-            maybe_entrypoint,
-            maybe_module_code: maybe_module_code.map(|v| v.into()),
-            conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
-                memory_limit_mb,
-                low_memory_multiplier,
-                worker_timeout_ms,
-                cpu_time_soft_limit_ms,
-                cpu_time_hard_limit_ms,
-                force_create,
-                net_access_disabled,
-                allow_remote_modules,
-                custom_module_root,
-                key: None,
-                pool_msg_tx: None,
-                events_msg_tx: None,
-                cancel: None,
-                service_path: None,
-            }),
-        };
+        let user_worker_options = worker_init_opts(&op_state, opts, &mut vec![])?;
 
         tx.send(UserWorkerMsgs::Create(user_worker_options, result_tx))?;
         result_rx
@@ -148,6 +125,254 @@ pub async fn op_user_worker_create(
     }
 }
 
+/// Turns the options the main worker created a worker with into the ones of the
+/// worker pool. `chain` holds the services the worker is bound from, starting
+/// with the one the main worker created.
+fn worker_init_opts(
+    op_state: &OpState,
+    opts: UserWorkerCreateOptions,
+    chain: &mut Vec<(PathBuf, Option<String>)>,
+) -> Result<WorkerContextInitOpts, AnyError> {
+    let UserWorkerCreateOptions {
+        service_path,
+        no_module_cache,
+        import_map_path,
+        env_vars,
+        force_create,
+        net_access_disabled,
+        allow_remote_modules,
+        custom_module_root,
+        maybe_eszip,
+        maybe_entrypoint,
+        function_name,
+        maybe_module_code,
+        lockfile_path,
+        npmrc_path,
+        node_modules_dir,
+        package_json_path,
+        static_patterns,
+        broadcast_with_main,
+        bindings,
+
+        memory_limit_mb,
+        low_memory_multiplier,
+        worker_timeout_ms,
+        cpu_time_soft_limit_ms,
+        cpu_time_hard_limit_ms,
+        background_timeout_ms,
+        tmp_quota_mb,
+        cache_backend,
+        cache_quota_mb,
+        kv_dir,
+        kv_namespace,
+        kv_quota_mb,
+    } = opts;
+
+    let mut env_vars_map = HashMap::new();
+    for (key, value) in env_vars {
+        env_vars_map.insert(key, value);
+    }
+
+    let service_path = PathBuf::from(service_path);
+
+    // `./a` and `a` are the same service
+    chain.push((resolve_from_cwd(&service_path)?, function_name.clone()));
+    if chain.len() > MAX_BINDING_DEPTH + 1 {
+        return Err(range_error(format!(
+            "service bindings can't be nested more than {} deep",
+            MAX_BINDING_DEPTH
+        )));
+    }
+
+    let mut service_bindings = HashMap::new();
+    for (name, binding_opts) in bindings {
+        if binding_opts.maybe_eszip.is_some() || binding_opts.maybe_module_code.is_some() {
+            return Err(type_error(format!(
+                "binding '{}' must be created from a service path",
+                name
+            )));
+        }
+
+        let binding_path = resolve_from_cwd(Path::new(&binding_opts.service_path))?;
+        if chain.iter().any(|(path, function)| {
+            *path == binding_path && *function == binding_opts.function_name
+        }) {
+            return Err(type_error(format!(
+                "binding '{}' loops back to {}",
+                name,
+                binding_path.display()
+            )));
+        }
+
+        let binding = worker_init_opts(op_state, binding_opts, chain)?;
+        chain.pop();
+
+        service_bindings.insert(name, user_worker_template(binding));
+    }
+
+    let requested_limits = UserWorkerLimits {
+        memory_limit_mb,
+        low_memory_multiplier,
+        worker_timeout_ms,
+        cpu_time_soft_limit_ms,
+        cpu_time_hard_limit_ms,
+    };
+    let limits = match op_state.try_borrow::<Arc<UserWorkerLimitsConfig>>() {
+        Some(config) => config.resolve(&service_path, requested_limits),
+        None => requested_limits,
+    };
+    let default_opts = UserWorkerRuntimeOpts::default();
+
+    let user_worker_options = WorkerContextInitOpts {
+        service_path,
+        no_module_cache,
+        // set by the worker pool
+        cached_only: false,
+        import_map_path,
+        env_vars: env_vars_map,
+        events_rx: None,
+        timing: None,
+        maybe_eszip: maybe_eszip.map(EszipPayloadKind::JsBufferKind),
+        maybe_entrypoint,
+        maybe_function_name: function_name,
+        maybe_module_code: maybe_module_code.map(|v| v.into()),
+        maybe_lockfile: lockfile_path.map(|path| LockfileOpts {
+            path: PathBuf::from(path),
+            overwrite: false,
+            frozen: false,
+        }),
+        // set by the worker pool
+        maybe_trusted_keys: None,
+        conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+            memory_limit_mb: limits
+                .memory_limit_mb
+                .unwrap_or(default_opts.memory_limit_mb),
+            low_memory_multiplier: limits
+                .low_memory_multiplier
+                .unwrap_or(default_opts.low_memory_multiplier),
+            worker_timeout_ms: limits
+                .worker_timeout_ms
+                .unwrap_or(default_opts.worker_timeout_ms),
+            cpu_time_soft_limit_ms: limits
+                .cpu_time_soft_limit_ms
+                .unwrap_or(default_opts.cpu_time_soft_limit_ms),
+            cpu_time_hard_limit_ms: limits
+                .cpu_time_hard_limit_ms
+                .unwrap_or(default_opts.cpu_time_hard_limit_ms),
+            background_timeout_ms: background_timeout_ms
+                .unwrap_or(default_opts.background_timeout_ms),
+            tmp_quota_mb,
+            cache_backend: cache_backend.unwrap_or(default_opts.cache_backend),
+            cache_quota_mb: cache_quota_mb.unwrap_or(default_opts.cache_quota_mb),
+            kv_dir,
+            kv_namespace,
+            kv_quota_mb: kv_quota_mb.unwrap_or(default_opts.kv_quota_mb),
+            broadcast_with_main,
+            bindings: service_bindings,
+            requested_limits: limits,
+            force_create,
+            net_access_disabled,
+            allow_remote_modules,
+            custom_module_root,
+            npmrc_path,
+            node_modules_dir,
+            package_json_path,
+            static_patterns,
+            key: None,
+            pool_msg_tx: None,
+            events_msg_tx: None,
+            cancel: None,
+            service_path: None,
+        }),
+    };
+
+    Ok(user_worker_options)
+}
+
+fn user_worker_template(opts: WorkerContextInitOpts) -> UserWorkerTemplate {
+    UserWorkerTemplate {
+        service_path: opts.service_path,
+        no_module_cache: opts.no_module_cache,
+        import_map_path: opts.import_map_path,
+        env_vars: opts.env_vars,
+        maybe_entrypoint: opts.maybe_entrypoint,
+        maybe_function_name: opts.maybe_function_name,
+        maybe_lockfile: opts.maybe_lockfile,
+        conf: opts.conf.into_user_worker().unwrap(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserWorkerScheduleOptions {
+    name: String,
+    cron: String,
+    overlap: OverlapPolicy,
+    path: String,
+    worker: UserWorkerCreateOptions,
+}
+
+fn scheduler_tx(op_state: &OpState) -> Result<mpsc::UnboundedSender<SchedulerMsgs>, AnyError> {
+    op_state
+        .try_borrow::<mpsc::UnboundedSender<SchedulerMsgs>>()
+        .cloned()
+        .ok_or_else(|| type_error("the scheduler is not available"))
+}
+
+#[op2(async)]
+pub async fn op_user_worker_schedule(
+    state: Rc<RefCell<OpState>>,
+    #[serde] opts: UserWorkerScheduleOptions,
+) -> Result<(), AnyError> {
+    let UserWorkerScheduleOptions {
+        name,
+        cron,
+        overlap,
+        path,
+        worker,
+    } = opts;
+
+    if worker.maybe_eszip.is_some() || worker.maybe_module_code.is_some() {
+        return Err(type_error(format!(
+            "schedule '{}' must run a service path",
+            name
+        )));
+    }
+
+    let result_rx = {
+        let op_state = state.borrow();
+        let tx = scheduler_tx(&op_state)?;
+        let worker = user_worker_template(worker_init_opts(&op_state, worker, &mut vec![])?);
+        let (result_tx, result_rx) = oneshot::channel::<Result<(), Error>>();
+
+        tx.send(SchedulerMsgs::Schedule(
+            ScheduleOpts {
+                name,
+                cron,
+                overlap,
+                path,
+                worker,
+            },
+            result_tx,
+        ))?;
+        result_rx
+    };
+
+    result_rx.await?.map_err(|err| type_error(err.to_string()))
+}
+
+#[op2(async)]
+pub async fn op_user_worker_unschedule(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+) -> Result<bool, AnyError> {
+    let tx = scheduler_tx(&state.borrow())?;
+    let (result_tx, result_rx) = oneshot::channel::<bool>();
+
+    tx.send(SchedulerMsgs::Unschedule(name, result_tx))?;
+    Ok(result_rx.await?)
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UserWorkerRequest {
@@ -170,7 +395,9 @@ pub struct UserWorkerResponse {
     status: u16,
     status_text: String,
     headers: Vec<(ByteString, ByteString)>,
-    body_rid: ResourceId,
+    body_rid: Option<ResourceId>,
+    /// Set instead of `body_rid` when the user worker switched protocols.
+    upgraded_rid: Option<ResourceId>,
     size: Option<u64>,
 }
 
@@ -257,6 +484,7 @@ impl Resource for UserWorkerResponseBodyResource {
 
     fn read(self: Rc<Self>, limit: usize) -> AsyncResult<BufView> {
         Box::pin(async move {
+            let conn_watch = self.conn_watch.clone();
             let reader = RcRef::map(&self, |r| &r.reader).borrow_mut().await;
 
             let fut = async move {
@@ -284,7 +512,13 @@ impl Resource for UserWorkerResponseBodyResource {
             };
 
             let cancel_handle = RcRef::map(self, |r| &r.cancel);
-            fut.try_or_cancel(cancel_handle).await
+
+            tokio::select! {
+                result = fut.try_or_cancel(cancel_handle) => result,
+                _ = wait_for_abort(conn_watch) => {
+                    Err(custom_error("Interrupted", "the client disconnected"))
+                }
+            }
         })
     }
 
@@ -298,7 +532,7 @@ impl Resource for UserWorkerResponseBodyResource {
 
         tokio::spawn(async move {
             if let Some(mut watch) = this.conn_watch.clone() {
-                match watch.wait_for(|it| *it == ConnSync::Recv).await {
+                match watch.wait_for(ConnSync::is_settled).await {
                     Ok(_) => {}
                     Err(ex) => error!(
                         "error while waiting for the outbound connection to be finished: {}",
@@ -385,41 +619,110 @@ pub async fn op_user_worker_fetch_send(
         (tx, request)
     };
 
-    let request = Rc::try_unwrap(request)
+    let mut request = Rc::try_unwrap(request)
         .ok()
         .expect("multiple op_user_worker_fetch_send ongoing");
 
-    let (result_tx, result_rx) = oneshot::channel::<Result<SendRequestResult, Error>>();
+    // only the requests sent through bindings are deeper
+    request.0.headers_mut().remove(BINDING_DEPTH_HEADER);
+
     let key_parsed = Uuid::try_parse(key.as_str())?;
 
+    // the watcher stays with the request, which also aborts its signal
     let watcher = watcher_rid
-        .and_then(|it| {
-            state
-                .borrow_mut()
-                .resource_table
-                .take::<ConnWatcher>(it)
-                .ok()
-        })
-        .map(Rc::try_unwrap);
+        .and_then(|it| state.borrow().resource_table.get::<ConnWatcher>(it).ok())
+        .and_then(|it| it.get());
 
-    let watcher = match watcher {
-        Some(Ok(it)) => it.get(),
-        Some(Err(_)) => {
-            error!("failed to unwrap connection watcher");
-            None
-        }
+    send_request(&state, &tx, key_parsed, request.0, watcher).await
+}
+
+/// Sends a request to the worker of a service bound to the calling one, without
+/// going through the public listener.
+#[op2(async)]
+#[serde]
+pub async fn op_user_worker_binding_fetch(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[smi] rid: ResourceId,
+) -> Result<UserWorkerResponse, AnyError> {
+    let (tx, binding, request) = {
+        let mut op_state = state.borrow_mut();
+        let bindings = op_state
+            .try_borrow::<WorkerBindings>()
+            .ok_or_else(|| type_error("the worker has no service bindings"))?;
+
+        let binding = bindings
+            .services
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| type_error(format!("no service is bound to '{}'", name)))?;
+        let tx = bindings.pool_msg_tx.clone();
 
-        None => None,
+        let request = op_state
+            .resource_table
+            .take::<UserWorkerRequestResource>(rid)?;
+
+        (tx, binding, request)
+    };
+
+    let mut request = Rc::try_unwrap(request)
+        .ok()
+        .expect("multiple op_user_worker_binding_fetch ongoing")
+        .0;
+
+    // the depth comes with the request the calling worker is forwarding, if it
+    // came through a binding itself
+    let depth = depth_of(&request) + 1;
+    if depth > MAX_BINDING_DEPTH {
+        return Err(range_error(format!(
+            "service bindings can't be nested more than {} deep",
+            MAX_BINDING_DEPTH
+        )));
+    }
+
+    set_depth(&mut request, depth);
+
+    // reuses the active worker of the service if there's one
+    let (result_tx, result_rx) = oneshot::channel::<Result<CreateUserWorkerResult, Error>>();
+    tx.send(UserWorkerMsgs::Create(binding.to_init_opts(), result_tx))?;
+
+    let key = match result_rx.await {
+        Ok(Ok(result)) => result.key,
+        Ok(Err(err)) => return Err(custom_error("InvalidWorkerCreation", err.to_string())),
+        Err(_) => {
+            return Err(custom_error(
+                "InvalidWorkerCreation",
+                "failed to create worker",
+            ))
+        }
     };
 
+    send_request(&state, &tx, key, request, None).await
+}
+
+async fn send_request(
+    state: &Rc<RefCell<OpState>>,
+    tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+    key: Uuid,
+    request: Request<Body>,
+    watcher: Option<watch::Receiver<ConnSync>>,
+) -> Result<UserWorkerResponse, AnyError> {
+    let (result_tx, result_rx) = oneshot::channel::<Result<SendRequestResult, Error>>();
+
     tx.send(UserWorkerMsgs::SendRequest(
-        key_parsed,
-        request.0,
+        key,
+        request,
         result_tx,
         watcher.clone(),
     ))?;
 
-    let result = result_rx.await?;
+    let result = tokio::select! {
+        result = result_rx => result?,
+        _ = wait_for_abort(watcher.clone()) => {
+            return Err(custom_error("Interrupted", "the client disconnected"));
+        }
+    };
+
     if result.is_err() {
         return Err(custom_error(
             "InvalidWorkerResponse",
@@ -427,7 +730,7 @@ pub async fn op_user_worker_fetch_send(
         ));
     }
 
-    let (result, req_end_tx) = result.unwrap();
+    let (mut result, req_end_tx) = result.unwrap();
 
     let mut headers = vec![];
     for (key, value) in result.headers().iter() {
@@ -444,6 +747,26 @@ pub async fn op_user_worker_fetch_send(
         .unwrap_or("<unknown status code>")
         .to_string();
 
+    // the request lasts as long as the upgraded connection
+    if let Some(on_upgraded) = result.extensions_mut().remove::<OnUpgradedStream>() {
+        let upgraded_rid = state
+            .borrow_mut()
+            .resource_table
+            .add(UpgradedResponseResource {
+                on_upgraded,
+                req_end_tx,
+            });
+
+        return Ok(UserWorkerResponse {
+            status,
+            status_text,
+            headers,
+            body_rid: None,
+            upgraded_rid: Some(upgraded_rid),
+            size: None,
+        });
+    }
+
     let size = HttpBody::size_hint(result.body()).exact();
     let stream: BytesStream = Box::pin(
         result
@@ -465,7 +788,8 @@ pub async fn op_user_worker_fetch_send(
         status,
         status_text,
         headers,
-        body_rid,
+        body_rid: Some(body_rid),
+        upgraded_rid: None,
         size,
     };
 
@@ -1,10 +1,11 @@
 const primordials = globalThis.__bootstrap.primordials;
 const {
+	ObjectFreeze,
 	TypeError,
 } = primordials;
 
 import { readableStreamForRid, writableStreamForRid } from 'ext:deno_web/06_streams.js';
-import { getWatcherRid } from 'ext:sb_core_main_js/js/http.js';
+import { getWatcherRid, upgradedResponse } from 'ext:sb_core_main_js/js/http.js';
 
 const core = globalThis.Deno.core;
 const ops = core.ops;
@@ -13,8 +14,22 @@ const ops = core.ops;
 //     servicePath: string;
 //     memoryLimitMb?: number;
 //     workerTimeoutMs?: number;
+//     backgroundTimeoutMs?: number;
+//     tmpQuotaMb?: number;
+//     cacheBackend?: 'memory' | 'sqlite';
+//     cacheQuotaMb?: number;
+//     kvDir?: string;
+//     kvNamespace?: string;
+//     kvQuotaMb?: number;
 //     noModuleCache?: boolean;
 //     importMapPath?: string;
+//     lockfilePath?: string;
+//     npmrcPath?: string;
+//     nodeModulesDir?: boolean;
+//     packageJsonPath?: string;
+//     staticPatterns?: Array<string>;
+//     broadcastWithMain?: boolean;
+//     bindings?: Record<string, string | WorkerOptions>;
 //     envVars?: Array<any>
 // }
 
@@ -29,115 +44,166 @@ function redirectStatus(status) {
 		status === 307 || status === 308;
 }
 
-class UserWorker {
-	constructor(key) {
-		this.key = key;
-	}
+// Sends `req` with `send(requestRid)` and turns what the worker responded into
+// a `Response`.
+async function sendRequest(req, opts, send) {
+	const { method, url, headers, body, bodyUsed } = req;
+	const { signal } = opts;
 
-	async fetch(req, opts = {}) {
-		const watcherRid = getWatcherRid(req);
-		
-		const { method, url, headers, body, bodyUsed } = req;
-		const { signal } = opts;
+	signal?.throwIfAborted();
 
-		signal?.throwIfAborted();
+	const headersArray = Array.from(headers.entries());
+	const hasReqBody = !bodyUsed && !!body;
 
-		if (watcherRid === void 0) {
-			console.warn(`Unable to find the connection watcher from the request instance.\n\
-Invoke \`EdgeRuntime.applyConnectionWatcher(origReq, newReq)\` if you have cloned the original request.`);
-		} 
-
-		const headersArray = Array.from(headers.entries());
-		const hasReqBody = !bodyUsed && !!body;
+	// const hasReqBody = !bodyUsed && !!body &&
+	// 	(chunkExpression.test(headers.get('transfer-encoding')) ||
+	// 		Number.parseInt(headers.get('content-length'), 10) > 0);
 
-		// const hasReqBody = !bodyUsed && !!body &&
-		// 	(chunkExpression.test(headers.get('transfer-encoding')) ||
-		// 		Number.parseInt(headers.get('content-length'), 10) > 0);
+	const userWorkerReq = {
+		method,
+		url,
+		headers: headersArray,
+		hasBody: hasReqBody,
+	};
 
-		const userWorkerReq = {
-			method,
-			url,
-			headers: headersArray,
-			hasBody: hasReqBody,
-		};
+	const { requestRid, requestBodyRid } = await ops.op_user_worker_fetch_build(
+		userWorkerReq,
+	);
 
-		const { requestRid, requestBodyRid } = await ops.op_user_worker_fetch_build(
-			userWorkerReq,
-		);
+	// stream the request body
+	let reqBodyPromise = null;
+	if (hasReqBody) {
+		let writableStream = writableStreamForRid(requestBodyRid);
+		reqBodyPromise = body.pipeTo(writableStream, { signal });
+	}
 
-		// stream the request body
-		let reqBodyPromise = null;
-		if (hasReqBody) {
-			let writableStream = writableStreamForRid(requestBodyRid);
-			reqBodyPromise = body.pipeTo(writableStream, { signal });
+	const resPromise = send(requestRid);
+	let [sent, res] = await Promise.allSettled([reqBodyPromise, resPromise]);
+	
+	if (sent.status === "rejected") {
+		if (res.status === "fulfilled") {
+			core.close(res.value.bodyRid ?? res.value.upgradedRid);
 		}
 
-		const resPromise = core.opAsync('op_user_worker_fetch_send', this.key, requestRid, watcherRid);
-		let [sent, res] = await Promise.allSettled([reqBodyPromise, resPromise]);
-		
-		if (sent.status === "rejected") {
-			if (res.status === "fulfilled") {
-				core.close(res.value.bodyRid);
-			}
-
-			throw sent.reason;
-		} else if (res.status === "rejected") {
-			throw res.reason;
-		} else {
-			res = res.value;
-		}
+		throw sent.reason;
+	} else if (res.status === "rejected") {
+		throw res.reason;
+	} else {
+		res = res.value;
+	}
 
-		const response = {
-			headers: res.headers,
-			status: res.status,
-			statusText: res.statusText,
-			body: null,
-		};
+	// the user worker switched protocols (e.g. accepted a WebSocket), so
+	// the connection is spliced through once the main worker responds
+	// with this response
+	if (res.upgradedRid !== null) {
+		signal?.addEventListener('abort', () => {
+			core.tryClose(res.upgradedRid);
+		});
 
-		// TODO: add a test
-		if (nullBodyStatus(res.status) || redirectStatus(res.status)) {
+		return upgradedResponse(res.upgradedRid, res.headers);
+	}
+
+	const response = {
+		headers: res.headers,
+		status: res.status,
+		statusText: res.statusText,
+		body: null,
+	};
+
+	// TODO: add a test
+	if (nullBodyStatus(res.status) || redirectStatus(res.status)) {
+		core.close(res.bodyRid);
+	} else {
+		if (req.method === 'HEAD' || req.method === 'CONNECT') {
+			response.body = null;
 			core.close(res.bodyRid);
 		} else {
-			if (req.method === 'HEAD' || req.method === 'CONNECT') {
-				response.body = null;
-				core.close(res.bodyRid);
-			} else {
-				const bodyStream = readableStreamForRid(res.bodyRid);
-
-				signal?.addEventListener('abort', () => {
-					core.tryClose(res.bodyRid);
-				});
-				response.body = bodyStream;
-			}
+			const bodyStream = readableStreamForRid(res.bodyRid);
+
+			signal?.addEventListener('abort', () => {
+				core.tryClose(res.bodyRid);
+			});
+			response.body = bodyStream;
 		}
+	}
 
-		return new Response(response.body ? response.body : null, {
-			headers: response.headers,
-			status: response.status,
-			statusText: response.statusText,
-		});
+	return new Response(response.body ? response.body : null, {
+		headers: response.headers,
+		status: response.status,
+		statusText: response.statusText,
+	});
+}
+
+function withDefaultOptions(opts) {
+	const readyOptions = {
+		// Limits left unset are resolved by the runtime, which applies the
+		// ones from the server config before falling back to its defaults.
+		memoryLimitMb: null,
+		lowMemoryMultiplier: null,
+		workerTimeoutMs: null,
+		cpuTimeSoftLimitMs: null,
+		cpuTimeHardLimitMs: null,
+		backgroundTimeoutMs: null,
+		tmpQuotaMb: null,
+		cacheBackend: null,
+		cacheQuotaMb: null,
+		kvDir: null,
+		kvNamespace: null,
+		kvQuotaMb: null,
+		noModuleCache: false,
+		importMapPath: null,
+		envVars: [],
+		forceCreate: false,
+		netAccessDisabled: false,
+		allowRemoteModules: true,
+		customModuleRoot: '',
+		maybeEszip: opts.eszip ?? null,
+		maybeEntrypoint: null,
+		functionName: null,
+		maybeModuleCode: null,
+		lockfilePath: null,
+		npmrcPath: null,
+		nodeModulesDir: false,
+		packageJsonPath: null,
+		staticPatterns: [],
+		broadcastWithMain: false,
+		...opts,
+	};
+
+	// a binding is either a service path or the options of its worker
+	const bindings = {};
+	for (const [name, binding] of Object.entries(opts.bindings ?? {})) {
+		bindings[name] = withDefaultOptions(
+			typeof binding === 'string' ? { servicePath: binding } : binding,
+		);
 	}
+	readyOptions.bindings = bindings;
 
-	static async create(opts) {
-		const readyOptions = {
-			memoryLimitMb: 512,
-			lowMemoryMultiplier: 5,
-			workerTimeoutMs: 5 * 60 * 1000,
-			cpuTimeSoftLimitMs: 50,
-			cpuTimeHardLimitMs: 100,
-			noModuleCache: false,
-			importMapPath: null,
-			envVars: [],
-			forceCreate: false,
-			netAccessDisabled: false,
-			allowRemoteModules: true,
-			customModuleRoot: '',
-			maybeEszip: null,
-			maybeEntrypoint: null,
-			maybeModuleCode: null,
-			...opts,
-		};
+	return readyOptions;
+}
 
+class UserWorker {
+	constructor(key) {
+		this.key = key;
+	}
+
+	async fetch(req, opts = {}) {
+		const watcherRid = getWatcherRid(req);
+
+		if (watcherRid === void 0) {
+			console.warn(`Unable to find the connection watcher from the request instance.\n\
+Invoke \`EdgeRuntime.applyConnectionWatcher(origReq, newReq)\` if you have cloned the original request.`);
+		} 
+
+		return await sendRequest(
+			req,
+			opts,
+			(requestRid) => core.opAsync('op_user_worker_fetch_send', this.key, requestRid, watcherRid),
+		);
+	}
+
+	static async create(opts) {
+		const readyOptions = withDefaultOptions(opts);
 		const { servicePath, maybeEszip } = readyOptions;
 
 		if (!maybeEszip && (!servicePath || servicePath === '')) {
@@ -150,5 +216,57 @@ Invoke \`EdgeRuntime.applyConnectionWatcher(origReq, newReq)\` if you have clone
 	}
 }
 
+// A service the main worker bound to a user worker, which it sends requests
+// to without going through the public listener.
+class ServiceBinding {
+	#name;
+
+	constructor(name) {
+		this.#name = name;
+	}
+
+	async fetch(input, init = {}) {
+		const req = new Request(input, init);
+
+		return await sendRequest(
+			req,
+			init,
+			(requestRid) => core.opAsync('op_user_worker_binding_fetch', this.#name, requestRid),
+		);
+	}
+}
+
+// Sends a request to the worker of `servicePath` whenever `cron` (in UTC)
+// matches. Scheduling again with the same name replaces the schedule.
+async function schedule(cron, servicePath, options = {}) {
+	const { name = servicePath, overlap = 'skip', path = '/', ...workerOptions } = options;
+
+	if (!servicePath || servicePath === '') {
+		throw new TypeError('service path must be defined');
+	}
+
+	await core.opAsync('op_user_worker_schedule', {
+		name,
+		cron,
+		overlap,
+		path,
+		worker: withDefaultOptions({ ...workerOptions, servicePath }),
+	});
+}
+
+// Resolves whether there was a schedule with the name.
+async function unschedule(name) {
+	return await core.opAsync('op_user_worker_unschedule', name);
+}
+
+function createServiceBindings(names) {
+	const bindings = {};
+	for (const name of names) {
+		bindings[name] = ObjectFreeze(new ServiceBinding(name));
+	}
+
+	return ObjectFreeze(bindings);
+}
+
 const SUPABASE_USER_WORKERS = UserWorker;
-export { SUPABASE_USER_WORKERS };
+export { createServiceBindings, schedule, SUPABASE_USER_WORKERS, unschedule };